@@ -67,6 +67,9 @@ pub enum StorageError {
     #[error("Serialization error: {0}")]
     Serialization(String),
 
+    #[error("Database error: {0}")]
+    Database(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 }
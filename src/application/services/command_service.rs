@@ -1,4 +1,4 @@
-use crate::domain::entities::{Command, CommandRegistry, Message, Content};
+use crate::domain::entities::{AliasTable, Command, CommandRegistry, Message, Content, ToolCall, ToolDefinition, User};
 use crate::application::errors::{CommandError, BotError};
 
 /// Service for managing and executing commands
@@ -73,6 +73,37 @@ impl CommandService {
         help
     }
 
+    /// Shared alias table backing `/alias`
+    pub fn aliases(&self) -> AliasTable {
+        self.registry.aliases()
+    }
+
+    /// Expand an alias or macro invocation, or `Ok(None)` if `text` is not an alias
+    pub fn expand_aliases(&self, user_id: &str, text: &str) -> Result<Option<Vec<String>>, CommandError> {
+        self.registry.expand(user_id, text, &self.prefix)
+    }
+
+    /// Commands the LLM may call as tools
+    pub fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.registry.tool_definitions()
+    }
+
+    /// Whether `name` is a command exposed as a tool
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.registry.find_tool(name).is_some()
+    }
+
+    /// Run a tool call as the matching command, sent by `user_id` in `chat_id`.
+    ///
+    /// The command's own permission checks apply, since they look at the sender.
+    pub fn call_tool(&self, chat_id: &str, user_id: &str, call: &ToolCall) -> Result<String, CommandError> {
+        let cmd = self.registry.find_tool(&call.name)
+            .ok_or_else(|| CommandError::NotFound(call.name.clone()))?;
+        let message = Message::from_command(chat_id, cmd.name.clone(), cmd.tool_args(&call.arguments))
+            .with_sender(User::new(user_id));
+        Ok(self.handle(&message)?.unwrap_or_default())
+    }
+
     pub fn prefix(&self) -> &str {
         &self.prefix
     }
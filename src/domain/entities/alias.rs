@@ -0,0 +1,349 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use crate::application::errors::CommandError;
+
+/// Maximum nesting depth when an alias expands to another alias
+pub const MAX_ALIAS_DEPTH: usize = 8;
+
+/// Maximum number of steps a single invocation may expand to
+pub const MAX_ALIAS_STEPS: usize = 16;
+
+/// Who an alias belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AliasScope {
+    /// Defined by an owner/admin, visible to everybody
+    Global,
+    /// Defined by a single user, visible only to them
+    User(String),
+}
+
+impl AliasScope {
+    /// Storage key for the scope ("global" or the user id)
+    pub fn as_key(&self) -> &str {
+        match self {
+            AliasScope::Global => "global",
+            AliasScope::User(id) => id,
+        }
+    }
+
+    pub fn from_key(key: &str) -> Self {
+        if key == "global" {
+            AliasScope::Global
+        } else {
+            AliasScope::User(key.to_string())
+        }
+    }
+}
+
+/// A user-defined shortcut (`/hn` → `/rss hn`) or macro (several steps run in order)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    pub name: String,
+    pub steps: Vec<String>,
+    pub scope: AliasScope,
+}
+
+impl Alias {
+    pub fn new(name: impl Into<String>, steps: Vec<String>, scope: AliasScope) -> Self {
+        Self {
+            name: normalize_name(&name.into()),
+            steps,
+            scope,
+        }
+    }
+
+    /// Parse a definition like `/rss hn; /finance crypto` into steps
+    pub fn parse_steps(definition: &str) -> Vec<String> {
+        definition
+            .split(';')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    pub fn is_macro(&self) -> bool {
+        self.steps.len() > 1
+    }
+
+    /// Human-readable expansion, steps joined with `; `
+    pub fn definition(&self) -> String {
+        self.steps.join("; ")
+    }
+}
+
+/// Lower-case and strip any leading `/` from an alias or command name
+pub fn normalize_name(name: &str) -> String {
+    name.trim().trim_start_matches('/').to_lowercase()
+}
+
+/// First word of a step or invocation, without prefix
+fn command_word(text: &str, prefix: &str) -> Option<String> {
+    let trimmed = text.trim();
+    if !(trimmed.starts_with('/') || (!prefix.is_empty() && trimmed.starts_with(prefix))) {
+        return None;
+    }
+    let cmd = trimmed.trim_start_matches(prefix).trim_start_matches('/');
+    cmd.split_whitespace().next().map(normalize_name)
+}
+
+/// Alias definitions shared between the command registry and the `/alias` handler
+#[derive(Clone, Default)]
+pub struct AliasTable {
+    inner: Arc<RwLock<HashMap<AliasScope, HashMap<String, Alias>>>>,
+    /// Registered command names, which no alias may shadow
+    reserved: Arc<RwLock<HashSet<String>>>,
+}
+
+impl AliasTable {
+    pub fn insert(&self, alias: Alias) {
+        let mut inner = self.inner.write().unwrap();
+        inner.entry(alias.scope.clone())
+            .or_default()
+            .insert(alias.name.clone(), alias);
+    }
+
+    pub fn remove(&self, scope: &AliasScope, name: &str) -> Option<Alias> {
+        let mut inner = self.inner.write().unwrap();
+        inner.get_mut(scope).and_then(|m| m.remove(&normalize_name(name)))
+    }
+
+    /// Drop every personal alias of a user
+    pub fn remove_user(&self, user_id: &str) -> usize {
+        let mut inner = self.inner.write().unwrap();
+        inner.remove(&AliasScope::User(user_id.to_string())).map_or(0, |m| m.len())
+    }
+
+    /// Keep aliases from taking over the command `name`
+    pub fn reserve(&self, name: &str) {
+        self.reserved.write().unwrap().insert(normalize_name(name));
+    }
+
+    /// Whether `name` belongs to a registered command
+    pub fn is_reserved(&self, name: &str) -> bool {
+        self.reserved.read().unwrap().contains(&normalize_name(name))
+    }
+
+    /// Look up an alias for a user; personal aliases shadow global ones, and
+    /// registered commands shadow both
+    pub fn get(&self, user_id: &str, name: &str) -> Option<Alias> {
+        let name = normalize_name(name);
+        if self.is_reserved(&name) {
+            return None;
+        }
+        let inner = self.inner.read().unwrap();
+        inner.get(&AliasScope::User(user_id.to_string()))
+            .and_then(|m| m.get(&name))
+            .or_else(|| inner.get(&AliasScope::Global).and_then(|m| m.get(&name)))
+            .cloned()
+    }
+
+    /// Aliases visible to a user: their own first, then global ones, sorted by name
+    pub fn list(&self, user_id: &str) -> Vec<Alias> {
+        let inner = self.inner.read().unwrap();
+        let mut own: Vec<Alias> = inner.get(&AliasScope::User(user_id.to_string()))
+            .map(|m| m.values().cloned().collect())
+            .unwrap_or_default();
+        let mut global: Vec<Alias> = inner.get(&AliasScope::Global)
+            .map(|m| m.values().cloned().collect())
+            .unwrap_or_default();
+        own.sort_by(|a, b| a.name.cmp(&b.name));
+        global.sort_by(|a, b| a.name.cmp(&b.name));
+        own.extend(global);
+        own
+    }
+
+    /// Check that adding `alias` would not create a cycle for its owner
+    pub fn check_cycle(&self, alias: &Alias, prefix: &str) -> Result<(), CommandError> {
+        let user_id = match &alias.scope {
+            AliasScope::User(id) => id.clone(),
+            AliasScope::Global => String::new(),
+        };
+        let mut stack = vec![alias.name.clone()];
+        let mut steps = 0;
+        for step in &alias.steps {
+            self.expand_step(&user_id, step, prefix, &mut stack, &mut Vec::new(), &mut steps, Some(alias))?;
+        }
+        Ok(())
+    }
+
+    /// Expand an invocation into the texts to run, or `None` if it is not an alias.
+    ///
+    /// Arguments given to the alias are appended to the last step, so `/hn 10`
+    /// with `hn` → `/rss hn` runs `/rss hn 10`.
+    pub fn expand(&self, user_id: &str, input: &str, prefix: &str) -> Result<Option<Vec<String>>, CommandError> {
+        let Some(name) = command_word(input, prefix) else {
+            return Ok(None);
+        };
+        let Some(alias) = self.get(user_id, &name) else {
+            return Ok(None);
+        };
+
+        let mut stack = vec![alias.name.clone()];
+        let mut out = Vec::new();
+        let mut steps = 0;
+        for step in &alias.steps {
+            self.expand_step(user_id, step, prefix, &mut stack, &mut out, &mut steps, None)?;
+        }
+
+        let args: Vec<&str> = input.split_whitespace().skip(1).collect();
+        if !args.is_empty() {
+            if let Some(last) = out.last_mut() {
+                last.push(' ');
+                last.push_str(&args.join(" "));
+            }
+        }
+        Ok(Some(out))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn expand_step(
+        &self,
+        user_id: &str,
+        step: &str,
+        prefix: &str,
+        stack: &mut Vec<String>,
+        out: &mut Vec<String>,
+        steps: &mut usize,
+        pending: Option<&Alias>,
+    ) -> Result<(), CommandError> {
+        *steps += 1;
+        if *steps > MAX_ALIAS_STEPS {
+            return Err(CommandError::InvalidArgs(format!(
+                "alias expands to more than {} steps", MAX_ALIAS_STEPS)));
+        }
+
+        let target = command_word(step, prefix).and_then(|name| {
+            match pending {
+                Some(p) if p.name == name => Some(p.clone()),
+                _ => self.get(user_id, &name),
+            }
+        });
+
+        let Some(target) = target else {
+            out.push(step.to_string());
+            return Ok(());
+        };
+
+        if stack.contains(&target.name) {
+            return Err(CommandError::InvalidArgs(format!(
+                "alias loop detected: {} -> {}", stack.join(" -> "), target.name)));
+        }
+        if stack.len() >= MAX_ALIAS_DEPTH {
+            return Err(CommandError::InvalidArgs(format!(
+                "alias nesting deeper than {}", MAX_ALIAS_DEPTH)));
+        }
+
+        stack.push(target.name.clone());
+        let extra: Vec<&str> = step.split_whitespace().skip(1).collect();
+        let start = out.len();
+        for inner in &target.steps {
+            self.expand_step(user_id, inner, prefix, stack, out, steps, pending)?;
+        }
+        if !extra.is_empty() && out.len() > start {
+            if let Some(last) = out.last_mut() {
+                last.push(' ');
+                last.push_str(&extra.join(" "));
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: &str) -> AliasScope {
+        AliasScope::User(id.to_string())
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let steps = Alias::parse_steps("/rss hn; /finance crypto ;");
+        assert_eq!(steps, vec!["/rss hn", "/finance crypto"]);
+    }
+
+    #[test]
+    fn test_simple_alias_with_args() {
+        let table = AliasTable::default();
+        table.insert(Alias::new("hn", vec!["/rss hn".to_string()], user("1")));
+
+        let out = table.expand("1", "/hn", "/").unwrap();
+        assert_eq!(out, Some(vec!["/rss hn".to_string()]));
+
+        let out = table.expand("1", "/HN more", "/").unwrap();
+        assert_eq!(out, Some(vec!["/rss hn more".to_string()]));
+
+        // Not visible to other users
+        assert_eq!(table.expand("2", "/hn", "/").unwrap(), None);
+    }
+
+    #[test]
+    fn test_user_alias_shadows_global() {
+        let table = AliasTable::default();
+        table.insert(Alias::new("news", vec!["/rss bbc".to_string()], AliasScope::Global));
+        table.insert(Alias::new("news", vec!["news about indonesia".to_string()], user("1")));
+
+        assert_eq!(table.expand("1", "/news", "/").unwrap(), Some(vec!["news about indonesia".to_string()]));
+        assert_eq!(table.expand("2", "/news", "/").unwrap(), Some(vec!["/rss bbc".to_string()]));
+    }
+
+    #[test]
+    fn test_macro_expands_nested_aliases() {
+        let table = AliasTable::default();
+        table.insert(Alias::new("hn", vec!["/rss hn".to_string()], user("1")));
+        table.insert(Alias::new("morning", Alias::parse_steps("/hn; /finance crypto"), user("1")));
+
+        let out = table.expand("1", "/morning", "/").unwrap().unwrap();
+        assert_eq!(out, vec!["/rss hn", "/finance crypto"]);
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let table = AliasTable::default();
+        table.insert(Alias::new("a", vec!["/b".to_string()], user("1")));
+        table.insert(Alias::new("b", vec!["/a".to_string()], user("1")));
+
+        let err = table.expand("1", "/a", "/").unwrap_err();
+        assert!(err.to_string().contains("loop"));
+
+        let pending = Alias::new("c", vec!["/c".to_string()], user("1"));
+        assert!(table.check_cycle(&pending, "/").is_err());
+
+        let ok = Alias::new("d", vec!["/rss hn".to_string()], user("1"));
+        assert!(table.check_cycle(&ok, "/").is_ok());
+    }
+
+    #[test]
+    fn test_remove() {
+        let table = AliasTable::default();
+        table.insert(Alias::new("/hn", vec!["/rss hn".to_string()], user("1")));
+        assert!(table.remove(&user("1"), "hn").is_some());
+        assert!(table.list("1").is_empty());
+
+        table.insert(Alias::new("hn", vec!["/rss hn".to_string()], user("1")));
+        table.insert(Alias::new("gm", vec!["/ping".to_string()], user("1")));
+        table.insert(Alias::new("hn", vec!["/rss hn".to_string()], user("2")));
+        assert_eq!(table.remove_user("1"), 2);
+        assert!(table.list("1").is_empty() && table.get("2", "hn").is_some());
+    }
+
+    #[test]
+    fn test_registry_commands_win_over_aliases() {
+        use crate::domain::entities::{Command, CommandRegistry};
+
+        let mut registry = CommandRegistry::new();
+        registry.register(Command::new("rss"));
+        registry.aliases().insert(Alias::new("hn", vec!["/rss hn".to_string()], AliasScope::Global));
+        registry.aliases().insert(Alias::new("rss", vec!["/finance crypto".to_string()], AliasScope::Global));
+
+        // Aliases only expand, keeping their arguments, and never replace a command
+        assert!(registry.find("hn").is_none());
+        assert_eq!(registry.expand("1", "/hn 5", "/").unwrap(), Some(vec!["/rss hn 5".to_string()]));
+        assert_eq!(registry.find("/RSS").map(|c| c.name.as_str()), Some("rss"));
+        assert_eq!(registry.expand("1", "/rss bbc", "/").unwrap(), None);
+        assert!(registry.aliases().is_reserved("RSS"));
+    }
+}
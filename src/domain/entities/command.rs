@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 
+use super::alias::AliasTable;
+use super::tool::{self, ToolDefinition, ToolParam};
+
 /// Represents a bot command
 pub struct Command {
     pub name: String,
@@ -8,6 +11,8 @@ pub struct Command {
     pub usage: Option<String>,
     pub handler: Option<CommandHandler>,
     pub permissions: Vec<String>,
+    /// Arguments when exposed to the LLM as a tool; `None` keeps it hidden
+    pub tool_params: Option<Vec<ToolParam>>,
 }
 
 /// Command handler function type
@@ -22,6 +27,7 @@ impl Command {
             usage: None,
             handler: None,
             permissions: Vec::new(),
+            tool_params: None,
         }
     }
 
@@ -45,6 +51,12 @@ impl Command {
         self
     }
 
+    /// Let the model call this command as a tool with the given arguments
+    pub fn with_tool(mut self, params: Vec<ToolParam>) -> Self {
+        self.tool_params = Some(params);
+        self
+    }
+
     pub fn with_handler<F>(mut self, handler: F) -> Self
     where
         F: Fn(crate::domain::entities::Message) -> Result<String, crate::application::errors::CommandError> + Send + Sync + 'static,
@@ -58,12 +70,34 @@ impl Command {
         self.name.to_lowercase() == input_lower || 
             self.aliases.iter().any(|a| a.to_lowercase() == input_lower)
     }
+
+    /// Tool definition, if this command is exposed as a tool
+    pub fn tool_definition(&self) -> Option<ToolDefinition> {
+        let params = self.tool_params.as_ref()?;
+        let mut description = self.description.clone().unwrap_or_else(|| format!("Run /{}", self.name));
+        if let Some(usage) = &self.usage {
+            description.push_str(&format!(" (usage: {})", usage.lines().next().unwrap_or("")));
+        }
+        Some(ToolDefinition {
+            name: tool::tool_name(&self.name),
+            description,
+            parameters: tool::parameters_schema(params),
+        })
+    }
+
+    /// Command arguments for a tool call's JSON arguments
+    pub fn tool_args(&self, arguments: &serde_json::Value) -> Vec<String> {
+        self.tool_params.as_deref()
+            .map(|params| tool::arguments_in_order(params, arguments))
+            .unwrap_or_default()
+    }
 }
 
 /// Command registry for managing available commands
 #[derive(Default)]
 pub struct CommandRegistry {
     commands: HashMap<String, Command>,
+    aliases: AliasTable,
 }
 
 impl CommandRegistry {
@@ -72,6 +106,9 @@ impl CommandRegistry {
     }
 
     pub fn register(&mut self, command: Command) {
+        for name in std::iter::once(&command.name).chain(&command.aliases) {
+            self.aliases.reserve(name);
+        }
         self.commands.insert(command.name.clone(), command);
     }
 
@@ -79,8 +116,36 @@ impl CommandRegistry {
         self.commands.get(name)
     }
 
+    /// Find a command by name or built-in alias; user-defined aliases go
+    /// through [`Self::expand`]
     pub fn find(&self, input: &str) -> Option<&Command> {
-        self.commands.values().find(|c| c.matches(input))
+        let name = input.trim_start_matches('/');
+        self.commands.values().find(|c| c.matches(name))
+    }
+
+    /// Shared alias table, for handlers that manage aliases at runtime
+    pub fn aliases(&self) -> AliasTable {
+        self.aliases.clone()
+    }
+
+    /// Expand a user's alias or macro invocation into the texts to run
+    pub fn expand(&self, user_id: &str, input: &str, prefix: &str) -> Result<Option<Vec<String>>, crate::application::errors::CommandError> {
+        self.aliases.expand(user_id, input, prefix)
+    }
+
+    /// Command exposed as the tool `name`
+    pub fn find_tool(&self, name: &str) -> Option<&Command> {
+        self.commands.values()
+            .find(|c| c.tool_params.is_some() && tool::tool_name(&c.name) == name)
+    }
+
+    /// Definitions of every command exposed as a tool, sorted by name
+    pub fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        let mut tools: Vec<ToolDefinition> = self.commands.values()
+            .filter_map(|c| c.tool_definition())
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
     }
 
     pub fn all(&self) -> impl Iterator<Item = &Command> {
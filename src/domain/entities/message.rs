@@ -1,8 +1,9 @@
 use super::User;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
 /// Type of message content
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     Text,
     Command,
@@ -34,7 +35,7 @@ impl MessageType {
 }
 
 /// Message content
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Content {
     Text(String),
     Command { name: String, args: Vec<String> },
@@ -56,7 +57,7 @@ impl Content {
 }
 
 /// Represents an incoming or outgoing message
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub id: String,
     pub chat_id: String,
@@ -3,7 +3,11 @@
 pub mod user;
 pub mod message;
 pub mod command;
+pub mod alias;
+pub mod tool;
 
 pub use user::User;
 pub use message::{Message, MessageType, Content};
 pub use command::{Command, CommandRegistry};
+pub use alias::{Alias, AliasScope, AliasTable};
+pub use tool::{ToolCall, ToolDefinition, ToolParam};
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// A function the model may call, described with a JSON schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments object
+    pub parameters: Value,
+}
+
+/// A call the model asked us to make
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Provider-assigned id, echoed back with the result
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// One named argument of a command exposed as a tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolParam {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+    /// Allowed values, if the argument is an enum
+    pub choices: Vec<String>,
+}
+
+impl ToolParam {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            required: true,
+            choices: Vec::new(),
+        }
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    pub fn with_choices(mut self, choices: &[&str]) -> Self {
+        self.choices = choices.iter().map(|c| c.to_string()).collect();
+        self
+    }
+}
+
+/// Tool names may only contain `[a-zA-Z0-9_]` for most providers
+pub fn tool_name(command: &str) -> String {
+    command
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// JSON schema for a list of string parameters
+pub fn parameters_schema(params: &[ToolParam]) -> Value {
+    let mut properties = Map::new();
+    for param in params {
+        let mut prop = json!({
+            "type": "string",
+            "description": param.description,
+        });
+        if !param.choices.is_empty() {
+            prop["enum"] = json!(param.choices);
+        }
+        properties.insert(param.name.clone(), prop);
+    }
+    let required: Vec<&str> = params.iter()
+        .filter(|p| p.required)
+        .map(|p| p.name.as_str())
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// Pull parameter values out of a call's arguments, in declaration order.
+///
+/// Commands take positional arguments, so this stops at the first one missing
+/// rather than shifting later values into its place.
+pub fn arguments_in_order(params: &[ToolParam], arguments: &Value) -> Vec<String> {
+    params.iter()
+        .map_while(|p| match arguments.get(&p.name)? {
+            Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Value::Null | Value::String(_) => None,
+            other => Some(other.to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_and_arguments() {
+        let params = vec![
+            ToolParam::new("category", "Market").with_choices(&["crypto", "forex"]),
+            ToolParam::new("limit", "How many").optional(),
+        ];
+        let schema = parameters_schema(&params);
+        assert_eq!(schema["required"], json!(["category"]));
+        assert_eq!(schema["properties"]["category"]["enum"], json!(["crypto", "forex"]));
+
+        let args = arguments_in_order(&params, &json!({"limit": 5, "category": "crypto"}));
+        assert_eq!(args, vec!["crypto", "5"]);
+
+        let args = arguments_in_order(&params, &json!({"category": " ", "limit": null}));
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn test_missing_optional_keeps_positions() {
+        let params = vec![
+            ToolParam::new("source", "Feed"),
+            ToolParam::new("limit", "How many").optional(),
+            ToolParam::new("filter", "Keyword").optional(),
+        ];
+        let args = arguments_in_order(&params, &json!({"source": "hn", "filter": "rust"}));
+        assert_eq!(args, vec!["hn"]);
+
+        let args = arguments_in_order(&params, &json!({"source": "hn", "limit": "", "filter": "rust"}));
+        assert_eq!(args, vec!["hn"]);
+    }
+
+    #[test]
+    fn test_tool_name() {
+        assert_eq!(tool_name("kiro-status"), "kiro_status");
+        assert_eq!(tool_name("rss"), "rss");
+    }
+}
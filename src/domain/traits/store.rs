@@ -11,6 +11,10 @@ pub trait Store: Send + Sync {
     // Message operations
     async fn save_message(&self, message: &crate::domain::entities::Message) -> Result<(), StorageError>;
     async fn get_messages(&self, chat_id: &str, limit: usize) -> Result<Vec<crate::domain::entities::Message>, StorageError>;
+    /// Messages a user sent or that were sent in their private chat, oldest first
+    async fn user_messages(&self, user_id: &str) -> Result<Vec<crate::domain::entities::Message>, StorageError>;
+    /// Forget a user and their messages; returns the records removed
+    async fn delete_user(&self, user_id: &str) -> Result<usize, StorageError>;
 
     // Key-value operations
     async fn get(&self, key: &str) -> Result<Option<String>, StorageError>;
@@ -27,6 +27,29 @@ pub struct Message {
     pub chat: Chat,
     pub text: Option<String>,
     pub reply_to_message: Option<Box<Message>>,
+    /// Attached file
+    pub document: Option<Document>,
+    /// Text sent with an attachment
+    pub caption: Option<String>,
+    /// Sizes of an attached photo, smallest first
+    pub photo: Option<Vec<PhotoSize>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PhotoSize {
+    pub file_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub file_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Document {
+    pub file_id: String,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    /// Size in bytes
+    pub file_size: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -34,6 +57,7 @@ pub struct User {
     pub id: i64,
     pub username: Option<String>,
     pub first_name: Option<String>,
+    pub last_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -270,6 +294,11 @@ impl TelegramAdapter {
             Command { command: "ping".to_string(), description: "Check bot is alive".to_string() },
             Command { command: "clear".to_string(), description: "Clear conversation".to_string() },
             Command { command: "quote".to_string(), description: "Get random quote".to_string() },
+            Command { command: "alias".to_string(), description: "Manage command aliases".to_string() },
+            Command { command: "history".to_string(), description: "Export conversation history".to_string() },
+            Command { command: "docs".to_string(), description: "Manage indexed documents".to_string() },
+            Command { command: "search".to_string(), description: "Search your chat history".to_string() },
+            Command { command: "mydata".to_string(), description: "Export or delete your data".to_string() },
         ];
 
         let url = self.api_url("setMyCommands");
@@ -432,6 +461,147 @@ impl Bot for TelegramAdapter {
 }
 
 impl TelegramAdapter {
+    /// Send an HTML message with inline buttons, or with `message_id` replace
+    /// that message's text and buttons (e.g. to turn a page). Returns the
+    /// message id.
+    pub async fn show_page(&self, chat_id: &str, message_id: Option<&str>, html: &str, buttons: Vec<Vec<KeyboardButton>>) -> Result<String, BotError> {
+        #[derive(Serialize)]
+        struct PageRequest {
+            chat_id: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            message_id: Option<i64>,
+            text: String,
+            parse_mode: &'static str,
+            disable_web_page_preview: bool,
+            reply_markup: InlineMarkup,
+        }
+
+        #[derive(Serialize)]
+        struct InlineMarkup {
+            inline_keyboard: Vec<Vec<InlineButton>>,
+        }
+
+        #[derive(Serialize)]
+        struct InlineButton {
+            text: String,
+            callback_data: String,
+        }
+
+        let message_id = message_id
+            .map(|id| id.parse::<i64>().map_err(|e| BotError::Parse(e.to_string())))
+            .transpose()?;
+        let inline_keyboard = buttons.into_iter()
+            .map(|row| row.into_iter()
+                .map(|btn| InlineButton { text: btn.text, callback_data: btn.callback_data.unwrap_or_default() })
+                .collect())
+            .collect();
+        let request = PageRequest {
+            chat_id: chat_id.to_string(),
+            message_id,
+            text: html.to_string(),
+            parse_mode: "HTML",
+            disable_web_page_preview: true,
+            reply_markup: InlineMarkup { inline_keyboard },
+        };
+
+        let method = if message_id.is_some() { "editMessageText" } else { "sendMessage" };
+        let response = self.client
+            .post(self.api_url(method))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| BotError::Network(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(BotError::Network(format!("Telegram API error: {}", response.status())));
+        }
+        if let Some(id) = message_id {
+            return Ok(id.to_string());
+        }
+
+        #[derive(Deserialize)]
+        struct Response {
+            result: MessageResult,
+        }
+
+        #[derive(Deserialize)]
+        struct MessageResult {
+            message_id: i64,
+        }
+
+        let data: Response = response
+            .json()
+            .await
+            .map_err(|e| BotError::Parse(e.to_string()))?;
+        Ok(data.result.message_id.to_string())
+    }
+
+    /// Send a file (e.g. a history export) as a document
+    pub async fn send_document(&self, chat_id: &str, filename: &str, bytes: Vec<u8>, caption: Option<&str>) -> Result<(), BotError> {
+        let part = reqwest::multipart::Part::bytes(bytes).file_name(filename.to_string());
+        let mut form = reqwest::multipart::Form::new()
+            .text("chat_id", chat_id.to_string())
+            .part("document", part);
+        if let Some(caption) = caption {
+            form = form.text("caption", caption.to_string());
+        }
+        
+        let response = self.client
+            .post(self.api_url("sendDocument"))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| BotError::Network(e.to_string()))?;
+        
+        if !response.status().is_success() {
+            return Err(BotError::Network(format!("Send document error: {}", response.status())));
+        }
+        Ok(())
+    }
+    
+    /// Download a file sent to the bot (bot API limit: 20 MB)
+    pub async fn download_file(&self, file_id: &str) -> Result<Vec<u8>, BotError> {
+        #[derive(Deserialize)]
+        struct Response {
+            result: FileInfo,
+        }
+
+        #[derive(Deserialize)]
+        struct FileInfo {
+            file_path: Option<String>,
+        }
+
+        let response = self.client
+            .get(self.api_url("getFile"))
+            .query(&[("file_id", file_id)])
+            .send()
+            .await
+            .map_err(|e| BotError::Network(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(BotError::Network(format!("Get file error: {}", response.status())));
+        }
+
+        let data: Response = response
+            .json()
+            .await
+            .map_err(|e| BotError::Parse(e.to_string()))?;
+        let path = data.result.file_path
+            .ok_or_else(|| BotError::Parse("File has no download path".to_string()))?;
+
+        let response = self.client
+            .get(format!("{}/file/bot{}/{}", API_BASE, self.token, path))
+            .send()
+            .await
+            .map_err(|e| BotError::Network(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(BotError::Network(format!("Download error: {}", response.status())));
+        }
+        let bytes = response.bytes().await.map_err(|e| BotError::Network(e.to_string()))?;
+        Ok(bytes.to_vec())
+    }
+    
     /// Send chat action (typing, upload_photo, etc.)
     pub async fn send_chat_action(&self, chat_id: &str, action: &str) -> Result<(), BotError> {
         #[derive(Serialize)]
@@ -0,0 +1,495 @@
+//! Backup - One archive with everything needed to rebuild the bot
+//!
+//! `carik-bot backup` writes `carik-backup-<time>.tar.gz` containing:
+//! - `carik-bot.db`, a consistent copy taken with SQLite's online backup API,
+//!   so the bot can keep running
+//! - `config.yaml`, `SOUL.md` and the prompt overrides under `prompts/`
+//! - `manifest.json` with the versions, a SHA-256 per file and the workspaces
+//!   under carik home (names and sizes; their files aren't copied)
+//!
+//! plus `<archive>.sha256` for the archive itself. Archives are packed with
+//! the system `tar` and, like their staging directories, only readable by
+//! their owner. [`restore`] checks both checksums before touching anything,
+//! copies each file next to its target and renames it into place, keeping the
+//! replaced file as `<name>.before-restore`.
+
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use crate::infrastructure::database::{migrations, Database};
+use crate::infrastructure::hash;
+
+/// Archive layout version written to the manifest
+pub const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const DATABASE: &str = "carik-bot.db";
+const CONFIG: &str = "config.yaml";
+const SOUL: &str = "SOUL.md";
+const PROMPTS: &str = "prompts";
+const PREFIX: &str = "carik-backup-";
+const EXTENSION: &str = ".tar.gz";
+
+/// Scheduled backups (`backup:` in config.yaml); `carik-bot backup` works
+/// without them
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct BackupConfig {
+    /// Back up while the bot runs
+    pub enabled: bool,
+    /// Where archives go
+    pub dir: String,
+    pub interval_hours: u64,
+    /// Archives kept; older ones are deleted after each scheduled backup
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "backups".to_string(),
+            interval_hours: 24,
+            keep: 7,
+        }
+    }
+}
+
+/// Where the bot's files live, to read them from or restore them to
+#[derive(Debug, Clone)]
+pub struct Paths {
+    pub database: PathBuf,
+    pub config: PathBuf,
+    pub soul: PathBuf,
+    pub prompts: PathBuf,
+    /// Carik home, holding the workspaces
+    pub home: PathBuf,
+}
+
+/// `manifest.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub created_at: String,
+    pub bot_version: String,
+    pub schema_version: u32,
+    pub files: Vec<FileEntry>,
+    pub workspaces: Vec<WorkspaceEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Relative, with `/` separators
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceEntry {
+    pub name: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Write a backup archive into `dir`; returns its path
+pub fn create(db: &Database, paths: &Paths, dir: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let staging = Staging::new(dir.join(format!(".{}{}", PREFIX, uuid::Uuid::new_v4())))?;
+
+    db.backup_to(staging.0.join(DATABASE)).map_err(|e| format!("database backup failed: {}", e))?;
+    let schema_version = db.schema_status().map_err(|e| e.to_string())?.current;
+    for (source, name) in [(&paths.config, CONFIG), (&paths.soul, SOUL)] {
+        if source.is_file() {
+            fs::copy(source, staging.0.join(name)).map_err(|e| format!("{}: {}", source.display(), e))?;
+        }
+    }
+    if paths.prompts.is_dir() {
+        copy_dir(&paths.prompts, &staging.0.join(PROMPTS))?;
+    }
+
+    let mut files = Vec::new();
+    for path in walk(&staging.0)? {
+        files.push(FileEntry {
+            path: relative(&staging.0, &path),
+            size: fs::metadata(&path).map_err(|e| e.to_string())?.len(),
+            sha256: sha256_file(&path)?,
+        });
+    }
+    let manifest = Manifest {
+        format: FORMAT,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        bot_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        files,
+        workspaces: workspaces(&paths.home),
+    };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(staging.0.join(MANIFEST), json).map_err(|e| e.to_string())?;
+
+    let name = format!("{}{}{}", PREFIX, chrono::Utc::now().format("%Y%m%d-%H%M%S"), EXTENSION);
+    let archive = dir.join(&name);
+    pack(&staging.0, &archive)?;
+    let checksum = format!("{}  {}\n", sha256_file(&archive)?, name);
+    create_private(&checksum_path(&archive))?
+        .write_all(checksum.as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(archive)
+}
+
+/// Check an archive and put its files back. Stop the bot first: the
+/// database file is replaced underneath it.
+pub fn restore(archive: &Path, paths: &Paths) -> Result<Manifest, String> {
+    let sidecar = checksum_path(archive);
+    match fs::read_to_string(&sidecar) {
+        Ok(expected) => {
+            let expected = expected.split_whitespace().next().unwrap_or_default().to_lowercase();
+            if sha256_file(archive)? != expected {
+                return Err(format!("{} does not match {}", archive.display(), sidecar.display()));
+            }
+        }
+        Err(_) => tracing::warn!("No {}, checking the files inside only", sidecar.display()),
+    }
+
+    let staging = Staging::new(std::env::temp_dir().join(format!("{}{}", PREFIX, uuid::Uuid::new_v4())))?;
+    tar(&["-xzf", &archive.to_string_lossy(), "-C", &staging.0.to_string_lossy()])?;
+    let json = fs::read_to_string(staging.0.join(MANIFEST)).map_err(|_| "not a carik-bot backup (no manifest.json)".to_string())?;
+    let manifest: Manifest = serde_json::from_str(&json).map_err(|e| format!("{}: {}", MANIFEST, e))?;
+    if manifest.format > FORMAT {
+        return Err(format!("backup format {} is newer than this build understands ({})", manifest.format, FORMAT));
+    }
+    if manifest.schema_version > migrations::latest_version() {
+        return Err(format!(
+            "backup has database schema {}, this build only knows up to {}; restore it with a newer carik-bot",
+            manifest.schema_version, migrations::latest_version()
+        ));
+    }
+
+    // Verify everything before replacing anything
+    let mut targets = Vec::new();
+    for file in &manifest.files {
+        let target = destination(&file.path, paths).ok_or_else(|| format!("unexpected file in backup: {}", file.path))?;
+        let source = staging.0.join(&file.path);
+        if sha256_file(&source)? != file.sha256 {
+            return Err(format!("{} is corrupt (checksum mismatch)", file.path));
+        }
+        targets.push((source, target));
+    }
+
+    // Copy everything next to its target first, so a failed copy leaves the
+    // old files alone; then swap each in with a rename
+    let mut incoming = Vec::new();
+    for (source, target) in &targets {
+        let copy = with_suffix(target, ".restoring");
+        let copied = target.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::copy(source, &copy));
+        if let Err(e) = copied {
+            for (copy, _) in &incoming {
+                let _ = fs::remove_file(copy);
+            }
+            let _ = fs::remove_file(&copy);
+            return Err(format!("{}: {}", target.display(), e));
+        }
+        incoming.push((copy, target));
+    }
+    for (copy, target) in incoming {
+        set_aside(target)?;
+        if target == &paths.database {
+            // A leftover WAL belongs to the old database
+            for suffix in ["-wal", "-shm"] {
+                set_aside(&with_suffix(target, suffix))?;
+            }
+        }
+        fs::rename(&copy, target).map_err(|e| format!("{}: {}", target.display(), e))?;
+    }
+    for workspace in &manifest.workspaces {
+        let _ = fs::create_dir_all(paths.home.join(&workspace.name));
+    }
+    Ok(manifest)
+}
+
+/// Delete all but the newest `keep` archives in `dir`; returns how many went
+pub fn prune(dir: &Path, keep: usize) -> Result<usize, String> {
+    let mut archives: Vec<PathBuf> = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(PREFIX) && n.ends_with(EXTENSION)))
+        .collect();
+    // Timestamped names sort oldest first
+    archives.sort();
+    let excess = archives.len().saturating_sub(keep);
+    for archive in &archives[..excess] {
+        fs::remove_file(archive).map_err(|e| format!("{}: {}", archive.display(), e))?;
+        let _ = fs::remove_file(checksum_path(archive));
+    }
+    Ok(excess)
+}
+
+/// Where a file from the archive goes; `None` for anything unexpected
+fn destination(path: &str, paths: &Paths) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    match path {
+        DATABASE => Some(paths.database.clone()),
+        CONFIG => Some(paths.config.clone()),
+        SOUL => Some(paths.soul.clone()),
+        _ => relative.strip_prefix(PROMPTS).ok()
+            .filter(|rest| !rest.as_os_str().is_empty())
+            .map(|rest| paths.prompts.join(rest)),
+    }
+}
+
+/// Keep an existing file as `<name>.before-restore`
+fn set_aside(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    fs::rename(path, with_suffix(path, ".before-restore")).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn checksum_path(archive: &Path) -> PathBuf {
+    with_suffix(archive, ".sha256")
+}
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Pack `staging` into `archive` through `<archive>.partial`, which is
+/// removed again if tar or the rename fails
+fn pack(staging: &Path, archive: &Path) -> Result<(), String> {
+    let partial = with_suffix(archive, ".partial");
+    // tar writes into the file as created here, keeping its mode
+    create_private(&partial)?;
+    let packed = tar(&["-czf", &partial.to_string_lossy(), "-C", &staging.to_string_lossy(), "."])
+        .and_then(|_| fs::rename(&partial, archive).map_err(|e| e.to_string()));
+    if packed.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    packed
+}
+
+/// Create a new file only its owner can read: backups hold the whole database
+fn create_private(path: &Path) -> Result<fs::File, String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Workspaces the way `/workspace list` sees them: directories under home
+fn workspaces(home: &Path) -> Vec<WorkspaceEntry> {
+    let Ok(entries) = fs::read_dir(home) else {
+        return Vec::new();
+    };
+    let mut workspaces: Vec<WorkspaceEntry> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir() && path.file_name().is_some_and(|n| !n.to_string_lossy().starts_with('.')))
+        .map(|path| {
+            let files = walk(&path).unwrap_or_default();
+            WorkspaceEntry {
+                name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                bytes: files.iter().filter_map(|f| fs::metadata(f).ok()).map(|m| m.len()).sum(),
+                files: files.len() as u64,
+            }
+        })
+        .collect();
+    workspaces.sort_by(|a, b| a.name.cmp(&b.name));
+    workspaces
+}
+
+/// Every file under `dir`, sorted
+fn walk(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    for file in walk(from)? {
+        let target = to.join(file.strip_prefix(from).map_err(|e| e.to_string())?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::copy(&file, &target).map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+    Ok(())
+}
+
+fn relative(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(hash::hex(&context.finish()))
+}
+
+fn tar(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("tar").args(args).output().map_err(|e| format!("could not run tar: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("tar failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Scratch directory removed on drop, whether or not the backup worked
+struct Staging(PathBuf);
+
+impl Staging {
+    /// Created for the owner only, since it holds a copy of the database
+    fn new(path: PathBuf) -> Result<Self, String> {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(root: &Path) -> Paths {
+        Paths {
+            database: root.join("carik-bot.db"),
+            config: root.join("config.yaml"),
+            soul: root.join("SOUL.md"),
+            prompts: root.join("home/prompts"),
+            home: root.join("home"),
+        }
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let root = std::env::temp_dir().join(format!("carik-backup-test-{}", uuid::Uuid::new_v4()));
+        let paths = layout(&root);
+        fs::create_dir_all(paths.home.join("default-workspace")).unwrap();
+        fs::create_dir_all(paths.prompts.join("jv")).unwrap();
+        fs::write(&paths.config, "bot: {}").unwrap();
+        fs::write(paths.prompts.join("jv/chat.txt"), "Sugeng").unwrap();
+        fs::write(paths.home.join("default-workspace/main.rs"), "fn main() {}").unwrap();
+        let db = Database::new(&paths.database).unwrap();
+        db.add_user("7", Some("budi"), "admin").unwrap();
+
+        let archive = create(&db, &paths, &root.join("backups")).unwrap();
+        assert!(checksum_path(&archive).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [archive.clone(), checksum_path(&archive)] {
+                assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600, "{}", file.display());
+            }
+        }
+        drop(db);
+
+        // Lose everything, then restore it
+        fs::remove_file(&paths.database).unwrap();
+        fs::write(&paths.config, "changed").unwrap();
+        fs::remove_dir_all(&paths.home).unwrap();
+        let manifest = restore(&archive, &paths).unwrap();
+        assert_eq!(manifest.schema_version, migrations::latest_version());
+        assert_eq!(manifest.files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), vec!["carik-bot.db", "config.yaml", "prompts/jv/chat.txt"]);
+        assert_eq!(manifest.workspaces.iter().find(|w| w.name == "default-workspace").map(|w| (w.files, w.bytes)), Some((1, 12)));
+        let db = Database::new(&paths.database).unwrap();
+        assert_eq!(db.get_user_by_telegram_id("7").unwrap().unwrap().role, "admin");
+        assert_eq!(fs::read_to_string(&paths.config).unwrap(), "bot: {}");
+        assert_eq!(fs::read_to_string(root.join("config.yaml.before-restore")).unwrap(), "changed");
+        assert_eq!(fs::read_to_string(paths.prompts.join("jv/chat.txt")).unwrap(), "Sugeng");
+        assert!(paths.home.join("default-workspace").is_dir());
+        assert!(!with_suffix(&paths.config, ".restoring").exists());
+
+        // A damaged archive is refused
+        let checksum = fs::read_to_string(checksum_path(&archive)).unwrap();
+        fs::write(checksum_path(&archive), checksum.replacen(|c: char| c.is_ascii_hexdigit(), "x", 1)).unwrap();
+        assert!(restore(&archive, &paths).unwrap_err().contains("does not match"));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_failed_pack_leaves_no_partial() {
+        let dir = std::env::temp_dir().join(format!("carik-backup-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join(format!("{}20240101-000000{}", PREFIX, EXTENSION));
+
+        assert!(pack(&dir.join("missing"), &archive).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune_keeps_newest() {
+        let dir = std::env::temp_dir().join(format!("carik-prune-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        for day in 1..=4 {
+            let archive = dir.join(format!("{}2026010{}-000000{}", PREFIX, day, EXTENSION));
+            fs::write(&archive, "").unwrap();
+            fs::write(checksum_path(&archive), "").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        assert_eq!(prune(&dir, 2).unwrap(), 2);
+        let mut left: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        assert_eq!(left, vec![
+            "carik-backup-20260103-000000.tar.gz", "carik-backup-20260103-000000.tar.gz.sha256",
+            "carik-backup-20260104-000000.tar.gz", "carik-backup-20260104-000000.tar.gz.sha256",
+            "notes.txt",
+        ]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_destination_stays_inside() {
+        let paths = layout(Path::new("/data"));
+        assert_eq!(destination("prompts/chat.txt", &paths), Some(PathBuf::from("/data/home/prompts/chat.txt")));
+        assert_eq!(destination("prompts/../../etc/passwd", &paths), None);
+        assert_eq!(destination("/etc/passwd", &paths), None);
+        assert_eq!(destination("notes.txt", &paths), None);
+    }
+}
@@ -3,6 +3,15 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use crate::application::errors::ConfigError;
+use crate::infrastructure::llm::{LLMConfig, UsageConfig};
+use crate::infrastructure::intent::IntentConfig;
+use crate::infrastructure::memory::MemoryConfig;
+use crate::infrastructure::rag::RagConfig;
+use crate::infrastructure::prompts::PromptsConfig;
+use crate::infrastructure::guard::GuardConfig;
+use crate::infrastructure::database::DatabaseConfig;
+use crate::infrastructure::backup::BackupConfig;
+use crate::infrastructure::storage::StorageConfig;
 
 /// Bot configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,6 +23,39 @@ pub struct Config {
     pub adapters: AdaptersConfig,
     pub whitelist: WhitelistConfig,
     pub guests: GuestConfig,
+    /// LLM providers, fallback chain and per-task routes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm: Option<LLMConfig>,
+    /// Intent classifier selection and confidence thresholds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intent: Option<IntentConfig>,
+    /// Conversation history size and retention
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryConfig>,
+    /// Model prices and per-role monthly budgets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageConfig>,
+    /// Long-term user memory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryConfig>,
+    /// Retrieval over workspace and uploaded documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag: Option<RagConfig>,
+    /// Prompt template overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<PromptsConfig>,
+    /// Prompt-injection and output guardrails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guard: Option<GuardConfig>,
+    /// SQLite connection pool and busy timeout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<DatabaseConfig>,
+    /// Scheduled backups and how many to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupConfig>,
+    /// Where user profiles and the message log are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -76,6 +118,25 @@ pub struct GuestConfig {
     pub approved: Vec<String>,  // User IDs who have been approved
 }
 
+/// Conversation history persisted in SQLite (`history:` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct HistoryConfig {
+    /// Messages older than this many days are deleted (0 = keep forever)
+    pub retention_days: u32,
+    /// Hard cap on stored messages per conversation (0 = unlimited)
+    pub max_per_conversation: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 30,
+            max_per_conversation: 500,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct AdaptersConfig {
@@ -139,6 +200,17 @@ impl Default for Config {
                 pending: vec![],
                 approved: vec![],
             },
+            llm: None,
+            intent: None,
+            history: None,
+            usage: None,
+            memory: None,
+            rag: None,
+            prompts: None,
+            guard: None,
+            database: None,
+            backup: None,
+            storage: None,
         }
     }
 }
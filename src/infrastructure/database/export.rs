@@ -0,0 +1,95 @@
+//! Conversation history export (JSON / Markdown)
+
+use serde_json::json;
+
+use super::StoredMessage;
+
+/// Output format for `/history export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Render a conversation in the requested format
+pub fn export_history(format: ExportFormat, chat_id: &str, user_id: &str, messages: &[StoredMessage]) -> String {
+    let exported_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    match format {
+        ExportFormat::Json => {
+            let messages: Vec<_> = messages.iter()
+                .map(|m| json!({"role": m.role, "content": m.content, "created_at": m.created_at}))
+                .collect();
+            let doc = json!({
+                "chat_id": chat_id,
+                "user_id": user_id,
+                "exported_at": exported_at,
+                "messages": messages,
+            });
+            serde_json::to_string_pretty(&doc).unwrap_or_default()
+        }
+        ExportFormat::Markdown => {
+            let mut out = format!(
+                "# Conversation history\n\n- Chat: {}\n- User: {}\n- Exported: {} UTC\n- Messages: {}\n",
+                chat_id, user_id, exported_at, messages.len()
+            );
+            for m in messages {
+                let who = match m.role.as_str() {
+                    "user" => "👤 You",
+                    "assistant" => "🤖 Carik",
+                    other => other,
+                };
+                out.push_str(&format!("\n### {} · {}\n\n{}\n", who, m.created_at, m.content));
+            }
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<StoredMessage> {
+        vec![
+            StoredMessage { id: 1, role: "user".to_string(), content: "halo".to_string(), created_at: "2026-01-01 10:00:00".to_string() },
+            StoredMessage { id: 2, role: "assistant".to_string(), content: "Sugeng rawuh!".to_string(), created_at: "2026-01-01 10:00:02".to_string() },
+        ]
+    }
+
+    #[test]
+    fn test_export_json() {
+        let out = export_history(ExportFormat::Json, "42", "7", &sample());
+        let doc: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(doc["chat_id"], "42");
+        assert_eq!(doc["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(doc["messages"][1]["content"], "Sugeng rawuh!");
+    }
+
+    #[test]
+    fn test_export_markdown() {
+        let out = export_history(ExportFormat::Markdown, "42", "7", &sample());
+        assert!(out.starts_with("# Conversation history"));
+        assert!(out.contains("- Messages: 2"));
+        assert!(out.contains("### 👤 You · 2026-01-01 10:00:00\n\nhalo"));
+        assert_eq!(ExportFormat::from_name("MD"), Some(ExportFormat::Markdown));
+        assert_eq!(ExportFormat::from_name("pdf"), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! Schema migrations - Ordered, versioned schema changes
+//!
+//! Each step runs in its own transaction together with its `schema_version`
+//! row, so a failed upgrade leaves the database at the last good version.
+//! Steps 1-8 only create what's missing: databases from before versioning
+//! already have some of those tables, and adopt the history by running them.
+//! Later steps may alter tables freely.
+//!
+//! To change the schema, append a step with the next version; never edit a
+//! released one.
+
+use rusqlite::{Connection, Result as SqliteResult};
+
+/// A schema change
+#[derive(Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "users, rate limits and settings",
+        sql: "
+            CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                telegram_id TEXT UNIQUE NOT NULL,
+                username TEXT,
+                role TEXT NOT NULL DEFAULT 'guest',
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            CREATE TABLE IF NOT EXISTS rate_limits (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                timestamp TEXT NOT NULL DEFAULT (datetime('now')),
+                query_type TEXT NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            );
+            -- User settings table for personalization
+            CREATE TABLE IF NOT EXISTS user_settings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL UNIQUE,
+                language TEXT DEFAULT 'en',
+                timezone TEXT DEFAULT 'UTC',
+                system_prompt TEXT,
+                preferences TEXT DEFAULT '{}',
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_rate_limits_user ON rate_limits(user_id);
+            CREATE INDEX IF NOT EXISTS idx_rate_limits_timestamp ON rate_limits(timestamp);
+        ",
+    },
+    Migration {
+        version: 2,
+        name: "command aliases",
+        sql: "
+            CREATE TABLE IF NOT EXISTS command_aliases (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                scope TEXT NOT NULL,
+                steps TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (name, scope)
+            );
+        ",
+    },
+    Migration {
+        version: 3,
+        name: "conversation history",
+        sql: "
+            -- One conversation per (chat, user)
+            CREATE TABLE IF NOT EXISTS conversations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                chat_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (chat_id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+            );
+            -- Rolling summary of turns no longer sent to the LLM verbatim
+            CREATE TABLE IF NOT EXISTS conversation_summaries (
+                conversation_id INTEGER PRIMARY KEY,
+                summary TEXT NOT NULL,
+                through_message_id INTEGER NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id, id);
+        ",
+    },
+    Migration {
+        version: 4,
+        name: "llm usage ledger",
+        sql: "
+            CREATE TABLE IF NOT EXISTS llm_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                chat_id TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                task TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                cost REAL NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            CREATE INDEX IF NOT EXISTS idx_llm_usage_user ON llm_usage(user_id, created_at);
+        ",
+    },
+    Migration {
+        version: 5,
+        name: "user memories",
+        sql: "
+            CREATE TABLE IF NOT EXISTS user_memories (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                category TEXT NOT NULL DEFAULT 'other',
+                content TEXT NOT NULL,
+                source TEXT NOT NULL DEFAULT 'explicit',
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (user_id, content COLLATE NOCASE)
+            );
+        ",
+    },
+    Migration {
+        version: 6,
+        name: "document index",
+        sql: "
+            -- scope is `user:<id>` or `workspace:<name>`
+            CREATE TABLE IF NOT EXISTS documents (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                scope TEXT NOT NULL,
+                source TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                chunks INTEGER NOT NULL DEFAULT 0,
+                embedding_model TEXT,
+                indexed_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (scope, source)
+            );
+            -- Embeddings are little-endian f32 arrays
+            CREATE TABLE IF NOT EXISTS document_chunks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                document_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                start_line INTEGER NOT NULL,
+                end_line INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                embedding BLOB
+            );
+            CREATE INDEX IF NOT EXISTS idx_document_chunks_document ON document_chunks(document_id);
+        ",
+    },
+    Migration {
+        version: 7,
+        name: "per-chat model selection",
+        sql: "
+            CREATE TABLE IF NOT EXISTS chat_models (
+                chat_id TEXT PRIMARY KEY,
+                provider TEXT NOT NULL,
+                model TEXT,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+        ",
+    },
+    Migration {
+        version: 8,
+        name: "llm response cache",
+        sql: "
+            -- Replies reused for deterministic LLM requests, by request hash
+            CREATE TABLE IF NOT EXISTS llm_cache (
+                key TEXT PRIMARY KEY,
+                task TEXT NOT NULL,
+                model TEXT NOT NULL,
+                content TEXT NOT NULL,
+                hits INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                expires_at TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 9,
+        name: "users.first_name",
+        sql: "ALTER TABLE users ADD COLUMN first_name TEXT;",
+    },
+    Migration {
+        version: 10,
+        name: "generic store: message log and key-value",
+        sql: "
+            ALTER TABLE users ADD COLUMN last_name TEXT;
+            -- Platform messages saved through the Store trait, as JSON
+            CREATE TABLE chat_log (
+                id TEXT PRIMARY KEY,
+                chat_id TEXT NOT NULL,
+                sender_id TEXT,
+                message TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX idx_chat_log_chat ON chat_log(chat_id, created_at);
+            CREATE TABLE kv_store (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+        ",
+    },
+    Migration {
+        version: 11,
+        name: "full-text search over conversation messages",
+        sql: "
+            -- Indexes messages.content; the triggers keep it in step
+            CREATE VIRTUAL TABLE messages_fts USING fts5(
+                content,
+                content = 'messages',
+                content_rowid = 'id',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+            CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+            END;
+            CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            END;
+            CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+                INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+            END;
+            INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
+        ",
+    },
+];
+
+/// Version the newest step brings the schema to
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// A step recorded in `schema_version`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub applied_at: String,
+}
+
+/// Where a database stands
+#[derive(Debug)]
+pub struct SchemaStatus {
+    pub current: u32,
+    pub latest: u32,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<&'static Migration>,
+}
+
+fn ensure_version_table(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn current_version(conn: &Connection) -> SqliteResult<u32> {
+    ensure_version_table(conn)?;
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+pub fn status(conn: &Connection) -> SqliteResult<SchemaStatus> {
+    let current = current_version(conn)?;
+    let mut stmt = conn.prepare("SELECT version, name, applied_at FROM schema_version ORDER BY version")?;
+    let applied = stmt.query_map([], |row| Ok(AppliedMigration {
+        version: row.get(0)?,
+        name: row.get(1)?,
+        applied_at: row.get(2)?,
+    }))?.collect::<SqliteResult<Vec<_>>>()?;
+    Ok(SchemaStatus {
+        current,
+        latest: latest_version(),
+        applied,
+        pending: MIGRATIONS.iter().filter(|m| m.version > current).collect(),
+    })
+}
+
+/// Apply every pending step in order; returns the ones applied. A database
+/// newer than this build is refused rather than guessed at.
+pub fn migrate(conn: &Connection) -> SqliteResult<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+            Some(format!(
+                "database schema is version {}, newer than this build supports ({}); upgrade carik-bot",
+                current, latest_version()
+            )),
+        ));
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+            rusqlite::params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        tracing::info!("Applied database migration {}: {}", migration.version, migration.name);
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Last step that must be safe to re-run on a pre-versioning database
+    const BASELINE_VERSION: u32 = 8;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        stmt.query_map([], |row| row.get(1)).unwrap().collect::<SqliteResult<_>>().unwrap()
+    }
+
+    #[test]
+    fn test_versions_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[1].version, pair[0].version + 1, "{} follows {}", pair[1].name, pair[0].name);
+        }
+        assert!(MIGRATIONS.iter().take_while(|m| m.version <= BASELINE_VERSION).all(|m| !m.sql.contains("ALTER")));
+    }
+
+    #[test]
+    fn test_adopts_unversioned_database() {
+        // A database created by `CREATE TABLE IF NOT EXISTS` before versioning
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute("INSERT INTO users (telegram_id, username, role) VALUES ('7', 'budi', 'owner')", []).unwrap();
+
+        let applied = migrate(&conn).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert!(columns(&conn, "users").contains(&"first_name".to_string()));
+        let role: String = conn.query_row("SELECT role FROM users WHERE telegram_id = '7'", [], |r| r.get(0)).unwrap();
+        assert_eq!(role, "owner");
+
+        // Nothing left to do
+        assert!(migrate(&conn).unwrap().is_empty());
+        let status = status(&conn).unwrap();
+        assert_eq!((status.current, status.latest), (latest_version(), latest_version()));
+        assert!(status.pending.is_empty());
+        assert_eq!(status.applied.len(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_failed_step_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_version_table(&conn).unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        // Version 1-8 recorded, but first_name already exists, so step 9 fails
+        for m in &MIGRATIONS[..8] {
+            conn.execute("INSERT INTO schema_version (version, name) VALUES (?1, ?2)", rusqlite::params![m.version, m.name]).unwrap();
+        }
+        conn.execute("ALTER TABLE users ADD COLUMN first_name TEXT", []).unwrap();
+
+        assert!(migrate(&conn).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_version_table(&conn).unwrap();
+        conn.execute("INSERT INTO schema_version (version, name) VALUES (?1, 'future')", [latest_version() + 1]).unwrap();
+        assert!(migrate(&conn).unwrap_err().to_string().contains("newer than this build"));
+    }
+}
@@ -1,12 +1,28 @@
-use rusqlite::{Connection, Result as SqliteResult};
+use rusqlite::Result as SqliteResult;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::sync::Arc;
+
+pub mod export;
+pub mod migrations;
+pub mod pool;
+pub mod privacy;
+pub mod search;
+pub mod store;
+
+pub use pool::DatabaseConfig;
+pub use search::SearchScope;
+use pool::{Pool, PooledConnection};
+
+pub use export::{export_history, ExportFormat};
+pub use migrations::{Migration, SchemaStatus};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub telegram_id: String,
     pub username: Option<String>,
+    pub first_name: Option<String>,
     pub role: String, // owner, admin, user, guest
     pub created_at: String,
 }
@@ -19,83 +35,86 @@ pub struct RateLimitEntry {
     pub query_type: String, // minute, hour
 }
 
+/// SQLite database over a connection pool; clones share the pool
+#[derive(Clone)]
 pub struct Database {
-    conn: Connection,
+    pool: Arc<Pool>,
 }
 
 impl Database {
     pub fn new(path: impl AsRef<Path>) -> SqliteResult<Self> {
-        let conn = Connection::open(path)?;
-        let db = Self { conn };
-        db.init_tables()?;
+        Self::with_config(path, DatabaseConfig::default())
+    }
+    
+    pub fn with_config(path: impl AsRef<Path>, config: DatabaseConfig) -> SqliteResult<Self> {
+        let db = Self::open(path, config)?;
+        db.migrate()?;
         Ok(db)
     }
     
-    fn init_tables(&self) -> SqliteResult<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS users (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                telegram_id TEXT UNIQUE NOT NULL,
-                username TEXT,
-                role TEXT NOT NULL DEFAULT 'guest',
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
-            )",
-            [],
-        )?;
-        
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS rate_limits (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                timestamp TEXT NOT NULL DEFAULT (datetime('now')),
-                query_type TEXT NOT NULL,
-                FOREIGN KEY (user_id) REFERENCES users(id)
-            )",
-            [],
-        )?;
-        
-        // User settings table for personalization
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS user_settings (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL UNIQUE,
-                language TEXT DEFAULT 'en',
-                timezone TEXT DEFAULT 'UTC',
-                system_prompt TEXT,
-                preferences TEXT DEFAULT '{}',
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-                FOREIGN KEY (user_id) REFERENCES users(id)
-            )",
-            [],
-        )?;
-        
-        // Create indexes
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rate_limits_user ON rate_limits(user_id)",
-            [],
-        )?;
-        
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rate_limits_timestamp ON rate_limits(timestamp)",
-            [],
-        )?;
-        
-        Ok(())
+    /// Open without touching the schema, e.g. to report its status
+    pub fn open(path: impl AsRef<Path>, config: DatabaseConfig) -> SqliteResult<Self> {
+        Ok(Self { pool: Arc::new(Pool::new(path, config)?) })
+    }
+    
+    /// A pooled connection, returned when dropped; don't call other methods
+    /// while holding it, they may need the pool's last free connection
+    fn conn(&self) -> SqliteResult<PooledConnection<'_>> {
+        self.pool.get()
+    }
+    
+    /// Run blocking database work off the async runtime:
+    /// `db.call(|db| db.list_users()).await`
+    pub async fn call<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Database) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(value) => value,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+    
+    /// Apply pending schema migrations; returns the ones applied
+    pub fn migrate(&self) -> SqliteResult<Vec<&'static Migration>> {
+        let conn = self.conn()?;
+        migrations::migrate(&conn)
+    }
+    
+    /// Consistent copy of the whole database into a new file, taken with
+    /// the online backup API while the bot keeps using it
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.backup(rusqlite::DatabaseName::Main, path, None)
+    }
+    
+    pub fn schema_status(&self) -> SqliteResult<SchemaStatus> {
+        let conn = self.conn()?;
+        migrations::status(&conn)
     }
     
     // User management
+    /// Add a user or change their role. An existing row is updated in place,
+    /// keeping its id (settings and rate limits refer to it) and known names.
     pub fn add_user(&self, telegram_id: &str, username: Option<&str>, role: &str) -> SqliteResult<i64> {
-        self.conn.execute(
-            "INSERT OR REPLACE INTO users (telegram_id, username, role) VALUES (?1, ?2, ?3)",
+        let conn = self.conn()?;
+        conn.query_row(
+            "INSERT INTO users (telegram_id, username, role) VALUES (?1, ?2, ?3)
+             ON CONFLICT(telegram_id) DO UPDATE SET
+                role = excluded.role,
+                username = COALESCE(NULLIF(excluded.username, ''), users.username)
+             RETURNING id",
             rusqlite::params![telegram_id, username.unwrap_or(""), role],
-        )?;
-        Ok(self.conn.last_insert_rowid())
+            |row| row.get(0),
+        )
     }
     
     pub fn get_user_by_telegram_id(&self, telegram_id: &str) -> SqliteResult<Option<User>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, telegram_id, username, role, created_at FROM users WHERE telegram_id = ?1"
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, telegram_id, username, first_name, role, created_at FROM users WHERE telegram_id = ?1"
         )?;
         
         let mut rows = stmt.query([telegram_id])?;
@@ -105,8 +124,9 @@ impl Database {
                 id: row.get(0)?,
                 telegram_id: row.get(1)?,
                 username: row.get(2)?,
-                role: row.get(3)?,
-                created_at: row.get(4)?,
+                first_name: row.get(3)?,
+                role: row.get(4)?,
+                created_at: row.get(5)?,
             }))
         } else {
             Ok(None)
@@ -114,8 +134,9 @@ impl Database {
     }
     
     pub fn list_users(&self) -> SqliteResult<Vec<User>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, telegram_id, username, role, created_at FROM users ORDER BY role, created_at"
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, telegram_id, username, first_name, role, created_at FROM users ORDER BY role, created_at"
         )?;
         
         let rows = stmt.query_map([], |row| {
@@ -123,8 +144,9 @@ impl Database {
                 id: row.get(0)?,
                 telegram_id: row.get(1)?,
                 username: row.get(2)?,
-                role: row.get(3)?,
-                created_at: row.get(4)?,
+                first_name: row.get(3)?,
+                role: row.get(4)?,
+                created_at: row.get(5)?,
             })
         })?;
         
@@ -136,7 +158,8 @@ impl Database {
     }
     
     pub fn remove_user(&self, telegram_id: &str) -> SqliteResult<bool> {
-        let rows = self.conn.execute(
+        let conn = self.conn()?;
+        let rows = conn.execute(
             "DELETE FROM users WHERE telegram_id = ?1",
             [telegram_id],
         )?;
@@ -144,7 +167,8 @@ impl Database {
     }
     
     pub fn update_user_role(&self, telegram_id: &str, role: &str) -> SqliteResult<bool> {
-        let rows = self.conn.execute(
+        let conn = self.conn()?;
+        let rows = conn.execute(
             "UPDATE users SET role = ?1 WHERE telegram_id = ?2",
             [role, telegram_id],
         )?;
@@ -153,7 +177,8 @@ impl Database {
     
     // Rate limiting
     pub fn record_query(&self, user_id: i64, query_type: &str) -> SqliteResult<()> {
-        self.conn.execute(
+        let conn = self.conn()?;
+        conn.execute(
             "INSERT INTO rate_limits (user_id, query_type) VALUES (?1, ?2)",
             rusqlite::params![user_id, query_type],
         )?;
@@ -161,7 +186,8 @@ impl Database {
     }
     
     pub fn count_recent_queries(&self, user_id: i64, query_type: &str, minutes: i64) -> SqliteResult<i64> {
-        let mut stmt = self.conn.prepare(
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
             "SELECT COUNT(*) FROM rate_limits 
              WHERE user_id = ?1 AND query_type = ?2 
              AND timestamp > datetime('now', ?3)"
@@ -176,7 +202,8 @@ impl Database {
     }
     
     pub fn count_hourly_queries(&self, user_id: i64, query_type: &str) -> SqliteResult<i64> {
-        let mut stmt = self.conn.prepare(
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
             "SELECT COUNT(*) FROM rate_limits 
              WHERE user_id = ?1 AND query_type = ?2 
              AND timestamp > datetime('now', '-1 hour')"
@@ -191,8 +218,9 @@ impl Database {
     }
     
     pub fn cleanup_old_rate_limits(&self) -> SqliteResult<()> {
+        let conn = self.conn()?;
         // Clean up rate limits older than 1 hour
-        self.conn.execute(
+        conn.execute(
             "DELETE FROM rate_limits WHERE timestamp < datetime('now', '-1 hour')",
             [],
         )?;
@@ -201,8 +229,9 @@ impl Database {
     
     // User settings
     pub fn get_user_settings(&self, telegram_id: &str) -> SqliteResult<Option<UserSettings>> {
+        let conn = self.conn()?;
         // First check if user exists
-        let user_exists = self.conn.query_row(
+        let user_exists = conn.query_row(
             "SELECT COUNT(*) FROM users WHERE telegram_id = ?1",
             [telegram_id],
             |row| row.get::<_, i32>(0)
@@ -212,7 +241,7 @@ impl Database {
             return Ok(None);
         }
         
-        let mut stmt = self.conn.prepare(
+        let mut stmt = conn.prepare(
             "SELECT us.language, us.timezone, us.system_prompt, us.preferences 
              FROM user_settings us
              JOIN users u ON u.id = us.user_id
@@ -244,8 +273,9 @@ impl Database {
     }
     
     pub fn set_user_settings(&self, telegram_id: &str, settings: &UserSettings) -> SqliteResult<()> {
+        let conn = self.conn()?;
         // Ensure user exists - create if not
-        let user_id: i64 = match self.conn.query_row(
+        let user_id: i64 = match conn.query_row(
             "SELECT id FROM users WHERE telegram_id = ?1",
             [telegram_id],
             |row| row.get(0)
@@ -253,15 +283,15 @@ impl Database {
             Ok(id) => id,
             Err(_) => {
                 // Create user with guest role
-                self.conn.execute(
+                conn.execute(
                     "INSERT INTO users (telegram_id, role) VALUES (?1, 'guest')",
                     [telegram_id],
                 )?;
-                self.conn.last_insert_rowid()
+                conn.last_insert_rowid()
             }
         };
         
-        self.conn.execute(
+        conn.execute(
             "INSERT OR REPLACE INTO user_settings (user_id, language, timezone, system_prompt, preferences, updated_at)
              VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))",
             rusqlite::params![
@@ -276,6 +306,666 @@ impl Database {
     }
 }
 
+impl Database {
+    // Command aliases
+    pub fn save_alias(&self, alias: &AliasRecord) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO command_aliases (name, scope, steps, created_by)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                alias.name,
+                alias.scope,
+                serde_json::to_string(&alias.steps).unwrap_or_else(|_| "[]".to_string()),
+                alias.created_by
+            ],
+        )?;
+        Ok(())
+    }
+    
+    pub fn remove_alias(&self, name: &str, scope: &str) -> SqliteResult<bool> {
+        let conn = self.conn()?;
+        let rows = conn.execute(
+            "DELETE FROM command_aliases WHERE name = ?1 AND scope = ?2",
+            [name, scope],
+        )?;
+        Ok(rows > 0)
+    }
+    
+    pub fn list_aliases(&self) -> SqliteResult<Vec<AliasRecord>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT name, scope, steps, created_by FROM command_aliases ORDER BY scope, name"
+        )?;
+        
+        let rows = stmt.query_map([], |row| {
+            let steps: String = row.get(2)?;
+            Ok(AliasRecord {
+                name: row.get(0)?,
+                scope: row.get(1)?,
+                steps: serde_json::from_str(&steps).unwrap_or_default(),
+                created_by: row.get(3)?,
+            })
+        })?;
+        
+        rows.collect()
+    }
+}
+
+impl Database {
+    // Conversation history
+    fn conversation_id(conn: &PooledConnection, chat_id: &str, user_id: &str) -> SqliteResult<i64> {
+        conn.execute(
+            "INSERT OR IGNORE INTO conversations (chat_id, user_id) VALUES (?1, ?2)",
+            [chat_id, user_id],
+        )?;
+        conn.query_row(
+            "SELECT id FROM conversations WHERE chat_id = ?1 AND user_id = ?2",
+            [chat_id, user_id],
+            |row| row.get(0),
+        )
+    }
+    
+    pub fn append_message(&self, chat_id: &str, user_id: &str, role: &str, content: &str) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        let conversation_id = Self::conversation_id(&conn, chat_id, user_id)?;
+        conn.execute(
+            "INSERT INTO messages (conversation_id, role, content) VALUES (?1, ?2, ?3)",
+            rusqlite::params![conversation_id, role, content],
+        )?;
+        conn.execute(
+            "UPDATE conversations SET updated_at = datetime('now') WHERE id = ?1",
+            [conversation_id],
+        )?;
+        Ok(())
+    }
+    
+    /// Messages not yet folded into the conversation summary, oldest first
+    pub fn unsummarized_messages(&self, chat_id: &str, user_id: &str) -> SqliteResult<Vec<StoredMessage>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.role, m.content, m.created_at FROM messages m
+             JOIN conversations c ON c.id = m.conversation_id
+             LEFT JOIN conversation_summaries s ON s.conversation_id = c.id
+             WHERE c.chat_id = ?1 AND c.user_id = ?2 AND m.id > COALESCE(s.through_message_id, 0)
+             ORDER BY m.id"
+        )?;
+        
+        let rows = stmt.query_map([chat_id, user_id], StoredMessage::from_row)?;
+        rows.collect()
+    }
+    
+    /// Rolling summary of the older part of a conversation
+    pub fn conversation_summary(&self, chat_id: &str, user_id: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT s.summary FROM conversation_summaries s
+             JOIN conversations c ON c.id = s.conversation_id
+             WHERE c.chat_id = ?1 AND c.user_id = ?2"
+        )?;
+        let mut rows = stmt.query([chat_id, user_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Store the summary covering every message up to `through_message_id`
+    pub fn save_conversation_summary(&self, chat_id: &str, user_id: &str, summary: &str, through_message_id: i64) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        let conversation_id = Self::conversation_id(&conn, chat_id, user_id)?;
+        conn.execute(
+            "INSERT INTO conversation_summaries (conversation_id, summary, through_message_id)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(conversation_id) DO UPDATE SET
+                summary = excluded.summary,
+                through_message_id = excluded.through_message_id,
+                updated_at = datetime('now')",
+            rusqlite::params![conversation_id, summary, through_message_id],
+        )?;
+        Ok(())
+    }
+    
+    /// Every message of a conversation, oldest first
+    pub fn conversation_history(&self, chat_id: &str, user_id: &str) -> SqliteResult<Vec<StoredMessage>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.role, m.content, m.created_at FROM messages m
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE c.chat_id = ?1 AND c.user_id = ?2
+             ORDER BY m.id"
+        )?;
+        
+        let rows = stmt.query_map([chat_id, user_id], StoredMessage::from_row)?;
+        rows.collect()
+    }
+    
+    /// Delete a user's conversation in a chat; returns the number of messages removed
+    pub fn clear_conversation(&self, chat_id: &str, user_id: &str) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM messages WHERE conversation_id IN
+             (SELECT id FROM conversations WHERE chat_id = ?1 AND user_id = ?2)",
+            [chat_id, user_id],
+        )?;
+        conn.execute(
+            "DELETE FROM conversation_summaries WHERE conversation_id IN
+             (SELECT id FROM conversations WHERE chat_id = ?1 AND user_id = ?2)",
+            [chat_id, user_id],
+        )?;
+        conn.execute(
+            "DELETE FROM conversations WHERE chat_id = ?1 AND user_id = ?2",
+            [chat_id, user_id],
+        )?;
+        Ok(removed)
+    }
+    
+    /// Delete every conversation in a chat (all users)
+    pub fn clear_chat(&self, chat_id: &str) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM messages WHERE conversation_id IN
+             (SELECT id FROM conversations WHERE chat_id = ?1)",
+            [chat_id],
+        )?;
+        conn.execute(
+            "DELETE FROM conversation_summaries WHERE conversation_id IN
+             (SELECT id FROM conversations WHERE chat_id = ?1)",
+            [chat_id],
+        )?;
+        conn.execute("DELETE FROM conversations WHERE chat_id = ?1", [chat_id])?;
+        Ok(removed)
+    }
+    
+    /// Apply retention: drop messages older than `retention_days` (0 = keep)
+    /// and keep at most `max_per_conversation` per conversation (0 = no cap)
+    pub fn prune_messages(&self, retention_days: u32, max_per_conversation: usize) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        let mut removed = 0;
+        if retention_days > 0 {
+            removed += conn.execute(
+                "DELETE FROM messages WHERE created_at < datetime('now', ?1)",
+                [format!("-{} days", retention_days)],
+            )?;
+        }
+        if max_per_conversation > 0 {
+            removed += conn.execute(
+                "DELETE FROM messages WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, ROW_NUMBER() OVER (PARTITION BY conversation_id ORDER BY id DESC) AS n
+                        FROM messages
+                    ) WHERE n > ?1
+                )",
+                [max_per_conversation as i64],
+            )?;
+        }
+        conn.execute(
+            "DELETE FROM conversation_summaries WHERE conversation_id NOT IN (SELECT DISTINCT conversation_id FROM messages)",
+            [],
+        )?;
+        conn.execute(
+            "DELETE FROM conversations WHERE id NOT IN (SELECT DISTINCT conversation_id FROM messages)",
+            [],
+        )?;
+        Ok(removed)
+    }
+}
+
+impl Database {
+    // LLM usage ledger
+    pub fn record_usage(&self, record: &UsageRecord) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO llm_usage (user_id, chat_id, provider, model, task, prompt_tokens, completion_tokens, cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                record.user_id, record.chat_id, record.provider, record.model, record.task,
+                record.prompt_tokens as i64, record.completion_tokens as i64, record.cost,
+            ],
+        )?;
+        Ok(())
+    }
+    
+    /// A user's usage since `since` (`YYYY-MM-DD HH:MM:SS`, UTC)
+    pub fn user_usage(&self, user_id: &str, since: &str) -> SqliteResult<UsageTotals> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "SELECT ?1, COUNT(*), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0), COALESCE(SUM(cost), 0)
+             FROM llm_usage WHERE user_id = ?1 AND created_at >= ?2",
+            [user_id, since],
+            UsageTotals::from_row,
+        )
+    }
+    
+    /// A user's usage since `since`, per provider/model
+    pub fn user_usage_by_model(&self, user_id: &str, since: &str) -> SqliteResult<Vec<UsageTotals>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT provider || '/' || model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost)
+             FROM llm_usage WHERE user_id = ?1 AND created_at >= ?2
+             GROUP BY provider, model ORDER BY SUM(prompt_tokens + completion_tokens) DESC"
+        )?;
+        let rows = stmt.query_map([user_id, since], UsageTotals::from_row)?;
+        rows.collect()
+    }
+    
+    /// Everyone's usage since `since`, per user
+    pub fn usage_by_user(&self, since: &str) -> SqliteResult<Vec<UsageTotals>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT user_id, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost)
+             FROM llm_usage WHERE created_at >= ?1
+             GROUP BY user_id ORDER BY SUM(cost) DESC, SUM(prompt_tokens + completion_tokens) DESC"
+        )?;
+        let rows = stmt.query_map([since], UsageTotals::from_row)?;
+        rows.collect()
+    }
+}
+
+impl Database {
+    // Long-term user memory
+    /// Store a fact; `None` if the user already has it
+    pub fn add_memory(&self, user_id: &str, category: &str, content: &str, source: &str) -> SqliteResult<Option<i64>> {
+        let conn = self.conn()?;
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO user_memories (user_id, category, content, source) VALUES (?1, ?2, ?3, ?4)",
+            [user_id, category, content, source],
+        )?;
+        Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+    }
+    
+    /// A user's facts, oldest first
+    pub fn list_memories(&self, user_id: &str) -> SqliteResult<Vec<MemoryRecord>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, category, content, source, created_at FROM user_memories WHERE user_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok(MemoryRecord {
+                id: row.get(0)?,
+                category: row.get(1)?,
+                content: row.get(2)?,
+                source: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+    
+    pub fn forget_memory(&self, user_id: &str, id: i64) -> SqliteResult<bool> {
+        let conn = self.conn()?;
+        let rows = conn.execute(
+            "DELETE FROM user_memories WHERE user_id = ?1 AND id = ?2",
+            rusqlite::params![user_id, id],
+        )?;
+        Ok(rows > 0)
+    }
+    
+    pub fn clear_memories(&self, user_id: &str) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM user_memories WHERE user_id = ?1", [user_id])
+    }
+    
+    /// Keep at most `max` facts, dropping extracted ones before explicit ones, oldest first
+    pub fn trim_memories(&self, user_id: &str, max: usize) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM user_memories WHERE id IN (
+                SELECT id FROM user_memories WHERE user_id = ?1
+                ORDER BY source = 'explicit' DESC, id DESC
+                LIMIT -1 OFFSET ?2
+            )",
+            rusqlite::params![user_id, max as i64],
+        )
+    }
+}
+
+impl Database {
+    // Document index
+    /// Content hash of an indexed document, to skip unchanged files
+    pub fn document_hash(&self, scope: &str, source: &str) -> SqliteResult<Option<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT content_hash FROM documents WHERE scope = ?1 AND source = ?2")?;
+        let mut rows = stmt.query([scope, source])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Store a document's chunks, replacing any earlier version
+    pub fn replace_document(
+        &self,
+        scope: &str,
+        source: &str,
+        content_hash: &str,
+        embedding_model: Option<&str>,
+        chunks: &[DocumentChunk],
+    ) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        let tx = conn.unchecked_transaction()?;
+        Self::delete_document(&conn, scope, source)?;
+        conn.execute(
+            "INSERT INTO documents (scope, source, content_hash, chunks, embedding_model) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![scope, source, content_hash, chunks.len() as i64, embedding_model],
+        )?;
+        let document_id = conn.last_insert_rowid();
+        let mut stmt = conn.prepare(
+            "INSERT INTO document_chunks (document_id, start_line, end_line, content, embedding) VALUES (?1, ?2, ?3, ?4, ?5)"
+        )?;
+        for chunk in chunks {
+            let embedding = chunk.embedding.as_ref()
+                .map(|v| v.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>());
+            stmt.execute(rusqlite::params![
+                document_id, chunk.start_line as i64, chunk.end_line as i64, chunk.content, embedding
+            ])?;
+        }
+        drop(stmt);
+        tx.commit()
+    }
+    
+    /// Documents in a scope, by source
+    pub fn list_documents(&self, scope: &str) -> SqliteResult<Vec<DocumentRecord>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT source, chunks, embedding_model, indexed_at FROM documents WHERE scope = ?1 ORDER BY source"
+        )?;
+        let rows = stmt.query_map([scope], |row| {
+            Ok(DocumentRecord {
+                source: row.get(0)?,
+                chunks: row.get::<_, i64>(1)? as usize,
+                embedding_model: row.get(2)?,
+                indexed_at: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+    
+    pub fn remove_document(&self, scope: &str, source: &str) -> SqliteResult<bool> {
+        Self::delete_document(&self.conn()?, scope, source)
+    }
+    
+    fn delete_document(conn: &PooledConnection, scope: &str, source: &str) -> SqliteResult<bool> {
+        conn.execute(
+            "DELETE FROM document_chunks WHERE document_id IN (SELECT id FROM documents WHERE scope = ?1 AND source = ?2)",
+            [scope, source],
+        )?;
+        let rows = conn.execute("DELETE FROM documents WHERE scope = ?1 AND source = ?2", [scope, source])?;
+        Ok(rows > 0)
+    }
+    
+    /// Remove every document in a scope; returns how many
+    pub fn remove_scope(&self, scope: &str) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM document_chunks WHERE document_id IN (SELECT id FROM documents WHERE scope = ?1)",
+            [scope],
+        )?;
+        conn.execute("DELETE FROM documents WHERE scope = ?1", [scope])
+    }
+    
+    /// All chunks of the documents in `scopes`
+    pub fn scope_chunks(&self, scopes: &[String]) -> SqliteResult<Vec<StoredChunk>> {
+        let conn = self.conn()?;
+        if scopes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; scopes.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT d.source, d.embedding_model, c.start_line, c.end_line, c.content, c.embedding
+             FROM document_chunks c JOIN documents d ON d.id = c.document_id
+             WHERE d.scope IN ({}) ORDER BY d.source, c.start_line",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(scopes), |row| {
+            let embedding: Option<Vec<u8>> = row.get(5)?;
+            Ok(StoredChunk {
+                source: row.get(0)?,
+                embedding_model: row.get(1)?,
+                start_line: row.get::<_, i64>(2)? as usize,
+                end_line: row.get::<_, i64>(3)? as usize,
+                content: row.get(4)?,
+                embedding: embedding.map(|bytes| {
+                    bytes.chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect()
+                }),
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+impl Database {
+    // Model selection
+    /// Provider and model chosen for a chat
+    pub fn chat_model(&self, chat_id: &str) -> SqliteResult<Option<ChatModel>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT provider, model FROM chat_models WHERE chat_id = ?1")?;
+        let mut rows = stmt.query([chat_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(ChatModel { provider: row.get(0)?, model: row.get(1)? })),
+            None => Ok(None),
+        }
+    }
+    
+    pub fn set_chat_model(&self, chat_id: &str, provider: &str, model: Option<&str>) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO chat_models (chat_id, provider, model, updated_at)
+             VALUES (?1, ?2, ?3, datetime('now'))",
+            rusqlite::params![chat_id, provider, model],
+        )?;
+        Ok(())
+    }
+    
+    /// Go back to the configured default; returns whether a choice was stored
+    pub fn clear_chat_model(&self, chat_id: &str) -> SqliteResult<bool> {
+        let conn = self.conn()?;
+        let rows = conn.execute("DELETE FROM chat_models WHERE chat_id = ?1", [chat_id])?;
+        Ok(rows > 0)
+    }
+}
+
+impl Database {
+    // LLM response cache
+    /// Unexpired reply stored under `key`; counts the hit
+    pub fn cached_response(&self, key: &str) -> SqliteResult<Option<CachedResponse>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT model, content FROM llm_cache WHERE key = ?1 AND expires_at > datetime('now')"
+        )?;
+        let mut rows = stmt.query([key])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let cached = CachedResponse { model: row.get(0)?, content: row.get(1)? };
+        conn.execute("UPDATE llm_cache SET hits = hits + 1 WHERE key = ?1", [key])?;
+        Ok(Some(cached))
+    }
+    
+    pub fn cache_response(&self, key: &str, task: &str, model: &str, content: &str, ttl_secs: u64) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO llm_cache (key, task, model, content, expires_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now', ?5))",
+            rusqlite::params![key, task, model, content, format!("+{} seconds", ttl_secs)],
+        )?;
+        Ok(())
+    }
+    
+    /// Drop cached replies, all or for one task; returns how many
+    pub fn clear_response_cache(&self, task: Option<&str>) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        match task {
+            Some(task) => conn.execute("DELETE FROM llm_cache WHERE task = ?1", [task]),
+            None => conn.execute("DELETE FROM llm_cache", []),
+        }
+    }
+    
+    pub fn purge_expired_responses(&self) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM llm_cache WHERE expires_at <= datetime('now')", [])
+    }
+    
+    /// Delete key-value entries under `prefix` not written for `max_age_secs`
+    pub fn purge_kv(&self, prefix: &str, max_age_secs: u64) -> SqliteResult<usize> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM kv_store WHERE substr(key, 1, length(?1)) = ?1 AND updated_at <= datetime('now', ?2)",
+            rusqlite::params![prefix, format!("-{} seconds", max_age_secs)],
+        )
+    }
+    
+    /// Unexpired entries and their hits per task
+    pub fn response_cache_stats(&self) -> SqliteResult<Vec<CacheStats>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT task, COUNT(*), COALESCE(SUM(hits), 0) FROM llm_cache
+             WHERE expires_at > datetime('now') GROUP BY task ORDER BY task"
+        )?;
+        let stats = stmt.query_map([], |row| Ok(CacheStats {
+            task: row.get(0)?,
+            entries: row.get::<_, i64>(1)? as u64,
+            hits: row.get::<_, i64>(2)? as u64,
+        }))?;
+        stats.collect()
+    }
+}
+
+/// A cached LLM reply
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub model: String,
+    pub content: String,
+}
+
+/// Cache entries for one task
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub task: String,
+    pub entries: u64,
+    /// Times the stored entries were reused
+    pub hits: u64,
+}
+
+/// Provider and model picked for a chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatModel {
+    pub provider: String,
+    /// `None` uses the provider's default model
+    pub model: Option<String>,
+}
+
+/// A chunk to store with [`Database::replace_document`]
+#[derive(Debug, Clone)]
+pub struct DocumentChunk {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// A stored chunk with its document's source
+#[derive(Debug, Clone)]
+pub struct StoredChunk {
+    pub source: String,
+    pub embedding_model: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// An indexed document
+#[derive(Debug, Clone)]
+pub struct DocumentRecord {
+    pub source: String,
+    pub chunks: usize,
+    pub embedding_model: Option<String>,
+    pub indexed_at: String,
+}
+
+/// A remembered fact about a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryRecord {
+    pub id: i64,
+    pub category: String,
+    pub content: String,
+    /// `explicit` ("remember ...") or `extracted` (picked up by the LLM)
+    pub source: String,
+    pub created_at: String,
+}
+
+/// One LLM call in the usage ledger
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub user_id: String,
+    pub chat_id: String,
+    pub provider: String,
+    pub model: String,
+    pub task: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// USD
+    pub cost: f64,
+}
+
+/// Aggregated usage; `label` is the grouping key (user id or provider/model)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageTotals {
+    pub label: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+    
+    fn from_row(row: &rusqlite::Row) -> SqliteResult<Self> {
+        Ok(Self {
+            label: row.get(0)?,
+            requests: row.get::<_, i64>(1)? as u64,
+            prompt_tokens: row.get::<_, i64>(2)? as u64,
+            completion_tokens: row.get::<_, i64>(3)? as u64,
+            cost: row.get(4)?,
+        })
+    }
+}
+
+/// A persisted conversation message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredMessage {
+    pub id: i64,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+impl StoredMessage {
+    fn from_row(row: &rusqlite::Row) -> SqliteResult<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            role: row.get(1)?,
+            content: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    }
+}
+
+/// Stored command alias; `scope` is "global" or the owning telegram id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasRecord {
+    pub name: String,
+    pub scope: String,
+    pub steps: Vec<String>,
+    pub created_by: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UserSettings {
     pub language: String,
@@ -283,3 +973,188 @@ pub struct UserSettings {
     pub system_prompt: Option<String>,
     pub preferences: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::traits::Store;
+
+    fn db() -> Database {
+        Database::new(":memory:").unwrap()
+    }
+
+    #[test]
+    fn test_conversation_roundtrip() {
+        let db = db();
+        db.append_message("-100", "1", "user", "halo").unwrap();
+        db.append_message("-100", "1", "assistant", "sugeng rawuh").unwrap();
+        db.append_message("-100", "2", "user", "other user").unwrap();
+
+        let recent = db.unsummarized_messages("-100", "1").unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].content, "halo");
+        assert_eq!(recent[1].role, "assistant");
+
+        db.save_conversation_summary("-100", "1", "User said hello", recent[0].id).unwrap();
+        assert_eq!(db.conversation_summary("-100", "1").unwrap().as_deref(), Some("User said hello"));
+        let last = db.unsummarized_messages("-100", "1").unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].content, "sugeng rawuh");
+
+        assert_eq!(db.clear_conversation("-100", "1").unwrap(), 2);
+        assert_eq!(db.conversation_summary("-100", "1").unwrap(), None);
+        assert!(db.conversation_history("-100", "1").unwrap().is_empty());
+        assert_eq!(db.conversation_history("-100", "2").unwrap().len(), 1);
+
+        assert_eq!(db.clear_chat("-100").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_usage_ledger() {
+        let db = db();
+        let record = |user: &str, model: &str, tokens: u64, cost: f64| UsageRecord {
+            user_id: user.to_string(),
+            chat_id: "-100".to_string(),
+            provider: "groq".to_string(),
+            model: model.to_string(),
+            task: "chat".to_string(),
+            prompt_tokens: tokens,
+            completion_tokens: tokens / 2,
+            cost,
+        };
+        db.record_usage(&record("1", "llama-3.3", 100, 0.01)).unwrap();
+        db.record_usage(&record("1", "llama-3.1", 10, 0.0)).unwrap();
+        db.record_usage(&record("2", "llama-3.3", 1000, 0.1)).unwrap();
+
+        let since = "2000-01-01 00:00:00";
+        let total = db.user_usage("1", since).unwrap();
+        assert_eq!((total.requests, total.total_tokens()), (2, 165));
+        assert!((total.cost - 0.01).abs() < 1e-9);
+
+        let by_model = db.user_usage_by_model("1", since).unwrap();
+        assert_eq!(by_model[0].label, "groq/llama-3.3");
+
+        let by_user = db.usage_by_user(since).unwrap();
+        assert_eq!(by_user.iter().map(|u| u.label.as_str()).collect::<Vec<_>>(), vec!["2", "1"]);
+
+        let none = db.user_usage("3", since).unwrap();
+        assert_eq!(none.requests, 0);
+        assert_eq!(db.user_usage("1", "2999-01-01 00:00:00").unwrap().total_tokens(), 0);
+    }
+
+    #[test]
+    fn test_memories() {
+        let db = db();
+        let first = db.add_memory("1", "profile", "Name is Budi", "explicit").unwrap().unwrap();
+        assert_eq!(db.add_memory("1", "profile", "name is budi", "extracted").unwrap(), None);
+        db.add_memory("1", "other", "Has a cat", "extracted").unwrap();
+        db.add_memory("1", "project", "Builds bots", "extracted").unwrap();
+        db.add_memory("2", "other", "Has a cat", "explicit").unwrap();
+
+        assert_eq!(db.trim_memories("1", 2).unwrap(), 1);
+        let left = db.list_memories("1").unwrap();
+        assert_eq!(left.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["Name is Budi", "Builds bots"]);
+
+        assert!(db.forget_memory("1", first).unwrap());
+        assert!(!db.forget_memory("2", first).unwrap());
+        assert_eq!(db.clear_memories("1").unwrap(), 1);
+        assert_eq!(db.list_memories("2").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_chat_models() {
+        let db = db();
+        assert_eq!(db.chat_model("-100").unwrap(), None);
+        db.set_chat_model("-100", "groq", Some("llama-3.1-8b-instant")).unwrap();
+        db.set_chat_model("-100", "claude", None).unwrap();
+        db.set_chat_model("7", "groq", Some("qwen")).unwrap();
+        assert_eq!(db.chat_model("-100").unwrap(), Some(ChatModel { provider: "claude".to_string(), model: None }));
+        assert!(db.clear_chat_model("-100").unwrap());
+        assert!(!db.clear_chat_model("-100").unwrap());
+        assert_eq!(db.chat_model("7").unwrap().unwrap().model.as_deref(), Some("qwen"));
+    }
+
+    #[test]
+    fn test_response_cache() {
+        let db = db();
+        db.cache_response("k1", "translation", "llama", "Good morning", 3600).unwrap();
+        db.cache_response("k2", "summarization", "llama", "News", 3600).unwrap();
+        db.conn().unwrap().execute(
+            "INSERT INTO llm_cache (key, task, model, content, expires_at) VALUES ('old', 'translation', 'llama', 'stale', datetime('now', '-1 seconds'))",
+            [],
+        ).unwrap();
+
+        assert_eq!(db.cached_response("k1").unwrap(), Some(CachedResponse { model: "llama".to_string(), content: "Good morning".to_string() }));
+        db.cached_response("k1").unwrap();
+        assert_eq!(db.cached_response("old").unwrap(), None);
+        assert_eq!(db.response_cache_stats().unwrap(), vec![
+            CacheStats { task: "summarization".to_string(), entries: 1, hits: 0 },
+            CacheStats { task: "translation".to_string(), entries: 1, hits: 2 },
+        ]);
+
+        assert_eq!(db.purge_expired_responses().unwrap(), 1);
+        assert_eq!(db.clear_response_cache(Some("summarization")).unwrap(), 1);
+        assert_eq!(db.clear_response_cache(None).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_purge_kv() {
+        let db = db();
+        db.set("search:1:1", "{}").await.unwrap();
+        db.set("search:1:2", "{}").await.unwrap();
+        db.set("other", "{}").await.unwrap();
+        db.conn().unwrap().execute("UPDATE kv_store SET updated_at = datetime('now', '-2 days') WHERE key != 'search:1:2'", []).unwrap();
+
+        assert_eq!(db.purge_kv("search:", 24 * 3600).unwrap(), 1);
+        assert!(db.get("search:1:1").await.unwrap().is_none());
+        assert!(db.get("search:1:2").await.unwrap().is_some());
+        assert!(db.get("other").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_documents() {
+        let db = db();
+        let chunk = |start: usize, content: &str, embedding: Option<Vec<f32>>| DocumentChunk {
+            start_line: start,
+            end_line: start + 1,
+            content: content.to_string(),
+            embedding,
+        };
+        db.replace_document("user:1", "notes.md", "h1", Some("embed"), &[
+            chunk(1, "first", Some(vec![0.5, -1.0])),
+            chunk(3, "second", Some(vec![0.25, 2.0])),
+        ]).unwrap();
+        db.replace_document("workspace:dev", "README.md", "h2", None, &[chunk(1, "readme", None)]).unwrap();
+        assert_eq!(db.document_hash("user:1", "notes.md").unwrap().as_deref(), Some("h1"));
+
+        // Re-indexing replaces the old chunks
+        db.replace_document("user:1", "notes.md", "h3", Some("embed"), &[chunk(1, "rewritten", Some(vec![1.0, 0.0]))]).unwrap();
+        let chunks = db.scope_chunks(&["user:1".to_string(), "workspace:dev".to_string()]).unwrap();
+        assert_eq!(chunks.iter().map(|c| c.content.as_str()).collect::<Vec<_>>(), vec!["readme", "rewritten"]);
+        assert_eq!(chunks[1].embedding, Some(vec![1.0, 0.0]));
+        assert_eq!(chunks[0].embedding, None);
+
+        assert_eq!(db.list_documents("user:1").unwrap()[0].chunks, 1);
+        assert!(db.remove_document("user:1", "notes.md").unwrap());
+        assert_eq!(db.remove_scope("workspace:dev").unwrap(), 1);
+        assert!(db.scope_chunks(&["user:1".to_string(), "workspace:dev".to_string()]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prune_messages() {
+        let db = db();
+        for i in 0..5 {
+            db.append_message("1", "1", "user", &format!("msg {}", i)).unwrap();
+        }
+        db.conn().unwrap().execute(
+            "UPDATE messages SET created_at = datetime('now', '-40 days') WHERE content = 'msg 0'",
+            [],
+        ).unwrap();
+
+        assert_eq!(db.prune_messages(30, 0).unwrap(), 1);
+        assert_eq!(db.prune_messages(0, 2).unwrap(), 2);
+
+        let left = db.conversation_history("1", "1").unwrap();
+        assert_eq!(left.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["msg 3", "msg 4"]);
+    }
+}
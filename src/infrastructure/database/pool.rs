@@ -0,0 +1,210 @@
+//! Connection pool - Several SQLite connections shared by the bot
+//!
+//! Connections are opened on demand up to `pool-size` and handed out as
+//! [`PooledConnection`]s that go back to the pool when dropped. File
+//! databases run in WAL mode so readers don't wait for a writer; writers wait
+//! for each other up to `busy-timeout-ms`. Statements run through a pooled
+//! connection are prepared once per connection and cached.
+
+use rusqlite::{CachedStatement, Connection, Params, Result as SqliteResult, Row};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Database settings (`database:` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DatabaseConfig {
+    /// Connections kept open; in-memory databases always use one
+    pub pool_size: usize,
+    /// How long a statement waits for a locked database, and a caller for a
+    /// free connection
+    pub busy_timeout_ms: u64,
+    /// Prepared statements kept per connection
+    pub statement_cache: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            pool_size: 4,
+            busy_timeout_ms: 5000,
+            statement_cache: 64,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    fn busy_timeout(&self) -> Duration {
+        Duration::from_millis(self.busy_timeout_ms)
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    idle: Vec<Connection>,
+    /// Connections opened so far, idle or handed out
+    open: usize,
+}
+
+pub struct Pool {
+    path: PathBuf,
+    config: DatabaseConfig,
+    slots: Mutex<Slots>,
+    released: Condvar,
+}
+
+impl Pool {
+    /// Open the first connection right away, so a bad path fails here
+    pub fn new(path: impl AsRef<Path>, mut config: DatabaseConfig) -> SqliteResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        // Each connection to ":memory:" would be a separate, empty database
+        if path.as_os_str() == ":memory:" || config.pool_size == 0 {
+            config.pool_size = 1;
+        }
+        let first = open(&path, &config)?;
+        Ok(Self {
+            path,
+            config,
+            slots: Mutex::new(Slots { idle: vec![first], open: 1 }),
+            released: Condvar::new(),
+        })
+    }
+
+    /// A free connection, opening one if the pool isn't full; waits up to the
+    /// busy timeout for one to be released otherwise
+    pub fn get(&self) -> SqliteResult<PooledConnection<'_>> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(conn) = slots.idle.pop() {
+                return Ok(PooledConnection { pool: self, conn: Some(conn) });
+            }
+            if slots.open < self.config.pool_size {
+                slots.open += 1;
+                drop(slots);
+                return match open(&self.path, &self.config) {
+                    Ok(conn) => Ok(PooledConnection { pool: self, conn: Some(conn) }),
+                    Err(e) => {
+                        self.slots.lock().unwrap_or_else(|e| e.into_inner()).open -= 1;
+                        Err(e)
+                    }
+                };
+            }
+            let (guard, timeout) = self.released
+                .wait_timeout(slots, self.config.busy_timeout())
+                .unwrap_or_else(|e| e.into_inner());
+            slots = guard;
+            if timeout.timed_out() && slots.idle.is_empty() {
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                    Some("no free database connection".to_string()),
+                ));
+            }
+        }
+    }
+
+    fn release(&self, conn: Connection) {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner()).idle.push(conn);
+        self.released.notify_one();
+    }
+}
+
+fn open(path: &Path, config: &DatabaseConfig) -> SqliteResult<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(config.busy_timeout())?;
+    conn.set_prepared_statement_cache_capacity(config.statement_cache);
+    // In-memory databases report "memory" and stay that way
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    Ok(conn)
+}
+
+/// A connection borrowed from the [`Pool`]; `execute`, `query_row` and
+/// `prepare` use the statement cache
+pub struct PooledConnection<'a> {
+    pool: &'a Pool,
+    conn: Option<Connection>,
+}
+
+impl PooledConnection<'_> {
+    pub fn prepare(&self, sql: &str) -> SqliteResult<CachedStatement<'_>> {
+        self.prepare_cached(sql)
+    }
+
+    pub fn execute<P: Params>(&self, sql: &str, params: P) -> SqliteResult<usize> {
+        self.prepare_cached(sql)?.execute(params)
+    }
+
+    pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> SqliteResult<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> SqliteResult<T>,
+    {
+        self.prepare_cached(sql)?.query_row(params, f)
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection taken")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.release(conn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn temp_db() -> PathBuf {
+        std::env::temp_dir().join(format!("carik-pool-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_connections_are_reused() {
+        let path = temp_db();
+        let pool = Pool::new(&path, DatabaseConfig { pool_size: 2, busy_timeout_ms: 50, ..DatabaseConfig::default() }).unwrap();
+        {
+            let a = pool.get().unwrap();
+            let mode: String = a.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+            assert_eq!(mode, "wal");
+            let _b = pool.get().unwrap();
+            // Both are out: the third caller times out
+            assert!(pool.get().is_err());
+        }
+        assert_eq!(pool.slots.lock().unwrap().idle.len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_waiters_get_released_connections() {
+        let pool = Arc::new(Pool::new(":memory:", DatabaseConfig::default()).unwrap());
+        let conn = pool.get().unwrap();
+        conn.execute("CREATE TABLE t (n INTEGER)", []).unwrap();
+
+        let waiter = {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                pool.get().unwrap().execute("INSERT INTO t VALUES (1)", []).unwrap();
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        drop(conn);
+        waiter.join().unwrap();
+
+        // ":memory:" keeps a single connection, so the table is still there
+        let count: i64 = pool.get().unwrap().query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+}
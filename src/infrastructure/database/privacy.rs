@@ -0,0 +1,206 @@
+//! Data subject requests (`/mydata`) - everything stored about one user
+//!
+//! A user's data is keyed by their Telegram id: their `users` row and what
+//! hangs off it (settings, rate limits), conversations in any chat, usage,
+//! memories, `user:<id>` documents, personal aliases, their private chat's
+//! model pick and Store messages, and key-value entries whose JSON value has
+//! their `user_id` (saved /search pages). The LLM response cache isn't linked
+//! to users and expires on its own. The export leaves Store messages to
+//! [`Store::user_messages`](crate::domain::traits::Store::user_messages),
+//! since the Store may be kept in JSON files instead.
+
+use rusqlite::types::ValueRef;
+use rusqlite::{Params, Result as SqliteResult};
+use serde_json::{json, Map, Value};
+
+use super::pool::PooledConnection;
+use super::Database;
+
+/// Rows of a query as JSON objects keyed by column name; blobs become their size
+fn rows_json<P: Params>(conn: &PooledConnection, sql: &str, params: P) -> SqliteResult<Vec<Value>> {
+    let mut stmt = conn.prepare(sql)?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut rows = stmt.query(params)?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(n) => json!(n),
+                ValueRef::Real(x) => json!(x),
+                ValueRef::Text(text) => json!(String::from_utf8_lossy(text)),
+                ValueRef::Blob(bytes) => json!(format!("<{} bytes>", bytes.len())),
+            };
+            object.insert(column.clone(), value);
+        }
+        out.push(Value::Object(object));
+    }
+    Ok(out)
+}
+
+impl Database {
+    /// Everything stored about a user, as one JSON document
+    pub fn export_user_data(&self, telegram_id: &str) -> SqliteResult<Value> {
+        let conn = self.conn()?;
+        let scope = format!("user:{}", telegram_id);
+
+        let mut conversations = rows_json(&conn,
+            "SELECT c.id, c.chat_id, c.created_at, c.updated_at, s.summary FROM conversations c
+             LEFT JOIN conversation_summaries s ON s.conversation_id = c.id
+             WHERE c.user_id = ?1 ORDER BY c.id",
+            [telegram_id])?;
+        for conversation in &mut conversations {
+            let id = conversation["id"].as_i64().unwrap_or_default();
+            conversation["messages"] = json!(rows_json(&conn,
+                "SELECT role, content, created_at FROM messages WHERE conversation_id = ?1 ORDER BY id",
+                [id])?);
+            if let Some(object) = conversation.as_object_mut() {
+                object.remove("id");
+            }
+        }
+
+        let mut documents = rows_json(&conn,
+            "SELECT id, source, chunks, indexed_at FROM documents WHERE scope = ?1 ORDER BY source",
+            [&scope])?;
+        for document in &mut documents {
+            let id = document["id"].as_i64().unwrap_or_default();
+            let chunks = rows_json(&conn, "SELECT content FROM document_chunks WHERE document_id = ?1 ORDER BY id", [id])?;
+            document["content"] = json!(chunks.iter().filter_map(|c| c["content"].as_str()).collect::<Vec<_>>().join("\n"));
+            if let Some(object) = document.as_object_mut() {
+                object.remove("id");
+            }
+        }
+
+        Ok(json!({
+            "user_id": telegram_id,
+            "exported_at": chrono::Utc::now().to_rfc3339(),
+            "user": rows_json(&conn,
+                "SELECT telegram_id, username, first_name, last_name, role, created_at FROM users WHERE telegram_id = ?1",
+                [telegram_id])?.into_iter().next(),
+            "settings": rows_json(&conn,
+                "SELECT s.language, s.timezone, s.system_prompt, s.preferences, s.created_at, s.updated_at
+                 FROM user_settings s JOIN users u ON u.id = s.user_id WHERE u.telegram_id = ?1",
+                [telegram_id])?.into_iter().next(),
+            "conversations": conversations,
+            "memories": rows_json(&conn,
+                "SELECT category, content, source, created_at FROM user_memories WHERE user_id = ?1 ORDER BY id",
+                [telegram_id])?,
+            "usage": rows_json(&conn,
+                "SELECT chat_id, provider, model, task, prompt_tokens, completion_tokens, cost, created_at
+                 FROM llm_usage WHERE user_id = ?1 ORDER BY id",
+                [telegram_id])?,
+            "documents": documents,
+            "aliases": rows_json(&conn,
+                "SELECT name, scope, steps, created_at FROM command_aliases WHERE scope = ?1 OR created_by = ?1 ORDER BY name",
+                [telegram_id])?,
+            "chat_model": rows_json(&conn,
+                "SELECT provider, model, updated_at FROM chat_models WHERE chat_id = ?1",
+                [telegram_id])?.into_iter().next(),
+        }))
+    }
+
+    /// Delete everything stored about a user in one transaction; returns the
+    /// rows removed per table (tables with none are left out). Global aliases
+    /// they created stay, without their name on them.
+    pub fn delete_user_data(&self, telegram_id: &str) -> SqliteResult<Vec<(&'static str, usize)>> {
+        const STEPS: &[(&str, &str)] = &[
+            ("rate_limits", "DELETE FROM rate_limits WHERE user_id IN (SELECT id FROM users WHERE telegram_id = ?1)"),
+            ("user_settings", "DELETE FROM user_settings WHERE user_id IN (SELECT id FROM users WHERE telegram_id = ?1)"),
+            ("messages", "DELETE FROM messages WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?1)"),
+            ("conversation_summaries", "DELETE FROM conversation_summaries WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?1)"),
+            ("conversations", "DELETE FROM conversations WHERE user_id = ?1"),
+            ("llm_usage", "DELETE FROM llm_usage WHERE user_id = ?1"),
+            ("user_memories", "DELETE FROM user_memories WHERE user_id = ?1"),
+            ("document_chunks", "DELETE FROM document_chunks WHERE document_id IN (SELECT id FROM documents WHERE scope = 'user:' || ?1)"),
+            ("documents", "DELETE FROM documents WHERE scope = 'user:' || ?1"),
+            ("command_aliases", "DELETE FROM command_aliases WHERE scope = ?1"),
+            ("chat_models", "DELETE FROM chat_models WHERE chat_id = ?1"),
+            ("chat_log", "DELETE FROM chat_log WHERE sender_id = ?1 OR chat_id = ?1"),
+            ("kv_store", "DELETE FROM kv_store WHERE json_valid(value) AND json_extract(value, '$.user_id') = ?1"),
+            ("users", "DELETE FROM users WHERE telegram_id = ?1"),
+        ];
+
+        let conn = self.conn()?;
+        let tx = conn.unchecked_transaction()?;
+        let mut removed = Vec::new();
+        for (table, sql) in STEPS {
+            let count = conn.execute(sql, [telegram_id])?;
+            if count > 0 {
+                removed.push((*table, count));
+            }
+        }
+        conn.execute("UPDATE command_aliases SET created_by = 'deleted' WHERE created_by = ?1", [telegram_id])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::{Message, User};
+    use crate::domain::traits::Store;
+    use crate::infrastructure::database::{AliasRecord, DocumentChunk, SearchScope, UserSettings};
+
+    /// A user with a bit of everything, next to someone else's data
+    async fn populated() -> Database {
+        let db = Database::new(":memory:").unwrap();
+        for id in ["7", "8"] {
+            db.add_user(id, Some("someone"), "user").unwrap();
+            db.set_user_settings(id, &UserSettings { language: "jv".to_string(), ..UserSettings::default() }).unwrap();
+            db.append_message(id, id, "user", "my secret plan").unwrap();
+            db.append_message("-100", id, "user", "hello group").unwrap();
+            db.add_memory(id, "preference", "likes tea", "explicit").unwrap();
+            db.save_alias(&AliasRecord { name: "gm".to_string(), scope: id.to_string(), steps: vec!["/ping".to_string()], created_by: id.to_string() }).unwrap();
+            let chunk = DocumentChunk { start_line: 1, end_line: 1, content: "cv".to_string(), embedding: None };
+            db.replace_document(&format!("user:{}", id), "cv.md", "h", None, &[chunk]).unwrap();
+            db.set_chat_model(id, "groq", None).unwrap();
+            db.save_message(&Message::from_text(id, "hi").with_sender(User::new(id))).await.unwrap();
+            db.set(&format!("search:{}:1", id), &json!({"user_id": id, "terms": "plan"}).to_string()).await.unwrap();
+        }
+        db.save_conversation_summary("-100", "7", "said hello", 1).unwrap();
+        db.save_alias(&AliasRecord { name: "team".to_string(), scope: "global".to_string(), steps: vec![], created_by: "7".to_string() }).unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_export_user_data() {
+        let db = populated().await;
+        let data = db.export_user_data("7").unwrap();
+        assert_eq!(data["user"]["role"], "user");
+        assert_eq!(data["settings"]["language"], "jv");
+        assert_eq!(data["conversations"].as_array().unwrap().len(), 2);
+        assert_eq!(data["conversations"][1]["summary"], "said hello");
+        assert_eq!(data["conversations"][0]["messages"][0]["content"], "my secret plan");
+        assert_eq!(data["memories"][0]["content"], "likes tea");
+        assert_eq!(data["documents"][0]["content"], "cv");
+        assert_eq!(data["aliases"].as_array().unwrap().len(), 2);
+        assert_eq!(data["chat_model"]["provider"], "groq");
+        assert!(!data.to_string().contains("\"8\""));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_data() {
+        let db = populated().await;
+        let removed = db.delete_user_data("7").unwrap();
+        let tables: Vec<&str> = removed.iter().map(|(table, _)| *table).collect();
+        assert!(tables.contains(&"messages") && tables.contains(&"users") && tables.contains(&"kv_store"));
+
+        let data = db.export_user_data("7").unwrap();
+        for key in ["conversations", "memories", "documents", "usage"] {
+            assert!(data[key].as_array().unwrap().is_empty(), "{} left", key);
+        }
+        assert!(data["user"].is_null() && data["settings"].is_null() && data["chat_model"].is_null());
+        assert_eq!(data["aliases"].as_array().unwrap().len(), 0);
+        assert!(db.search_messages("secret", &SearchScope::default(), 10, 0).unwrap().unwrap().iter().all(|h| h.user_id == "8"));
+        assert_eq!(db.list_aliases().unwrap().iter().find(|a| a.name == "team").unwrap().created_by, "deleted");
+        assert!(db.user_messages("7").await.unwrap().is_empty());
+
+        // Nobody else is touched
+        let other = db.export_user_data("8").unwrap();
+        assert_eq!(other["conversations"].as_array().unwrap().len(), 2);
+        assert!(db.get("search:8:1").await.unwrap().is_some());
+        assert!(db.delete_user_data("7").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+//! Full-text search over conversation history (`/search`)
+//!
+//! `messages_fts` indexes every stored message; hits are ranked by BM25 and
+//! come with a snippet around the matched terms, marked with
+//! [`HIGHLIGHT_START`] and [`HIGHLIGHT_END`] for the caller to render.
+
+use rusqlite::Result as SqliteResult;
+use serde::{Deserialize, Serialize};
+
+use super::Database;
+
+/// Marks the start of a matched term in [`SearchHit::snippet`]
+pub const HIGHLIGHT_START: char = '\u{2}';
+/// Marks the end of a matched term in [`SearchHit::snippet`]
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+/// Which conversations a search covers; `None` means any
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchScope {
+    pub user_id: Option<String>,
+    pub chat_id: Option<String>,
+}
+
+/// A matching message
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub chat_id: String,
+    pub user_id: String,
+    pub role: String,
+    pub snippet: String,
+    pub created_at: String,
+}
+
+/// Turn user input into an FTS5 query: every word must appear, `word*`
+/// matches a prefix. Quoting each word keeps FTS5 operators and punctuation
+/// (`AND`, `-`, `:` in links) from being parsed as query syntax.
+pub fn fts_query(terms: &str) -> Option<String> {
+    let words: Vec<String> = terms.split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(stem) => (stem, true),
+                None => (word, false),
+            };
+            let word = word.replace('"', "");
+            if word.is_empty() {
+                return None;
+            }
+            Some(format!("\"{}\"{}", word, if prefix { "*" } else { "" }))
+        })
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+impl Database {
+    /// Messages matching `terms`, best first; `None` if there's nothing to search for
+    pub fn search_messages(&self, terms: &str, scope: &SearchScope, limit: usize, offset: usize) -> SqliteResult<Option<Vec<SearchHit>>> {
+        let Some(query) = fts_query(terms) else {
+            return Ok(None);
+        };
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.chat_id, c.user_id, m.role, m.created_at,
+                    snippet(messages_fts, 0, char(2), char(3), '…', 16)
+             FROM messages_fts
+             JOIN messages m ON m.id = messages_fts.rowid
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE messages_fts MATCH ?1
+               AND (?2 IS NULL OR c.user_id = ?2)
+               AND (?3 IS NULL OR c.chat_id = ?3)
+             ORDER BY bm25(messages_fts), m.id DESC
+             LIMIT ?4 OFFSET ?5"
+        )?;
+        let rows = stmt.query_map(
+            rusqlite::params![query, scope.user_id, scope.chat_id, limit as i64, offset as i64],
+            |row| Ok(SearchHit {
+                chat_id: row.get(0)?,
+                user_id: row.get(1)?,
+                role: row.get(2)?,
+                created_at: row.get(3)?,
+                snippet: row.get(4)?,
+            }),
+        )?;
+        rows.collect::<SqliteResult<Vec<_>>>().map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(user_id: Option<&str>, chat_id: Option<&str>) -> SearchScope {
+        SearchScope { user_id: user_id.map(String::from), chat_id: chat_id.map(String::from) }
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("rust  async*").as_deref(), Some("\"rust\" \"async\"*"));
+        assert_eq!(fts_query("say \"hi\" OR -x").as_deref(), Some("\"say\" \"hi\" \"OR\" \"-x\""));
+        assert_eq!(fts_query(" * \"\" "), None);
+    }
+
+    #[test]
+    fn test_search_scoped_and_ranked() {
+        let db = Database::new(":memory:").unwrap();
+        db.append_message("1", "1", "user", "how do I parse json in rust").unwrap();
+        db.append_message("1", "1", "assistant", "Use serde_json: https://docs.rs/serde_json").unwrap();
+        db.append_message("-100", "1", "user", "rust meetup on friday").unwrap();
+        db.append_message("-100", "2", "user", "rust rust rust, also json").unwrap();
+
+        let hits = db.search_messages("json", &scope(Some("1"), None), 10, 0).unwrap().unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|h| h.user_id == "1"));
+        assert!(hits.iter().any(|h| h.snippet.contains("\u{2}json\u{3}")));
+
+        // Links are searchable, prefixes too
+        let hits = db.search_messages("docs.rs/serde", &scope(Some("1"), None), 10, 0).unwrap().unwrap();
+        assert_eq!(hits[0].role, "assistant");
+        assert_eq!(db.search_messages("meet*", &scope(None, None), 10, 0).unwrap().unwrap().len(), 1);
+
+        // Everyone's, one chat, then paged
+        let hits = db.search_messages("rust", &scope(None, Some("-100")), 10, 0).unwrap().unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].user_id, "2");
+        let page = db.search_messages("rust", &scope(None, None), 2, 2).unwrap().unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(db.search_messages("  ", &scope(None, None), 10, 0).unwrap().map(|h| h.len()), None);
+
+        // Cleared conversations drop out of the index
+        db.clear_conversation("1", "1").unwrap();
+        assert!(db.search_messages("serde_json", &scope(None, None), 10, 0).unwrap().unwrap().is_empty());
+    }
+}
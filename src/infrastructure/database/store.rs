@@ -0,0 +1,217 @@
+//! [`Store`] over SQLite
+//!
+//! Users map to the `users` table by Telegram id; saving a new one adds it as
+//! a guest and saving a known one keeps its role. Messages go to `chat_log`
+//! as JSON, key-value pairs to `kv_store`. Deleting a user removes everything
+//! [`Database::delete_user_data`] does. Queries run on the blocking pool
+//! through [`Database::call`].
+
+use async_trait::async_trait;
+
+use super::Database;
+use crate::application::errors::StorageError;
+use crate::domain::entities::{Message, User};
+use crate::domain::traits::Store;
+
+fn db_error(e: rusqlite::Error) -> StorageError {
+    StorageError::Database(e.to_string())
+}
+
+fn json_error(e: serde_json::Error) -> StorageError {
+    StorageError::Serialization(e.to_string())
+}
+
+#[async_trait]
+impl Store for Database {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, StorageError> {
+        let id = id.to_string();
+        self.call(move |db| {
+            let conn = db.conn()?;
+            let mut stmt = conn.prepare(
+                "SELECT username, first_name, last_name FROM users WHERE telegram_id = ?1"
+            )?;
+            let mut rows = stmt.query([&id])?;
+            let Some(row) = rows.next()? else {
+                return Ok(None);
+            };
+            // add_user stores a missing username as ""
+            let username: Option<String> = row.get(0)?;
+            Ok(Some(User {
+                username: username.filter(|u| !u.is_empty()),
+                first_name: row.get(1)?,
+                last_name: row.get(2)?,
+                is_bot: false,
+                id,
+            }))
+        }).await.map_err(db_error)
+    }
+
+    async fn save_user(&self, user: &User) -> Result<(), StorageError> {
+        let user = user.clone();
+        self.call(move |db| db.conn()?.execute(
+            "INSERT INTO users (telegram_id, username, first_name, last_name, role) VALUES (?1, ?2, ?3, ?4, 'guest')
+             ON CONFLICT(telegram_id) DO UPDATE SET
+                username = excluded.username,
+                first_name = excluded.first_name,
+                last_name = excluded.last_name",
+            rusqlite::params![user.id, user.username, user.first_name, user.last_name],
+        )).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn save_message(&self, message: &Message) -> Result<(), StorageError> {
+        let json = serde_json::to_string(message).map_err(json_error)?;
+        let params = (
+            message.id.clone(),
+            message.chat_id.clone(),
+            message.sender.as_ref().map(|u| u.id.clone()),
+            json,
+            message.timestamp.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+        );
+        self.call(move |db| db.conn()?.execute(
+            "INSERT OR REPLACE INTO chat_log (id, chat_id, sender_id, message, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params,
+        )).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn get_messages(&self, chat_id: &str, limit: usize) -> Result<Vec<Message>, StorageError> {
+        let chat_id = chat_id.to_string();
+        let rows = self.call(move |db| {
+            let conn = db.conn()?;
+            let mut stmt = conn.prepare(
+                "SELECT message FROM chat_log WHERE chat_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT ?2"
+            )?;
+            let rows = stmt.query_map(rusqlite::params![chat_id, limit as i64], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<String>>>()
+        }).await.map_err(db_error)?;
+        rows.iter().map(|json| serde_json::from_str(json).map_err(json_error)).collect()
+    }
+
+    async fn user_messages(&self, user_id: &str) -> Result<Vec<Message>, StorageError> {
+        let user_id = user_id.to_string();
+        let rows = self.call(move |db| {
+            let conn = db.conn()?;
+            let mut stmt = conn.prepare(
+                "SELECT message FROM chat_log WHERE sender_id = ?1 OR chat_id = ?1 ORDER BY created_at, rowid"
+            )?;
+            let rows = stmt.query_map([user_id], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<String>>>()
+        }).await.map_err(db_error)?;
+        rows.iter().map(|json| serde_json::from_str(json).map_err(json_error)).collect()
+    }
+
+    async fn delete_user(&self, user_id: &str) -> Result<usize, StorageError> {
+        let user_id = user_id.to_string();
+        let removed = self.call(move |db| db.delete_user_data(&user_id)).await.map_err(db_error)?;
+        Ok(removed.iter().map(|(_, count)| count).sum())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let key = key.to_string();
+        self.call(move |db| {
+            let conn = db.conn()?;
+            let mut stmt = conn.prepare("SELECT value FROM kv_store WHERE key = ?1")?;
+            let mut rows = stmt.query([key])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(row.get(0)?)),
+                None => Ok(None),
+            }
+        }).await.map_err(db_error)
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.call(move |db| db.conn()?.execute(
+            "INSERT OR REPLACE INTO kv_store (key, value, updated_at) VALUES (?1, ?2, datetime('now'))",
+            [key, value],
+        )).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let key = key.to_string();
+        self.call(move |db| db.conn()?.execute("DELETE FROM kv_store WHERE key = ?1", [key]))
+            .await.map_err(db_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::Content;
+
+    fn db() -> Database {
+        Database::new(":memory:").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_users_keep_their_role() {
+        let db = db();
+        db.add_user("7", None, "admin").unwrap();
+        assert_eq!(db.get_user("7").await.unwrap(), Some(User::new("7")));
+
+        db.save_user(&User::new("7").with_username("budi").with_name("Budi", Some("S"))).await.unwrap();
+        db.save_user(&User::new("8")).await.unwrap();
+        let user = db.get_user("7").await.unwrap().unwrap();
+        assert_eq!((user.username.as_deref(), user.display_name().as_str()), (Some("budi"), "budi"));
+        assert_eq!(user.last_name.as_deref(), Some("S"));
+        assert_eq!(db.get_user_by_telegram_id("7").unwrap().unwrap().role, "admin");
+        assert_eq!(db.get_user_by_telegram_id("8").unwrap().unwrap().role, "guest");
+        assert_eq!(db.get_user("9").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_role_change_keeps_names_and_settings() {
+        let db = db();
+        let id = db.add_user("7", Some("budi"), "user").unwrap();
+        db.save_user(&User::new("7").with_username("budi").with_name("Budi", Some("S"))).await.unwrap();
+        let settings = crate::infrastructure::database::UserSettings { language: "jv".to_string(), ..Default::default() };
+        db.set_user_settings("7", &settings).unwrap();
+
+        // Owner seeding on start, then a /whitelist role change
+        assert_eq!(db.add_user("7", None, "owner").unwrap(), id);
+        assert_eq!(db.add_user("7", None, "admin").unwrap(), id);
+
+        let user = db.get_user("7").await.unwrap().unwrap();
+        assert_eq!(user.username.as_deref(), Some("budi"));
+        assert_eq!((user.first_name.as_deref(), user.last_name.as_deref()), (Some("Budi"), Some("S")));
+        assert_eq!(db.get_user_by_telegram_id("7").unwrap().unwrap().role, "admin");
+        assert_eq!(db.get_user_settings("7").unwrap().unwrap().language, "jv");
+    }
+
+    #[tokio::test]
+    async fn test_messages_newest_first() {
+        let db = db();
+        for text in ["one", "two", "three"] {
+            db.save_message(&Message::from_text("c1", text).with_sender(User::new("7"))).await.unwrap();
+        }
+        db.save_message(&Message::from_command("c2", "help", vec![])).await.unwrap();
+
+        let messages = db.get_messages("c1", 2).await.unwrap();
+        let texts: Vec<_> = messages.iter().filter_map(|m| m.content.text()).collect();
+        assert_eq!(texts, vec!["three", "two"]);
+        assert_eq!(messages[0].sender.as_ref().map(|u| u.id.as_str()), Some("7"));
+        let commands = db.get_messages("c2", 10).await.unwrap();
+        assert!(matches!(&commands[0].content, Content::Command { name, .. } if name == "help"));
+
+        db.save_message(&Message::from_text("7", "private")).await.unwrap();
+        let texts: Vec<_> = db.user_messages("7").await.unwrap().iter().filter_map(|m| m.content.text()).map(String::from).collect();
+        assert_eq!(texts, vec!["one", "two", "three", "private"]);
+        assert_eq!(db.delete_user("7").await.unwrap(), 4);
+        assert!(db.user_messages("7").await.unwrap().is_empty());
+        assert_eq!(db.get_messages("c2", 10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_value() {
+        let db = db();
+        assert_eq!(db.get("k").await.unwrap(), None);
+        db.set("k", "v1").await.unwrap();
+        db.set("k", "v2").await.unwrap();
+        assert_eq!(db.get("k").await.unwrap().as_deref(), Some("v2"));
+        db.delete("k").await.unwrap();
+        assert_eq!(db.get("k").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Rule checks on a reply
+
+use serde::{Deserialize, Serialize};
+
+/// Checks a reply must pass; unset checks are skipped
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Checks {
+    /// Language the reply should be in: `en`, `id` or `jv`
+    pub language: Option<String>,
+    /// Longest allowed reply, in characters
+    pub max_length: Option<usize>,
+    /// Shortest allowed reply, in characters
+    pub min_length: Option<usize>,
+    /// Phrases that must all appear (case-insensitive)
+    pub contains: Vec<String>,
+    /// Phrases of which at least one must appear
+    pub contains_any: Vec<String>,
+    /// Phrases that must not appear
+    pub not_contains: Vec<String>,
+    /// Whether the reply must (or must not) have an http(s) URL
+    pub contains_url: Option<bool>,
+    /// Regex the reply must match
+    pub matches: Option<String>,
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    /// What was found when the check failed
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: impl Into<String>, passed: bool, detail: impl Into<String>) -> Self {
+        let detail = if passed { String::new() } else { detail.into() };
+        Self { name: name.into(), passed, detail }
+    }
+}
+
+impl Checks {
+    /// Run every configured check on `reply`
+    pub fn run(&self, reply: &str) -> Vec<CheckResult> {
+        let lower = reply.to_lowercase();
+        let length = reply.chars().count();
+        let mut results = Vec::new();
+
+        if let Some(expected) = &self.language {
+            let detected = detect_language(reply);
+            results.push(CheckResult::new(
+                format!("language {}", expected),
+                detected == Some(expected.as_str()),
+                format!("detected {}", detected.unwrap_or("unknown")),
+            ));
+        }
+        if let Some(max) = self.max_length {
+            results.push(CheckResult::new(format!("max-length {}", max), length <= max, format!("{} chars", length)));
+        }
+        if let Some(min) = self.min_length {
+            results.push(CheckResult::new(format!("min-length {}", min), length >= min, format!("{} chars", length)));
+        }
+        for phrase in &self.contains {
+            results.push(CheckResult::new(format!("contains \"{}\"", phrase), lower.contains(&phrase.to_lowercase()), "missing"));
+        }
+        if !self.contains_any.is_empty() {
+            let found = self.contains_any.iter().any(|p| lower.contains(&p.to_lowercase()));
+            results.push(CheckResult::new(format!("contains any of {:?}", self.contains_any), found, "none found"));
+        }
+        for phrase in &self.not_contains {
+            results.push(CheckResult::new(format!("not-contains \"{}\"", phrase), !lower.contains(&phrase.to_lowercase()), "present"));
+        }
+        if let Some(expected) = self.contains_url {
+            let found = lower.contains("http://") || lower.contains("https://");
+            let detail = if found { "has a URL" } else { "no URL" };
+            results.push(CheckResult::new(format!("contains-url {}", expected), found == expected, detail));
+        }
+        if let Some(pattern) = &self.matches {
+            let passed = regex_lite::Regex::new(pattern).map(|re| re.is_match(reply)).unwrap_or(false);
+            results.push(CheckResult::new(format!("matches /{}/", pattern), passed, "no match"));
+        }
+        results
+    }
+}
+
+/// Common function words per language; Javanese covers ngoko and krama
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "are", "you", "to", "of", "it", "that", "this", "for", "with", "what", "have", "be", "your", "can", "will", "morning", "good"]),
+    ("id", &["yang", "dan", "di", "ini", "itu", "untuk", "dengan", "tidak", "saya", "anda", "kamu", "ada", "apa", "akan", "bisa", "dari", "juga", "sudah", "kami", "selamat", "pagi", "terima", "kasih"]),
+    ("jv", &["ingkang", "kula", "panjenengan", "sampeyan", "aku", "kowe", "ora", "mboten", "boten", "niki", "niku", "iki", "kuwi", "lan", "karo", "wonten", "sing", "opo", "menapa", "sugeng", "enjing", "nggih", "saged", "iso", "matur", "nuwun", "wis", "sampun", "dhateng", "kanggo"]),
+];
+
+/// Best guess at the language of `text` (`en`, `id` or `jv`) from function
+/// words; `None` when nothing matches or two languages tie
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut scores: Vec<(&'static str, usize)> = STOPWORDS.iter()
+        .map(|(lang, stopwords)| (*lang, words.iter().filter(|w| stopwords.contains(w)).count()))
+        .collect();
+    scores.sort_by_key(|s| std::cmp::Reverse(s.1));
+    match scores.as_slice() {
+        [(lang, best), (_, second), ..] if *best > 0 && best > second => Some(lang),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("Good morning! What can I do for you today?"), Some("en"));
+        assert_eq!(detect_language("Selamat pagi! Ada yang bisa saya bantu hari ini?"), Some("id"));
+        assert_eq!(detect_language("Sugeng enjing! Menapa ingkang saged kula bantu?"), Some("jv"));
+        assert_eq!(detect_language("Aku ora ngerti, kowe wis mangan?"), Some("jv"));
+        assert_eq!(detect_language("🙂 12345"), None);
+    }
+
+    #[test]
+    fn test_checks() {
+        let checks = Checks {
+            language: Some("en".to_string()),
+            max_length: Some(80),
+            contains: vec!["Rust".to_string()],
+            contains_any: vec!["release".to_string(), "launch".to_string()],
+            not_contains: vec!["as an ai".to_string()],
+            contains_url: Some(true),
+            matches: Some(r"\d\.\d".to_string()),
+            ..Checks::default()
+        };
+        let results = checks.run("The rust 2.0 release is out: https://example.com/rust");
+        assert!(results.iter().all(|r| r.passed), "{:?}", results);
+
+        let results = checks.run("Sudah rilis, tidak ada link.");
+        let failed: Vec<&str> = results.iter().filter(|r| !r.passed).map(|r| r.name.as_str()).collect();
+        assert_eq!(failed.len(), 5, "{:?}", failed);
+        assert_eq!(results[0].detail, "detected id");
+    }
+}
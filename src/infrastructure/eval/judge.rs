@@ -0,0 +1,87 @@
+//! LLM-as-judge scoring
+//!
+//! The judge sees the case, its rubric and the reply, and answers with a
+//! 0-10 score and a reason as structured output. Uses the `classification`
+//! route, so a different model than the one under test can grade.
+
+use serde::{Deserialize, Serialize};
+
+use super::Case;
+use crate::infrastructure::llm::{LLMMessage, LLMResult, LLMRouter, LLMTask, ResponseSchema};
+
+const SYSTEM_PROMPT: &str = "You grade replies of a Telegram assistant that speaks English, Indonesian and Javanese. \
+Score the reply from 0 (useless or wrong) to 10 (exactly what the rubric asks for). Judge correctness, \
+language and tone against the rubric; ignore formatting unless the rubric mentions it. \
+Reply with JSON: {\"score\": 0-10, \"reason\": \"one sentence\"}.";
+
+/// The judge's grade
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Verdict {
+    /// 0-1
+    pub score: f64,
+    pub reason: String,
+}
+
+/// Raw reply of the judge
+#[derive(Deserialize)]
+struct Grade {
+    score: f64,
+    reason: String,
+}
+
+fn grade_schema() -> ResponseSchema {
+    ResponseSchema::new("grade", serde_json::json!({
+        "type": "object",
+        "properties": {
+            "score": {"type": "number", "minimum": 0, "maximum": 10},
+            "reason": {"type": "string"}
+        },
+        "required": ["score", "reason"]
+    }))
+}
+
+/// What the judge is shown
+fn grading_prompt(case: &Case, reply: &str) -> String {
+    let mut prompt = String::new();
+    if let Some(lang) = &case.lang {
+        prompt.push_str(&format!("User language setting: {}\n", lang));
+    }
+    if let Some(target) = &case.target_language {
+        prompt.push_str(&format!("Task: translate to {}\n", target));
+    }
+    for turn in &case.history {
+        prompt.push_str(&format!("{}: {}\n", turn.role, turn.content));
+    }
+    prompt.push_str(&format!("user: {}\n\nRubric: {}\n\nReply to grade:\n{}", case.input, case.rubric.as_deref().unwrap_or("A helpful, correct answer"), reply));
+    prompt
+}
+
+/// Grade `reply` to `case`
+pub async fn judge(llm: &LLMRouter, case: &Case, reply: &str) -> LLMResult<Verdict> {
+    let messages = vec![LLMMessage::system(SYSTEM_PROMPT), LLMMessage::user(grading_prompt(case, reply))];
+    let grade: Grade = llm.chat_structured(LLMTask::Classification, messages, &grade_schema(), Some(0.0), Some(200)).await?;
+    Ok(Verdict {
+        score: (grade.score / 10.0).clamp(0.0, 1.0),
+        reason: grade.reason.trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::llm::{LLMConfig, LLMProvider, MockProvider};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_judge_scales_score() {
+        let mock = Arc::new(MockProvider::new("groq").otherwise(r#"{"score": 8, "reason": "Polite krama."}"#));
+        let config = LLMConfig { provider: LLMProvider::Groq, ..LLMConfig::default() };
+        let router = LLMRouter::new(config).with_provider("groq", mock.clone());
+        let case: Case = serde_yaml::from_str("name: greet\nlang: jv\ninput: Sugeng enjing\nrubric: Answers in krama\n").unwrap();
+
+        let verdict = judge(&router, &case, "Sugeng enjing, Pak").await.unwrap();
+        assert_eq!(verdict, Verdict { score: 0.8, reason: "Polite krama.".to_string() });
+        let prompt = mock.last_request().unwrap().last_user().to_string();
+        assert!(prompt.contains("Rubric: Answers in krama") && prompt.ends_with("Sugeng enjing, Pak"));
+    }
+}
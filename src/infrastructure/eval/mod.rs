@@ -0,0 +1,132 @@
+//! Prompt evaluation - Regression suite for the bot's prompts
+//!
+//! `carik-bot eval` runs a YAML suite of conversations against a provider,
+//! scores each reply with rule [`checks`] and optionally an LLM [`judge`],
+//! and writes a [`report`] compared against a previous one.
+
+pub mod checks;
+pub mod judge;
+pub mod report;
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub use checks::{CheckResult, Checks};
+pub use judge::{judge, Verdict};
+pub use report::{CaseResult, Report};
+
+/// Suite file used when `--suite` is not given
+pub const DEFAULT_SUITE: &str = "evals/suite.yaml";
+
+/// What a case exercises
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseKind {
+    /// A chat turn with the persona and language prompts
+    #[default]
+    Chat,
+    /// The translation prompts; needs `target-language`
+    Translate,
+    /// The news summary prompt; `input` holds the fetched headlines
+    News,
+}
+
+/// An earlier message of the conversation
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Turn {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: String,
+}
+
+/// One conversation to evaluate
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Case {
+    /// Unique name, used to match cases against the baseline
+    pub name: String,
+    #[serde(default)]
+    pub kind: CaseKind,
+    /// User language setting (`en`, `id`, `jv`), picks per-language prompts
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Conversation before `input`
+    #[serde(default)]
+    pub history: Vec<Turn>,
+    /// The message to answer (headlines for `news`)
+    pub input: String,
+    /// Translation target, e.g. `English` or `Javanese`
+    #[serde(default)]
+    pub target_language: Option<String>,
+    /// Topic hint for `news` cases
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub checks: Checks,
+    /// What a good reply looks like, for the LLM judge
+    #[serde(default)]
+    pub rubric: Option<String>,
+}
+
+/// A YAML suite of cases
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct Suite {
+    pub name: String,
+    /// Lowest judge score (0-1) for a case to pass
+    pub judge_threshold: f64,
+    pub cases: Vec<Case>,
+}
+
+impl Suite {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut suite: Suite = serde_yaml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if suite.name.is_empty() {
+            suite.name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        }
+        if suite.judge_threshold <= 0.0 {
+            suite.judge_threshold = 0.6;
+        }
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    /// Names must be unique and translate cases need a target
+    fn validate(&self) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for case in &self.cases {
+            if !names.insert(case.name.as_str()) {
+                return Err(format!("duplicate case name '{}'", case.name));
+            }
+            if case.kind == CaseKind::Translate && case.target_language.is_none() {
+                return Err(format!("translate case '{}' needs target-language", case.name));
+            }
+            if let Some(pattern) = &case.checks.matches {
+                regex_lite::Regex::new(pattern).map_err(|e| format!("case '{}': {}", case.name, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_suite_is_valid() {
+        let suite = Suite::load(Path::new(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_SUITE)).unwrap();
+        assert!(suite.cases.iter().any(|c| c.lang.as_deref() == Some("jv")));
+        assert!(suite.cases.iter().any(|c| c.lang.as_deref() == Some("id")));
+        assert!(suite.cases.iter().any(|c| c.kind == CaseKind::Translate));
+        assert!(suite.cases.iter().any(|c| c.kind == CaseKind::News));
+    }
+
+    #[test]
+    fn test_rejects_translate_without_target() {
+        let suite: Suite = serde_yaml::from_str("cases:\n- name: t\n  kind: translate\n  input: halo\n").unwrap();
+        assert!(suite.validate().unwrap_err().contains("target-language"));
+    }
+}
@@ -0,0 +1,256 @@
+//! Eval results, baseline comparison and rendering
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{Case, CaseKind, CheckResult, Verdict};
+
+/// Score changes smaller than this are noise
+const SCORE_EPSILON: f64 = 0.01;
+
+/// Outcome of one case
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub kind: CaseKind,
+    #[serde(default)]
+    pub lang: Option<String>,
+    pub reply: String,
+    /// Why no reply was produced
+    #[serde(default)]
+    pub error: Option<String>,
+    pub checks: Vec<CheckResult>,
+    #[serde(default)]
+    pub verdict: Option<Verdict>,
+    /// 0-1: share of checks passed, averaged with the judge score if any
+    pub score: f64,
+    pub passed: bool,
+    pub latency_ms: u64,
+}
+
+impl CaseResult {
+    /// Score a reply (or the error that prevented one); cases pass when every
+    /// check does and the judge score reaches `threshold`
+    pub fn new(case: &Case, reply: Result<String, String>, verdict: Option<Verdict>, threshold: f64, latency_ms: u64) -> Self {
+        let (reply, error) = match reply {
+            Ok(reply) => (reply, None),
+            Err(e) => (String::new(), Some(e)),
+        };
+        let checks = if error.is_none() { case.checks.run(&reply) } else { Vec::new() };
+
+        let check_score = if checks.is_empty() {
+            1.0
+        } else {
+            checks.iter().filter(|c| c.passed).count() as f64 / checks.len() as f64
+        };
+        let score = match (&error, &verdict) {
+            (Some(_), _) => 0.0,
+            (None, Some(verdict)) => (check_score + verdict.score) / 2.0,
+            (None, None) => check_score,
+        };
+        let passed = error.is_none()
+            && checks.iter().all(|c| c.passed)
+            && verdict.as_ref().is_none_or(|v| v.score >= threshold);
+
+        Self {
+            name: case.name.clone(),
+            kind: case.kind,
+            lang: case.lang.clone(),
+            reply,
+            error,
+            checks,
+            verdict,
+            score,
+            passed,
+            latency_ms,
+        }
+    }
+}
+
+/// A full eval run
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Report {
+    pub suite: String,
+    /// Provider/model under test, or `default` for the configured chain
+    pub target: String,
+    pub created_at: String,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn new(suite: impl Into<String>, target: impl Into<String>, cases: Vec<CaseResult>) -> Self {
+        Self {
+            suite: suite.into(),
+            target: target.into(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            cases,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Mean case score
+    pub fn score(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        self.cases.iter().map(|c| c.score).sum::<f64>() / self.cases.len() as f64
+    }
+
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|c| c.passed).count()
+    }
+
+    /// Differences from an earlier run, matched by case name
+    pub fn compare(&self, baseline: &Report) -> Comparison {
+        let before: HashMap<&str, &CaseResult> = baseline.cases.iter().map(|c| (c.name.as_str(), c)).collect();
+        let mut comparison = Comparison {
+            baseline_target: baseline.target.clone(),
+            baseline_created_at: baseline.created_at.clone(),
+            baseline_score: baseline.score(),
+            score: self.score(),
+            ..Comparison::default()
+        };
+
+        for case in &self.cases {
+            let Some(old) = before.get(case.name.as_str()) else {
+                comparison.added.push(case.name.clone());
+                continue;
+            };
+            match (old.passed, case.passed) {
+                (true, false) => comparison.regressions.push(case.name.clone()),
+                (false, true) => comparison.fixed.push(case.name.clone()),
+                _ => {}
+            }
+            if (case.score - old.score).abs() >= SCORE_EPSILON {
+                comparison.changes.push((case.name.clone(), old.score, case.score));
+            }
+        }
+        comparison.removed = baseline.cases.iter()
+            .filter(|old| !self.cases.iter().any(|c| c.name == old.name))
+            .map(|old| old.name.clone())
+            .collect();
+        comparison
+    }
+
+    /// Markdown summary, with the baseline comparison if given
+    pub fn to_markdown(&self, comparison: Option<&Comparison>) -> String {
+        let mut md = format!(
+            "# Eval: {}\n\nTarget: {}  \nRun: {}  \nPassed: {}/{}  \nScore: {:.2}\n",
+            self.suite, self.target, self.created_at, self.passed(), self.cases.len(), self.score()
+        );
+
+        if let Some(c) = comparison {
+            md.push_str(&format!(
+                "\n## Compared to baseline\n\nBaseline: {} ({})  \nScore: {:.2} → {:.2} ({:+.2})\n",
+                c.baseline_target, c.baseline_created_at, c.baseline_score, c.score, c.score - c.baseline_score
+            ));
+            for (label, names) in [("Regressions", &c.regressions), ("Fixed", &c.fixed), ("New cases", &c.added), ("Removed cases", &c.removed)] {
+                if !names.is_empty() {
+                    md.push_str(&format!("\n**{}:** {}\n", label, names.join(", ")));
+                }
+            }
+            if !c.changes.is_empty() {
+                md.push_str("\n| Case | Before | After |\n|------|--------|-------|\n");
+                for (name, before, after) in &c.changes {
+                    md.push_str(&format!("| {} | {:.2} | {:.2} |\n", name, before, after));
+                }
+            }
+        }
+
+        md.push_str("\n## Cases\n\n| Case | Kind | Lang | Score | Result | Latency |\n|------|------|------|-------|--------|---------|\n");
+        for case in &self.cases {
+            md.push_str(&format!(
+                "| {} | {:?} | {} | {:.2} | {} | {} ms |\n",
+                case.name, case.kind, case.lang.as_deref().unwrap_or("-"), case.score,
+                if case.passed { "✅" } else { "❌" }, case.latency_ms
+            ));
+        }
+
+        let failed: Vec<&CaseResult> = self.cases.iter().filter(|c| !c.passed).collect();
+        if !failed.is_empty() {
+            md.push_str("\n## Failures\n");
+            for case in failed {
+                md.push_str(&format!("\n### {}\n\n", case.name));
+                if let Some(error) = &case.error {
+                    md.push_str(&format!("Error: {}\n", error));
+                }
+                for check in case.checks.iter().filter(|c| !c.passed) {
+                    md.push_str(&format!("- {}: {}\n", check.name, check.detail));
+                }
+                if let Some(verdict) = &case.verdict {
+                    md.push_str(&format!("- judge {:.2}: {}\n", verdict.score, verdict.reason));
+                }
+                if !case.reply.is_empty() {
+                    md.push_str(&format!("\n> {}\n", case.reply.replace('\n', "\n> ")));
+                }
+            }
+        }
+        md
+    }
+}
+
+/// How a run differs from its baseline
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    pub baseline_target: String,
+    pub baseline_created_at: String,
+    pub baseline_score: f64,
+    pub score: f64,
+    /// Cases that passed before and fail now
+    pub regressions: Vec<String>,
+    /// Cases that failed before and pass now
+    pub fixed: Vec<String>,
+    /// (case, old score, new score) for scores that moved
+    pub changes: Vec<(String, f64, f64)>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str) -> Case {
+        serde_yaml::from_str(&format!(
+            "name: {}\nlang: en\ninput: hi\nchecks:\n  contains: [hello]\n  max-length: 20\n", name
+        )).unwrap()
+    }
+
+    #[test]
+    fn test_scoring_and_comparison() {
+        let verdict = Verdict { score: 0.4, reason: "Terse".to_string() };
+        let baseline = Report::new("suite", "groq/llama", vec![
+            CaseResult::new(&case("greet"), Ok("hello there".to_string()), None, 0.6, 10),
+            CaseResult::new(&case("bye"), Ok("goodbye".to_string()), None, 0.6, 10),
+            CaseResult::new(&case("gone"), Err("timeout".to_string()), None, 0.6, 10),
+        ]);
+        assert_eq!(baseline.passed(), 1);
+        assert_eq!(baseline.cases[1].score, 0.5);
+        assert_eq!(baseline.cases[2].score, 0.0);
+
+        let run = Report::new("suite", "claude/haiku", vec![
+            CaseResult::new(&case("greet"), Ok("hello there".to_string()), Some(verdict), 0.6, 12),
+            CaseResult::new(&case("bye"), Ok("hello, bye".to_string()), None, 0.6, 8),
+            CaseResult::new(&case("new"), Ok("hello".to_string()), None, 0.6, 8),
+        ]);
+        // Checks pass but the judge score is below the threshold
+        assert!(!run.cases[0].passed);
+        assert!((run.cases[0].score - 0.7).abs() < 1e-9);
+
+        let comparison = run.compare(&baseline);
+        assert_eq!(comparison.regressions, vec!["greet"]);
+        assert_eq!(comparison.fixed, vec!["bye"]);
+        assert_eq!(comparison.added, vec!["new"]);
+        assert_eq!(comparison.removed, vec!["gone"]);
+
+        let md = run.to_markdown(Some(&comparison));
+        assert!(md.contains("**Regressions:** greet"));
+        assert!(md.contains("- judge 0.40: Terse"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Audit log - Security events as JSON lines (`security.audit` in config.yaml)
+
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::infrastructure::config::AuditConfig;
+use crate::infrastructure::llm::UsageScope;
+
+/// One audit log line
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub time: String,
+    /// e.g. `prompt_injection`, `secret_redacted`, `output_blocked`
+    pub event: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<String>,
+    pub detail: String,
+}
+
+impl AuditEvent {
+    pub fn new(event: impl Into<String>, scope: Option<&UsageScope>, detail: impl Into<String>) -> Self {
+        Self {
+            time: chrono::Utc::now().to_rfc3339(),
+            event: event.into(),
+            user_id: scope.map(|s| s.user_id.clone()),
+            chat_id: scope.map(|s| s.chat_id.clone()),
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Append-only audit log; events are also logged as warnings
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig) -> Self {
+        Self {
+            path: config.enabled.then(|| config.path.clone().unwrap_or_else(|| PathBuf::from("logs/audit.log"))),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Log to tracing only
+    #[cfg(test)]
+    pub fn disabled() -> Self {
+        Self { path: None, lock: Mutex::new(()) }
+    }
+
+    pub fn record(&self, event: &AuditEvent) {
+        tracing::warn!("Audit: {} user={} chat={}: {}", event.event,
+            event.user_id.as_deref().unwrap_or("-"), event.chat_id.as_deref().unwrap_or("-"), event.detail);
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let _guard = self.lock.lock().unwrap();
+        let result = (|| {
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(event).unwrap_or_default())
+        })();
+        if let Err(e) = result {
+            tracing::error!("Failed to write audit log {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("carik-audit-{}/audit.log", uuid::Uuid::new_v4()));
+        let log = AuditLog::new(&AuditConfig { enabled: true, path: Some(path.clone()) });
+        let scope = UsageScope::new("7", "-100");
+        log.record(&AuditEvent::new("prompt_injection", Some(&scope), "rss: ignore-instructions"));
+        log.record(&AuditEvent::new("secret_redacted", None, "groq-key"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "prompt_injection");
+        assert_eq!(lines[0]["chat_id"], "-100");
+        assert!(lines[1].get("user_id").is_none());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,92 @@
+//! Secret redaction for LLM replies
+
+use regex_lite::Regex;
+
+/// Shapes of common credentials, redacted even when not in config
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("anthropic-key", r"sk-ant-[A-Za-z0-9_-]{20,}"),
+    ("openai-key", r"sk-(proj-)?[A-Za-z0-9_-]{20,}"),
+    ("groq-key", r"gsk_[A-Za-z0-9]{20,}"),
+    ("telegram-token", r"\b\d{8,10}:[A-Za-z0-9_-]{35}\b"),
+    ("github-token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+    ("aws-key", r"\bAKIA[0-9A-Z]{16}\b"),
+    ("private-key", r"-----BEGIN [A-Z ]*PRIVATE KEY-----"),
+];
+
+/// Configured values shorter than this are not treated as secrets
+const MIN_SECRET_LEN: usize = 8;
+
+const REDACTED: &str = "[redacted]";
+
+/// Redacts known secret values and key-shaped strings
+pub struct SecretFilter {
+    known: Vec<String>,
+    patterns: Vec<(String, Regex)>,
+}
+
+impl SecretFilter {
+    /// `known` are exact values (API keys, bot token); `extra` are regexes
+    /// from `guard.secret-patterns`, invalid ones are skipped with a warning
+    pub fn new(known: Vec<String>, extra: &[String]) -> Self {
+        let mut known: Vec<String> = known.into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| s.len() >= MIN_SECRET_LEN)
+            .collect();
+        // Longest first, so a key containing another is redacted whole
+        known.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        known.dedup();
+
+        let mut patterns: Vec<(String, Regex)> = BUILTIN_PATTERNS.iter()
+            .map(|(name, pattern)| (name.to_string(), Regex::new(pattern).expect("valid secret pattern")))
+            .collect();
+        for (i, pattern) in extra.iter().enumerate() {
+            match Regex::new(pattern) {
+                Ok(re) => patterns.push((format!("custom-{}", i + 1), re)),
+                Err(e) => tracing::warn!("Ignoring guard.secret-patterns entry {:?}: {}", pattern, e),
+            }
+        }
+        Self { known, patterns }
+    }
+
+    /// Replace secrets in `text`; returns what kinds were found
+    pub fn redact(&self, text: &mut String) -> Vec<String> {
+        let mut found = Vec::new();
+        for secret in &self.known {
+            if text.contains(secret.as_str()) {
+                *text = text.replace(secret.as_str(), REDACTED);
+                if !found.iter().any(|f| f == "configured-secret") {
+                    found.push("configured-secret".to_string());
+                }
+            }
+        }
+        for (name, re) in &self.patterns {
+            if re.is_match(text) {
+                *text = re.replace_all(text, REDACTED).into_owned();
+                found.push(name.clone());
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let filter = SecretFilter::new(
+            vec!["my-endpoint-key-123".to_string(), "short".to_string()],
+            &[r"internal-[0-9]{6}".to_string(), "(".to_string()],
+        );
+        let mut text = "Keys: my-endpoint-key-123, gsk_abcdefghijklmnopqrstuvwxyz012345, internal-424242. \
+Bot 123456789:AAHdqTcvCH1vGWJxfSeofSAs0K5PALDsaw0 is short for nothing."
+            .to_string();
+        let found = filter.redact(&mut text);
+        assert_eq!(found, vec!["configured-secret", "groq-key", "telegram-token", "custom-1"]);
+        assert_eq!(text, "Keys: [redacted], [redacted], [redacted]. Bot [redacted] is short for nothing.");
+
+        let mut clean = "Use sk- prefixed keys from the dashboard".to_string();
+        assert!(filter.redact(&mut clean).is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+//! Hashing - SHA-256 digests as lowercase hex
+//!
+//! Used for cache keys, document change detection and backup checksums.
+
+use ring::digest::{digest, Digest, SHA256};
+
+/// SHA-256 of `data`, as hex
+pub fn sha256(data: impl AsRef<[u8]>) -> String {
+    hex(&digest(&SHA256, data.as_ref()))
+}
+
+/// Lowercase hex of a finished digest
+pub fn hex(digest: &Digest) -> String {
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(sha256("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(sha256(b"").len(), 64);
+    }
+}
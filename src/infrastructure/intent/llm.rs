@@ -0,0 +1,99 @@
+//! LLM-based intent classifier
+//!
+//! Asks the model for a JSON verdict (in structured output mode); uses the
+//! `classification` route so a small, fast model can be configured for it.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{Intent, IntentClassifier, IntentContext, IntentResult};
+use crate::infrastructure::llm::{LLMMessage, LLMRouter, LLMTask, ResponseSchema};
+
+const SYSTEM_PROMPT: &str = "You classify messages sent to a Telegram assistant. \
+Messages may be in English, Indonesian or Javanese.\n\
+Intents:\n\
+- chat: general conversation or questions\n\
+- news: wants news headlines (slot `topic`: country or subject, `source`: feed name)\n\
+- finance: wants crypto, stock or currency data (slot `category`: crypto, stocks or currency)\n\
+- coding: wants code written, fixed or explained (slot `task`)\n\
+- translate: wants text translated (slot `target_language` in English, e.g. Javanese)\n\
+- capabilities: asks what the bot can do\n\
+- skill: weather, github, spotify or obsidian (slot `skill`)\n\
+Reply with JSON only: {\"intent\": \"...\", \"confidence\": 0.0-1.0, \"slots\": {}}";
+
+/// Schema of [`Verdict`]
+fn verdict_schema() -> ResponseSchema {
+    let intents: Vec<&str> = Intent::ALL.iter().map(|i| i.as_str()).collect();
+    ResponseSchema::new("intent_verdict", serde_json::json!({
+        "type": "object",
+        "properties": {
+            "intent": {"type": "string", "enum": intents},
+            "confidence": {"type": "number", "minimum": 0, "maximum": 1},
+            "slots": {"type": "object"}
+        },
+        "required": ["intent", "confidence"]
+    }))
+}
+
+/// JSON reply expected from the model
+#[derive(Deserialize)]
+struct Verdict {
+    intent: String,
+    #[serde(default)]
+    confidence: f32,
+    #[serde(default)]
+    slots: HashMap<String, serde_json::Value>,
+}
+
+impl Verdict {
+    fn into_result(self) -> Option<IntentResult> {
+        let intent = Intent::from_name(&self.intent)?;
+        let mut result = IntentResult::new(intent, self.confidence, "llm");
+        for (key, value) in self.slots {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Null => continue,
+                other => other.to_string(),
+            };
+            result = result.with_slot(key, value);
+        }
+        Some(result)
+    }
+}
+
+/// Classifier that asks the LLM
+pub struct LLMClassifier {
+    llm: Arc<LLMRouter>,
+}
+
+impl LLMClassifier {
+    pub fn new(llm: Arc<LLMRouter>) -> Self {
+        Self { llm }
+    }
+}
+
+#[async_trait]
+impl IntentClassifier for LLMClassifier {
+    fn name(&self) -> &str {
+        "llm"
+    }
+
+    async fn classify(&self, text: &str, context: &IntentContext) -> Option<IntentResult> {
+        let mut user = format!("Message: {}", text);
+        if context.is_reply {
+            user.push_str("\n(The message is a reply to another message.)");
+        }
+        let messages = vec![LLMMessage::system(SYSTEM_PROMPT), LLMMessage::user(user)];
+
+        let schema = verdict_schema();
+        match self.llm.chat_structured::<Verdict>(LLMTask::Classification, messages, &schema, Some(0.0), Some(150)).await {
+            Ok(verdict) => verdict.into_result(),
+            Err(e) => {
+                tracing::warn!("LLM intent classification failed: {}", e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Intent routing - Decide what a free-text message is asking for
+//!
+//! Classifiers are tried in order; the first whose confidence reaches its
+//! accept threshold wins. Otherwise the most confident result is used if it
+//! clears `min-confidence`, and the message falls back to plain chat.
+
+pub mod rules;
+pub mod llm;
+#[cfg(test)]
+mod tests;
+
+pub use rules::RuleClassifier;
+pub use llm::LLMClassifier;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::infrastructure::llm::LLMRouter;
+
+/// What a message is asking for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Intent {
+    /// Plain conversation with the LLM
+    Chat,
+    /// News headlines / RSS
+    News,
+    /// Crypto, stock and currency data
+    Finance,
+    /// Coding task for the Kiro agent
+    Coding,
+    /// Translate text (or the replied-to message)
+    Translate,
+    /// What the bot can do
+    Capabilities,
+    /// External skill (weather, github, ...); name in the `skill` slot
+    Skill,
+}
+
+impl Intent {
+    pub const ALL: [Intent; 7] = [
+        Intent::Chat,
+        Intent::News,
+        Intent::Finance,
+        Intent::Coding,
+        Intent::Translate,
+        Intent::Capabilities,
+        Intent::Skill,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Intent::Chat => "chat",
+            Intent::News => "news",
+            Intent::Finance => "finance",
+            Intent::Coding => "coding",
+            Intent::Translate => "translate",
+            Intent::Capabilities => "capabilities",
+            Intent::Skill => "skill",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Self::ALL.into_iter().find(|i| i.as_str() == name)
+    }
+}
+
+/// A classifier's verdict
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntentResult {
+    pub intent: Intent,
+    /// 0.0 - 1.0
+    pub confidence: f32,
+    /// Extracted values, e.g. `topic`, `target_language`, `category`, `skill`
+    pub slots: HashMap<String, String>,
+    /// Name of the classifier that produced this result
+    pub classifier: String,
+}
+
+impl IntentResult {
+    pub fn new(intent: Intent, confidence: f32, classifier: impl Into<String>) -> Self {
+        Self {
+            intent,
+            confidence: confidence.clamp(0.0, 1.0),
+            slots: HashMap::new(),
+            classifier: classifier.into(),
+        }
+    }
+
+    /// Fallback when nothing is confident enough
+    pub fn chat() -> Self {
+        Self::new(Intent::Chat, 1.0, "default")
+    }
+
+    pub fn with_slot(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.slots.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn slot(&self, key: &str) -> Option<&str> {
+        self.slots.get(key).map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+}
+
+/// Conversation state that affects classification
+#[derive(Debug, Clone, Default)]
+pub struct IntentContext {
+    /// The message is a reply to another message
+    pub is_reply: bool,
+}
+
+/// Something that can guess the intent of a message
+#[async_trait]
+pub trait IntentClassifier: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Classify `text`, or `None` if this classifier can't tell (e.g. LLM error)
+    async fn classify(&self, text: &str, context: &IntentContext) -> Option<IntentResult>;
+}
+
+/// Which classifiers to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassifierMode {
+    /// Keyword rules only
+    Rules,
+    /// LLM only (rules if no LLM is configured)
+    Llm,
+    /// Rules first, LLM when the rules aren't sure
+    #[default]
+    Hybrid,
+}
+
+/// Intent routing configuration (`intent:` in config.yaml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct IntentConfig {
+    pub classifier: ClassifierMode,
+    /// Results below this confidence fall back to chat
+    pub min_confidence: f32,
+    /// In hybrid mode, rule results at least this confident skip the LLM
+    pub rules_accept: f32,
+}
+
+impl Default for IntentConfig {
+    fn default() -> Self {
+        Self {
+            classifier: ClassifierMode::Hybrid,
+            min_confidence: 0.5,
+            rules_accept: 0.75,
+        }
+    }
+}
+
+/// Ordered chain of classifiers with thresholds
+pub struct IntentRouter {
+    /// Classifier and the confidence at which its answer is taken immediately
+    classifiers: Vec<(Box<dyn IntentClassifier>, f32)>,
+    min_confidence: f32,
+}
+
+impl IntentRouter {
+    pub fn new(min_confidence: f32) -> Self {
+        Self {
+            classifiers: Vec::new(),
+            min_confidence,
+        }
+    }
+
+    /// Build the chain described by `config`; the LLM classifier is only
+    /// added when an LLM is available
+    pub fn from_config(config: &IntentConfig, llm: Option<Arc<LLMRouter>>) -> Self {
+        let mut router = Self::new(config.min_confidence);
+        let rules = || Box::new(RuleClassifier::new()) as Box<dyn IntentClassifier>;
+
+        match (config.classifier, llm) {
+            (ClassifierMode::Rules, _) | (_, None) => {
+                router = router.with_classifier(rules(), config.min_confidence);
+            }
+            (ClassifierMode::Llm, Some(llm)) => {
+                router = router.with_classifier(Box::new(LLMClassifier::new(llm)), config.min_confidence);
+            }
+            (ClassifierMode::Hybrid, Some(llm)) => {
+                router = router
+                    .with_classifier(rules(), config.rules_accept)
+                    .with_classifier(Box::new(LLMClassifier::new(llm)), config.min_confidence);
+            }
+        }
+        router
+    }
+
+    pub fn with_classifier(mut self, classifier: Box<dyn IntentClassifier>, accept: f32) -> Self {
+        self.classifiers.push((classifier, accept));
+        self
+    }
+
+    pub fn classifier_names(&self) -> Vec<&str> {
+        self.classifiers.iter().map(|(c, _)| c.name()).collect()
+    }
+
+    /// Classify a message, falling back to [`Intent::Chat`]
+    pub async fn classify(&self, text: &str, context: &IntentContext) -> IntentResult {
+        let mut best: Option<IntentResult> = None;
+
+        for (classifier, accept) in &self.classifiers {
+            let Some(result) = classifier.classify(text, context).await else {
+                continue;
+            };
+            tracing::debug!(
+                "Intent classifier '{}': {} ({:.2})",
+                classifier.name(), result.intent.as_str(), result.confidence
+            );
+            if result.confidence >= *accept {
+                return result;
+            }
+            if best.as_ref().is_none_or(|b| result.confidence > b.confidence) {
+                best = Some(result);
+            }
+        }
+
+        best.filter(|b| b.confidence >= self.min_confidence)
+            .unwrap_or_else(IntentResult::chat)
+    }
+}
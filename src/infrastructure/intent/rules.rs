@@ -0,0 +1,198 @@
+//! Rule-based intent classifier
+//!
+//! Keywords are matched on whole words (or whole phrases), so "barcode" no
+//! longer counts as "code". Each rule has strong keywords that are enough on
+//! their own and weak ones that only count together; confidence combines the
+//! hits as `1 - Π(1 - weight)`. A lone strong keyword stays just below the
+//! hybrid `rules-accept` threshold, so the LLM confirms it unless something
+//! else in the message backs it up.
+
+use async_trait::async_trait;
+
+use super::{Intent, IntentClassifier, IntentContext, IntentResult};
+
+/// Weight of a keyword that identifies the intent by itself; below the
+/// default `rules-accept` (0.75) until another keyword agrees
+const STRONG: f32 = 0.7;
+/// Weight of a keyword that only hints at the intent
+const WEAK: f32 = 0.25;
+/// Extra weight for translation when the message is a reply
+const REPLY_TRANSLATE: f32 = 0.6;
+
+/// Keywords for one intent (and optionally one slot value)
+struct Rule {
+    intent: Intent,
+    slot: Option<(&'static str, &'static str)>,
+    strong: &'static [&'static str],
+    weak: &'static [&'static str],
+}
+
+/// Rules in priority order; on equal confidence the earlier rule wins
+const RULES: &[Rule] = &[
+    Rule {
+        intent: Intent::Capabilities,
+        slot: None,
+        strong: &[
+            "what can you do", "what can i do", "what are your skills", "what can bot do",
+            "your capabilities", "your features",
+            // Indonesian
+            "apa yang kamu bisa", "bisa apa saja", "fitur apa saja", "apa kemampuanmu",
+            "skillmu apa", "bisa ngapain", "apa gunanya",
+            // Javanese
+            "iso apa wae", "isa apa wae", "bisa apa wae", "kowe iso apa", "sampeyan saged menapa",
+        ],
+        weak: &["help me", "tolong", "bantu"],
+    },
+    Rule {
+        intent: Intent::Translate,
+        slot: None,
+        strong: &[
+            "translate", "translation", "meaning of",
+            // Indonesian
+            "terjemahkan", "terjemahan", "terjemah", "artinya", "apa arti", "penterjemah",
+            // Javanese
+            "tegese", "apa tegese", "terjemahna", "jarwakna",
+        ],
+        weak: &["meaning", "mean", "arti", "in english", "ke inggris", "basa jawa", "bahasa jawa"],
+    },
+    Rule {
+        intent: Intent::News,
+        slot: None,
+        strong: &[
+            "news", "headlines", "headline", "rss", "breaking news",
+            // Indonesian
+            "berita", "kabar terbaru",
+            // Javanese
+            "warta", "kabar anyar",
+        ],
+        weak: &["latest", "update", "updates", "feed", "breaking", "terbaru", "anyar", "kabar"],
+    },
+    Rule {
+        intent: Intent::Finance,
+        slot: None,
+        strong: &[
+            "crypto", "cryptocurrency", "bitcoin", "btc", "ethereum", "solana",
+            "stock", "stocks", "nasdaq", "s&p", "dow jones", "forex", "exchange rate",
+            "eur", "idr", "jpy",
+            // Indonesian / Javanese
+            "kripto", "saham", "ihsg", "kurs", "nilai tukar", "rupiah", "dolar",
+        ],
+        weak: &[
+            "price", "prices", "market", "trading", "currency", "exchange", "index", "dollar", "usd",
+            "harga", "pasar", "mata uang", "rega", "dhuwit",
+        ],
+    },
+    Rule {
+        intent: Intent::Coding,
+        slot: None,
+        strong: &[
+            "coding", "programming", "algorithm", "debug", "refactor", "compile", "regex",
+            "source code", "python", "javascript", "typescript", "rust", "golang", "sql",
+            // Indonesian / Javanese
+            "kode", "skrip", "fungsi", "algoritma", "ngoding", "pemrograman",
+        ],
+        weak: &[
+            // Too common outside programming to count on their own
+            "code", "program", "script", "function", "java",
+            "write", "create", "build", "make", "fix", "implement", "develop", "optimize",
+            "app", "application", "website", "web", "api", "database", "class", "bug",
+            "bikin", "buat", "tulis", "aplikasi", "gawe", "nggawe", "gawekna",
+        ],
+    },
+    Rule {
+        intent: Intent::Skill,
+        slot: Some(("skill", "weather")),
+        strong: &["weather", "forecast", "cuaca", "ramalan cuaca", "hawane"],
+        weak: &["rain", "hujan", "udan", "temperature", "suhu"],
+    },
+    Rule {
+        intent: Intent::Skill,
+        slot: Some(("skill", "github")),
+        strong: &["github", "pull request"],
+        weak: &["repo", "repository", "git"],
+    },
+    Rule {
+        intent: Intent::Skill,
+        slot: Some(("skill", "spotify")),
+        strong: &["spotify"],
+        weak: &["music", "musik", "song", "lagu", "playlist"],
+    },
+    Rule {
+        intent: Intent::Skill,
+        slot: Some(("skill", "obsidian")),
+        strong: &["obsidian"],
+        weak: &["note", "notes", "catatan"],
+    },
+];
+
+/// Lower-case words separated by single spaces, padded with a space each side
+fn normalize(text: &str) -> String {
+    let words: Vec<String> = text
+        .to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '&'))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect();
+    format!(" {} ", words.join(" "))
+}
+
+fn contains_word(normalized: &str, keyword: &str) -> bool {
+    normalized.contains(&format!(" {} ", keyword))
+}
+
+/// Keyword classifier built from the bot's original intent lists
+#[derive(Debug, Default)]
+pub struct RuleClassifier;
+
+impl RuleClassifier {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Confidence for every intent with at least one hit, best first
+    pub fn scores(&self, text: &str, context: &IntentContext) -> Vec<IntentResult> {
+        let normalized = normalize(text);
+        let mut results: Vec<IntentResult> = Vec::new();
+
+        for rule in RULES {
+            let mut miss = 1.0_f32;
+            for kw in rule.strong {
+                if contains_word(&normalized, kw) {
+                    miss *= 1.0 - STRONG;
+                }
+            }
+            for kw in rule.weak {
+                if contains_word(&normalized, kw) {
+                    miss *= 1.0 - WEAK;
+                }
+            }
+            if rule.intent == Intent::Translate && context.is_reply {
+                miss *= 1.0 - REPLY_TRANSLATE;
+            }
+            if miss >= 1.0 {
+                continue;
+            }
+
+            let mut result = IntentResult::new(rule.intent, 1.0 - miss, "rules");
+            if let Some((key, value)) = rule.slot {
+                result = result.with_slot(key, value);
+            }
+            results.push(result);
+        }
+
+        // Stable sort keeps rule priority on ties
+        results.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        results
+    }
+}
+
+#[async_trait]
+impl IntentClassifier for RuleClassifier {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    async fn classify(&self, text: &str, context: &IntentContext) -> Option<IntentResult> {
+        self.scores(text, context).into_iter().next()
+    }
+}
@@ -0,0 +1,167 @@
+//! Intent classifier tests, including the example corpus
+
+use super::*;
+use crate::infrastructure::llm::{LLMConfig, MockProvider};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Example {
+    lang: String,
+    text: String,
+    intent: Intent,
+    #[serde(default)]
+    reply: bool,
+}
+
+fn corpus() -> Vec<Example> {
+    serde_yaml::from_str(include_str!("corpus.yaml")).expect("valid corpus")
+}
+
+fn rules_only() -> IntentRouter {
+    let config = IntentConfig { classifier: ClassifierMode::Rules, ..IntentConfig::default() };
+    IntentRouter::from_config(&config, None)
+}
+
+/// Router over a mock LLM that always gives `reply`
+fn llm_router(reply: &str) -> (Arc<LLMRouter>, Arc<MockProvider>) {
+    let mock = Arc::new(MockProvider::new("mock").otherwise(reply));
+    let config = LLMConfig { fallback: vec!["mock".to_string()], ..LLMConfig::default() };
+    let router = LLMRouter::new(config).with_provider("mock", mock.clone());
+    (Arc::new(router), mock)
+}
+
+#[tokio::test]
+async fn test_rule_classifier_corpus() {
+    let router = rules_only();
+    let mut failures = Vec::new();
+
+    for example in corpus() {
+        let context = IntentContext { is_reply: example.reply };
+        let result = router.classify(&example.text, &context).await;
+        if result.intent != example.intent {
+            failures.push(format!(
+                "[{}] {:?}: expected {}, got {} ({:.2})",
+                example.lang, example.text, example.intent.as_str(), result.intent.as_str(), result.confidence
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "misclassified:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_corpus_covers_languages_and_intents() {
+    let corpus = corpus();
+    for lang in ["en", "id", "jv"] {
+        assert!(corpus.iter().any(|e| e.lang == lang), "no {} examples", lang);
+    }
+    for intent in Intent::ALL {
+        assert!(corpus.iter().any(|e| e.intent == intent), "no {} examples", intent.as_str());
+    }
+}
+
+#[test]
+fn test_whole_word_matching() {
+    let rules = RuleClassifier::new();
+    let context = IntentContext::default();
+
+    assert!(rules.scores("my barcode is broken", &context).is_empty());
+    assert!(rules.scores("that's useful", &context).is_empty());
+    assert!(rules.scores("eth", &context).is_empty());
+    assert_eq!(rules.scores("check the S&P today", &context)[0].intent, Intent::Finance);
+
+    let weather = &rules.scores("weather in jakarta", &context)[0];
+    assert_eq!(weather.intent, Intent::Skill);
+    assert_eq!(weather.slot("skill"), Some("weather"));
+}
+
+#[tokio::test]
+async fn test_llm_verdict() {
+    let config = IntentConfig { classifier: ClassifierMode::Llm, ..IntentConfig::default() };
+    let reply = "```json\n{\"intent\": \"translate\", \"confidence\": 0.92, \"slots\": {\"target_language\": \"Javanese\", \"n\": 2}}\n```";
+    let (llm, _) = llm_router(reply);
+    let result = IntentRouter::from_config(&config, Some(llm)).classify("ngoko", &IntentContext::default()).await;
+    assert_eq!(result.intent, Intent::Translate);
+    assert!((result.confidence - 0.92).abs() < 1e-6);
+    assert_eq!(result.slot("target_language"), Some("Javanese"));
+    assert_eq!(result.slot("n"), Some("2"));
+    assert_eq!(result.classifier, "llm");
+
+    // Invalid verdicts are sent back for correction, then given up on
+    for reply in ["{\"intent\": \"dance\", \"confidence\": 1}", "no json here"] {
+        let (llm, mock) = llm_router(reply);
+        let result = IntentRouter::from_config(&config, Some(llm)).classify("ngoko", &IntentContext::default()).await;
+        assert_eq!(result.classifier, "default");
+        assert_eq!(mock.calls(), 3);
+    }
+}
+
+#[tokio::test]
+async fn test_hybrid_skips_llm_when_rules_are_confident() {
+    let (llm, mock) = llm_router(r#"{"intent": "chat", "confidence": 0.9}"#);
+    let router = IntentRouter::from_config(&IntentConfig::default(), Some(llm));
+    assert_eq!(router.classifier_names(), vec!["rules", "llm"]);
+
+    let result = router.classify("berita terbaru dari india", &IntentContext::default()).await;
+    assert_eq!(result.intent, Intent::News);
+    assert_eq!(result.classifier, "rules");
+    assert_eq!(mock.calls(), 0);
+}
+
+#[test]
+fn test_ambiguous_keywords_need_support() {
+    let rules = RuleClassifier::new();
+    let context = IntentContext::default();
+    let rules_accept = IntentConfig::default().rules_accept;
+
+    // Words like "code" and "java" only count next to something clearer
+    for text in ["what's the zip code", "promo code", "my trip to Java"] {
+        assert!(rules.scores(text, &context).iter().all(|r| r.confidence < 0.5), "{}", text);
+    }
+    assert_eq!(rules.scores("fix this java code", &context)[0].intent, Intent::Coding);
+
+    // One strong keyword alone isn't enough to skip the LLM, one more is
+    assert!(rules.scores("python", &context)[0].confidence < rules_accept);
+    assert!(rules.scores("write some python", &context)[0].confidence >= rules_accept);
+}
+
+#[tokio::test]
+async fn test_hybrid_confirms_a_single_keyword_with_llm() {
+    let (llm, mock) = llm_router(r#"{"intent": "chat", "confidence": 0.9}"#);
+    let router = IntentRouter::from_config(&IntentConfig::default(), Some(llm));
+
+    let result = router.classify("is rust bad for my bike chain?", &IntentContext::default()).await;
+    assert_eq!(mock.calls(), 1);
+    assert_eq!(result.intent, Intent::Chat);
+}
+
+#[tokio::test]
+async fn test_hybrid_asks_llm_when_rules_are_unsure() {
+    let (llm, mock) = llm_router(r#"{"intent": "finance", "confidence": 0.8, "slots": {"category": "currency"}}"#);
+    let router = IntentRouter::from_config(&IntentConfig::default(), Some(llm));
+
+    // Only weak finance hints ("harga", "pasar")
+    let result = router.classify("harga di pasar naik terus", &IntentContext::default()).await;
+    assert_eq!(mock.calls(), 1);
+    assert_eq!(result.intent, Intent::Finance);
+    assert_eq!(result.slot("category"), Some("currency"));
+}
+
+#[tokio::test]
+async fn test_low_confidence_falls_back_to_chat() {
+    let (llm, _) = llm_router(r#"{"intent": "coding", "confidence": 0.3}"#);
+    let config = IntentConfig { classifier: ClassifierMode::Llm, min_confidence: 0.6, ..IntentConfig::default() };
+    let router = IntentRouter::from_config(&config, Some(llm));
+
+    let result = router.classify("make it nicer", &IntentContext::default()).await;
+    assert_eq!(result.intent, Intent::Chat);
+    assert_eq!(result.classifier, "default");
+}
+
+#[test]
+fn test_config_from_yaml() {
+    let config: IntentConfig = serde_yaml::from_str("classifier: llm\nmin-confidence: 0.7\n").unwrap();
+    assert_eq!(config.classifier, ClassifierMode::Llm);
+    assert_eq!(config.min_confidence, 0.7);
+    assert_eq!(config.rules_accept, 0.75);
+}
@@ -0,0 +1,123 @@
+//! Response caching - Reuse replies to identical deterministic requests
+//!
+//! Translations of the same text and summaries of the same feed snapshot
+//! don't need a fresh completion per user. The router looks requests up in a
+//! [`ResponseCache`] by a key over provider, model, sampling settings and the
+//! normalized messages, for tasks with a TTL in `llm.cache.ttl`. Chat, vision
+//! and requests offering tools are never cached.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::infrastructure::hash;
+use crate::infrastructure::llm::{LLMMessage, LLMResponse, LLMTask, ResponseSchema};
+
+/// Cache settings (`llm.cache` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Task route name (`translation`, `summarization`, ...) -> seconds a
+    /// reply is reused; tasks not listed aren't cached
+    pub ttl: HashMap<String, u64>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: HashMap::from([
+                ("translation".to_string(), 7 * 24 * 3600),
+                ("summarization".to_string(), 3600),
+            ]),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// How long replies for `task` are kept, if they are cached at all
+    pub fn ttl(&self, task: LLMTask) -> Option<Duration> {
+        if !self.enabled || matches!(task, LLMTask::Chat | LLMTask::Vision | LLMTask::Embedding) {
+            return None;
+        }
+        self.ttl.get(task.as_str()).filter(|secs| **secs > 0).map(|secs| Duration::from_secs(*secs))
+    }
+}
+
+/// Stores replies by [`cache_key`]; implementations track their own hit rate
+#[async_trait]
+pub trait ResponseCache: Send + Sync {
+    /// Unexpired reply stored under `key`
+    async fn get(&self, task: LLMTask, key: &str) -> Option<LLMResponse>;
+
+    /// Store `response` under `key` for `ttl`
+    async fn put(&self, task: LLMTask, key: &str, response: &LLMResponse, ttl: Duration);
+}
+
+/// Role and content with whitespace collapsed, so formatting-only
+/// differences share an entry
+fn normalize(message: &LLMMessage) -> String {
+    format!("{}\u{1f}{}", message.role, message.content.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Key for a request to `provider`/`model`
+pub fn cache_key(
+    provider: &str,
+    model: &str,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    schema: Option<&ResponseSchema>,
+    messages: &[LLMMessage],
+) -> String {
+    let mut text = format!(
+        "{}\u{1e}{}\u{1e}{:?}\u{1e}{:?}\u{1e}",
+        provider.to_lowercase(), model, temperature, max_tokens
+    );
+    if let Some(schema) = schema {
+        text.push_str(&format!("{}:{}", schema.name, schema.schema));
+    }
+    for message in messages {
+        text.push('\u{1e}');
+        text.push_str(&normalize(message));
+    }
+    hash::sha256(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let messages = vec![LLMMessage::system("Translate"), LLMMessage::user("Sugeng  enjing\n")];
+        let key = cache_key("groq", "llama", Some(0.3), None, None, &messages);
+        assert_eq!(key.len(), 64);
+
+        // Whitespace doesn't matter, everything else does
+        let reformatted = vec![LLMMessage::system("Translate "), LLMMessage::user("Sugeng enjing")];
+        assert_eq!(cache_key("Groq", "llama", Some(0.3), None, None, &reformatted), key);
+        assert_ne!(cache_key("groq", "llama", Some(0.7), None, None, &messages), key);
+        assert_ne!(cache_key("groq", "mixtral", Some(0.3), None, None, &messages), key);
+        assert_ne!(cache_key("claude", "llama", Some(0.3), None, None, &messages), key);
+        let schema = ResponseSchema::new("digest", serde_json::json!({"type": "object"}));
+        assert_ne!(cache_key("groq", "llama", Some(0.3), None, Some(&schema), &messages), key);
+        let other = vec![LLMMessage::system("Translate"), LLMMessage::user("Sugeng dalu")];
+        assert_ne!(cache_key("groq", "llama", Some(0.3), None, None, &other), key);
+    }
+
+    #[test]
+    fn test_ttl_per_task() {
+        let mut config = CacheConfig::default();
+        assert_eq!(config.ttl(LLMTask::Summarization), Some(Duration::from_secs(3600)));
+        assert_eq!(config.ttl(LLMTask::Classification), None);
+
+        // Conversational chat is never cached
+        config.ttl.insert("chat".to_string(), 60);
+        assert_eq!(config.ttl(LLMTask::Chat), None);
+
+        config.enabled = false;
+        assert_eq!(config.ttl(LLMTask::Translation), None);
+    }
+}
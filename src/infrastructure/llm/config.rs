@@ -1,6 +1,10 @@
 //! LLM Configuration
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::infrastructure::llm::context::ContextConfig;
+use crate::infrastructure::llm::cache::CacheConfig;
 
 /// LLM Provider type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
@@ -9,6 +13,9 @@ pub enum LLMProvider {
     MiniMax,
     Claude,
     Groq,
+    /// The endpoints listed under `openai-compatible`, in order
+    #[serde(rename = "openai-compatible")]
+    OpenAICompatible,
 }
 
 impl Default for LLMProvider {
@@ -17,9 +24,147 @@ impl Default for LLMProvider {
     }
 }
 
-/// LLM Configuration
+impl LLMProvider {
+    /// Provider name as used in routes and fallback lists
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LLMProvider::MiniMax => "minimax",
+            LLMProvider::Claude => "claude",
+            LLMProvider::Groq => "groq",
+            LLMProvider::OpenAICompatible => "openai-compatible",
+        }
+    }
+    
+    /// Parse a provider name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "minimax" => Some(LLMProvider::MiniMax),
+            "claude" | "anthropic" => Some(LLMProvider::Claude),
+            "groq" => Some(LLMProvider::Groq),
+            "openai-compatible" => Some(LLMProvider::OpenAICompatible),
+            _ => None,
+        }
+    }
+}
+
+/// One step in a routing chain: a provider and optionally the model to use on it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LLMRoute {
+    pub provider: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl LLMRoute {
+    pub fn new(provider: impl Into<String>, model: Option<&str>) -> Self {
+        Self {
+            provider: provider.into(),
+            model: model.map(|m| m.to_string()),
+        }
+    }
+}
+
+/// A generic OpenAI-compatible endpoint (Ollama, llama.cpp, vLLM, LM Studio, OpenRouter)
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
+pub struct OpenAICompatibleConfig {
+    /// Name used in `fallback` and `routes`
+    pub name: String,
+    /// Base URL including the version prefix, e.g. `http://localhost:11434/v1`
+    pub base_url: String,
+    /// API key; local servers usually need none
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Environment variable to read the API key from
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Header carrying the key
+    #[serde(default = "default_auth_header")]
+    pub auth_header: String,
+    /// Scheme prepended to the key; empty sends the bare key
+    #[serde(default = "default_auth_scheme")]
+    pub auth_scheme: Option<String>,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Default model; falls back to the first entry of `models`
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Models served by this endpoint
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Model for `/embeddings`, e.g. `nomic-embed-text`
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+}
+
+fn default_auth_header() -> String {
+    "Authorization".to_string()
+}
+
+fn default_auth_scheme() -> Option<String> {
+    Some("Bearer".to_string())
+}
+
+impl OpenAICompatibleConfig {
+    /// API key from config, or from `api_key_env` if set
+    pub fn resolved_api_key(&self) -> Option<String> {
+        self.api_key.clone().or_else(|| {
+            self.api_key_env.as_ref().and_then(|var| std::env::var(var).ok())
+        })
+    }
+}
+
+/// Circuit breaker settings for provider fallback
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures before a provider is skipped
+    pub failure_threshold: u32,
+    /// How long a failing provider is skipped
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            cooldown_secs: 60,
+        }
+    }
+}
+
+/// Timeouts and retries for provider HTTP calls
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct HttpConfig {
+    /// Time allowed to establish a connection
+    pub connect_timeout_secs: u64,
+    /// Time allowed for a whole request, including reading the response
+    pub timeout_secs: u64,
+    /// Retries after the first attempt on 429, 5xx, timeouts and network errors
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled (with jitter) for each next one
+    pub initial_backoff_ms: u64,
+    /// Longest wait between attempts; a longer `Retry-After` fails straight away
+    pub max_backoff_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            timeout_secs: 120,
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_secs: 20,
+        }
+    }
+}
+
+/// LLM Configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
 pub struct LLMConfig {
     /// Default provider
     pub provider: LLMProvider,
@@ -38,6 +183,31 @@ pub struct LLMConfig {
     pub temperature: f32,
     pub max_tokens: Option<u32>,
     pub system_prompt: Option<String>,
+    
+    /// Providers tried in order after `provider` when it fails
+    pub fallback: Vec<String>,
+    
+    /// Per-task provider chains, e.g. `translation` or `chat`
+    pub routes: HashMap<String, Vec<LLMRoute>>,
+    
+    /// Circuit breaker for failing providers
+    pub circuit_breaker: CircuitBreakerConfig,
+    
+    /// Timeouts and retries for every provider
+    pub http: HttpConfig,
+    
+    /// Generic OpenAI-compatible endpoints, addressed by name
+    pub openai_compatible: Vec<OpenAICompatibleConfig>,
+    
+    /// Token budget and history summarization
+    pub context: ContextConfig,
+    
+    /// Model name (or name prefix) -> lowest role (`guest`, `user`, `admin`,
+    /// `owner`) allowed to pick it with /model; the longest matching key wins
+    pub model_roles: HashMap<String, String>,
+    
+    /// Reply reuse for deterministic tasks
+    pub cache: CacheConfig,
 }
 
 impl Default for LLMConfig {
@@ -49,18 +219,26 @@ impl Default for LLMConfig {
             groq_api_key: None,
             minimax_model: Some("abab6.5s-chat".to_string()),
             claude_model: Some("claude-3-haiku-20240307".to_string()),
-            groq_model: Some("llama-3.1-70b-versatile".to_string()),
+            groq_model: Some("llama-3.3-70b-versatile".to_string()),
             temperature: 0.7,
             max_tokens: Some(1024),
             system_prompt: Some("You are carik, a helpful AI assistant.".to_string()),
+            fallback: Vec::new(),
+            routes: HashMap::new(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            http: HttpConfig::default(),
+            openai_compatible: Vec::new(),
+            context: ContextConfig::default(),
+            model_roles: HashMap::new(),
+            cache: CacheConfig::default(),
         }
     }
 }
 
 impl LLMConfig {
-    /// Load from environment variables
-    pub fn from_env() -> Self {
-        let mut config = Self::default();
+    /// Apply environment variable overrides on top of this config
+    pub fn with_env(self) -> Self {
+        let mut config = self;
         
         if let Ok(key) = std::env::var("MINIMAX_API_KEY") {
             config.minimax_api_key = Some(key);
@@ -91,12 +269,52 @@ impl LLMConfig {
         config
     }
     
+    /// Default provider chain: `provider` followed by `fallback`, without duplicates
+    pub fn default_chain(&self) -> Vec<LLMRoute> {
+        let mut chain = match self.provider {
+            LLMProvider::OpenAICompatible => self.openai_compatible.iter()
+                .map(|e| LLMRoute::new(e.name.to_lowercase(), None))
+                .collect(),
+            provider => vec![LLMRoute::new(provider.as_str(), None)],
+        };
+        for name in &self.fallback {
+            if !chain.iter().any(|r| r.provider.eq_ignore_ascii_case(name)) {
+                chain.push(LLMRoute::new(name.to_lowercase(), None));
+            }
+        }
+        chain
+    }
+    
+    /// Provider chain for a task, falling back to the default chain
+    pub fn chain_for(&self, task: &str) -> Vec<LLMRoute> {
+        match self.routes.get(task) {
+            Some(routes) if !routes.is_empty() => routes.clone(),
+            _ => self.default_chain(),
+        }
+    }
+    
+    /// API keys in use, so replies can be checked for leaks
+    pub fn secrets(&self) -> Vec<String> {
+        [&self.minimax_api_key, &self.claude_api_key, &self.groq_api_key]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(self.openai_compatible.iter().filter_map(|e| e.resolved_api_key()))
+            .collect()
+    }
+    
+    /// Find an OpenAI-compatible endpoint by name
+    pub fn endpoint(&self, name: &str) -> Option<&OpenAICompatibleConfig> {
+        self.openai_compatible.iter().find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+    
     /// Get API key for a provider
     pub fn api_key(&self, provider: LLMProvider) -> Option<&str> {
         match provider {
             LLMProvider::MiniMax => self.minimax_api_key.as_deref(),
             LLMProvider::Claude => self.claude_api_key.as_deref(),
             LLMProvider::Groq => self.groq_api_key.as_deref(),
+            LLMProvider::OpenAICompatible => self.openai_compatible.first().and_then(|e| e.api_key.as_deref()),
         }
     }
     
@@ -105,7 +323,46 @@ impl LLMConfig {
         match provider {
             LLMProvider::MiniMax => self.minimax_model.as_deref().unwrap_or("abab6.5s-chat"),
             LLMProvider::Claude => self.claude_model.as_deref().unwrap_or("claude-3-haiku-20240307"),
-            LLMProvider::Groq => self.groq_model.as_deref().unwrap_or("llama-3.1-70b-versatile"),
+            LLMProvider::Groq => self.groq_model.as_deref().unwrap_or("llama-3.3-70b-versatile"),
+            LLMProvider::OpenAICompatible => self.openai_compatible.first()
+                .and_then(|e| e.model.as_deref().or(e.models.first().map(|m| m.as_str())))
+                .unwrap_or("default"),
+        }
+    }
+    
+    /// Lowest role allowed to pick `model`, if it is restricted
+    pub fn model_role(&self, model: &str) -> Option<&str> {
+        let model = model.to_lowercase();
+        self.model_roles.iter()
+            .filter(|(key, _)| model.starts_with(&key.to_lowercase()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, role)| role.as_str())
+    }
+    
+    /// Models config names for a provider: its default, route models and
+    /// an endpoint's `models`
+    pub fn configured_models(&self, provider: &str) -> Vec<String> {
+        let mut models = vec![self.route_model(&LLMRoute::new(provider, None))];
+        let routes = self.routes.values().flatten().filter(|r| r.provider.eq_ignore_ascii_case(provider));
+        models.extend(routes.filter_map(|r| r.model.clone()));
+        if let Some(endpoint) = self.endpoint(provider) {
+            models.extend(endpoint.models.iter().cloned());
+        }
+        models.sort();
+        models.dedup();
+        models
+    }
+    
+    /// Model a route will use: its own, else the provider's default
+    pub fn route_model(&self, route: &LLMRoute) -> String {
+        if let Some(model) = &route.model {
+            return model.clone();
+        }
+        match LLMProvider::from_name(&route.provider) {
+            Some(provider) => self.model(provider).to_string(),
+            None => self.endpoint(&route.provider)
+                .and_then(|e| e.model.clone().or_else(|| e.models.first().cloned()))
+                .unwrap_or_else(|| "default".to_string()),
         }
     }
 }
@@ -0,0 +1,296 @@
+//! Context window management - Fit conversation history into a token budget
+//!
+//! The system prompt (persona + personal instructions) always goes first.
+//! When the history no longer fits the model's budget, the oldest turns are
+//! folded into a rolling LLM-written summary instead of being dropped.
+
+use serde::{Deserialize, Serialize};
+
+use crate::infrastructure::llm::{LLMMessage, LLMRouter, LLMTask};
+
+/// Tokens added per message for role markers and separators
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Rough tokens per attached image (a downscaled ~1 megapixel photo)
+const IMAGE_TOKENS: usize = 1000;
+
+const SUMMARY_PROMPT: &str = "You maintain the memory of a chat assistant. \
+Merge the previous summary (if any) and the conversation below into one short summary \
+written in the third person. Keep names, facts, preferences, decisions and open questions; \
+drop greetings and small talk. Use the language of the conversation. Reply with the summary only.";
+
+/// Context budgeting settings (`llm.context` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ContextConfig {
+    /// Upper bound on prompt size, even for long-context models
+    pub max_context_tokens: usize,
+    /// Override the model's context window
+    pub context_window: Option<usize>,
+    /// Tokens kept free for the reply
+    pub reserve_tokens: usize,
+    /// Most recent messages that are never folded into the summary
+    pub keep_recent: usize,
+    /// Length limit for the rolling summary
+    pub summary_max_tokens: u32,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_context_tokens: 8192,
+            context_window: None,
+            reserve_tokens: 1024,
+            keep_recent: 6,
+            summary_max_tokens: 400,
+        }
+    }
+}
+
+/// Context window of a model, by name (conservative when unknown)
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    let known: [(&str, usize); 12] = [
+        ("claude", 200_000),
+        ("gpt-4o", 128_000),
+        ("gpt-4.1", 1_000_000),
+        ("gpt-3.5", 16_385),
+        ("llama-3.1", 131_072),
+        ("llama-3.2", 131_072),
+        ("llama-3.3", 131_072),
+        ("llama-4", 131_072),
+        ("mixtral", 32_768),
+        ("qwen", 32_768),
+        ("gemma", 8_192),
+        ("abab", 245_760),
+    ];
+    known.iter()
+        .find(|(prefix, _)| model.contains(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(8_192)
+}
+
+/// Rough token counting tuned per provider/model tokenizer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenEstimator {
+    /// Average ASCII characters per token
+    chars_per_token: f32,
+}
+
+impl TokenEstimator {
+    pub fn for_model(provider: &str, model: &str) -> Self {
+        let provider = provider.to_lowercase();
+        let model = model.to_lowercase();
+        let chars_per_token = if provider == "claude" || model.contains("claude") {
+            3.5
+        } else if model.contains("gpt") || model.contains("llama-3") || model.contains("llama-4") {
+            // tiktoken-style 100k+ vocabularies
+            4.0
+        } else if provider == "minimax" || model.contains("abab") {
+            3.0
+        } else {
+            3.5
+        };
+        Self { chars_per_token }
+    }
+
+    /// Estimated tokens in `text`; non-ASCII characters (Javanese script,
+    /// emoji, ...) are counted as a token each
+    pub fn count(&self, text: &str) -> usize {
+        let (ascii, other) = text.chars().fold((0usize, 0usize), |(a, o), c| {
+            if c.is_ascii() { (a + 1, o) } else { (a, o + 1) }
+        });
+        (ascii as f32 / self.chars_per_token).ceil() as usize + other
+    }
+
+    /// Estimated tokens for a whole request
+    pub fn count_messages(&self, messages: &[LLMMessage]) -> usize {
+        messages.iter().map(|m| {
+            let calls: usize = m.tool_calls.iter()
+                .map(|c| self.count(&c.name) + self.count(&c.arguments.to_string()))
+                .sum();
+            MESSAGE_OVERHEAD + self.count(&m.content) + calls + m.image_count() * IMAGE_TOKENS
+        }).sum()
+    }
+}
+
+/// History handed to the context manager for one turn
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    /// Rolling summary of turns no longer sent verbatim
+    pub summary: Option<String>,
+    /// Unsummarized turns, oldest first
+    pub messages: Vec<LLMMessage>,
+    /// Leading messages folded into `summary` during this turn
+    pub summarized: usize,
+}
+
+impl Conversation {
+    pub fn new(summary: Option<String>, messages: Vec<LLMMessage>) -> Self {
+        Self { summary, messages, summarized: 0 }
+    }
+}
+
+/// Builds prompts that fit the model's token budget
+pub struct ContextManager {
+    config: ContextConfig,
+}
+
+impl ContextManager {
+    pub fn new(config: ContextConfig) -> Self {
+        Self { config }
+    }
+
+    /// Prompt tokens available for `model`
+    pub fn budget(&self, model: &str) -> usize {
+        self.config.context_window.unwrap_or_else(|| context_window(model))
+            .min(self.config.max_context_tokens)
+            .saturating_sub(self.config.reserve_tokens)
+    }
+
+    /// Assemble `[system, summary, history..., user]` for the chat route,
+    /// summarizing old turns first if the request would not fit
+    pub async fn prepare(
+        &self,
+        llm: &LLMRouter,
+        system_prompt: &str,
+        conversation: &mut Conversation,
+        text: &str,
+    ) -> Vec<LLMMessage> {
+        let (provider, model) = llm.task_model(LLMTask::Chat).await.unwrap_or_default();
+        let estimator = TokenEstimator::for_model(&provider, &model);
+        let budget = self.budget(&model);
+
+        let mut messages = assemble(system_prompt, conversation, text);
+        if estimator.count_messages(&messages) > budget && self.summarize(llm, conversation).await {
+            messages = assemble(system_prompt, conversation, text);
+        }
+
+        // Recent turns alone are too long (or summarizing failed): drop the oldest
+        let start = if conversation.summary.is_some() { 2 } else { 1 };
+        while estimator.count_messages(&messages) > budget && messages.len() > start + 1 {
+            messages.remove(start);
+        }
+
+        tracing::debug!(
+            "Context for {}: ~{} of {} tokens, {} messages",
+            model, estimator.count_messages(&messages), budget, messages.len()
+        );
+        messages
+    }
+
+    /// Fold all but the `keep_recent` newest messages into the summary
+    async fn summarize(&self, llm: &LLMRouter, conversation: &mut Conversation) -> bool {
+        let fold = conversation.messages.len().saturating_sub(self.config.keep_recent);
+        if fold == 0 {
+            return false;
+        }
+
+        let mut transcript = String::new();
+        if let Some(summary) = &conversation.summary {
+            transcript.push_str(&format!("Previous summary:\n{}\n\n", summary));
+        }
+        transcript.push_str("Conversation:\n");
+        for message in &conversation.messages[..fold] {
+            let who = if message.role == "assistant" { "Assistant" } else { "User" };
+            transcript.push_str(&format!("{}: {}\n", who, message.content));
+        }
+
+        let request = vec![LLMMessage::system(SUMMARY_PROMPT), LLMMessage::user(transcript)];
+        match llm.chat_task(LLMTask::Summarization, request, Some(0.3), Some(self.config.summary_max_tokens)).await {
+            Ok(response) if !response.content.trim().is_empty() => {
+                conversation.summary = Some(response.content.trim().to_string());
+                conversation.messages.drain(..fold);
+                conversation.summarized += fold;
+                tracing::info!("Summarized {} older messages", fold);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                tracing::warn!("Failed to summarize conversation: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// System prompt first, then the summary, history and the new message
+fn assemble(system_prompt: &str, conversation: &Conversation, text: &str) -> Vec<LLMMessage> {
+    let mut messages = vec![LLMMessage::system(system_prompt)];
+    if let Some(summary) = &conversation.summary {
+        messages.push(LLMMessage::system(format!("Summary of the earlier conversation:\n{}", summary)));
+    }
+    messages.extend(conversation.messages.iter().cloned());
+    messages.push(LLMMessage::user(text));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::llm::{LLMConfig, MockProvider};
+    use std::sync::Arc;
+
+    fn router() -> LLMRouter {
+        let summarizer = MockProvider::new("mock").otherwise("User is planning a trip to Yogyakarta.");
+        let config = LLMConfig { fallback: vec!["mock".to_string()], ..LLMConfig::default() };
+        LLMRouter::new(config).with_provider("mock", Arc::new(summarizer))
+    }
+
+    fn history(turns: usize) -> Vec<LLMMessage> {
+        (0..turns).flat_map(|i| [
+            LLMMessage::user(format!("question {} {}", i, "x".repeat(300))),
+            LLMMessage::assistant(format!("answer {} {}", i, "y".repeat(300))),
+        ]).collect()
+    }
+
+    #[test]
+    fn test_token_estimates() {
+        let llama = TokenEstimator::for_model("groq", "llama-3.3-70b-versatile");
+        let claude = TokenEstimator::for_model("claude", "claude-3-haiku-20240307");
+        assert_eq!(llama.count("abcdefgh"), 2);
+        assert_eq!(claude.count("abcdefg"), 2);
+        assert_eq!(llama.count("ꦧꦱꦗꦮ"), 4);
+        assert_eq!(context_window("llama-3.3-70b-versatile"), 131_072);
+        assert_eq!(context_window("unknown-model"), 8_192);
+    }
+
+    #[tokio::test]
+    async fn test_short_history_is_kept_verbatim() {
+        let manager = ContextManager::new(ContextConfig::default());
+        let mut conversation = Conversation::new(None, history(2));
+
+        let messages = manager.prepare(&router(), "You are carik", &mut conversation, "hi").await;
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0].role, "system");
+        assert_eq!(messages[5].content, "hi");
+        assert_eq!(conversation.summarized, 0);
+    }
+
+    #[tokio::test]
+    async fn test_old_turns_are_summarized() {
+        let config = ContextConfig { max_context_tokens: 1500, reserve_tokens: 200, keep_recent: 4, ..ContextConfig::default() };
+        let manager = ContextManager::new(config);
+        let mut conversation = Conversation::new(None, history(10));
+
+        let messages = manager.prepare(&router(), "You are carik", &mut conversation, "hi").await;
+        assert_eq!(conversation.summarized, 16);
+        assert_eq!(conversation.messages.len(), 4);
+        assert_eq!(messages[0].content, "You are carik");
+        assert!(messages[1].content.contains("Yogyakarta"));
+        assert_eq!(messages.len(), 2 + 4 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_recent_turns_are_dropped() {
+        let config = ContextConfig { max_context_tokens: 300, reserve_tokens: 100, keep_recent: 4, ..ContextConfig::default() };
+        let manager = ContextManager::new(config);
+        let mut conversation = Conversation::new(None, history(2));
+
+        let messages = manager.prepare(&router(), "You are carik", &mut conversation, "hi").await;
+        let estimator = TokenEstimator::for_model("stub", "");
+        assert!(estimator.count_messages(&messages) <= 200);
+        assert_eq!(messages[0].content, "You are carik");
+        assert_eq!(messages.last().unwrap().content, "hi");
+    }
+}
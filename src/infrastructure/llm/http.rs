@@ -0,0 +1,247 @@
+//! Provider HTTP layer - Timeouts, retries and cancellation
+//!
+//! Every provider sends its requests through [`HttpClient`]: connect and
+//! request timeouts, jittered exponential backoff on 429/5xx (waiting for
+//! `Retry-After` when the server sends one) and structured errors carrying
+//! the status code. Calls made inside [`CancelToken::run`] stop as soon as
+//! the token is cancelled.
+
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::infrastructure::llm::config::HttpConfig;
+use crate::infrastructure::llm::{LLMError, LLMResult};
+
+tokio::task_local! {
+    static CANCEL: CancelToken;
+}
+
+/// Cancels the LLM calls of the tasks it is scoped to.
+///
+/// Cancelling is idempotent: calling [`Self::cancel`] again, or after the
+/// calls finished, does nothing.
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self { sender: Arc::new(watch::Sender::new(false)) }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the token is cancelled
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Run `future` with its LLM calls tied to this token
+    pub async fn run<F: Future>(self, future: F) -> F::Output {
+        CANCEL.scope(self, future).await
+    }
+
+    /// Token of the running task, if any
+    pub fn current() -> Option<Self> {
+        CANCEL.try_with(|token| token.clone()).ok()
+    }
+}
+
+/// Await `future` unless `token` is cancelled first
+async fn or_cancelled<F: Future>(token: Option<&CancelToken>, future: F) -> LLMResult<F::Output> {
+    match token {
+        Some(token) => tokio::select! {
+            output = future => Ok(output),
+            _ = token.cancelled() => Err(LLMError::Cancelled),
+        },
+        None => Ok(future.await),
+    }
+}
+
+/// `Retry-After` as delay-seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Error for a non-success response, reading its body
+pub async fn error_from_response(response: Response) -> LLMError {
+    let status = response.status();
+    let retry_after = response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after);
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return LLMError::RateLimited { retry_after };
+    }
+    let body = response.text().await.unwrap_or_default();
+    LLMError::ApiError { status: status.as_u16(), message: body }
+}
+
+fn request_error(error: reqwest::Error) -> LLMError {
+    if error.is_timeout() {
+        LLMError::Timeout
+    } else {
+        LLMError::NetworkError(error.to_string())
+    }
+}
+
+/// HTTP client shared by the providers
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    config: HttpConfig,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(&HttpConfig::default())
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Self {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to build HTTP client, using defaults: {}", e);
+                Client::new()
+            });
+        Self { client, config: config.clone() }
+    }
+
+    pub fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Delay before retry number `attempt` (0-based): exponential with
+    /// jitter, or the server's `Retry-After`
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(wait) = retry_after {
+            return wait;
+        }
+        let base = self.config.initial_backoff_ms.saturating_mul(1 << attempt.min(16));
+        let capped = base.min(self.config.max_backoff_secs * 1000);
+        // Random point in the upper half so concurrent retries spread out
+        let jitter = RandomState::new().build_hasher().finish() % (capped / 2 + 1);
+        Duration::from_millis(capped / 2 + jitter)
+    }
+
+    /// Send `request`, retrying retryable failures; returns the successful response
+    pub async fn send(&self, request: RequestBuilder) -> LLMResult<Response> {
+        let token = CancelToken::current();
+        let mut attempt = 0;
+        loop {
+            if token.as_ref().is_some_and(|t| t.is_cancelled()) {
+                return Err(LLMError::Cancelled);
+            }
+            let attempt_request = request.try_clone()
+                .ok_or_else(|| LLMError::InvalidRequest("request body can't be retried".to_string()))?;
+
+            let error = match or_cancelled(token.as_ref(), attempt_request.send()).await? {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => error_from_response(response).await,
+                Err(e) => request_error(e),
+            };
+
+            if !error.is_retryable() || attempt >= self.config.max_retries {
+                return Err(error);
+            }
+            let retry_after = match &error {
+                LLMError::RateLimited { retry_after } => *retry_after,
+                _ => None,
+            };
+            let delay = self.backoff(attempt, retry_after);
+            // Let the router try another provider rather than wait that long
+            if delay > Duration::from_secs(self.config.max_backoff_secs) {
+                return Err(error);
+            }
+
+            tracing::debug!("Retrying LLM request in {:?} after: {}", delay, error);
+            or_cancelled(token.as_ref(), tokio::time::sleep(delay)).await?;
+            attempt += 1;
+        }
+    }
+
+    /// Send `request` and parse the JSON response
+    pub async fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> LLMResult<T> {
+        let token = CancelToken::current();
+        let response = self.send(request).await?;
+        or_cancelled(token.as_ref(), response.json::<T>())
+            .await?
+            .map_err(|e| if e.is_timeout() { LLMError::Timeout } else { LLMError::ParseError(e.to_string()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let wait = parse_retry_after(&later).unwrap();
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let config = HttpConfig { initial_backoff_ms: 400, max_backoff_secs: 2, ..HttpConfig::default() };
+        let client = HttpClient::new(&config);
+        for _ in 0..20 {
+            let first = client.backoff(0, None);
+            assert!(first >= Duration::from_millis(200) && first <= Duration::from_millis(400));
+            let third = client.backoff(2, None);
+            assert!(third >= Duration::from_millis(800) && third <= Duration::from_millis(1600));
+            assert!(client.backoff(10, None) <= Duration::from_secs(2));
+        }
+        assert_eq!(client.backoff(0, Some(Duration::from_secs(3))), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_is_idempotent() {
+        let token = CancelToken::new();
+        let scoped = token.clone().run(async {
+            or_cancelled(CancelToken::current().as_ref(), std::future::pending::<()>()).await
+        });
+        token.cancel();
+        token.cancel();
+        assert!(matches!(scoped.await, Err(LLMError::Cancelled)));
+        assert!(token.is_cancelled());
+    }
+}
@@ -3,9 +3,21 @@
 pub mod traits;
 pub mod config;
 pub mod providers;
+pub mod router;
+pub mod context;
+pub mod usage;
+pub mod http;
+pub mod structured;
+pub mod cache;
 #[cfg(test)]
 pub mod tests;
 
-pub use traits::{LLM, LLMMessage, LLMResponse, LLMError, LLMResult, LLMUsage};
-pub use config::{LLMConfig, LLMProvider};
-pub use providers::{MiniMaxProvider, ClaudeProvider, GroqProvider};
+pub use traits::{LLM, LLMMessage, ContentPart, ImageSource, LLMResponse, LLMError, LLMResult, LLMUsage, EmbeddingResponse, ResponseSchema, ToolCall, ToolDefinition};
+pub use config::{LLMConfig, LLMProvider, LLMRoute};
+#[cfg(test)]
+pub use providers::MockProvider;
+pub use router::{Guardrail, LLMRouter, LLMTask, ModelSelector};
+pub use context::{ContextManager, Conversation};
+pub use usage::{UsageConfig, UsageEvent, UsageScope, UsageSink};
+pub use http::CancelToken;
+pub use cache::ResponseCache;
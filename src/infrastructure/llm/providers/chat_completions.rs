@@ -0,0 +1,358 @@
+//! Wire types for the OpenAI `/chat/completions` dialect
+//!
+//! Shared by Groq, MiniMax and OpenAI-compatible endpoints, which all use the
+//! same message, tool and tool-call shapes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::infrastructure::llm::{ContentPart, LLMMessage, LLMResponse, LLMUsage, ToolCall, ToolDefinition};
+
+/// Chat completion request
+#[derive(Serialize)]
+pub(super) struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<WireMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<WireTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    pub stream: bool,
+}
+
+/// `response_format` of a JSON-mode request
+#[derive(Serialize)]
+pub(super) struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+impl ChatRequest {
+    pub fn new(
+        model: &str,
+        messages: &[LLMMessage],
+        tools: &[ToolDefinition],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Self {
+        Self {
+            model: model.to_string(),
+            messages: messages.iter().map(WireMessage::from).collect(),
+            temperature,
+            max_tokens,
+            tools: tools.iter().map(WireTool::from).collect(),
+            tool_choice: if tools.is_empty() { None } else { Some("auto") },
+            response_format: None,
+            stream: false,
+        }
+    }
+
+    /// Ask for a JSON object reply. Only `json_object` is used since
+    /// `json_schema` support varies between models and servers; the schema
+    /// itself goes in the prompt.
+    pub fn json_object(mut self) -> Self {
+        self.response_format = Some(ResponseFormat { kind: "json_object" });
+        self
+    }
+}
+
+/// Message as sent on the wire
+#[derive(Serialize)]
+pub(super) struct WireMessage {
+    role: String,
+    /// `null` is allowed for assistant turns that only call tools
+    content: Option<WireContent>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<WireToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl From<&LLMMessage> for WireMessage {
+    fn from(msg: &LLMMessage) -> Self {
+        let content = if !msg.parts.is_empty() {
+            Some(WireContent::Parts(msg.parts.iter().map(WirePart::from).collect()))
+        } else if msg.content.is_empty() && !msg.tool_calls.is_empty() {
+            None
+        } else {
+            Some(WireContent::Text(msg.content.clone()))
+        };
+        Self {
+            role: msg.role.clone(),
+            content,
+            tool_calls: msg.tool_calls.iter().map(WireToolCall::from).collect(),
+            tool_call_id: msg.tool_call_id.clone(),
+        }
+    }
+}
+
+/// Plain text, or text and image parts for vision models
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(super) enum WireContent {
+    Text(String),
+    Parts(Vec<WirePart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum WirePart {
+    Text { text: String },
+    ImageUrl { image_url: WireImageUrl },
+}
+
+#[derive(Serialize)]
+pub(super) struct WireImageUrl {
+    /// `https:` or `data:` URL
+    url: String,
+}
+
+impl From<&ContentPart> for WirePart {
+    fn from(part: &ContentPart) -> Self {
+        match part {
+            ContentPart::Text { text } => Self::Text { text: text.clone() },
+            ContentPart::Image { source } => Self::ImageUrl { image_url: WireImageUrl { url: source.to_url() } },
+        }
+    }
+}
+
+/// Tool offered to the model
+#[derive(Serialize)]
+pub(super) struct WireTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: WireFunction,
+}
+
+#[derive(Serialize)]
+struct WireFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+impl From<&ToolDefinition> for WireTool {
+    fn from(tool: &ToolDefinition) -> Self {
+        Self {
+            kind: "function",
+            function: WireFunction {
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                parameters: tool.parameters.clone(),
+            },
+        }
+    }
+}
+
+/// Tool call; `arguments` is a JSON document encoded as a string
+#[derive(Serialize, Deserialize, Debug)]
+pub(super) struct WireToolCall {
+    #[serde(default)]
+    id: String,
+    #[serde(rename = "type", default = "function_kind")]
+    kind: String,
+    function: WireFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WireFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+fn function_kind() -> String {
+    "function".to_string()
+}
+
+impl From<&ToolCall> for WireToolCall {
+    fn from(call: &ToolCall) -> Self {
+        Self {
+            id: call.id.clone(),
+            kind: function_kind(),
+            function: WireFunctionCall {
+                name: call.name.clone(),
+                arguments: call.arguments.to_string(),
+            },
+        }
+    }
+}
+
+impl From<WireToolCall> for ToolCall {
+    fn from(call: WireToolCall) -> Self {
+        // Models occasionally emit invalid JSON; keep the raw text so the
+        // command can still see it rather than dropping the call
+        let arguments = match call.function.arguments.trim() {
+            "" => serde_json::json!({}),
+            raw => serde_json::from_str(raw).unwrap_or(serde_json::Value::String(raw.to_string())),
+        };
+        Self {
+            id: call.id,
+            name: call.function.name,
+            arguments,
+        }
+    }
+}
+
+/// Chat completion response
+#[derive(Deserialize, Debug)]
+pub(super) struct ChatResponse {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: ResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<WireToolCall>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+    total_tokens: Option<u32>,
+}
+
+impl ChatResponse {
+    /// Convert the first choice into an [`LLMResponse`]
+    pub fn into_response(self, requested_model: &str) -> Option<LLMResponse> {
+        let choice = self.choices.into_iter().next()?;
+        let usage = self.usage.map(|u| LLMUsage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+        });
+
+        Some(LLMResponse {
+            content: choice.message.content.unwrap_or_default(),
+            model: self.model.unwrap_or_else(|| requested_model.to_string()),
+            usage,
+            finish_reason: choice.finish_reason,
+            tool_calls: choice.message.tool_calls
+                .unwrap_or_default()
+                .into_iter()
+                .map(ToolCall::from)
+                .collect(),
+        })
+    }
+}
+
+/// `GET /models` response; Anthropic's `/v1/models` has the same shape
+#[derive(Deserialize, Debug)]
+pub(super) struct ModelList {
+    #[serde(default)]
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelEntry {
+    id: String,
+}
+
+impl ModelList {
+    /// Model ids, sorted
+    pub fn into_ids(self) -> Vec<String> {
+        let mut ids: Vec<String> = self.data.into_iter().map(|m| m.id).collect();
+        ids.sort();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_with_tools_and_results() {
+        let tool = ToolDefinition {
+            name: "rss".to_string(),
+            description: "Fetch news".to_string(),
+            parameters: json!({"type": "object", "properties": {}}),
+        };
+        let call = ToolCall { id: "call_1".to_string(), name: "rss".to_string(), arguments: json!({"feed": "hn"}) };
+        let messages = vec![
+            LLMMessage::user("any news?"),
+            LLMMessage::assistant_tool_calls("", vec![call]),
+            LLMMessage::tool_result("call_1", "1. Rust 2.0 released"),
+        ];
+
+        let body = serde_json::to_value(ChatRequest::new("m", &messages, &[tool], None, None)).unwrap();
+        assert_eq!(body["tool_choice"], "auto");
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "rss");
+        assert!(body["messages"][1]["content"].is_null());
+        assert_eq!(body["messages"][1]["tool_calls"][0]["function"]["arguments"], "{\"feed\":\"hn\"}");
+        assert_eq!(body["messages"][2]["role"], "tool");
+        assert_eq!(body["messages"][2]["tool_call_id"], "call_1");
+
+        let plain = serde_json::to_value(ChatRequest::new("m", &messages[..1], &[], None, None)).unwrap();
+        assert!(plain.get("tools").is_none());
+        assert!(plain.get("response_format").is_none());
+
+        let json = serde_json::to_value(ChatRequest::new("m", &messages[..1], &[], None, None).json_object()).unwrap();
+        assert_eq!(json["response_format"]["type"], "json_object");
+        assert!(plain["messages"][0].get("tool_calls").is_none());
+    }
+
+    #[test]
+    fn test_request_with_image() {
+        let image = crate::infrastructure::llm::ImageSource::Bytes { media_type: "image/png".to_string(), data: b"png".to_vec() };
+        let messages = vec![LLMMessage::user_with_images("what's this?", vec![image])];
+        let body = serde_json::to_value(ChatRequest::new("m", &messages, &[], None, None)).unwrap();
+        assert_eq!(body["messages"][0]["content"], json!([
+            {"type": "text", "text": "what's this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,cG5n"}},
+        ]));
+    }
+
+    #[test]
+    fn test_response_with_tool_calls() {
+        let raw = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_9",
+                        "type": "function",
+                        "function": {"name": "finance", "arguments": "{\"category\":\"crypto\"}"}
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        });
+        let response: ChatResponse = serde_json::from_value(raw).unwrap();
+        let response = response.into_response("m").unwrap();
+
+        assert!(response.has_tool_calls());
+        assert_eq!(response.content, "");
+        assert_eq!(response.model, "m");
+        assert_eq!(response.tool_calls[0].id, "call_9");
+        assert_eq!(response.tool_calls[0].arguments, json!({"category": "crypto"}));
+    }
+
+    #[test]
+    fn test_model_list() {
+        let raw = json!({"object": "list", "data": [{"id": "qwen-2.5-32b", "owned_by": "Alibaba"}, {"id": "llama-3.1-8b-instant"}]});
+        let models: ModelList = serde_json::from_value(raw).unwrap();
+        assert_eq!(models.into_ids(), vec!["llama-3.1-8b-instant", "qwen-2.5-32b"]);
+    }
+}
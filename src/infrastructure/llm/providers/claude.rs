@@ -1,10 +1,13 @@
 //! Anthropic Claude Provider
 
 use async_trait::async_trait;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::infrastructure::llm::{LLMMessage, LLMResponse, LLMError, LLMResult, LLM, LLMUsage};
+use super::chat_completions::ModelList;
+use crate::infrastructure::llm::config::HttpConfig;
+use crate::infrastructure::llm::http::HttpClient;
+use crate::infrastructure::llm::traits::base64_encode;
+use crate::infrastructure::llm::{ContentPart, ImageSource, LLMMessage, LLMResponse, LLMError, LLMResult, LLM, LLMUsage, ResponseSchema, ToolCall, ToolDefinition};
 
 /// Claude API endpoint
 const API_BASE: &str = "https://api.anthropic.com/v1";
@@ -12,7 +15,7 @@ const API_BASE: &str = "https://api.anthropic.com/v1";
 /// Claude provider
 pub struct ClaudeProvider {
     api_key: String,
-    client: Client,
+    http: HttpClient,
     model: String,
 }
 
@@ -20,73 +23,231 @@ impl ClaudeProvider {
     pub fn new(api_key: impl Into<String>, model: Option<&str>) -> Self {
         Self {
             api_key: api_key.into(),
-            client: Client::new(),
+            http: HttpClient::default(),
             model: model.unwrap_or("claude-3-haiku-20240307").to_string(),
         }
     }
     
+    /// Use the given timeouts and retry policy
+    pub fn with_http(mut self, config: &HttpConfig) -> Self {
+        self.http = HttpClient::new(config);
+        self
+    }
+    
     /// Get base URL for API
     fn base_url(&self) -> String {
         format!("{}/messages", API_BASE)
     }
+    
+    /// Send a message request, offering `tools` when non-empty
+    async fn complete(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let model = model.unwrap_or(&self.model);
+        
+        // Convert messages to Claude format
+        let (system, claude_messages) = to_claude_messages(&messages);
+        
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: claude_messages,
+            system,
+            temperature,
+            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            tools: tools.iter().map(ClaudeTool::from).collect(),
+            tool_choice: None,
+        };
+        self.send(request).await
+    }
+
+    /// POST a message request
+    async fn send(&self, request: ChatRequest) -> LLMResult<LLMResponse> {
+        let builder = self.http
+            .post(self.base_url())
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(&request);
+        let chat_response: ChatResponse = self.http.send_json(builder).await?;
+        
+        Ok(chat_response.into_response(&request.model))
+    }
 }
 
+/// Claude requires `max_tokens`; used when the caller passes none
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
 /// API request structure
 #[derive(Serialize)]
 struct ChatRequest {
     model: String,
     messages: Vec<ClaudeMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
-    max_tokens: Option<u32>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ClaudeTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
 }
 
 /// Claude message format
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 struct ClaudeMessage {
     role: String,
-    content: String,
+    content: Vec<ContentBlock>,
 }
 
-impl From<&LLMMessage> for ClaudeMessage {
-    fn from(msg: &LLMMessage) -> Self {
+/// Tool offered to Claude
+#[derive(Serialize)]
+struct ClaudeTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+impl From<&ToolDefinition> for ClaudeTool {
+    fn from(tool: &ToolDefinition) -> Self {
         Self {
-            role: msg.role.clone(),
-            content: msg.content.clone(),
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            input_schema: tool.parameters.clone(),
         }
     }
 }
 
+/// Split out system prompts and convert the rest to Claude's block format.
+///
+/// Tool results become `tool_result` blocks in a user turn; consecutive
+/// results are merged since Claude expects them in a single message.
+fn to_claude_messages(messages: &[LLMMessage]) -> (Option<String>, Vec<ClaudeMessage>) {
+    let mut system = Vec::new();
+    let mut out: Vec<ClaudeMessage> = Vec::new();
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "system" => system.push(msg.content.clone()),
+            "tool" => {
+                let block = ContentBlock::ToolResult {
+                    tool_use_id: msg.tool_call_id.clone().unwrap_or_default(),
+                    content: msg.content.clone(),
+                };
+                match out.last_mut() {
+                    Some(last) if last.role == "user"
+                        && last.content.iter().all(|b| matches!(b, ContentBlock::ToolResult { .. })) =>
+                    {
+                        last.content.push(block);
+                    }
+                    _ => out.push(ClaudeMessage { role: "user".to_string(), content: vec![block] }),
+                }
+            }
+            role => {
+                let mut content = Vec::new();
+                if !msg.parts.is_empty() {
+                    content.extend(msg.parts.iter().map(ContentBlock::from));
+                } else if !msg.content.is_empty() {
+                    content.push(ContentBlock::Text { text: msg.content.clone() });
+                }
+                content.extend(msg.tool_calls.iter().map(|call| ContentBlock::ToolUse {
+                    id: call.id.clone(),
+                    name: call.name.clone(),
+                    input: call.arguments.clone(),
+                }));
+                out.push(ClaudeMessage { role: role.to_string(), content });
+            }
+        }
+    }
+
+    let system = if system.is_empty() { None } else { Some(system.join("\n\n")) };
+    (system, out)
+}
+
 /// API response structure
 #[derive(Deserialize, Debug)]
 struct ChatResponse {
-    id: String,
-    #[serde(rename = "type")]
-    response_type: String,
-    role: String,
+    #[serde(default)]
+    model: Option<String>,
     content: Vec<ContentBlock>,
-    #[serde(rename = "stop_reason")]
     stop_reason: Option<String>,
-    #[serde(rename = "stop_sequence")]
-    stop_sequence: Option<String>,
     usage: Option<Usage>,
 }
 
 /// Content block
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
 enum ContentBlock {
-    #[serde(rename = "text")]
     Text { text: String },
+    ToolUse { id: String, name: String, input: serde_json::Value },
+    ToolResult { tool_use_id: String, content: String },
+    Image { source: ClaudeImage },
+    #[serde(other)]
+    Unknown,
+}
+
+/// Image block source
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeImage {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+impl From<&ContentPart> for ContentBlock {
+    fn from(part: &ContentPart) -> Self {
+        match part {
+            ContentPart::Text { text } => Self::Text { text: text.clone() },
+            ContentPart::Image { source: ImageSource::Bytes { media_type, data } } => Self::Image {
+                source: ClaudeImage::Base64 { media_type: media_type.clone(), data: base64_encode(data) },
+            },
+            ContentPart::Image { source: ImageSource::Url(url) } => Self::Image {
+                source: ClaudeImage::Url { url: url.clone() },
+            },
+        }
+    }
 }
 
 /// Usage information
 #[derive(Deserialize, Debug)]
 struct Usage {
-    #[serde(rename = "input_tokens")]
     input_tokens: u32,
-    #[serde(rename = "output_tokens")]
     output_tokens: u32,
 }
 
+impl ChatResponse {
+    fn into_response(self, requested_model: &str) -> LLMResponse {
+        let mut text = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in self.content {
+            match block {
+                ContentBlock::Text { text: t } => text.push(t),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall { id, name, arguments: input }),
+                _ => {}
+            }
+        }
+
+        let usage = self.usage.map(|u| LLMUsage {
+            prompt_tokens: Some(u.input_tokens),
+            completion_tokens: Some(u.output_tokens),
+            total_tokens: Some(u.input_tokens + u.output_tokens),
+        });
+
+        LLMResponse {
+            content: text.join("\n"),
+            model: self.model.unwrap_or_else(|| requested_model.to_string()),
+            usage,
+            finish_reason: self.stop_reason,
+            tool_calls,
+        }
+    }
+}
+
 #[async_trait]
 impl LLM for ClaudeProvider {
     fn name(&self) -> &str {
@@ -100,68 +261,61 @@ impl LLM for ClaudeProvider {
         temperature: Option<f32>,
         max_tokens: Option<u32>,
     ) -> LLMResult<LLMResponse> {
-        let model = model.unwrap_or(&self.model);
-        
-        // Convert messages to Claude format
-        let claude_messages: Vec<ClaudeMessage> = messages.iter().map(ClaudeMessage::from).collect();
-        
+        self.complete(messages, &[], model, temperature, max_tokens).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.complete(messages, tools, model, temperature, max_tokens).await
+    }
+    
+    /// Claude has no JSON mode; forcing a call to a tool whose input schema
+    /// is `schema` gets the same result, returned as the reply text
+    async fn chat_json(
+        &self,
+        messages: Vec<LLMMessage>,
+        schema: &ResponseSchema,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let (system, claude_messages) = to_claude_messages(&messages);
+        let tool = ClaudeTool {
+            name: schema.name.clone(),
+            description: "Reply with structured data".to_string(),
+            input_schema: schema.schema.clone(),
+        };
         let request = ChatRequest {
-            model: model.to_string(),
+            model: model.unwrap_or(&self.model).to_string(),
             messages: claude_messages,
+            system,
             temperature,
-            max_tokens,
+            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            tools: vec![tool],
+            tool_choice: Some(serde_json::json!({"type": "tool", "name": schema.name})),
         };
-        
-        let response = self.client
-            .post(self.base_url())
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| LLMError::NetworkError(e.to_string()))?;
-        
-        if response.status() == 429 {
-            return Err(LLMError::RateLimited);
-        }
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(LLMError::ApiError(format!("status: {}, body: {}", status, body)));
+
+        let mut response = self.send(request).await?;
+        if let Some(call) = response.tool_calls.iter().find(|c| c.name == schema.name) {
+            response.content = call.arguments.to_string();
         }
-        
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| LLMError::ParseError(e.to_string()))?;
-        
-        // Extract text content
-        let content = chat_response.content
-            .into_iter()
-            .filter_map(|block| {
-                if let ContentBlock::Text { text } = block {
-                    Some(text)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
-        
-        let usage = chat_response.usage.map(|u| LLMUsage {
-            prompt_tokens: Some(u.input_tokens),
-            completion_tokens: Some(u.output_tokens),
-            total_tokens: Some(u.input_tokens + u.output_tokens),
-        });
-        
-        Ok(LLMResponse {
-            content,
-            model: model.to_string(),
-            usage,
-            finish_reason: chat_response.stop_reason,
-        })
+        response.tool_calls.clear();
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> LLMResult<Vec<String>> {
+        let builder = self.http
+            .get(format!("{}/models?limit=1000", API_BASE))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01");
+        let models: ModelList = self.http.send_json(builder).await?;
+        Ok(models.into_ids())
     }
     
     async fn chat_streaming(
@@ -175,3 +329,70 @@ impl LLM for ClaudeProvider {
         Err(LLMError::InvalidRequest("Streaming not yet implemented".to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_system_and_tool_blocks() {
+        let call = ToolCall { id: "toolu_1".to_string(), name: "rss".to_string(), arguments: json!({"feed": "hn"}) };
+        let messages = vec![
+            LLMMessage::system("You are carik"),
+            LLMMessage::user("news?"),
+            LLMMessage::assistant_tool_calls("Let me check.", vec![call]),
+            LLMMessage::tool_result("toolu_1", "1. headline"),
+            LLMMessage::tool_result("toolu_2", "2. headline"),
+        ];
+
+        let (system, converted) = to_claude_messages(&messages);
+        assert_eq!(system.as_deref(), Some("You are carik"));
+        assert_eq!(converted.len(), 3);
+
+        let body = serde_json::to_value(&converted).unwrap();
+        assert_eq!(body[1]["content"][0]["type"], "text");
+        assert_eq!(body[1]["content"][1]["type"], "tool_use");
+        assert_eq!(body[1]["content"][1]["input"]["feed"], "hn");
+        assert_eq!(body[2]["role"], "user");
+        assert_eq!(body[2]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(body[2]["content"][1]["tool_use_id"], "toolu_2");
+    }
+
+    #[test]
+    fn test_image_blocks() {
+        let images = vec![
+            ImageSource::from_bytes(b"\x89PNG".to_vec()),
+            ImageSource::Url("https://example.com/cat.jpg".to_string()),
+        ];
+        let (_, converted) = to_claude_messages(&[LLMMessage::user_with_images("what's this?", images)]);
+        let body = serde_json::to_value(&converted).unwrap();
+        assert_eq!(body[0]["content"], json!([
+            {"type": "text", "text": "what's this?"},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw=="}},
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/cat.jpg"}},
+        ]));
+    }
+
+    #[test]
+    fn test_response_tool_use() {
+        let raw = json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Checking prices"},
+                {"type": "tool_use", "id": "toolu_9", "name": "finance", "input": {"category": "crypto"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        });
+        let response: ChatResponse = serde_json::from_value(raw).unwrap();
+        let response = response.into_response("claude-3-haiku-20240307");
+
+        assert_eq!(response.content, "Checking prices");
+        assert_eq!(response.tool_calls[0].name, "finance");
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.usage.unwrap().total_tokens, Some(15));
+    }
+}
@@ -1,10 +1,11 @@
 //! MiniMax AI Provider
 
 use async_trait::async_trait;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
 
-use crate::infrastructure::llm::{LLMMessage, LLMResponse, LLMError, LLMResult, LLM, LLMUsage};
+use super::chat_completions::{ChatRequest, ChatResponse};
+use crate::infrastructure::llm::config::HttpConfig;
+use crate::infrastructure::llm::http::HttpClient;
+use crate::infrastructure::llm::{LLMMessage, LLMResponse, LLMError, LLMResult, LLM, ToolDefinition};
 
 /// MiniMax API endpoint
 const API_BASE: &str = "https://api.minimax.chat/v1";
@@ -12,7 +13,7 @@ const API_BASE: &str = "https://api.minimax.chat/v1";
 /// MiniMax provider
 pub struct MiniMaxProvider {
     api_key: String,
-    client: Client,
+    http: HttpClient,
     model: String,
 }
 
@@ -20,58 +21,48 @@ impl MiniMaxProvider {
     pub fn new(api_key: impl Into<String>, model: Option<&str>) -> Self {
         Self {
             api_key: api_key.into(),
-            client: Client::new(),
+            http: HttpClient::default(),
             model: model.unwrap_or("abab6.5s-chat").to_string(),
         }
     }
     
+    /// Use the given timeouts and retry policy
+    pub fn with_http(mut self, config: &HttpConfig) -> Self {
+        self.http = HttpClient::new(config);
+        self
+    }
+    
+    /// Send a chat completion, offering `tools` when non-empty
+    async fn complete(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let model = model.unwrap_or(&self.model);
+        
+        let request = ChatRequest::new(model, &messages, tools, temperature, max_tokens);
+        
+        let builder = self.http
+            .post(self.base_url())
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request);
+        let chat_response: ChatResponse = self.http.send_json(builder).await?;
+        
+        chat_response
+            .into_response(model)
+            .ok_or_else(|| LLMError::InvalidRequest("No choices in response".to_string()))
+    }
+    
     /// Get base URL for API
     fn base_url(&self) -> String {
         format!("{}/text/chatcompletion_v2", API_BASE)
     }
 }
 
-/// API request structure
-#[derive(Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<LLMMessage>,
-    temperature: Option<f32>,
-    max_tokens: Option<u32>,
-    stream: bool,
-}
-
-/// API response structure
-#[derive(Deserialize, Debug)]
-struct ChatResponse {
-    id: String,
-    choices: Vec<Choice>,
-    usage: Option<Usage>,
-}
-
-/// Choice in response
-#[derive(Deserialize, Debug)]
-struct Choice {
-    index: usize,
-    message: ResponseMessage,
-    finish_reason: Option<String>,
-}
-
-/// Response message
-#[derive(Deserialize, Debug)]
-struct ResponseMessage {
-    role: String,
-    content: String,
-}
-
-/// Usage information
-#[derive(Deserialize, Debug)]
-struct Usage {
-    prompt_tokens: Option<u32>,
-    completion_tokens: Option<u32>,
-    total_tokens: Option<u32>,
-}
-
 #[async_trait]
 impl LLM for MiniMaxProvider {
     fn name(&self) -> &str {
@@ -85,57 +76,18 @@ impl LLM for MiniMaxProvider {
         temperature: Option<f32>,
         max_tokens: Option<u32>,
     ) -> LLMResult<LLMResponse> {
-        let model = model.unwrap_or(&self.model);
-        
-        let request = ChatRequest {
-            model: model.to_string(),
-            messages,
-            temperature,
-            max_tokens,
-            stream: false,
-        };
-        
-        let response = self.client
-            .post(self.base_url())
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| LLMError::NetworkError(e.to_string()))?;
-        
-        if response.status() == 429 {
-            return Err(LLMError::RateLimited);
-        }
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(LLMError::ApiError(format!("status: {}, body: {}", status, body)));
-        }
-        
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| LLMError::ParseError(e.to_string()))?;
-        
-        let choice = chat_response.choices
-            .into_iter()
-            .next()
-            .ok_or_else(|| LLMError::InvalidRequest("No choices in response".to_string()))?;
-        
-        let usage = chat_response.usage.map(|u| LLMUsage {
-            prompt_tokens: u.prompt_tokens,
-            completion_tokens: u.completion_tokens,
-            total_tokens: u.total_tokens,
-        });
-        
-        Ok(LLMResponse {
-            content: choice.message.content,
-            model: model.to_string(),
-            usage,
-            finish_reason: choice.finish_reason,
-        })
+        self.complete(messages, &[], model, temperature, max_tokens).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.complete(messages, tools, model, temperature, max_tokens).await
     }
     
     async fn chat_streaming(
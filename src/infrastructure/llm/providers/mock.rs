@@ -0,0 +1,343 @@
+//! Mock Provider - Scripted replies for offline tests
+//!
+//! Replies are picked from one-shot replies queued with [`MockProvider::then`],
+//! then from rules matching the last user message, then the fallback. Every
+//! request is recorded for assertions.
+
+use async_trait::async_trait;
+use regex_lite::Regex;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::infrastructure::llm::{LLMMessage, LLMResponse, LLMError, LLMResult, LLMUsage, LLM, ResponseSchema, ToolCall, ToolDefinition};
+
+/// What the mock answers with
+#[derive(Clone)]
+pub enum MockReply {
+    Text(String),
+    /// Ask for tool calls instead of answering
+    ToolCalls(Vec<ToolCall>),
+    /// Fail, e.g. with `|| LLMError::RateLimited { retry_after: None }`
+    Error(fn() -> LLMError),
+}
+
+impl From<&str> for MockReply {
+    fn from(text: &str) -> Self {
+        MockReply::Text(text.to_string())
+    }
+}
+
+impl From<String> for MockReply {
+    fn from(text: String) -> Self {
+        MockReply::Text(text)
+    }
+}
+
+impl From<fn() -> LLMError> for MockReply {
+    fn from(error: fn() -> LLMError) -> Self {
+        MockReply::Error(error)
+    }
+}
+
+/// How a rule matches the last user message
+enum Matcher {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Matcher::Contains(needle) => text.to_lowercase().contains(needle),
+            Matcher::Regex(re) => re.is_match(text),
+        }
+    }
+}
+
+/// A request the mock received
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub messages: Vec<LLMMessage>,
+    pub model: Option<String>,
+    /// Names of the tools offered
+    pub tools: Vec<String>,
+    /// Whether JSON mode was asked for
+    pub json: bool,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl MockRequest {
+    /// Text of the last user message
+    pub fn last_user(&self) -> &str {
+        last_user(&self.messages)
+    }
+
+    /// Text of the system message, if any
+    pub fn system(&self) -> Option<&str> {
+        self.messages.iter().find(|m| m.role == "system").map(|m| m.content.as_str())
+    }
+}
+
+fn last_user(messages: &[LLMMessage]) -> &str {
+    messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.as_str()).unwrap_or("")
+}
+
+/// Provider answering from a script instead of an API
+pub struct MockProvider {
+    name: String,
+    model: String,
+    models: Vec<String>,
+    queue: Mutex<VecDeque<MockReply>>,
+    rules: Vec<(Matcher, MockReply)>,
+    fallback: Option<MockReply>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+impl MockProvider {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: "mock".to_string(),
+            models: Vec::new(),
+            queue: Mutex::new(VecDeque::new()),
+            rules: Vec::new(),
+            fallback: None,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answer with `reply` when the last user message contains `needle` (case-insensitive)
+    pub fn on(mut self, needle: &str, reply: impl Into<MockReply>) -> Self {
+        self.rules.push((Matcher::Contains(needle.to_lowercase()), reply.into()));
+        self
+    }
+
+    /// Answer with `reply` when the last user message matches `pattern`
+    ///
+    /// Panics on an invalid pattern, since scripts are written by tests.
+    pub fn on_regex(mut self, pattern: &str, reply: impl Into<MockReply>) -> Self {
+        let re = Regex::new(pattern).expect("valid mock pattern");
+        self.rules.push((Matcher::Regex(re), reply.into()));
+        self
+    }
+
+    /// Answer the next request with `reply`, before any rule; queued replies are used in order
+    pub fn then(self, reply: impl Into<MockReply>) -> Self {
+        self.queue.lock().unwrap().push_back(reply.into());
+        self
+    }
+
+    /// Answer with `reply` when nothing else matches; without one such requests fail
+    pub fn otherwise(mut self, reply: impl Into<MockReply>) -> Self {
+        self.fallback = Some(reply.into());
+        self
+    }
+
+    /// Model reported when the request names none
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Models returned by `list_models`
+    pub fn with_models(mut self, models: &[&str]) -> Self {
+        self.models = models.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    /// Every request received so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Number of requests received
+    pub fn calls(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    /// Most recent request
+    pub fn last_request(&self) -> Option<MockRequest> {
+        self.requests.lock().unwrap().last().cloned()
+    }
+
+    /// Record the request and pick its reply
+    fn respond(&self, request: MockRequest) -> LLMResult<LLMResponse> {
+        let text = request.last_user().to_string();
+        let model = request.model.clone().unwrap_or_else(|| self.model.clone());
+        self.requests.lock().unwrap().push(request);
+
+        let queued = self.queue.lock().unwrap().pop_front();
+        let reply = queued
+            .or_else(|| self.rules.iter().find(|(m, _)| m.matches(&text)).map(|(_, r)| r.clone()))
+            .or_else(|| self.fallback.clone())
+            .ok_or_else(|| LLMError::InvalidRequest(format!("{}: no scripted reply for {:?}", self.name, text)))?;
+
+        let (content, tool_calls) = match reply {
+            MockReply::Text(content) => (content, Vec::new()),
+            MockReply::ToolCalls(calls) => (String::new(), calls),
+            MockReply::Error(error) => return Err(error()),
+        };
+        // Whitespace word counts stand in for tokens
+        let prompt_tokens = text.split_whitespace().count() as u32;
+        let completion_tokens = content.split_whitespace().count() as u32;
+        Ok(LLMResponse {
+            content,
+            model,
+            usage: Some(LLMUsage {
+                prompt_tokens: Some(prompt_tokens),
+                completion_tokens: Some(completion_tokens),
+                total_tokens: Some(prompt_tokens + completion_tokens),
+            }),
+            finish_reason: Some(if tool_calls.is_empty() { "stop" } else { "tool_calls" }.to_string()),
+            tool_calls,
+        })
+    }
+}
+
+#[async_trait]
+impl LLM for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<LLMMessage>,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.chat_with_tools(messages, &[], model, temperature, max_tokens).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.respond(MockRequest {
+            messages,
+            model: model.map(|m| m.to_string()),
+            tools: tools.iter().map(|t| t.name.clone()).collect(),
+            json: false,
+            temperature,
+            max_tokens,
+        })
+    }
+
+    async fn chat_json(
+        &self,
+        messages: Vec<LLMMessage>,
+        _schema: &ResponseSchema,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.respond(MockRequest {
+            messages,
+            model: model.map(|m| m.to_string()),
+            tools: Vec::new(),
+            json: true,
+            temperature,
+            max_tokens,
+        })
+    }
+
+    async fn list_models(&self) -> LLMResult<Vec<String>> {
+        Ok(self.models.clone())
+    }
+
+    /// Streams the scripted reply text
+    async fn chat_streaming(
+        &self,
+        messages: Vec<LLMMessage>,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
+        let response = self.chat(messages, model, temperature, max_tokens).await?;
+        Ok(Box::new(std::io::Cursor::new(response.content.into_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::llm::{LLMConfig, LLMProvider, LLMRoute, LLMRouter, LLMTask};
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_script_order() {
+        let mock = MockProvider::new("mock")
+            .on("weather", "Sunny")
+            .on_regex(r"^\d+\s*\+\s*\d+$", "4")
+            .then("first")
+            .otherwise("?");
+
+        let reply = |text: &'static str| mock.chat(vec![LLMMessage::system("be brief"), LLMMessage::user(text)], None, None, None);
+        assert_eq!(reply("weather?").await.unwrap().content, "first");
+        assert_eq!(reply("What's the WEATHER?").await.unwrap().content, "Sunny");
+        assert_eq!(reply("2 + 2").await.unwrap().content, "4");
+        assert_eq!(reply("hello").await.unwrap().content, "?");
+
+        assert_eq!(mock.calls(), 4);
+        let request = mock.requests().remove(1);
+        assert_eq!(request.last_user(), "What's the WEATHER?");
+        assert_eq!(request.system(), Some("be brief"));
+    }
+
+    #[tokio::test]
+    async fn test_unscripted_request_fails() {
+        let mock = MockProvider::new("mock").on("hi", "hello");
+        assert!(matches!(mock.chat(vec![LLMMessage::user("bye")], None, None, None).await, Err(LLMError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_and_models() {
+        let mock = MockProvider::new("mock").otherwise("streamed reply").with_models(&["a", "b"]);
+        let mut stream = mock.chat_streaming(vec![LLMMessage::user("go")], None, None, None).await.unwrap();
+        let mut text = String::new();
+        stream.read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "streamed reply");
+        assert_eq!(mock.list_models().await.unwrap(), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_recorded() {
+        let call = ToolCall { id: "call_1".to_string(), name: "rss".to_string(), arguments: serde_json::json!({}) };
+        let mock = MockProvider::new("mock").with_model("mock-large").on("news", MockReply::ToolCalls(vec![call]));
+        let tool = ToolDefinition { name: "rss".to_string(), description: "Fetch news".to_string(), parameters: serde_json::json!({}) };
+
+        let response = mock.chat_with_tools(vec![LLMMessage::user("any news?")], &[tool], None, None, Some(64)).await.unwrap();
+        assert!(response.has_tool_calls());
+        assert_eq!(response.model, "mock-large");
+        let request = mock.last_request().unwrap();
+        assert_eq!(request.tools, vec!["rss"]);
+        assert_eq!(request.max_tokens, Some(64));
+    }
+
+    #[tokio::test]
+    async fn test_router_falls_over_to_next_mock() {
+        let groq = Arc::new(MockProvider::new("groq").then((|| LLMError::RateLimited { retry_after: None }) as fn() -> LLMError));
+        let claude = Arc::new(MockProvider::new("claude").otherwise("from claude"));
+        let mut config = LLMConfig { provider: LLMProvider::Groq, fallback: vec!["claude".to_string()], ..LLMConfig::default() };
+        config.routes.insert("translation".to_string(), vec![LLMRoute::new("claude", Some("haiku"))]);
+        let router = LLMRouter::new(config)
+            .with_provider("groq", groq.clone())
+            .with_provider("claude", claude.clone());
+
+        let response = router.chat(vec![LLMMessage::user("hi")], None, None, None).await.unwrap();
+        assert_eq!(response.content, "from claude");
+        assert_eq!(groq.calls(), 1);
+
+        router.chat_task(LLMTask::Translation, vec![LLMMessage::user("halo")], Some(0.3), None).await.unwrap();
+        let request = claude.last_request().unwrap();
+        assert_eq!(request.model.as_deref(), Some("haiku"));
+        assert_eq!(request.temperature, Some(0.3));
+    }
+}
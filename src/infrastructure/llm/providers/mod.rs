@@ -3,7 +3,40 @@
 pub mod minimax;
 pub mod claude;
 pub mod groq;
+pub mod openai;
+#[cfg(test)]
+pub mod mock;
+mod chat_completions;
 
 pub use minimax::MiniMaxProvider;
 pub use claude::ClaudeProvider;
 pub use groq::GroqProvider;
+pub use openai::OpenAICompatibleProvider;
+#[cfg(test)]
+pub use mock::MockProvider;
+
+use std::sync::Arc;
+
+use crate::infrastructure::llm::{LLM, LLMConfig, LLMProvider};
+
+/// Build a provider by name from config, or `None` if unknown or missing an API key.
+/// Names of `openai-compatible` endpoints are checked after the built-in providers.
+pub fn build(name: &str, config: &LLMConfig) -> Option<Arc<dyn LLM>> {
+    let provider = match LLMProvider::from_name(name) {
+        Some(LLMProvider::OpenAICompatible) | None => {
+            let endpoint = config.endpoint(name)?;
+            return Some(Arc::new(OpenAICompatibleProvider::new(endpoint).with_http(&config.http)));
+        }
+        Some(provider) => provider,
+    };
+    let api_key = config.api_key(provider)?;
+    let model = Some(config.model(provider));
+    
+    let llm: Arc<dyn LLM> = match provider {
+        LLMProvider::MiniMax => Arc::new(MiniMaxProvider::new(api_key, model).with_http(&config.http)),
+        LLMProvider::Claude => Arc::new(ClaudeProvider::new(api_key, model).with_http(&config.http)),
+        LLMProvider::Groq => Arc::new(GroqProvider::new(api_key, model).with_http(&config.http)),
+        LLMProvider::OpenAICompatible => return None,
+    };
+    Some(llm)
+}
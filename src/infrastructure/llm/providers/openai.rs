@@ -0,0 +1,275 @@
+//! OpenAI-compatible Provider - Any server speaking the `/chat/completions` API
+//!
+//! Works with Ollama, llama.cpp server, vLLM, LM Studio, OpenRouter and
+//! OpenAI itself; base URL, auth and headers come from config.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::chat_completions::{ChatRequest, ChatResponse, ModelList};
+use crate::infrastructure::llm::http::HttpClient;
+use crate::infrastructure::llm::{EmbeddingResponse, LLMMessage, LLMResponse, LLMError, LLMResult, LLMUsage, LLM, ResponseSchema, ToolDefinition};
+use crate::infrastructure::llm::config::{HttpConfig, OpenAICompatibleConfig};
+
+/// Generic OpenAI-compatible provider
+pub struct OpenAICompatibleProvider {
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+    auth_header: String,
+    auth_scheme: Option<String>,
+    headers: HashMap<String, String>,
+    http: HttpClient,
+    model: String,
+    models: Vec<String>,
+    embedding_model: Option<String>,
+}
+
+/// `/embeddings` request body
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingUsage {
+    prompt_tokens: Option<u32>,
+    total_tokens: Option<u32>,
+}
+
+/// `/embeddings` response body
+#[derive(Deserialize)]
+struct EmbeddingReply {
+    data: Vec<EmbeddingData>,
+    model: Option<String>,
+    usage: Option<EmbeddingUsage>,
+}
+
+impl OpenAICompatibleProvider {
+    pub fn new(config: &OpenAICompatibleConfig) -> Self {
+        let model = config.model.clone()
+            .or_else(|| config.models.first().cloned())
+            .unwrap_or_else(|| "default".to_string());
+
+        Self {
+            name: config.name.clone(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            api_key: config.resolved_api_key(),
+            auth_header: config.auth_header.clone(),
+            auth_scheme: config.auth_scheme.clone(),
+            headers: config.headers.clone(),
+            http: HttpClient::default(),
+            model,
+            models: config.models.clone(),
+            embedding_model: config.embedding_model.clone(),
+        }
+    }
+
+    /// Use the given timeouts and retry policy
+    pub fn with_http(mut self, config: &HttpConfig) -> Self {
+        self.http = HttpClient::new(config);
+        self
+    }
+
+    /// Get chat completions URL
+    fn chat_url(&self) -> String {
+        format!("{}/chat/completions", self.base_url)
+    }
+
+    /// Get embeddings URL
+    fn embeddings_url(&self) -> String {
+        format!("{}/embeddings", self.base_url)
+    }
+
+    /// Get models URL
+    fn models_url(&self) -> String {
+        format!("{}/models", self.base_url)
+    }
+
+    /// POST with auth and configured headers
+    fn post(&self, url: String) -> reqwest::RequestBuilder {
+        let builder = self.http
+            .post(url)
+            .header("Content-Type", "application/json");
+        self.authorize(builder)
+    }
+
+    /// Add auth and configured headers to a request
+    fn authorize(&self, mut builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(auth) = self.auth_value() {
+            builder = builder.header(self.auth_header.as_str(), auth);
+        }
+        for (key, value) in &self.headers {
+            builder = builder.header(key.as_str(), value.as_str());
+        }
+        builder
+    }
+
+    /// Value for the auth header, if a key is configured
+    fn auth_value(&self) -> Option<String> {
+        self.api_key.as_ref().map(|key| match &self.auth_scheme {
+            Some(scheme) if !scheme.is_empty() => format!("{} {}", scheme, key),
+            _ => key.clone(),
+        })
+    }
+
+    /// Send a chat completion, offering `tools` when non-empty
+    async fn complete(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let model = model.unwrap_or(&self.model);
+        self.send(ChatRequest::new(model, &messages, tools, temperature, max_tokens)).await
+    }
+
+    /// POST a chat completion request
+    async fn send(&self, request: ChatRequest) -> LLMResult<LLMResponse> {
+        let builder = self.post(self.chat_url()).json(&request);
+        let chat_response: ChatResponse = self.http.send_json(builder).await?;
+
+        chat_response
+            .into_response(&request.model)
+            .ok_or_else(|| LLMError::InvalidRequest("No choices in response".to_string()))
+    }
+}
+
+#[async_trait]
+impl LLM for OpenAICompatibleProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<LLMMessage>,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.complete(messages, &[], model, temperature, max_tokens).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.complete(messages, tools, model, temperature, max_tokens).await
+    }
+
+    async fn chat_json(
+        &self,
+        messages: Vec<LLMMessage>,
+        _schema: &ResponseSchema,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let model = model.unwrap_or(&self.model);
+        self.send(ChatRequest::new(model, &messages, &[], temperature, max_tokens).json_object()).await
+    }
+
+    async fn embed(&self, texts: &[String], model: Option<&str>) -> LLMResult<EmbeddingResponse> {
+        let model = model.or(self.embedding_model.as_deref()).unwrap_or(&self.model);
+
+        let builder = self.post(self.embeddings_url()).json(&EmbeddingRequest { model, input: texts });
+        let mut reply: EmbeddingReply = self.http.send_json(builder).await?;
+
+        if reply.data.len() != texts.len() {
+            return Err(LLMError::ParseError(format!(
+                "expected {} embeddings, got {}", texts.len(), reply.data.len()
+            )));
+        }
+        reply.data.sort_by_key(|d| d.index);
+
+        Ok(EmbeddingResponse {
+            model: reply.model.unwrap_or_else(|| model.to_string()),
+            embeddings: reply.data.into_iter().map(|d| d.embedding).collect(),
+            usage: reply.usage.map(|u| LLMUsage {
+                prompt_tokens: u.prompt_tokens,
+                completion_tokens: Some(0),
+                total_tokens: u.total_tokens,
+            }),
+        })
+    }
+
+    /// Models from `/models`; the ones declared in config if the server has no such endpoint
+    async fn list_models(&self) -> LLMResult<Vec<String>> {
+        let builder = self.authorize(self.http.get(self.models_url()));
+        match self.http.send_json::<ModelList>(builder).await {
+            Ok(models) => Ok(models.into_ids()),
+            Err(e) if !self.models.is_empty() => {
+                tracing::debug!("Listing models of '{}' failed, using configured ones: {}", self.name, e);
+                Ok(self.models.clone())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn chat_streaming(
+        &self,
+        _messages: Vec<LLMMessage>,
+        _model: Option<&str>,
+        _temperature: Option<f32>,
+        _max_tokens: Option<u32>,
+    ) -> LLMResult<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
+        // TODO: Implement streaming
+        Err(LLMError::InvalidRequest("Streaming not yet implemented".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(yaml: &str) -> OpenAICompatibleConfig {
+        serde_yaml::from_str(yaml).expect("valid endpoint config")
+    }
+
+    #[test]
+    fn test_local_endpoint_without_key() {
+        let config = endpoint("name: ollama\nbase-url: http://localhost:11434/v1/\nmodel: qwen2.5:3b\n");
+        let provider = OpenAICompatibleProvider::new(&config);
+
+        assert_eq!(provider.name(), "ollama");
+        assert_eq!(provider.chat_url(), "http://localhost:11434/v1/chat/completions");
+        assert_eq!(provider.model, "qwen2.5:3b");
+        assert!(provider.auth_value().is_none());
+    }
+
+    #[test]
+    fn test_auth_scheme_and_models() {
+        let config = endpoint(
+            "name: openrouter\nbase-url: https://openrouter.ai/api/v1\napi-key: sk-test\n\
+             models: [meta-llama/llama-3.1-8b-instruct, openai/gpt-4o-mini]\n\
+             headers:\n  X-Title: carik-bot\n",
+        );
+        let provider = OpenAICompatibleProvider::new(&config);
+
+        assert_eq!(provider.auth_value().as_deref(), Some("Bearer sk-test"));
+        assert_eq!(provider.model, "meta-llama/llama-3.1-8b-instruct");
+        assert_eq!(provider.models.len(), 2);
+        assert_eq!(provider.headers.get("X-Title").map(|s| s.as_str()), Some("carik-bot"));
+
+        let raw = endpoint("name: vllm\nbase-url: http://gpu:8000/v1\napi-key: k\nauth-header: X-Api-Key\nauth-scheme: ''\n");
+        let provider = OpenAICompatibleProvider::new(&raw);
+        assert_eq!(provider.auth_header, "X-Api-Key");
+        assert_eq!(provider.auth_value().as_deref(), Some("k"));
+    }
+}
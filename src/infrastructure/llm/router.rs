@@ -0,0 +1,802 @@
+//! LLM Router - Ordered provider fallback with circuit breaking
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::infrastructure::llm::{EmbeddingResponse, LLMMessage, LLMResponse, LLMError, LLMResult, LLM, ResponseSchema, ToolDefinition};
+use crate::infrastructure::llm::config::{CircuitBreakerConfig, LLMConfig, LLMRoute};
+use crate::infrastructure::llm::providers;
+use crate::infrastructure::llm::structured;
+use crate::infrastructure::llm::context::TokenEstimator;
+use crate::infrastructure::llm::usage::{UsageEvent, UsageScope, UsageSink};
+use crate::infrastructure::llm::cache::{cache_key, ResponseCache};
+
+/// Kind of work an LLM call does, used to pick a route from config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LLMTask {
+    Chat,
+    Translation,
+    Summarization,
+    Classification,
+    Extraction,
+    Embedding,
+    Vision,
+}
+
+impl LLMTask {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LLMTask::Chat => "chat",
+            LLMTask::Translation => "translation",
+            LLMTask::Summarization => "summarization",
+            LLMTask::Classification => "classification",
+            LLMTask::Extraction => "extraction",
+            LLMTask::Embedding => "embedding",
+            LLMTask::Vision => "vision",
+        }
+    }
+}
+
+/// Picks the provider/model for a request, e.g. the one a chat chose with /model
+#[async_trait]
+pub trait ModelSelector: Send + Sync {
+    /// Route to try first for `scope`, if one was picked
+    async fn select(&self, scope: &UsageScope) -> Option<LLMRoute>;
+}
+
+/// Checks every request before it is sent and every reply before it is
+/// returned, e.g. [`crate::infrastructure::guard::Guard`]
+pub trait Guardrail: Send + Sync {
+    /// Inspect and rewrite the messages; `Err` blocks the request
+    fn check_input(&self, task: LLMTask, scope: Option<&UsageScope>, messages: &mut Vec<LLMMessage>) -> Result<(), String>;
+
+    /// Inspect and rewrite the reply; `Err` withholds it
+    fn check_output(&self, task: LLMTask, scope: Option<&UsageScope>, content: &mut String) -> Result<(), String>;
+}
+
+/// A fixed route for every scope, e.g. the provider under test in `carik-bot eval`
+#[async_trait]
+impl ModelSelector for LLMRoute {
+    async fn select(&self, _scope: &UsageScope) -> Option<LLMRoute> {
+        Some(self.clone())
+    }
+}
+
+/// How long a provider's model list is reused before it is fetched again
+const MODEL_LIST_TTL: Duration = Duration::from_secs(3600);
+
+/// Health of a single provider
+#[derive(Debug, Default)]
+struct ProviderHealth {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A provider and its health tracking
+struct ProviderSlot {
+    llm: Arc<dyn LLM>,
+    health: Mutex<ProviderHealth>,
+    /// Last fetched model list and when it was fetched
+    models: Mutex<Option<(Instant, Vec<String>)>>,
+}
+
+impl ProviderSlot {
+    fn new(llm: Arc<dyn LLM>) -> Self {
+        Self {
+            llm,
+            health: Mutex::new(ProviderHealth::default()),
+            models: Mutex::new(None),
+        }
+    }
+
+    /// Whether the circuit is open (provider is cooling down)
+    fn is_open(&self) -> bool {
+        let health = self.health.lock().unwrap();
+        health.open_until.map(|t| Instant::now() < t).unwrap_or(false)
+    }
+
+    fn record_success(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures = 0;
+        health.open_until = None;
+    }
+
+    fn record_failure(&self, error: &LLMError, breaker: &CircuitBreakerConfig) {
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        // Rate limits open the circuit straight away (for `Retry-After` if given);
+        // other failures after the threshold
+        let cooldown = Duration::from_secs(breaker.cooldown_secs);
+        if let LLMError::RateLimited { retry_after } = error {
+            health.open_until = Some(Instant::now() + retry_after.unwrap_or(cooldown));
+        } else if health.consecutive_failures >= breaker.failure_threshold {
+            health.open_until = Some(Instant::now() + cooldown);
+        }
+    }
+}
+
+/// Snapshot of a provider's health
+#[derive(Debug, Clone)]
+pub struct ProviderStatus {
+    pub name: String,
+    /// Remaining cooldown if the provider is currently skipped
+    pub cooldown: Option<Duration>,
+}
+
+/// LLM implementation that tries an ordered list of providers
+///
+/// Falls over to the next provider on rate limits, network errors and 5xx
+/// responses. Providers that keep failing are skipped for a cooldown period.
+pub struct LLMRouter {
+    providers: HashMap<String, ProviderSlot>,
+    config: LLMConfig,
+    usage: Option<Arc<dyn UsageSink>>,
+    selector: Option<Arc<dyn ModelSelector>>,
+    guard: Option<Arc<dyn Guardrail>>,
+    cache: Option<Arc<dyn ResponseCache>>,
+}
+
+impl LLMRouter {
+    /// Create a router with no providers; add them with [`Self::with_provider`]
+    pub fn new(config: LLMConfig) -> Self {
+        Self {
+            providers: HashMap::new(),
+            config,
+            usage: None,
+            selector: None,
+            guard: None,
+            cache: None,
+        }
+    }
+
+    /// Build every provider referenced by the config that has an API key
+    pub fn from_config(config: LLMConfig) -> Self {
+        let mut names: Vec<String> = config.default_chain().into_iter().map(|r| r.provider).collect();
+        for routes in config.routes.values() {
+            names.extend(routes.iter().map(|r| r.provider.to_lowercase()));
+        }
+
+        let mut router = Self::new(config);
+        for name in names {
+            if router.providers.contains_key(&name) {
+                continue;
+            }
+            match providers::build(&name, &router.config) {
+                Some(llm) => router = router.with_provider(name, llm),
+                None => tracing::warn!("LLM provider '{}' not available (unknown or missing API key)", name),
+            }
+        }
+        router
+    }
+
+    /// Register a provider under a name used by routes
+    pub fn with_provider(mut self, name: impl Into<String>, llm: Arc<dyn LLM>) -> Self {
+        self.providers.insert(name.into().to_lowercase(), ProviderSlot::new(llm));
+        self
+    }
+
+    /// Report usage of every successful call and enforce budgets
+    pub fn with_usage_sink(mut self, sink: Arc<dyn UsageSink>) -> Self {
+        self.usage = Some(sink);
+        self
+    }
+
+    /// Try the route picked for the current [`UsageScope`] first
+    pub fn with_model_selector(mut self, selector: Arc<dyn ModelSelector>) -> Self {
+        self.selector = Some(selector);
+        self
+    }
+
+    /// Run every chat request and reply through `guard`
+    pub fn with_guardrail(mut self, guard: Arc<dyn Guardrail>) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Reuse replies for tasks with a TTL in `llm.cache`
+    pub fn with_response_cache(mut self, cache: Arc<dyn ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Names of the providers in the default chain that are available, in order
+    pub fn provider_names(&self) -> Vec<String> {
+        self.config.default_chain()
+            .into_iter()
+            .map(|r| r.provider)
+            .filter(|name| self.providers.contains_key(name))
+            .collect()
+    }
+
+    pub fn config(&self) -> &LLMConfig {
+        &self.config
+    }
+
+    /// Whether a provider is registered under `name`
+    pub fn has_provider(&self, name: &str) -> bool {
+        self.providers.contains_key(&name.to_lowercase())
+    }
+
+    /// Models `provider` serves: its API's list (cached for an hour), else
+    /// the ones named in config. `None` if the provider isn't registered.
+    pub async fn models(&self, provider: &str) -> Option<Vec<String>> {
+        let name = provider.to_lowercase();
+        let slot = self.providers.get(&name)?;
+        if let Some((fetched, models)) = slot.models.lock().unwrap().as_ref() {
+            if fetched.elapsed() < MODEL_LIST_TTL {
+                return Some(models.clone());
+            }
+        }
+
+        match slot.llm.list_models().await {
+            Ok(models) if !models.is_empty() => {
+                *slot.models.lock().unwrap() = Some((Instant::now(), models.clone()));
+                Some(models)
+            }
+            Ok(_) => Some(self.config.configured_models(&name)),
+            Err(e) => {
+                tracing::debug!("Listing models of '{}' failed: {}", name, e);
+                Some(self.config.configured_models(&name))
+            }
+        }
+    }
+
+    /// Health of every registered provider
+    pub fn status(&self) -> Vec<ProviderStatus> {
+        let now = Instant::now();
+        let mut status: Vec<ProviderStatus> = self.providers.iter().map(|(name, slot)| {
+            let health = slot.health.lock().unwrap();
+            ProviderStatus {
+                name: name.clone(),
+                cooldown: health.open_until.and_then(|t| t.checked_duration_since(now)),
+            }
+        }).collect();
+        status.sort_by(|a, b| a.name.cmp(&b.name));
+        status
+    }
+
+    /// Provider and model the first healthy route for `task` would use
+    pub async fn task_model(&self, task: LLMTask) -> Option<(String, String)> {
+        self.chain_for(task).await
+            .into_iter()
+            .find(|r| self.providers.get(&r.provider.to_lowercase()).is_some_and(|slot| !slot.is_open()))
+            .map(|r| (r.provider.to_lowercase(), self.config.route_model(&r)))
+    }
+
+    /// Whether `task` has its own routes rather than the default chain.
+    /// Vision needs one: text-only models can't read image parts.
+    pub fn has_route(&self, task: LLMTask) -> bool {
+        self.config.routes.get(task.as_str()).is_some_and(|routes| !routes.is_empty())
+    }
+
+    /// Default chain with the route picked for the current scope first
+    async fn default_chain(&self) -> Vec<LLMRoute> {
+        let mut chain = self.config.default_chain();
+        if let Some(route) = self.selected_route().await {
+            chain.retain(|r| !r.provider.eq_ignore_ascii_case(&route.provider));
+            chain.insert(0, route);
+        }
+        chain
+    }
+
+    /// Chain for `task`. A picked route applies to chat and to tasks without
+    /// their own routes; e.g. vision and translation keep their models.
+    async fn chain_for(&self, task: LLMTask) -> Vec<LLMRoute> {
+        if task != LLMTask::Chat && self.has_route(task) {
+            return self.config.chain_for(task.as_str());
+        }
+        self.default_chain().await
+    }
+
+    /// Route picked by the selector for the current scope, if its provider is registered
+    async fn selected_route(&self) -> Option<LLMRoute> {
+        let selector = self.selector.as_ref()?;
+        let scope = UsageScope::current()?;
+        selector.select(&scope).await.filter(|route| self.has_provider(&route.provider))
+    }
+
+    /// Chat using the route configured for `task`
+    pub async fn chat_task(
+        &self,
+        task: LLMTask,
+        messages: Vec<LLMMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.chat_task_with_tools(task, messages, &[], temperature, max_tokens).await
+    }
+
+    /// Chat using the route configured for `task`, offering `tools` to the model
+    pub async fn chat_task_with_tools(
+        &self,
+        task: LLMTask,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let chain = self.chain_for(task).await;
+        self.chat_chain(task, &chain, None, messages, tools, None, temperature, max_tokens).await
+    }
+
+    /// Typed reply matching `schema` from the route configured for `task`
+    /// in JSON mode, see [`structured`]. The reply is cached under the
+    /// original request only once it validates, repaired or not.
+    pub async fn chat_structured<T: DeserializeOwned>(
+        &self,
+        task: LLMTask,
+        messages: Vec<LLMMessage>,
+        schema: &ResponseSchema,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<T> {
+        let chain = self.chain_for(task).await;
+        // Cache entry of the first request; repair rounds extend the conversation
+        let entry = Mutex::new(None);
+        let (chain, first) = (&chain, &entry);
+        let (value, response) = structured::run(messages, schema, move |messages| async move {
+            let (response, entry) = self.chat_chain_keyed(task, chain, None, messages, &[], Some(schema), temperature, max_tokens).await?;
+            first.lock().unwrap().get_or_insert(entry);
+            Ok(response)
+        }).await?;
+        if let (Some(cache), Some(Some((key, ttl)))) = (&self.cache, entry.into_inner().unwrap()) {
+            cache.put(task, &key, &response, ttl).await;
+        }
+        Ok(value)
+    }
+
+    /// Embed `texts` with the `embedding` route.
+    ///
+    /// Unlike chat there is no default chain: embeddings from different
+    /// models don't compare, so only explicitly configured routes are used.
+    /// Any provider error moves on to the next route.
+    pub async fn embed(&self, texts: &[String]) -> LLMResult<EmbeddingResponse> {
+        let chain = self.config.routes.get(LLMTask::Embedding.as_str()).cloned().unwrap_or_default();
+        if chain.is_empty() {
+            return Err(LLMError::ConfigError("No embedding route configured".to_string()));
+        }
+
+        let scope = UsageScope::current();
+        if let (Some(sink), Some(scope)) = (&self.usage, &scope) {
+            sink.check_budget(scope).await.map_err(LLMError::BudgetExceeded)?;
+        }
+
+        let mut last_error = None;
+        for route in &chain {
+            let name = route.provider.to_lowercase();
+            let Some(slot) = self.providers.get(&name) else {
+                continue;
+            };
+            if slot.is_open() {
+                continue;
+            }
+
+            match slot.llm.embed(texts, route.model.as_deref()).await {
+                Ok(response) => {
+                    slot.record_success();
+                    if let Some(sink) = &self.usage {
+                        let estimator = TokenEstimator::for_model(&name, &response.model);
+                        let estimate = (texts.iter().map(|t| estimator.count(t) as u64).sum(), 0);
+                        let event = UsageEvent::new(&name, &response.model, LLMTask::Embedding.as_str(), response.usage.as_ref(), estimate);
+                        sink.record(scope.as_ref(), &event).await;
+                    }
+                    return Ok(response);
+                }
+                Err(e) => {
+                    tracing::warn!("Embedding with '{}' failed: {}", name, e);
+                    if e.is_retryable() {
+                        slot.record_failure(&e, &self.config.circuit_breaker);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            LLMError::ConfigError("No embedding provider available".to_string())
+        }))
+    }
+
+    /// Try each route in order. An explicit `model` only applies to the first
+    /// provider attempted, since model names are provider-specific.
+    #[allow(clippy::too_many_arguments)]
+    async fn chat_chain(
+        &self,
+        task: LLMTask,
+        chain: &[LLMRoute],
+        model: Option<&str>,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        schema: Option<&ResponseSchema>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        self.chat_chain_keyed(task, chain, model, messages, tools, schema, temperature, max_tokens).await
+            .map(|(response, _)| response)
+    }
+
+    /// [`Self::chat_chain`], also returning the cache key and TTL of a fresh
+    /// reply. Replies to requests with a `schema` aren't cached here: they
+    /// may still fail validation, so the caller stores them once they pass.
+    #[allow(clippy::too_many_arguments)]
+    async fn chat_chain_keyed(
+        &self,
+        task: LLMTask,
+        chain: &[LLMRoute],
+        model: Option<&str>,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        schema: Option<&ResponseSchema>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<(LLMResponse, Option<(String, Duration)>)> {
+        let scope = UsageScope::current();
+        if let (Some(sink), Some(scope)) = (&self.usage, &scope) {
+            sink.check_budget(scope).await.map_err(LLMError::BudgetExceeded)?;
+        }
+        let mut messages = messages;
+        if let Some(guard) = &self.guard {
+            guard.check_input(task, scope.as_ref(), &mut messages).map_err(LLMError::Blocked)?;
+        }
+
+        // Requests offering tools depend on what the tools return, so they always go out
+        let cache_ttl = self.cache.as_ref()
+            .filter(|_| tools.is_empty())
+            .and_then(|_| self.config.cache.ttl(task));
+
+        let mut last_error = None;
+        let mut attempted = false;
+
+        for route in chain {
+            let name = route.provider.to_lowercase();
+            let Some(slot) = self.providers.get(&name) else {
+                continue;
+            };
+
+            if slot.is_open() {
+                tracing::debug!("Skipping LLM provider '{}' (cooling down)", name);
+                continue;
+            }
+
+            let route_model = route.model.as_deref().or(if attempted { None } else { model });
+            attempted = true;
+
+            let key = cache_ttl.map(|_| {
+                let model = route_model.map(str::to_string).unwrap_or_else(|| self.config.route_model(route));
+                cache_key(&name, &model, temperature, max_tokens, schema, &messages)
+            });
+            if let (Some(cache), Some(key)) = (&self.cache, &key) {
+                if let Some(mut response) = cache.get(task, key).await {
+                    tracing::debug!("Cache hit for {} on '{}'", task.as_str(), name);
+                    if let Some(guard) = &self.guard {
+                        guard.check_output(task, scope.as_ref(), &mut response.content).map_err(LLMError::Blocked)?;
+                    }
+                    return Ok((response, None));
+                }
+            }
+
+            let result = match schema {
+                Some(schema) => slot.llm.chat_json(messages.clone(), schema, route_model, temperature, max_tokens).await,
+                None => slot.llm.chat_with_tools(messages.clone(), tools, route_model, temperature, max_tokens).await,
+            };
+            match result {
+                Ok(mut response) => {
+                    slot.record_success();
+                    if let Some(sink) = &self.usage {
+                        let estimator = TokenEstimator::for_model(&name, &response.model);
+                        let estimate = (
+                            estimator.count_messages(&messages) as u64,
+                            estimator.count(&response.content) as u64,
+                        );
+                        let event = UsageEvent::new(&name, &response.model, task.as_str(), response.usage.as_ref(), estimate);
+                        sink.record(scope.as_ref(), &event).await;
+                    }
+                    if let Some(guard) = &self.guard {
+                        guard.check_output(task, scope.as_ref(), &mut response.content).map_err(LLMError::Blocked)?;
+                    }
+                    let entry = key.zip(cache_ttl);
+                    if let (Some(cache), Some((key, ttl)), None) = (&self.cache, &entry, schema) {
+                        cache.put(task, key, &response, *ttl).await;
+                    }
+                    return Ok((response, entry));
+                }
+                Err(e) if e.is_retryable() => {
+                    tracing::warn!("LLM provider '{}' failed, trying next: {}", name, e);
+                    slot.record_failure(&e, &self.config.circuit_breaker);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            LLMError::ConfigError("No LLM provider available (all missing or cooling down)".to_string())
+        }))
+    }
+}
+
+#[async_trait]
+impl LLM for LLMRouter {
+    fn name(&self) -> &str {
+        "router"
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<LLMMessage>,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let chain = self.default_chain().await;
+        self.chat_chain(LLMTask::Chat, &chain, model, messages, &[], None, temperature, max_tokens).await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let chain = self.default_chain().await;
+        self.chat_chain(LLMTask::Chat, &chain, model, messages, tools, None, temperature, max_tokens).await
+    }
+
+    async fn chat_json(
+        &self,
+        messages: Vec<LLMMessage>,
+        schema: &ResponseSchema,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let chain = self.default_chain().await;
+        self.chat_chain(LLMTask::Chat, &chain, model, messages, &[], Some(schema), temperature, max_tokens).await
+    }
+
+    async fn chat_streaming(
+        &self,
+        messages: Vec<LLMMessage>,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
+        // Streams are passed through as they arrive, so only the request is guarded
+        let mut messages = messages;
+        if let Some(guard) = &self.guard {
+            guard.check_input(LLMTask::Chat, UsageScope::current().as_ref(), &mut messages).map_err(LLMError::Blocked)?;
+        }
+
+        // Streams can't be replayed on another provider, so use the first healthy one
+        let (route, slot) = self.default_chain().await
+            .into_iter()
+            .filter_map(|r| self.providers.get(&r.provider.to_lowercase()).map(|slot| (r, slot)))
+            .find(|(_, slot)| !slot.is_open())
+            .ok_or_else(|| LLMError::ConfigError("No LLM provider available".to_string()))?;
+        slot.llm.chat_streaming(messages, route.model.as_deref().or(model), temperature, max_tokens).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::llm::MockProvider;
+
+    /// Provider answering with its own name
+    fn mock(name: &str) -> Arc<MockProvider> {
+        Arc::new(MockProvider::new(name).otherwise(name))
+    }
+
+    /// Provider failing every request with `error`
+    fn failing(name: &str, error: fn() -> LLMError) -> Arc<MockProvider> {
+        Arc::new(MockProvider::new(name).otherwise(error))
+    }
+
+    fn config(fallback: &[&str]) -> LLMConfig {
+        LLMConfig {
+            provider: crate::infrastructure::llm::LLMProvider::Groq,
+            fallback: fallback.iter().map(|s| s.to_string()).collect(),
+            ..LLMConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_over_on_rate_limit() {
+        let groq = failing("groq", || LLMError::RateLimited { retry_after: None });
+        let claude = mock("claude");
+        let router = LLMRouter::new(config(&["claude"]))
+            .with_provider("groq", groq.clone())
+            .with_provider("claude", claude.clone());
+
+        let response = router.chat(vec![LLMMessage::user("hi")], None, None, None).await.unwrap();
+        assert_eq!(response.content, "claude");
+
+        // Rate-limited provider is skipped during its cooldown
+        router.chat(vec![LLMMessage::user("hi")], None, None, None).await.unwrap();
+        assert_eq!(groq.calls(), 1);
+        assert_eq!(claude.calls(), 2);
+        assert!(router.status().iter().any(|s| s.name == "groq" && s.cooldown.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_cooldown_honors_retry_after() {
+        let groq = failing("groq", || LLMError::RateLimited { retry_after: Some(Duration::from_secs(5)) });
+        let router = LLMRouter::new(config(&["claude"]))
+            .with_provider("groq", groq)
+            .with_provider("claude", mock("claude"));
+
+        router.chat(vec![LLMMessage::user("hi")], None, None, None).await.unwrap();
+        let cooldown = router.status().into_iter().find(|s| s.name == "groq").and_then(|s| s.cooldown).unwrap();
+        assert!(cooldown <= Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fall_over_on_client_error() {
+        let groq = failing("groq", || LLMError::ApiError { status: 400, message: "{}".to_string() });
+        let claude = mock("claude");
+        let router = LLMRouter::new(config(&["claude"]))
+            .with_provider("groq", groq)
+            .with_provider("claude", claude.clone());
+
+        assert!(router.chat(vec![LLMMessage::user("hi")], None, None, None).await.is_err());
+        assert_eq!(claude.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_after_threshold() {
+        let groq = failing("groq", || LLMError::ApiError { status: 503, message: String::new() });
+        let claude = mock("claude");
+        let mut cfg = config(&["claude"]);
+        cfg.circuit_breaker.failure_threshold = 2;
+        let router = LLMRouter::new(cfg)
+            .with_provider("groq", groq.clone())
+            .with_provider("claude", claude);
+
+        for _ in 0..4 {
+            router.chat(vec![LLMMessage::user("hi")], None, None, None).await.unwrap();
+        }
+        assert_eq!(groq.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_task_route_uses_configured_model() {
+        let mut cfg = config(&[]);
+        cfg.routes.insert("translation".to_string(), vec![LLMRoute::new("claude", Some("haiku"))]);
+        let router = LLMRouter::new(cfg)
+            .with_provider("groq", mock("groq"))
+            .with_provider("claude", mock("claude"));
+
+        let response = router.chat_task(LLMTask::Translation, vec![LLMMessage::user("hi")], None, None).await.unwrap();
+        assert_eq!(response.content, "claude");
+        assert_eq!(response.model, "haiku");
+
+        // Tasks without a route use the default chain
+        let response = router.chat_task(LLMTask::Chat, vec![LLMMessage::user("hi")], None, None).await.unwrap();
+        assert_eq!(response.content, "groq");
+    }
+
+    /// Picks a fixed route for chat `-100`
+    struct FixedSelector(LLMRoute);
+
+    #[async_trait]
+    impl ModelSelector for FixedSelector {
+        async fn select(&self, scope: &UsageScope) -> Option<LLMRoute> {
+            (scope.chat_id == "-100").then(|| self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_selected_route_applies_per_scope() {
+        let mut cfg = config(&["claude"]);
+        cfg.routes.insert("vision".to_string(), vec![LLMRoute::new("groq", Some("llava"))]);
+        let router = LLMRouter::new(cfg)
+            .with_provider("groq", mock("groq"))
+            .with_provider("claude", mock("claude"))
+            .with_model_selector(Arc::new(FixedSelector(LLMRoute::new("claude", Some("sonnet")))));
+
+        let picked = UsageScope::new("7", "-100").run(router.chat(vec![LLMMessage::user("hi")], None, None, None)).await.unwrap();
+        assert_eq!((picked.content.as_str(), picked.model.as_str()), ("claude", "sonnet"));
+
+        // Other chats, and tasks with their own route, are unaffected
+        let other = UsageScope::new("7", "42").run(router.chat(vec![LLMMessage::user("hi")], None, None, None)).await.unwrap();
+        assert_eq!(other.content, "groq");
+        let vision = UsageScope::new("7", "-100")
+            .run(router.chat_task(LLMTask::Vision, vec![LLMMessage::user("hi")], None, None))
+            .await
+            .unwrap();
+        assert_eq!(vision.model, "llava");
+    }
+
+    /// Cache kept in memory
+    #[derive(Default)]
+    struct MemoryCache(Mutex<HashMap<String, LLMResponse>>);
+
+    #[async_trait]
+    impl ResponseCache for MemoryCache {
+        async fn get(&self, _task: LLMTask, key: &str) -> Option<LLMResponse> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        async fn put(&self, _task: LLMTask, key: &str, response: &LLMResponse, _ttl: Duration) {
+            self.0.lock().unwrap().insert(key.to_string(), response.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_reuses_deterministic_replies() {
+        let groq = mock("groq");
+        let router = LLMRouter::new(config(&[]))
+            .with_provider("groq", groq.clone())
+            .with_response_cache(Arc::new(MemoryCache::default()));
+        let ask = |task, text: &str| router.chat_task(task, vec![LLMMessage::user(text)], Some(0.3), None);
+
+        ask(LLMTask::Translation, "halo").await.unwrap();
+        ask(LLMTask::Translation, "halo").await.unwrap();
+        assert_eq!(groq.calls(), 1);
+        ask(LLMTask::Translation, "selamat pagi").await.unwrap();
+        assert_eq!(groq.calls(), 2);
+
+        // Conversational chat always goes to the provider
+        ask(LLMTask::Chat, "halo").await.unwrap();
+        ask(LLMTask::Chat, "halo").await.unwrap();
+        assert_eq!(groq.calls(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_cache_keeps_only_valid_structured_replies() {
+        let schema = ResponseSchema::new("digest", serde_json::json!({"type": "object", "required": ["summary"]}));
+        let groq = Arc::new(MockProvider::new("groq")
+            .then("{\"headline\": \"Rust 2.0\"}")
+            .then("{\"summary\": \"Rust 2.0\"}")
+            .otherwise("not json"));
+        let router = LLMRouter::new(config(&[]))
+            .with_provider("groq", groq.clone())
+            .with_response_cache(Arc::new(MemoryCache::default()));
+        let digest = |text: &str| router.chat_structured::<serde_json::Value>(
+            LLMTask::Summarization, vec![LLMMessage::user(text)], &schema, Some(0.7), None,
+        );
+
+        // The repaired reply is stored under the original request
+        assert_eq!(digest("feed").await.unwrap()["summary"], "Rust 2.0");
+        assert_eq!(digest("feed").await.unwrap()["summary"], "Rust 2.0");
+        assert_eq!(groq.calls(), 2);
+
+        // A reply that never validates isn't cached
+        assert!(digest("other feed").await.is_err());
+        assert!(digest("other feed").await.is_err());
+        assert_eq!(groq.calls(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_models_fall_back_to_config() {
+        let mut cfg = config(&[]);
+        cfg.groq_model = Some("llama-3.3-70b-versatile".to_string());
+        cfg.routes.insert("translation".to_string(), vec![LLMRoute::new("groq", Some("llama-3.1-8b-instant"))]);
+        let router = LLMRouter::new(cfg).with_provider("groq", mock("groq"));
+
+        assert_eq!(router.models("groq").await.unwrap(), vec!["llama-3.1-8b-instant", "llama-3.3-70b-versatile"]);
+        assert!(router.models("claude").await.is_none());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(LLMError::RateLimited { retry_after: None }.is_retryable());
+        assert!(LLMError::NetworkError("connection reset".to_string()).is_retryable());
+        assert!(LLMError::Timeout.is_retryable());
+        assert!(LLMError::ApiError { status: 502, message: String::new() }.is_retryable());
+        assert!(LLMError::ApiError { status: 408, message: String::new() }.is_retryable());
+        assert!(!LLMError::ApiError { status: 401, message: String::new() }.is_retryable());
+        assert!(!LLMError::Cancelled.is_retryable());
+        assert!(!LLMError::MissingApiKey.is_retryable());
+        assert_eq!(LLMError::RateLimited { retry_after: None }.status(), Some(429));
+    }
+}
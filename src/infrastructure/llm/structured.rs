@@ -0,0 +1,339 @@
+//! Structured output - Typed JSON replies checked against a schema
+//!
+//! Used through `LLMRouter::chat_structured`. The schema goes into the prompt
+//! and, where the provider has one, its native JSON mode (`LLM::chat_json`).
+//! Replies are cleaned up (code fences, surrounding prose, trailing commas,
+//! truncated closers), validated and deserialized; invalid ones are sent back
+//! to the model with the errors so it can correct them.
+//!
+//! Validation covers the JSON Schema keywords the bot's schemas use: `type`,
+//! `enum`, `properties`, `required`, `additionalProperties: false`, `items`,
+//! `minItems`/`maxItems`, `minLength`/`maxLength` and `minimum`/`maximum`.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+
+use crate::infrastructure::llm::{LLMError, LLMMessage, LLMResponse, LLMResult, ResponseSchema};
+
+/// Times an invalid reply is sent back for correction
+const MAX_REPAIRS: usize = 2;
+
+/// Ask through `send` until a reply parses, re-asking up to [`MAX_REPAIRS`] times.
+/// Returns the value with the reply it came from.
+pub(crate) async fn run<T, F, Fut>(mut messages: Vec<LLMMessage>, schema: &ResponseSchema, mut send: F) -> LLMResult<(T, LLMResponse)>
+where
+    T: DeserializeOwned,
+    F: FnMut(Vec<LLMMessage>) -> Fut,
+    Fut: Future<Output = LLMResult<LLMResponse>>,
+{
+    add_instructions(&mut messages, schema);
+    let mut repairs = 0;
+    loop {
+        let response = send(messages.clone()).await?;
+        let error = match parse::<T>(&response.content, schema) {
+            Ok(value) => return Ok((value, response)),
+            Err(error) => error,
+        };
+        if repairs >= MAX_REPAIRS {
+            return Err(LLMError::ParseError(format!("{} reply: {}", schema.name, error)));
+        }
+        tracing::debug!("Invalid {} reply ({}), asking again", schema.name, error);
+        messages.push(LLMMessage::assistant(response.content));
+        messages.push(LLMMessage::user(format!(
+            "That reply is invalid: {}. Reply again with only the corrected JSON.", error
+        )));
+        repairs += 1;
+    }
+}
+
+/// Append the schema to the system prompt (or add one)
+fn add_instructions(messages: &mut Vec<LLMMessage>, schema: &ResponseSchema) {
+    let instructions = format!(
+        "Reply with a single JSON value and nothing else, matching this JSON schema:\n{}",
+        schema.schema
+    );
+    match messages.first_mut() {
+        Some(first) if first.role == "system" => {
+            first.content = format!("{}\n\n{}", first.content, instructions);
+        }
+        _ => messages.insert(0, LLMMessage::system(instructions)),
+    }
+}
+
+/// Extract, validate and deserialize a reply; the error lists what's wrong
+pub fn parse<T: DeserializeOwned>(reply: &str, schema: &ResponseSchema) -> Result<T, String> {
+    let value = extract_json(reply).ok_or_else(|| "no JSON found".to_string())?;
+    let errors = validate(&schema.schema, &value);
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// First JSON object or array in `reply`, tolerating code fences,
+/// surrounding prose, trailing commas and missing closing brackets
+pub fn extract_json(reply: &str) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(reply.trim()) {
+        return Some(value);
+    }
+    let start = reply.find(['{', '['])?;
+
+    let mut out = String::new();
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in reply[start..].chars() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                // Drop a trailing comma before the closer
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                if closers.pop() != Some(c) {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+        out.push(c);
+        if closers.is_empty() {
+            break;
+        }
+    }
+
+    // Reply cut off (e.g. by max_tokens): close what is still open
+    if in_string {
+        out.push('"');
+    }
+    while let Some(closer) = closers.pop() {
+        let trimmed = out.trim_end().len();
+        if out[..trimmed].ends_with(',') {
+            out.truncate(trimmed - 1);
+        }
+        out.push(closer);
+    }
+    serde_json::from_str(&out).ok()
+}
+
+/// Schema violations in `value`, as `path: problem`
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(schema, value, "$", &mut errors);
+    errors
+}
+
+fn type_matches(kind: &str, value: &Value) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(kind) = schema.get("type") {
+        let kinds: Vec<&str> = match kind {
+            Value::String(k) => vec![k.as_str()],
+            Value::Array(ks) => ks.iter().filter_map(|k| k.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !kinds.is_empty() && !kinds.iter().any(|k| type_matches(k, value)) {
+            errors.push(format!("{}: expected {}, got {}", path, kinds.join(" or "), value));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            errors.push(format!("{}: {} is not one of {}", path, value, allowed.join(", ")));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(|n| n.as_str()) {
+                    if !fields.contains_key(name) {
+                        errors.push(format!("{}: missing required field `{}`", path, name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, field) in fields {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => check(field_schema, field, &format!("{}.{}", path, name), errors),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{}: unexpected field `{}`", path, name));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            let count = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()).filter(|&m| count < m) {
+                errors.push(format!("{}: expected at least {} items, got {}", path, min, count));
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()).filter(|&m| count > m) {
+                errors.push(format!("{}: expected at most {} items, got {}", path, max, count));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            let length = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()).filter(|&m| length < m) {
+                errors.push(format!("{}: shorter than {} characters", path, min));
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()).filter(|&m| length > m) {
+                errors.push(format!("{}: longer than {} characters", path, max));
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()).filter(|&m| n < m) {
+                errors.push(format!("{}: {} is below the minimum {}", path, n, min));
+            }
+            if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()).filter(|&m| n > m) {
+                errors.push(format!("{}: {} is above the maximum {}", path, n, max));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn headline_schema() -> ResponseSchema {
+        ResponseSchema::new("headlines", json!({
+            "type": "object",
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string", "minLength": 1},
+                            "url": {"type": "string"},
+                            "score": {"type": "integer", "minimum": 0, "maximum": 10}
+                        },
+                        "required": ["title", "url"],
+                        "additionalProperties": false
+                    }
+                },
+                "mood": {"type": "string", "enum": ["good", "bad"]}
+            },
+            "required": ["items"]
+        }))
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Headline {
+        title: String,
+        url: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Headlines {
+        items: Vec<Headline>,
+    }
+
+    #[test]
+    fn test_extract_json_repairs() {
+        assert_eq!(extract_json("{\"a\": 1}"), Some(json!({"a": 1})));
+        assert_eq!(extract_json("Sure!\n```json\n{\"a\": [1, 2,], \"b\": \"}\"}\n```"), Some(json!({"a": [1, 2], "b": "}"})));
+        assert_eq!(extract_json("[{\"a\": 1}, {\"a\": \"cut"), Some(json!([{"a": 1}, {"a": "cut"}])));
+        assert_eq!(extract_json("{\"a\": [1, 2, "), Some(json!({"a": [1, 2]})));
+        assert_eq!(extract_json("{\"a\": 1]"), None);
+        assert_eq!(extract_json("no json"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = headline_schema().schema;
+        assert!(validate(&schema, &json!({"items": [{"title": "Rust 2.0", "url": "https://x", "score": 3}]})).is_empty());
+
+        let errors = validate(&schema, &json!({
+            "items": [{"title": "", "link": "https://x", "score": 11}],
+            "mood": "meh"
+        }));
+        assert_eq!(errors, vec![
+            "$.items[0]: missing required field `url`",
+            "$.items[0]: unexpected field `link`",
+            "$.items[0].score: 11 is above the maximum 10",
+            "$.items[0].title: shorter than 1 characters",
+            "$.mood: \"meh\" is not one of \"good\", \"bad\"",
+        ]);
+        assert_eq!(validate(&schema, &json!([])), vec!["$: expected object, got []"]);
+        assert_eq!(validate(&schema, &json!({"items": []})), vec!["$.items: expected at least 1 items, got 0"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_reasks_until_valid() {
+        let replies = Mutex::new(vec![
+            "{\"items\": [{\"title\": \"Rust 2.0\", \"url\": \"https://x\"}]}".to_string(),
+            "{\"items\": [{\"title\": \"Rust 2.0\"}]}".to_string(),
+        ]);
+        let seen = Mutex::new(Vec::new());
+        let schema = headline_schema();
+
+        let (headlines, reply): (Headlines, _) = run(vec![LLMMessage::user("news?")], &schema, |messages| {
+            seen.lock().unwrap().push(messages);
+            let content = replies.lock().unwrap().pop().unwrap();
+            async move {
+                Ok(LLMResponse { content, model: "m".to_string(), usage: None, finish_reason: None, tool_calls: Vec::new() })
+            }
+        }).await.unwrap();
+
+        assert_eq!(headlines.items, vec![Headline { title: "Rust 2.0".to_string(), url: "https://x".to_string() }]);
+        assert!(reply.content.contains("https://x"));
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0][0].role, "system");
+        assert!(seen[0][0].content.contains("\"required\":[\"items\"]"));
+        assert!(seen[1][3].content.contains("missing required field `url`"));
+    }
+
+    #[tokio::test]
+    async fn test_run_gives_up() {
+        let schema = headline_schema();
+        let result: LLMResult<(Headlines, _)> = run(vec![LLMMessage::user("news?")], &schema, |_| async {
+            Ok(LLMResponse { content: "no idea".to_string(), model: "m".to_string(), usage: None, finish_reason: None, tool_calls: Vec::new() })
+        }).await;
+        assert!(matches!(result, Err(LLMError::ParseError(e)) if e == "headlines reply: no JSON found"));
+    }
+}
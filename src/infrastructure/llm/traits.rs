@@ -2,14 +2,82 @@
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub use crate::domain::entities::{ToolCall, ToolDefinition};
 
 /// Chat message for LLM conversations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LLMMessage {
-    /// Role: "system", "user", or "assistant"
+    /// Role: "system", "user", "assistant" or "tool"
     pub role: String,
     /// Message content
     pub content: String,
+    /// Tools the assistant asked to call (assistant messages only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Call this message answers (tool messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Text and images, for messages with images; `content` keeps the text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ContentPart>,
+}
+
+/// Part of a multimodal message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    Image { source: ImageSource },
+}
+
+/// Where an image part comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Raw image bytes, e.g. a downloaded photo
+    Bytes { media_type: String, data: Vec<u8> },
+    /// Publicly reachable image URL
+    Url(String),
+}
+
+impl ImageSource {
+    /// Image bytes with the media type guessed from their signature
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        let media_type = match data.as_slice() {
+            [0x89, b'P', b'N', b'G', ..] => "image/png",
+            [b'G', b'I', b'F', b'8', ..] => "image/gif",
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+            _ => "image/jpeg",
+        };
+        Self::Bytes { media_type: media_type.to_string(), data }
+    }
+
+    /// `data:` URL for bytes, or the URL itself
+    pub fn to_url(&self) -> String {
+        match self {
+            Self::Bytes { media_type, data } => format!("data:{};base64,{}", media_type, base64_encode(data)),
+            Self::Url(url) => url.clone(),
+        }
+    }
+}
+
+/// Standard base64 with padding
+pub fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
 impl LLMMessage {
@@ -17,6 +85,9 @@ impl LLMMessage {
         Self {
             role: "system".to_string(),
             content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            parts: Vec::new(),
         }
     }
     
@@ -24,6 +95,9 @@ impl LLMMessage {
         Self {
             role: "user".to_string(),
             content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            parts: Vec::new(),
         }
     }
     
@@ -31,8 +105,46 @@ impl LLMMessage {
         Self {
             role: "assistant".to_string(),
             content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            parts: Vec::new(),
         }
     }
+
+    /// Assistant turn that requested tool calls
+    pub fn assistant_tool_calls(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+            tool_calls,
+            tool_call_id: None,
+            parts: Vec::new(),
+        }
+    }
+
+    /// Result of running a tool, sent back to the model
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: Some(tool_call_id.into()),
+            parts: Vec::new(),
+        }
+    }
+
+    /// User turn with images attached to the text
+    pub fn user_with_images(content: impl Into<String>, images: Vec<ImageSource>) -> Self {
+        let content = content.into();
+        let mut parts = vec![ContentPart::Text { text: content.clone() }];
+        parts.extend(images.into_iter().map(|source| ContentPart::Image { source }));
+        Self { parts, ..Self::user(content) }
+    }
+
+    /// Number of image parts
+    pub fn image_count(&self) -> usize {
+        self.parts.iter().filter(|p| matches!(p, ContentPart::Image { .. })).count()
+    }
 }
 
 /// LLM response
@@ -46,6 +158,39 @@ pub struct LLMResponse {
     pub usage: Option<LLMUsage>,
     /// Finish reason
     pub finish_reason: Option<String>,
+    /// Tools the model wants called before it answers
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl LLMResponse {
+    pub fn has_tool_calls(&self) -> bool {
+        !self.tool_calls.is_empty()
+    }
+}
+
+/// Embedding vectors for a batch of texts
+#[derive(Debug, Clone)]
+pub struct EmbeddingResponse {
+    /// Model that produced the vectors; only vectors of the same model compare
+    pub model: String,
+    /// One vector per input text, in input order
+    pub embeddings: Vec<Vec<f32>>,
+    pub usage: Option<LLMUsage>,
+}
+
+/// JSON schema a structured reply has to match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseSchema {
+    /// Short identifier, e.g. `intent_verdict`; some APIs require one
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+impl ResponseSchema {
+    pub fn new(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        Self { name: name.into(), schema }
+    }
 }
 
 /// Token usage information
@@ -63,16 +208,24 @@ pub enum LLMError {
     MissingApiKey,
     /// Invalid request
     InvalidRequest(String),
-    /// API error from provider
-    ApiError(String),
+    /// Non-success response from the provider
+    ApiError { status: u16, message: String },
     /// Network error
     NetworkError(String),
-    /// Rate limited
-    RateLimited,
+    /// No response within the configured timeout
+    Timeout,
+    /// Rate limited (429); `retry_after` is the server's `Retry-After`
+    RateLimited { retry_after: Option<Duration> },
+    /// The caller cancelled the request
+    Cancelled,
     /// Parse error
     ParseError(String),
     /// Configuration error
     ConfigError(String),
+    /// The user's usage budget is spent
+    BudgetExceeded(String),
+    /// A guardrail refused the request or the reply
+    Blocked(String),
 }
 
 impl std::fmt::Display for LLMError {
@@ -80,17 +233,43 @@ impl std::fmt::Display for LLMError {
         match self {
             LLMError::MissingApiKey => write!(f, "Missing API key"),
             LLMError::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
-            LLMError::ApiError(msg) => write!(f, "API error: {}", msg),
+            LLMError::ApiError { status, message } => write!(f, "API error ({}): {}", status, message),
             LLMError::NetworkError(msg) => write!(f, "Network error: {}", msg),
-            LLMError::RateLimited => write!(f, "Rate limited"),
+            LLMError::Timeout => write!(f, "Request timed out"),
+            LLMError::RateLimited { retry_after: Some(wait) } => write!(f, "Rate limited (retry after {}s)", wait.as_secs()),
+            LLMError::RateLimited { retry_after: None } => write!(f, "Rate limited"),
+            LLMError::Cancelled => write!(f, "Request cancelled"),
             LLMError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             LLMError::ConfigError(msg) => write!(f, "Config error: {}", msg),
+            LLMError::BudgetExceeded(msg) => write!(f, "{}", msg),
+            LLMError::Blocked(msg) => write!(f, "Blocked: {}", msg),
         }
     }
 }
 
 impl std::error::Error for LLMError {}
 
+impl LLMError {
+    /// HTTP status of the failed response, if there was one
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            LLMError::ApiError { status, .. } => Some(*status),
+            LLMError::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    /// Whether a retry (or another provider) may succeed where this failed:
+    /// rate limits, timeouts, network failures, 408 and 5xx responses
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LLMError::RateLimited { .. } | LLMError::NetworkError(_) | LLMError::Timeout => true,
+            LLMError::ApiError { status, .. } => *status == 408 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
 /// Result type for LLM operations
 pub type LLMResult<T> = Result<T, LLMError>;
 
@@ -108,7 +287,54 @@ pub trait LLM: Send + Sync {
         temperature: Option<f32>,
         max_tokens: Option<u32>,
     ) -> LLMResult<LLMResponse>;
+
+    /// Chat completion offering `tools` to the model.
+    ///
+    /// Providers without tool support answer as if no tools were given.
+    async fn chat_with_tools(
+        &self,
+        messages: Vec<LLMMessage>,
+        tools: &[ToolDefinition],
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let _ = tools;
+        self.chat(messages, model, temperature, max_tokens).await
+    }
     
+    /// Chat completion whose reply should be JSON matching `schema`.
+    ///
+    /// Providers with a native JSON mode use it; the default relies on the
+    /// schema instructions in the prompt. Callers still validate the reply,
+    /// see [`crate::infrastructure::llm::structured`].
+    async fn chat_json(
+        &self,
+        messages: Vec<LLMMessage>,
+        schema: &ResponseSchema,
+        model: Option<&str>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> LLMResult<LLMResponse> {
+        let _ = schema;
+        self.chat(messages, model, temperature, max_tokens).await
+    }
+
+    /// Embed `texts`, one vector per text.
+    ///
+    /// Providers without an embeddings API return [`LLMError::InvalidRequest`].
+    async fn embed(&self, texts: &[String], model: Option<&str>) -> LLMResult<EmbeddingResponse> {
+        let _ = (texts, model);
+        Err(LLMError::InvalidRequest(format!("{} has no embeddings API", self.name())))
+    }
+
+    /// Models the provider serves, e.g. from its `/models` endpoint.
+    ///
+    /// Providers without a model list return [`LLMError::InvalidRequest`].
+    async fn list_models(&self) -> LLMResult<Vec<String>> {
+        Err(LLMError::InvalidRequest(format!("{} can't list its models", self.name())))
+    }
+
     /// Streaming chat completion
     async fn chat_streaming(
         &self,
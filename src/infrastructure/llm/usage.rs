@@ -0,0 +1,208 @@
+//! Usage accounting - Token/cost ledger hooks and per-role budgets
+//!
+//! The router reports every successful call to a [`UsageSink`], attributed
+//! to the user and chat of the current [`UsageScope`] (a task-local set
+//! around message handling). Sinks can also veto a call when the user's
+//! monthly budget is spent.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::infrastructure::llm::LLMUsage;
+
+tokio::task_local! {
+    static USAGE_SCOPE: UsageScope;
+}
+
+/// Who an LLM call is made for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageScope {
+    pub user_id: String,
+    pub chat_id: String,
+}
+
+impl UsageScope {
+    pub fn new(user_id: impl Into<String>, chat_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            chat_id: chat_id.into(),
+        }
+    }
+
+    /// Run `future` with LLM calls attributed to this scope
+    pub async fn run<F: Future>(self, future: F) -> F::Output {
+        USAGE_SCOPE.scope(self, future).await
+    }
+
+    /// Scope of the running task, if any
+    pub fn current() -> Option<Self> {
+        USAGE_SCOPE.try_with(|scope| scope.clone()).ok()
+    }
+}
+
+/// One successful provider call
+#[derive(Debug, Clone)]
+pub struct UsageEvent {
+    pub provider: String,
+    pub model: String,
+    /// Task route name, e.g. `chat` or `summarization`
+    pub task: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Token counts were estimated because the provider sent none
+    pub estimated: bool,
+}
+
+impl UsageEvent {
+    /// Token counts from the provider's `usage`, or the given estimates
+    pub fn new(
+        provider: impl Into<String>,
+        model: impl Into<String>,
+        task: impl Into<String>,
+        usage: Option<&LLMUsage>,
+        estimate: (u64, u64),
+    ) -> Self {
+        let reported = usage.and_then(|u| match (u.prompt_tokens, u.completion_tokens) {
+            (Some(prompt), Some(completion)) => Some((prompt as u64, completion as u64)),
+            (Some(prompt), None) => Some((prompt as u64, u.total_tokens.unwrap_or(prompt).saturating_sub(prompt) as u64)),
+            _ => None,
+        });
+        let ((prompt_tokens, completion_tokens), estimated) = match reported {
+            Some(tokens) => (tokens, false),
+            None => (estimate, true),
+        };
+        Self {
+            provider: provider.into(),
+            model: model.into(),
+            task: task.into(),
+            prompt_tokens,
+            completion_tokens,
+            estimated,
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Receives usage from the router (e.g. the database ledger)
+#[async_trait]
+pub trait UsageSink: Send + Sync {
+    /// Record a successful call
+    async fn record(&self, scope: Option<&UsageScope>, event: &UsageEvent);
+
+    /// Refuse calls for a user whose budget is spent; the error is shown to them
+    async fn check_budget(&self, _scope: &UsageScope) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Monthly limits for a role; unset limits are unlimited
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct RoleBudget {
+    pub monthly_tokens: Option<u64>,
+    /// USD
+    pub monthly_cost: Option<f64>,
+}
+
+impl RoleBudget {
+    /// Why `tokens`/`cost` already used this month exhaust the budget, if they do
+    pub fn exceeded(&self, tokens: u64, cost: f64) -> Option<String> {
+        if let Some(limit) = self.monthly_tokens.filter(|limit| tokens >= *limit) {
+            return Some(format!("Monthly token budget reached ({} / {} tokens)", tokens, limit));
+        }
+        if let Some(limit) = self.monthly_cost.filter(|limit| cost >= *limit) {
+            return Some(format!("Monthly cost budget reached (${:.4} / ${:.2})", cost, limit));
+        }
+        None
+    }
+}
+
+/// Pricing table and budgets (`usage:` in config.yaml)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct UsageConfig {
+    /// Model name (or name prefix) -> price; the longest matching key wins
+    pub prices: HashMap<String, ModelPrice>,
+    /// Role (`owner`, `admin`, `user`, `guest`) -> monthly budget
+    pub budgets: HashMap<String, RoleBudget>,
+}
+
+impl UsageConfig {
+    /// Price for `model`, if the table has one
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        let model = model.to_lowercase();
+        self.prices.iter()
+            .filter(|(key, _)| model.starts_with(&key.to_lowercase()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost of a call in USD (0 for unpriced models)
+    pub fn cost(&self, event: &UsageEvent) -> f64 {
+        self.price(&event.model)
+            .map(|p| (event.prompt_tokens as f64 * p.input + event.completion_tokens as f64 * p.output) / 1_000_000.0)
+            .unwrap_or(0.0)
+    }
+
+    pub fn budget(&self, role: &str) -> Option<&RoleBudget> {
+        self.budgets.get(role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> UsageConfig {
+        serde_yaml::from_str(
+            "prices:\n  llama-3.3: {input: 0.59, output: 0.79}\n  llama: {input: 0.05, output: 0.08}\n\
+             budgets:\n  guest: {monthly-tokens: 1000}\n  user: {monthly-cost: 0.5}\n",
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_cost_uses_longest_prefix() {
+        let config = config();
+        let usage = LLMUsage { prompt_tokens: Some(1_000_000), completion_tokens: Some(500_000), total_tokens: None };
+        let event = UsageEvent::new("groq", "llama-3.3-70b-versatile", "chat", Some(&usage), (0, 0));
+        assert!((config.cost(&event) - 0.985).abs() < 1e-9);
+
+        let event = UsageEvent::new("groq", "llama-3.1-8b-instant", "chat", Some(&usage), (0, 0));
+        assert!((config.cost(&event) - 0.09).abs() < 1e-9);
+
+        let event = UsageEvent::new("claude", "claude-3-haiku", "chat", None, (10, 5));
+        assert!(event.estimated);
+        assert_eq!(config.cost(&event), 0.0);
+    }
+
+    #[test]
+    fn test_budgets() {
+        let config = config();
+        let guest = config.budget("guest").unwrap();
+        assert!(guest.exceeded(999, 10.0).is_none());
+        assert!(guest.exceeded(1000, 0.0).is_some());
+        assert!(config.budget("user").unwrap().exceeded(1_000_000, 0.5).is_some());
+        assert!(config.budget("owner").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scope_is_task_local() {
+        assert_eq!(UsageScope::current(), None);
+        let scope = UsageScope::new("7", "-100");
+        let seen = scope.clone().run(async { UsageScope::current() }).await;
+        assert_eq!(seen, Some(scope));
+    }
+}
@@ -0,0 +1,92 @@
+//! LLM fact extraction
+//!
+//! After a chat turn the model is asked which durable facts about the user
+//! the exchange revealed. Uses the `extraction` route so a small model can
+//! do it.
+
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{Fact, FactCategory};
+use crate::infrastructure::llm::{LLMMessage, LLMRouter, LLMTask};
+
+const SYSTEM_PROMPT: &str = "You pick out long-term facts about a user from one exchange with a chat assistant. \
+Only keep durable facts the user stated about themselves: name, location, language, job, family, \
+preferences, ongoing projects. Ignore one-off requests, questions, and anything about other people. \
+Skip facts already known. Write each fact as a short third-person sentence in English.\n\
+Reply with JSON only: [{\"category\": \"profile|preference|project|other\", \"fact\": \"...\"}], or [] if there are none.";
+
+/// JSON item expected from the model
+#[derive(Deserialize)]
+struct Extracted {
+    #[serde(default)]
+    category: String,
+    fact: String,
+}
+
+/// Parse the model's reply, tolerating code fences and surrounding prose
+pub fn parse_facts(reply: &str) -> Vec<(FactCategory, String)> {
+    let (Some(start), Some(end)) = (reply.find('['), reply.rfind(']')) else {
+        return Vec::new();
+    };
+    if end < start {
+        return Vec::new();
+    }
+    serde_json::from_str::<Vec<Extracted>>(&reply[start..=end])
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| (FactCategory::from_name(&e.category), e.fact.trim().to_string()))
+        .filter(|(_, fact)| !fact.is_empty())
+        .collect()
+}
+
+/// Asks the LLM for new facts in a chat turn
+pub struct FactExtractor {
+    llm: Arc<LLMRouter>,
+}
+
+impl FactExtractor {
+    pub fn new(llm: Arc<LLMRouter>) -> Self {
+        Self { llm }
+    }
+
+    /// New facts revealed by `user_text` (and the `reply` to it)
+    pub async fn extract(&self, known: &[Fact], user_text: &str, reply: &str) -> Vec<(FactCategory, String)> {
+        let mut prompt = String::new();
+        if !known.is_empty() {
+            prompt.push_str("Already known:\n");
+            for fact in known {
+                prompt.push_str(&format!("- {}\n", fact.content));
+            }
+            prompt.push('\n');
+        }
+        prompt.push_str(&format!("User: {}\nAssistant: {}", user_text, reply));
+
+        let messages = vec![LLMMessage::system(SYSTEM_PROMPT), LLMMessage::user(prompt)];
+        match self.llm.chat_task(LLMTask::Extraction, messages, Some(0.0), Some(300)).await {
+            Ok(response) => parse_facts(&response.content),
+            Err(e) => {
+                tracing::warn!("Fact extraction failed: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_facts() {
+        let reply = "```json\n[{\"category\": \"profile\", \"fact\": \"Lives in Solo\"}, {\"category\": \"hobby\", \"fact\": \"Plays gamelan\"}, {\"fact\": \" \"}]\n```";
+        let facts = parse_facts(reply);
+        assert_eq!(facts, vec![
+            (FactCategory::Profile, "Lives in Solo".to_string()),
+            (FactCategory::Other, "Plays gamelan".to_string()),
+        ]);
+
+        assert!(parse_facts("[]").is_empty());
+        assert!(parse_facts("Nothing to remember.").is_empty());
+    }
+}
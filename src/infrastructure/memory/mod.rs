@@ -0,0 +1,209 @@
+//! Long-term user memory - Durable facts about a user across conversations
+//!
+//! Facts come from explicit requests ("remember that ...", "ingat ...") or
+//! are extracted by the LLM after a chat turn. The most relevant ones are
+//! added to the system prompt; users manage them with `/memory`.
+
+pub mod extract;
+
+pub use extract::FactExtractor;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Memory configuration (`memory:` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct MemoryConfig {
+    pub enabled: bool,
+    /// Let the LLM pick up facts from normal conversation
+    pub auto_extract: bool,
+    /// Facts kept per user; the oldest extracted ones go first
+    pub max_facts: usize,
+    /// Facts added to the system prompt per message
+    pub inject_limit: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auto_extract: true,
+            max_facts: 100,
+            inject_limit: 10,
+        }
+    }
+}
+
+/// Kind of fact; `profile` facts are always included in the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FactCategory {
+    /// Name, location, language, job
+    Profile,
+    /// Likes, dislikes, how they want answers
+    Preference,
+    /// Things they are working on
+    Project,
+    Other,
+}
+
+impl FactCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FactCategory::Profile => "profile",
+            FactCategory::Preference => "preference",
+            FactCategory::Project => "project",
+            FactCategory::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "profile" => FactCategory::Profile,
+            "preference" => FactCategory::Preference,
+            "project" => FactCategory::Project,
+            _ => FactCategory::Other,
+        }
+    }
+}
+
+/// A remembered fact
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fact {
+    pub id: i64,
+    pub category: FactCategory,
+    pub content: String,
+}
+
+/// Leading phrases of an explicit memory request (EN/ID/JV), longest first
+const REMEMBER_PREFIXES: &[&str] = &[
+    "please remember that",
+    "remember that",
+    "please remember",
+    "remember",
+    "tolong ingat bahwa",
+    "tolong ingat kalau",
+    "ingatlah bahwa",
+    "ingat bahwa",
+    "ingat kalau",
+    "tolong ingat",
+    "ingatlah",
+    "ingat",
+    "elingana yen",
+    "elingna yen",
+    "elingana",
+    "elingna",
+    "eling yen",
+];
+
+/// The fact in an explicit "remember ..." request, if `text` is one
+pub fn explicit_fact(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    // "ingat nggak ...?" asks, it doesn't tell
+    if trimmed.ends_with('?') {
+        return None;
+    }
+    let lower = trimmed.to_lowercase();
+
+    REMEMBER_PREFIXES.iter().find_map(|prefix| {
+        let rest = lower.strip_prefix(prefix)?;
+        // Whole word only ("remembered ..." is not a request)
+        if !rest.is_empty() && !rest.starts_with([' ', ':', ',']) {
+            return None;
+        }
+        let fact = trimmed.get(prefix.len()..)?
+            .trim_start_matches([' ', ':', ','])
+            .trim()
+            .trim_end_matches(['.', '!']);
+        (!fact.is_empty()).then(|| fact.to_string())
+    })
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(|w| w.to_string())
+        .collect()
+}
+
+/// Facts worth adding to the prompt for `text`: every profile fact, then the
+/// others by word overlap with the message (newest first on ties)
+pub fn relevant<'a>(facts: &'a [Fact], text: &str, limit: usize) -> Vec<&'a Fact> {
+    let query = words(text);
+    let mut scored: Vec<(usize, &Fact)> = facts.iter()
+        .map(|f| {
+            let overlap = words(&f.content).intersection(&query).count();
+            let score = if f.category == FactCategory::Profile { usize::MAX } else { overlap };
+            (score, f)
+        })
+        .filter(|(score, _)| *score > 0)
+        .collect();
+    scored.sort_by(|(a, fa), (b, fb)| b.cmp(a).then(fb.id.cmp(&fa.id)));
+
+    let mut picked: Vec<&Fact> = scored.into_iter().map(|(_, f)| f).take(limit).collect();
+    // Fill remaining slots with the newest facts so preferences still apply
+    for fact in facts.iter().rev() {
+        if picked.len() >= limit {
+            break;
+        }
+        if !picked.iter().any(|p| p.id == fact.id) {
+            picked.push(fact);
+        }
+    }
+    picked
+}
+
+/// System prompt section listing `facts`
+pub fn prompt_section(facts: &[&Fact]) -> Option<String> {
+    if facts.is_empty() {
+        return None;
+    }
+    let mut section = "What you remember about this user (use when relevant, don't recite):".to_string();
+    for fact in facts {
+        section.push_str(&format!("\n- {}", fact.content));
+    }
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fact(id: i64, category: FactCategory, content: &str) -> Fact {
+        Fact { id, category, content: content.to_string() }
+    }
+
+    #[test]
+    fn test_explicit_fact() {
+        assert_eq!(explicit_fact("Remember that my name is Budi."), Some("my name is Budi".to_string()));
+        assert_eq!(explicit_fact("ingat: aku vegetarian"), Some("aku vegetarian".to_string()));
+        assert_eq!(explicit_fact("tolong ingat bahwa aku tinggal di Solo"), Some("aku tinggal di Solo".to_string()));
+        assert_eq!(explicit_fact("elingana yen aku seneng wedang jahe"), Some("aku seneng wedang jahe".to_string()));
+        assert_eq!(explicit_fact("remembered the milk?"), None);
+        assert_eq!(explicit_fact("remember"), None);
+        assert_eq!(explicit_fact("do you remember me?"), None);
+        assert_eq!(explicit_fact("ingat nggak namaku?"), None);
+    }
+
+    #[test]
+    fn test_relevant_facts() {
+        let facts = vec![
+            fact(1, FactCategory::Profile, "Name is Budi"),
+            fact(2, FactCategory::Project, "Building a rust telegram bot"),
+            fact(3, FactCategory::Preference, "Likes spicy food"),
+            fact(4, FactCategory::Other, "Has a cat"),
+        ];
+
+        let picked = relevant(&facts, "any tips for my rust project?", 2);
+        assert_eq!(picked.iter().map(|f| f.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        let picked = relevant(&facts, "hello", 3);
+        assert_eq!(picked.iter().map(|f| f.id).collect::<Vec<_>>(), vec![1, 4, 3]);
+
+        let section = prompt_section(&picked).unwrap();
+        assert!(section.ends_with("- Likes spicy food"));
+        assert_eq!(prompt_section(&[]), None);
+    }
+}
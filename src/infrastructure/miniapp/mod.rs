@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 
 use crate::infrastructure::llm::{LLMMessage, LLM};
+use crate::infrastructure::prompts::{Prompts, Vars};
 
 /// Mini-app trait - implemented by each mini-app
 pub trait MiniApp: Send + Sync {
@@ -33,6 +34,7 @@ impl Default for AppState {
 }
 
 /// Scramble game state
+#[derive(Debug)]
 pub struct ScrambleState {
     pub word: String,
     pub scrambled: String,
@@ -152,18 +154,13 @@ impl ScrambleApp {
     }
     
     /// Generate creative hint via LLM
-    pub fn generate_creative_hint_llm(word: &str, hint_num: u32, theme: Option<&str>) -> Option<String> {
-        let theme_hint = theme.map(|t| format!("Theme: {}. ", t)).unwrap_or_default();
-        
-        let prompt = if hint_num == 1 {
-            format!("{}Give a creative, fun hint for the word '{}' ({} letters). \
-Don't reveal the word! Be playful and indirect. Format: just the hint, nothing else.",
-                theme_hint, word, word.len())
-        } else {
-            format!("{}Give a second, more specific hint for the word '{}' ({} letters). \
-This is the second hint, so give more information but still don't reveal. Format: just the hint.",
-                theme_hint, word, word.len())
-        };
+    pub fn generate_creative_hint_llm(prompts: &Prompts, word: &str, hint_num: u32, theme: Option<&str>) -> Option<String> {
+        let vars = Vars::new()
+            .with("theme", theme.map(|t| format!("Theme: {}. ", t)).unwrap_or_default())
+            .with("word", word)
+            .with("letters", word.len().to_string());
+        let name = if hint_num == 1 { "scramble_hint" } else { "scramble_hint_second" };
+        let prompt = prompts.render(name, None, &vars);
         
         // Use blocking call to get LLM response
         // This requires the LLM to be passed in - placeholder for integration
@@ -6,6 +6,14 @@
 //! - Adapters: Platform integrations (Telegram, Discord, etc.)
 //! - Plugins: Plugin system
 //! - LLM: AI integration
+//! - Intent: Message intent classification
+//! - Memory: Long-term facts about users
+//! - RAG: Retrieval over workspace and uploaded documents
+//! - Prompts: LLM prompt templates
+//! - Eval: Prompt regression suite
+//! - Guard: Prompt-injection and output guardrails, audit log
+//! - Backup: Archives of the database, config and prompts
+//! - Hash: SHA-256 digests shared by the cache, RAG and backups
 
 pub mod config;
 pub mod database;
@@ -13,6 +21,14 @@ pub mod storage;
 pub mod adapters;
 pub mod plugins;
 pub mod llm;
+pub mod intent;
+pub mod memory;
+pub mod rag;
+pub mod prompts;
+pub mod eval;
+pub mod guard;
+pub mod backup;
+pub mod hash;
 pub mod webcrawler;
 pub mod financial;
 pub mod miniapp;
@@ -0,0 +1,215 @@
+//! Prompt templates - LLM prompts kept out of the code
+//!
+//! Every prompt the bot sends has a built-in default that a deployment can
+//! override with a file in the prompts directory (`<carik home>/prompts` by
+//! default): `<name>.txt` for all languages, `<name>.<lang>.txt` for users
+//! with that language. Templates use `{{variable}}` placeholders, e.g.
+//! `{{persona}}`, `{{user.language}}` or `{{headlines}}`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Prompt configuration (`prompts:` in config.yaml)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PromptsConfig {
+    /// Directory with template overrides; defaults to `<carik home>/prompts`
+    pub dir: Option<String>,
+}
+
+/// Built-in templates as (name, text); `name.lang` entries are per language
+const BUILTIN: &[(&str, &str)] = &[
+    ("system", "{{persona}}"),
+    ("language", ""),
+    ("language.jv", "IMPORTANT: Respond in Javanese language (ꦧꦱꦗꦮ)."),
+    ("language.id", "IMPORTANT: Respond in Indonesian language."),
+    ("personal_instructions", "Personal instructions: {{instructions}}"),
+    ("translate_system", "You are a professional translator. Provide accurate translations."),
+    ("translate", "Translate the following text to {{target_language}}.\n\nText:\n{{text}}\n\nTranslation:"),
+    ("news_summary", "You're a news reporter. Summarize these headlines in a friendly, conversational way: \
+a 2-3 sentence intro, then the headlines, each with its article URL.{{topic_hint}}\n\nHeadlines:\n{{headlines}}"),
+    ("finance_summary", "You're a financial analyst. Provide a brief, friendly summary (2-3 sentences) of these \
+market data, then list the key numbers. Keep it conversational and easy to understand.\n\n{{market_data}}"),
+    ("scramble_hint", "{{theme}}Give a creative, fun hint for the word '{{word}}' ({{letters}} letters). \
+Don't reveal the word! Be playful and indirect. Format: just the hint, nothing else."),
+    ("scramble_hint_second", "{{theme}}Give a second, more specific hint for the word '{{word}}' ({{letters}} letters). \
+This is the second hint, so give more information but still don't reveal. Format: just the hint."),
+];
+
+/// Values substituted into a template
+#[derive(Debug, Clone, Default)]
+pub struct Vars(HashMap<String, String>);
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.0.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|v| v.as_str())
+    }
+}
+
+/// Replace `{{name}}` placeholders (spaces inside the braces are allowed).
+/// Unknown variables render empty; an unclosed `{{` is kept as text.
+pub fn render(template: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = rest[start + 2..start + 2 + len].trim();
+        match vars.get(name) {
+            Some(value) => out.push_str(value),
+            None => tracing::warn!("Prompt variable {{{{{}}}}} is not set", name),
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Where a template comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Builtin,
+    File(PathBuf),
+}
+
+/// Built-in templates plus the overrides found in a directory
+#[derive(Debug, Clone, Default)]
+pub struct Prompts {
+    dir: Option<PathBuf>,
+    /// Keyed by file stem: `name` or `name.lang`
+    overrides: HashMap<String, (PathBuf, String)>,
+}
+
+impl Prompts {
+    /// Built-in templates only
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    /// Built-ins overridden by the `.txt` files in `dir` (which may not exist)
+    pub fn load(dir: impl Into<PathBuf>) -> Self {
+        let mut prompts = Self { dir: Some(dir.into()), overrides: HashMap::new() };
+        if let Err(e) = prompts.reload() {
+            tracing::warn!("Failed to load prompt templates: {}", e);
+        }
+        prompts
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Re-read the override files; returns how many were found
+    pub fn reload(&mut self) -> std::io::Result<usize> {
+        self.overrides.clear();
+        let Some(dir) = &self.dir else {
+            return Ok(0);
+        };
+        if !dir.exists() {
+            return Ok(0);
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string()) else {
+                continue;
+            };
+            let text = std::fs::read_to_string(&path)?;
+            // Editors add a final newline; prompts are joined with their own spacing
+            let text = text.strip_suffix('\n').unwrap_or(&text).to_string();
+            self.overrides.insert(stem, (path, text));
+        }
+        Ok(self.overrides.len())
+    }
+
+    /// Names of the built-in templates (without language variants)
+    pub fn names() -> Vec<&'static str> {
+        BUILTIN.iter().map(|(name, _)| *name).filter(|name| !name.contains('.')).collect()
+    }
+
+    /// Template text for `name`, most specific first: override for the
+    /// language, built-in for the language, override, built-in
+    pub fn template(&self, name: &str, lang: Option<&str>) -> Option<(&str, Source)> {
+        let builtin = |key: &str| BUILTIN.iter().find(|(n, _)| *n == key).map(|(_, text)| (*text, Source::Builtin));
+        let file = |key: &str| self.overrides.get(key).map(|(path, text)| (text.as_str(), Source::File(path.clone())));
+
+        let localized = lang.filter(|l| !l.is_empty()).map(|l| format!("{}.{}", name, l));
+        localized.as_deref()
+            .and_then(|key| file(key).or_else(|| builtin(key)))
+            .or_else(|| file(name))
+            .or_else(|| builtin(name))
+    }
+
+    /// Render `name` for a user with language `lang`; unknown names render empty
+    pub fn render(&self, name: &str, lang: Option<&str>, vars: &Vars) -> String {
+        match self.template(name, lang) {
+            Some((template, _)) => render(template, vars),
+            None => {
+                tracing::warn!("Unknown prompt template '{}'", name);
+                String::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = Vars::new().with("word", "RUST").with("user.language", "jv");
+        assert_eq!(render("Guess {{word}} in {{ user.language }}!", &vars), "Guess RUST in jv!");
+        assert_eq!(render("{{missing}}: {{word}}", &vars), ": RUST");
+        assert_eq!(render("{{word}} {{unclosed", &vars), "RUST {{unclosed");
+        assert_eq!(render("no placeholders", &vars), "no placeholders");
+    }
+
+    #[test]
+    fn test_builtin_languages() {
+        let prompts = Prompts::builtin();
+        let vars = Vars::new();
+        assert!(prompts.render("language", Some("jv"), &vars).contains("Javanese"));
+        assert!(prompts.render("language", Some("id"), &vars).contains("Indonesian"));
+        assert_eq!(prompts.render("language", Some("en"), &vars), "");
+        assert_eq!(prompts.render("language", None, &vars), "");
+        assert_eq!(prompts.render("nope", None, &vars), "");
+        assert!(Prompts::names().contains(&"news_summary"));
+        assert!(!Prompts::names().contains(&"language.jv"));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let dir = std::env::temp_dir().join(format!("carik-prompts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("system.txt"), "{{persona}} Keep it short.\n").unwrap();
+        std::fs::write(dir.join("language.jv.txt"), "Wangsulana nganggo basa Jawa krama.").unwrap();
+        std::fs::write(dir.join("notes.md"), "ignored").unwrap();
+
+        let mut prompts = Prompts::load(&dir);
+        let vars = Vars::new().with("persona", "I am carik.");
+        assert_eq!(prompts.render("system", Some("id"), &vars), "I am carik. Keep it short.");
+        assert_eq!(prompts.render("language", Some("jv"), &vars), "Wangsulana nganggo basa Jawa krama.");
+        assert!(prompts.render("language", Some("id"), &vars).contains("Indonesian"));
+        assert_eq!(prompts.template("system", None).unwrap().1, Source::File(dir.join("system.txt")));
+
+        std::fs::remove_file(dir.join("system.txt")).unwrap();
+        assert_eq!(prompts.reload().unwrap(), 1);
+        assert_eq!(prompts.render("system", None, &vars), "I am carik.");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+//! BM25 keyword ranking, used when no embedding model is configured
+
+use std::collections::HashMap;
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Lowercase words of two or more characters
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_string())
+        .collect()
+}
+
+/// Term statistics for a fixed set of documents
+pub struct Bm25 {
+    /// Term frequencies per document
+    docs: Vec<HashMap<String, usize>>,
+    lengths: Vec<usize>,
+    avg_length: f32,
+    /// Number of documents containing each term
+    doc_freq: HashMap<String, usize>,
+}
+
+impl Bm25 {
+    pub fn new<'a>(docs: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut freqs = Vec::new();
+        let mut lengths = Vec::new();
+        let mut doc_freq: HashMap<String, usize> = HashMap::new();
+
+        for doc in docs {
+            let terms = tokenize(doc);
+            let mut tf: HashMap<String, usize> = HashMap::new();
+            for term in &terms {
+                *tf.entry(term.clone()).or_default() += 1;
+            }
+            for term in tf.keys() {
+                *doc_freq.entry(term.clone()).or_default() += 1;
+            }
+            lengths.push(terms.len());
+            freqs.push(tf);
+        }
+
+        let avg_length = if lengths.is_empty() {
+            0.0
+        } else {
+            lengths.iter().sum::<usize>() as f32 / lengths.len() as f32
+        };
+        Self { docs: freqs, lengths, avg_length, doc_freq }
+    }
+
+    /// Score of every document for `query`, in document order
+    pub fn scores(&self, query: &str) -> Vec<f32> {
+        let n = self.docs.len() as f32;
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        self.docs.iter().zip(&self.lengths).map(|(tf, &length)| {
+            terms.iter().map(|term| {
+                let Some(&f) = tf.get(term) else {
+                    return 0.0;
+                };
+                let df = self.doc_freq.get(term).copied().unwrap_or(0) as f32;
+                let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                let f = f as f32;
+                let norm = 1.0 - B + B * length as f32 / self.avg_length.max(1.0);
+                idf * f * (K1 + 1.0) / (f + K1 * norm)
+            }).sum()
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rare_terms_rank_higher() {
+        let docs = [
+            "the bot answers questions in javanese",
+            "the bot stores history in sqlite",
+            "the bot the bot the bot",
+        ];
+        let bm25 = Bm25::new(docs);
+        let scores = bm25.scores("where is history stored? sqlite");
+        assert!(scores[1] > scores[0]);
+        assert!(scores[1] > scores[2]);
+        assert_eq!(scores[0], 0.0);
+        assert!(bm25.scores("bot")[2] > bm25.scores("bot")[0]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Chunking - Split documents into overlapping, line-addressed pieces
+//!
+//! Chunks follow line boundaries so citations can point at `file:12-30`.
+//! Markdown is split at headings, code prefers blank lines; lines longer
+//! than a whole chunk are cut.
+
+/// A piece of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// First line, 1-based
+    pub start_line: usize,
+    /// Last line, inclusive
+    pub end_line: usize,
+    pub text: String,
+    /// Markdown heading the chunk falls under
+    pub heading: Option<String>,
+}
+
+/// One line (or piece of an overlong line)
+struct Piece<'a> {
+    line: usize,
+    text: &'a str,
+}
+
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].starts_with(' ')
+}
+
+/// Cut `line` into pieces of at most `max_chars` characters
+fn split_line(line: &str, max_chars: usize) -> Vec<&str> {
+    if line.chars().count() <= max_chars {
+        return vec![line];
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (count, (i, _)) in line.char_indices().enumerate() {
+        if count > 0 && count % max_chars == 0 {
+            pieces.push(&line[start..i]);
+            start = i;
+        }
+    }
+    pieces.push(&line[start..]);
+    pieces
+}
+
+/// Split `text` into chunks of about `max_chars`. A chunk cut in the middle
+/// of a block repeats its last `overlap_lines` lines at the start of the next.
+pub fn chunk_text(text: &str, markdown: bool, max_chars: usize, overlap_lines: usize) -> Vec<Chunk> {
+    let max_chars = max_chars.max(1);
+    let pieces: Vec<Piece> = text.lines()
+        .enumerate()
+        .flat_map(|(i, line)| split_line(line, max_chars).into_iter().map(move |text| Piece { line: i + 1, text }))
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<&Piece> = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut chunk_heading: Option<String> = None;
+
+    let size = |pieces: &[&Piece]| pieces.iter().map(|p| p.text.len() + 1).sum::<usize>();
+
+    for piece in &pieces {
+        let starts_section = markdown && is_heading(piece.text);
+        if !current.is_empty() && (starts_section || size(&current) + piece.text.len() > max_chars) {
+            // Code: end at a blank line in the second half of the chunk if there is one
+            let split = if starts_section || markdown {
+                current.len()
+            } else {
+                current.iter()
+                    .rposition(|p| p.text.trim().is_empty())
+                    .filter(|&i| i >= current.len() / 2)
+                    .map(|i| i + 1)
+                    .unwrap_or(current.len())
+            };
+            let rest = current.split_off(split);
+            push_chunk(&mut chunks, &current, chunk_heading.take());
+
+            current = if starts_section || !rest.is_empty() {
+                rest
+            } else {
+                current[current.len().saturating_sub(overlap_lines)..].to_vec()
+            };
+            if size(&current) + piece.text.len() > max_chars {
+                current.clear();
+            }
+        }
+        if starts_section {
+            heading = Some(piece.text.trim_start_matches('#').trim().to_string());
+        }
+        if current.is_empty() {
+            chunk_heading = heading.clone();
+        }
+        current.push(piece);
+    }
+    push_chunk(&mut chunks, &current, chunk_heading);
+    chunks
+}
+
+fn push_chunk(chunks: &mut Vec<Chunk>, pieces: &[&Piece], heading: Option<String>) {
+    // Leading and trailing blank lines aren't part of the citation
+    let blank = |p: &&Piece| p.text.trim().is_empty();
+    let start = pieces.iter().position(|p| !blank(p)).unwrap_or(pieces.len());
+    let end = pieces.iter().rposition(|p| !blank(p)).map(|i| i + 1).unwrap_or(start);
+    let pieces = &pieces[start..end];
+    let (Some(first), Some(last)) = (pieces.first(), pieces.last()) else {
+        return;
+    };
+    let mut text = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        // Pieces of one overlong line join without a newline
+        if i > 0 && pieces[i - 1].line != piece.line {
+            text.push('\n');
+        }
+        text.push_str(piece.text);
+    }
+    chunks.push(Chunk {
+        start_line: first.line,
+        end_line: last.line,
+        text,
+        heading,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_splits_at_headings() {
+        let text = "# Install\ncargo build\n\n## Run\ncargo run\nthen chat\n";
+        let chunks = chunk_text(text, true, 1000, 1);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 2));
+        assert_eq!(chunks[1].heading.as_deref(), Some("Run"));
+        assert_eq!(chunks[1].text, "## Run\ncargo run\nthen chat");
+    }
+
+    #[test]
+    fn test_code_prefers_blank_lines_and_overlaps() {
+        let text = "fn a() {\n    one();\n}\n\nfn b() {\n    two();\n}\n";
+        let chunks = chunk_text(text, false, 40, 1);
+        assert_eq!(chunks[0].text, "fn a() {\n    one();\n}");
+        assert_eq!(chunks[1].start_line, 5);
+        assert!(chunks.last().unwrap().text.ends_with("two();\n}"));
+
+        let lines = (1..=10).map(|i| format!("line {:02}", i)).collect::<Vec<_>>().join("\n");
+        let chunks = chunk_text(&lines, false, 40, 2);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 5));
+        assert_eq!(chunks[1].start_line, 4);
+    }
+
+    #[test]
+    fn test_long_lines_are_cut() {
+        let text = "x".repeat(25);
+        let chunks = chunk_text(&text, false, 10, 0);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.start_line == 1 && c.text.len() <= 10));
+    }
+}
@@ -0,0 +1,276 @@
+//! Retrieval - Answer from workspace files and uploaded documents
+//!
+//! Documents are split into line-addressed chunks and stored with their
+//! embeddings (when an `embedding` route is configured). For each chat
+//! message the closest chunks are added to the prompt with numbered
+//! citations; without embeddings chunks are ranked with BM25 instead.
+
+pub mod bm25;
+pub mod chunk;
+
+pub use chunk::{chunk_text, Chunk};
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::infrastructure::guard;
+use crate::infrastructure::llm::LLMRouter;
+
+/// Texts per embeddings request
+const EMBED_BATCH: usize = 32;
+
+/// Retrieval configuration (`rag:` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct RagConfig {
+    pub enabled: bool,
+    /// Target chunk size in characters
+    pub chunk_size: usize,
+    /// Lines repeated between consecutive chunks
+    pub chunk_overlap: usize,
+    /// Chunks added to the prompt per message
+    pub top_k: usize,
+    /// Minimum cosine similarity for embedding matches
+    pub min_similarity: f32,
+    /// Minimum BM25 score for keyword matches
+    pub min_bm25: f32,
+    /// Larger files are not indexed
+    pub max_file_kb: u64,
+    /// File extensions that are indexed
+    pub extensions: Vec<String>,
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            chunk_size: 1200,
+            chunk_overlap: 2,
+            top_k: 4,
+            min_similarity: 0.35,
+            min_bm25: 1.0,
+            max_file_kb: 512,
+            extensions: ["md", "markdown", "txt", "rst", "rs", "py", "js", "ts", "go", "java", "c", "h",
+                "cpp", "sh", "toml", "yaml", "yml", "json", "sql", "html", "css"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl RagConfig {
+    /// Whether `path` has an indexed extension
+    pub fn indexes(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+    }
+
+    /// Chunk a document; Markdown is split at headings
+    pub fn chunk(&self, source: &str, text: &str) -> Vec<Chunk> {
+        let markdown = [".md", ".markdown"].iter().any(|e| source.to_lowercase().ends_with(e));
+        chunk_text(text, markdown, self.chunk_size, self.chunk_overlap)
+    }
+}
+
+/// A stored chunk, ready for ranking
+#[derive(Debug, Clone)]
+pub struct IndexedChunk {
+    /// File path or upload name
+    pub source: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+    /// Model the embedding came from
+    pub embedding_model: Option<String>,
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// A chunk picked for the prompt
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub source: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+    pub score: f32,
+}
+
+impl Hit {
+    /// `source:start-end`
+    pub fn citation(&self) -> String {
+        if self.start_line == self.end_line {
+            format!("{}:{}", self.source, self.start_line)
+        } else {
+            format!("{}:{}-{}", self.source, self.start_line, self.end_line)
+        }
+    }
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+/// Best chunks for `query`. Chunks embedded with the query's model are
+/// ranked by cosine similarity; if none of them match, all chunks by BM25.
+pub fn search(
+    chunks: &[IndexedChunk],
+    query: &str,
+    query_embedding: Option<(&str, &[f32])>,
+    config: &RagConfig,
+) -> Vec<Hit> {
+    let mut scored: Vec<(f32, &IndexedChunk)> = Vec::new();
+
+    if let Some((model, vector)) = query_embedding {
+        scored = chunks.iter()
+            .filter(|c| c.embedding_model.as_deref() == Some(model))
+            .filter_map(|c| Some((cosine(c.embedding.as_deref()?, vector), c)))
+            .collect();
+        if !scored.is_empty() {
+            scored.retain(|(score, _)| *score >= config.min_similarity);
+        }
+    }
+
+    if scored.is_empty() {
+        let bm25 = bm25::Bm25::new(chunks.iter().map(|c| c.content.as_str()));
+        scored = bm25.scores(query).into_iter()
+            .zip(chunks)
+            .filter(|(score, _)| *score >= config.min_bm25)
+            .collect();
+    }
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter()
+        .take(config.top_k)
+        .map(|(score, c)| Hit {
+            source: c.source.clone(),
+            start_line: c.start_line,
+            end_line: c.end_line,
+            content: c.content.clone(),
+            score,
+        })
+        .collect()
+}
+
+/// System prompt section with numbered excerpts
+pub fn prompt_section(hits: &[Hit]) -> Option<String> {
+    if hits.is_empty() {
+        return None;
+    }
+    let mut section = "Excerpts from the user's documents. Use them when they answer the question \
+and cite them inline as [1], [2], ...; don't cite excerpts you didn't use."
+        .to_string();
+    for (i, hit) in hits.iter().enumerate() {
+        section.push_str(&format!("\n\n[{}] {}\n{}", i + 1, hit.citation(), guard::untrusted("document", &hit.content)));
+    }
+    Some(section)
+}
+
+/// "Sources" footer listing the excerpts `reply` cites
+pub fn sources_footer(hits: &[Hit], reply: &str) -> Option<String> {
+    let cited: Vec<String> = hits.iter()
+        .enumerate()
+        .filter(|(i, _)| reply.contains(&format!("[{}]", i + 1)))
+        .map(|(i, hit)| format!("[{}] {}", i + 1, hit.citation()))
+        .collect();
+    (!cited.is_empty()).then(|| format!("📎 Sources:\n{}", cited.join("\n")))
+}
+
+/// Computes embeddings through the router's `embedding` route
+pub struct Embedder {
+    llm: Arc<LLMRouter>,
+}
+
+impl Embedder {
+    pub fn new(llm: Arc<LLMRouter>) -> Self {
+        Self { llm }
+    }
+
+    /// Embeddings for all `texts` and the model used, or `None` when no
+    /// embedding provider is available (documents are then keyword-ranked)
+    pub async fn embed_all(&self, texts: &[String]) -> Option<(String, Vec<Vec<f32>>)> {
+        let mut model = String::new();
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            match self.llm.embed(batch).await {
+                // A fallback provider mid-way would mix incomparable vectors
+                Ok(response) if !model.is_empty() && response.model != model => {
+                    tracing::warn!("Embedding model changed from {} to {}", model, response.model);
+                    return None;
+                }
+                Ok(response) => {
+                    model = response.model;
+                    vectors.extend(response.embeddings);
+                }
+                Err(e) => {
+                    tracing::debug!("Embeddings unavailable: {}", e);
+                    return None;
+                }
+            }
+        }
+        Some((model, vectors))
+    }
+
+    /// Embedding of a query
+    pub async fn embed_query(&self, text: &str) -> Option<(String, Vec<f32>)> {
+        let (model, mut vectors) = self.embed_all(&[text.to_string()]).await?;
+        vectors.pop().map(|v| (model, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(source: &str, content: &str, embedding: Option<Vec<f32>>) -> IndexedChunk {
+        IndexedChunk {
+            source: source.to_string(),
+            start_line: 1,
+            end_line: 3,
+            content: content.to_string(),
+            embedding_model: embedding.as_ref().map(|_| "embed".to_string()),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn test_search_by_embedding() {
+        let chunks = vec![
+            chunk("a.md", "installing the bot", Some(vec![1.0, 0.0])),
+            chunk("b.md", "configuring providers", Some(vec![0.6, 0.8])),
+            chunk("c.md", "unrelated", Some(vec![-1.0, 0.0])),
+        ];
+        let config = RagConfig::default();
+        let hits = search(&chunks, "setup", Some(("embed", &[0.8, 0.6])), &config);
+        assert_eq!(hits.iter().map(|h| h.source.as_str()).collect::<Vec<_>>(), vec!["b.md", "a.md"]);
+
+        // Vectors from another model don't compare: fall back to keywords
+        let hits = search(&chunks, "configuring providers", Some(("other", &[0.8, 0.6])), &config);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].source, "b.md");
+    }
+
+    #[test]
+    fn test_citations() {
+        let chunks = vec![
+            chunk("docs/setup.md", "run cargo build to compile the bot", None),
+            chunk("notes.txt", "the bot speaks javanese", None),
+        ];
+        let hits = search(&chunks, "how do I compile with cargo?", None, &RagConfig::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].citation(), "docs/setup.md:1-3");
+
+        let section = prompt_section(&hits).unwrap();
+        assert!(section.contains("[1] docs/setup.md:1-3\n<<<UNTRUSTED source=document>>>\nrun cargo build"));
+        assert_eq!(sources_footer(&hits, "Run `cargo build` [1]."), Some("📎 Sources:\n[1] docs/setup.md:1-3".to_string()));
+        assert_eq!(sources_footer(&hits, "No idea."), None);
+    }
+}
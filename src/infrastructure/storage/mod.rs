@@ -1,7 +1,14 @@
 //! File-based storage implementation
+//!
+//! Everything is kept in memory and written back to `users.json`,
+//! `messages.json` and `kv.json` under `base_path` after each change. Files
+//! are replaced atomically (write a temp file, then rename), so a crash never
+//! leaves a half-written one.
 
 use async_trait::async_trait;
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
@@ -10,6 +17,37 @@ use crate::domain::traits::Store;
 use crate::domain::entities::{User, Message};
 use crate::application::errors::StorageError;
 
+const USERS_FILE: &str = "users.json";
+const MESSAGES_FILE: &str = "messages.json";
+const KV_FILE: &str = "kv.json";
+
+/// Where user profiles and the message log are kept (`storage:` in config.yaml)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Directory of the JSON files, for the `json` backend
+    pub path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Sqlite,
+            path: "data".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// The bot's SQLite database
+    Sqlite,
+    /// [`JsonStore`] files
+    Json,
+}
+
 /// JSON file-based store
 pub struct JsonStore {
     base_path: PathBuf,
@@ -28,12 +66,40 @@ impl JsonStore {
         }
     }
 
+    /// Create `base_path` and load whatever was saved there
     pub async fn init(&self) -> Result<(), StorageError> {
         tokio::fs::create_dir_all(&self.base_path).await?;
+        *self.users.write().await = load(&self.base_path.join(USERS_FILE)).await?;
+        *self.messages.write().await = load(&self.base_path.join(MESSAGES_FILE)).await?;
+        *self.kv.write().await = load(&self.base_path.join(KV_FILE)).await?;
         Ok(())
     }
 }
 
+/// Contents of a JSON file; empty if it doesn't exist yet
+async fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T, StorageError> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| StorageError::Serialization(format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether `user_id` sent `message` or it was sent in their private chat
+fn is_about(message: &Message, user_id: &str) -> bool {
+    message.chat_id == user_id || message.sender.as_ref().is_some_and(|u| u.id == user_id)
+}
+
+/// Replace `path` with `value` as JSON
+async fn flush<T: Serialize>(path: &Path, value: &T) -> Result<(), StorageError> {
+    let json = serde_json::to_vec_pretty(value).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
 #[async_trait]
 impl Store for JsonStore {
     async fn get_user(&self, id: &str) -> Result<Option<User>, StorageError> {
@@ -44,7 +110,7 @@ impl Store for JsonStore {
     async fn save_user(&self, user: &User) -> Result<(), StorageError> {
         let mut users = self.users.write().await;
         users.insert(user.id.clone(), user.clone());
-        Ok(())
+        flush(&self.base_path.join(USERS_FILE), &*users).await
     }
 
     async fn save_message(&self, message: &Message) -> Result<(), StorageError> {
@@ -52,19 +118,51 @@ impl Store for JsonStore {
         messages.entry(message.chat_id.clone())
             .or_insert_with(Vec::new)
             .push(message.clone());
-        Ok(())
+        flush(&self.base_path.join(MESSAGES_FILE), &*messages).await
     }
 
     async fn get_messages(&self, chat_id: &str, limit: usize) -> Result<Vec<Message>, StorageError> {
         let messages = self.messages.read().await;
         let chat_messages = messages.get(chat_id);
-        
+
         match chat_messages {
             Some(msgs) => Ok(msgs.iter().rev().take(limit).cloned().collect()),
             None => Ok(Vec::new()),
         }
     }
 
+    async fn user_messages(&self, user_id: &str) -> Result<Vec<Message>, StorageError> {
+        let messages = self.messages.read().await;
+        let mut found: Vec<Message> = messages.values()
+            .flatten()
+            .filter(|m| is_about(m, user_id))
+            .cloned()
+            .collect();
+        found.sort_by_key(|m| m.timestamp);
+        Ok(found)
+    }
+
+    async fn delete_user(&self, user_id: &str) -> Result<usize, StorageError> {
+        let mut users = self.users.write().await;
+        let mut messages = self.messages.write().await;
+        let mut removed = 0;
+        if users.remove(user_id).is_some() {
+            removed += 1;
+            flush(&self.base_path.join(USERS_FILE), &*users).await?;
+        }
+        let before: usize = messages.values().map(Vec::len).sum();
+        messages.retain(|_, chat| {
+            chat.retain(|m| !is_about(m, user_id));
+            !chat.is_empty()
+        });
+        let deleted = before - messages.values().map(Vec::len).sum::<usize>();
+        if deleted > 0 {
+            removed += deleted;
+            flush(&self.base_path.join(MESSAGES_FILE), &*messages).await?;
+        }
+        Ok(removed)
+    }
+
     async fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
         let kv = self.kv.read().await;
         Ok(kv.get(key).cloned())
@@ -73,12 +171,56 @@ impl Store for JsonStore {
     async fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
         let mut kv = self.kv.write().await;
         kv.insert(key.to_string(), value.to_string());
-        Ok(())
+        flush(&self.base_path.join(KV_FILE), &*kv).await
     }
 
     async fn delete(&self, key: &str) -> Result<(), StorageError> {
         let mut kv = self.kv.write().await;
-        kv.remove(key);
+        if kv.remove(key).is_some() {
+            flush(&self.base_path.join(KV_FILE), &*kv).await?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_persists_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("carik-store-{}", uuid::Uuid::new_v4()));
+        let store = JsonStore::new(&dir);
+        store.init().await.unwrap();
+        store.save_user(&User::new("7").with_username("budi")).await.unwrap();
+        store.save_message(&Message::from_text("c1", "hello").with_sender(User::new("7"))).await.unwrap();
+        store.set("theme", "dark").await.unwrap();
+        store.set("gone", "soon").await.unwrap();
+        store.delete("gone").await.unwrap();
+
+        let reopened = JsonStore::new(&dir);
+        reopened.init().await.unwrap();
+        assert_eq!(reopened.get_user("7").await.unwrap().and_then(|u| u.username).as_deref(), Some("budi"));
+        let messages = reopened.get_messages("c1", 10).await.unwrap();
+        assert_eq!(messages[0].content.text(), Some("hello"));
+        assert_eq!(reopened.get("theme").await.unwrap().as_deref(), Some("dark"));
+        assert_eq!(reopened.get("gone").await.unwrap(), None);
+        assert!(!dir.join("kv.json.tmp").exists());
+
+        // Deleting a user takes their messages, in any chat, along
+        reopened.save_message(&Message::from_text("7", "private")).await.unwrap();
+        reopened.save_message(&Message::from_text("c1", "other")).await.unwrap();
+        assert_eq!(reopened.user_messages("7").await.unwrap().len(), 2);
+        assert_eq!(reopened.delete_user("7").await.unwrap(), 3);
+        let reopened = JsonStore::new(&dir);
+        reopened.init().await.unwrap();
+        assert_eq!(reopened.get_user("7").await.unwrap(), None);
+        assert!(reopened.user_messages("7").await.unwrap().is_empty());
+        assert_eq!(reopened.get_messages("c1", 10).await.unwrap().len(), 1);
+
+        // A corrupt file is reported, not silently replaced
+        std::fs::write(dir.join(KV_FILE), "{not json").unwrap();
+        assert!(matches!(JsonStore::new(&dir).init().await, Err(StorageError::Serialization(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,8 +1,9 @@
+use async_trait::async_trait;
 use clap::{Parser, Subcommand};
 use tracing_subscriber;
 use std::collections::HashMap;
 use std::fs;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use once_cell::sync::Lazy;
 
@@ -11,23 +12,35 @@ mod application;
 mod infrastructure;
 mod plugins;
 
-use infrastructure::config::Config;
-use infrastructure::database;
+use infrastructure::config::{Config, HistoryConfig};
+use infrastructure::database::{self, Database, DatabaseConfig};
 use infrastructure::adapters::telegram::TelegramAdapter;
 use infrastructure::adapters::console::ConsoleAdapter;
-use infrastructure::llm::{LLM, GroqProvider, LLMMessage};
+use infrastructure::llm::{CancelToken, ContextManager, Conversation, ImageSource, LLMConfig, LLMError, LLMMessage, LLMRoute, LLMRouter, LLMTask, ModelSelector, UsageConfig, UsageEvent, UsageScope, UsageSink, ResponseCache};
+use infrastructure::intent::{Intent, IntentConfig, IntentContext, IntentRouter};
+use infrastructure::memory::{self, explicit_fact, FactCategory, FactExtractor, MemoryConfig};
+use infrastructure::rag::{self, Embedder, RagConfig};
+use infrastructure::prompts::{Prompts, Source, Vars};
+use infrastructure::eval;
+use infrastructure::guard::{self, AuditLog, Guard};
+use infrastructure::backup;
+use infrastructure::hash;
+use infrastructure::storage::{JsonStore, StorageBackend, StorageConfig};
 use application::services::CommandService;
-use domain::traits::Bot;
+use domain::traits::{Bot, KeyboardButton, Store};
 use plugins::{PluginManager, trait_def::ExtendedPluginConfig};
 use infrastructure::miniapp::{MiniAppManager, AppState};
 
-// Global database instance
-static DB: Lazy<Mutex<Option<database::Database>>> = Lazy::new(|| Mutex::new(None));
-
 // Global mini-app manager
 static MINI_APPS: Lazy<MiniAppManager> = Lazy::new(|| MiniAppManager::new());
 
+// Prompt templates (built-ins plus overrides from the prompts directory)
+static PROMPTS: Lazy<RwLock<Prompts>> = Lazy::new(|| RwLock::new(Prompts::builtin()));
+
 // User mini-app states
+/// Response cache hits and misses per task since startup
+static CACHE_METRICS: Lazy<Mutex<HashMap<&'static str, (u64, u64)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
 static APP_STATES: Lazy<Mutex<HashMap<String, AppState>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Parser)]
@@ -54,8 +67,65 @@ enum Commands {
     Version,
     /// Generate default config
     InitConfig,
+    /// Run the prompt eval suite and write a report
+    Eval {
+        /// Suite of conversations (YAML)
+        #[arg(long, default_value = eval::DEFAULT_SUITE)]
+        suite: String,
+        /// Provider to test; defaults to the configured chain
+        #[arg(long)]
+        provider: Option<String>,
+        /// Model on that provider
+        #[arg(long)]
+        model: Option<String>,
+        /// Also grade replies with an LLM judge (classification route)
+        #[arg(long)]
+        judge: bool,
+        /// Earlier JSON report to compare against
+        #[arg(long)]
+        baseline: Option<String>,
+        /// Report path without extension; writes .json and .md
+        #[arg(long, default_value = "evals/report")]
+        out: String,
+    },
+    /// Database schema maintenance
+    Db {
+        /// SQLite database file
+        #[arg(long, default_value = DB_PATH)]
+        path: String,
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Archive the database, config and prompts into one file
+    Backup {
+        /// SQLite database file
+        #[arg(long, default_value = DB_PATH)]
+        db: String,
+        /// Directory for the archive; defaults to backup.dir in config
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Restore an archive made by `backup` (stop the bot first)
+    Restore {
+        /// The .tar.gz archive
+        archive: String,
+        /// SQLite database file to replace
+        #[arg(long, default_value = DB_PATH)]
+        db: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Apply pending schema migrations
+    Migrate,
+    /// Show applied and pending migrations
+    Status,
 }
 
+/// Database file used by the bot
+const DB_PATH: &str = "carik-bot.db";
+
 fn main() {
     // Initialize logging
     let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
@@ -78,42 +148,242 @@ fn main() {
         Commands::InitConfig => {
             init_config();
         }
+        Commands::Eval { suite, provider, model, judge, baseline, out } => {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let options = EvalOptions { suite, provider, model, judge, baseline, out };
+            let regressions = rt.block_on(run_eval(&cli.config, options));
+            if regressions {
+                std::process::exit(1);
+            }
+        }
+        Commands::Db { path, action } => {
+            run_db_command(&path, action);
+        }
+        Commands::Backup { db, out } => {
+            run_backup_command(&cli.config, &db, out);
+        }
+        Commands::Restore { archive, db } => {
+            run_restore_command(&cli.config, &db, &archive);
+        }
     }
 }
 
-fn run_bot(config_path: String, token_override: Option<String>) {
-    // Load config
-    let config = if std::path::Path::new(&config_path).exists() {
-        Config::load(&config_path).unwrap_or_else(|e| {
+/// Where `backup` reads the bot's files from and `restore` puts them back
+fn backup_paths(config_path: &str, config: &Config, db_path: &str) -> backup::Paths {
+    backup::Paths {
+        database: db_path.into(),
+        config: config_path.into(),
+        soul: "SOUL.md".into(),
+        prompts: prompts_dir(config).into(),
+        home: CARIK_HOME.into(),
+    }
+}
+
+/// `carik-bot backup`
+fn run_backup_command(config_path: &str, db_path: &str, out: Option<String>) {
+    let config = load_config(config_path);
+    let out = out.unwrap_or_else(|| config.backup.clone().unwrap_or_default().dir);
+    let result = Database::open(db_path, DatabaseConfig::default())
+        .map_err(|e| format!("{}: {}", db_path, e))
+        .and_then(|db| backup::create(&db, &backup_paths(config_path, &config, db_path), std::path::Path::new(&out)));
+    match result {
+        Ok(archive) => println!("Backup written to {}", archive.display()),
+        Err(e) => {
+            eprintln!("❌ Backup failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `carik-bot restore <archive>`
+fn run_restore_command(config_path: &str, db_path: &str, archive: &str) {
+    let config = load_config(config_path);
+    match backup::restore(std::path::Path::new(archive), &backup_paths(config_path, &config, db_path)) {
+        Ok(manifest) => {
+            println!("Restored backup from {} (carik-bot v{}, schema {})", manifest.created_at, manifest.bot_version, manifest.schema_version);
+            for file in &manifest.files {
+                println!("  {}", file.path);
+            }
+            if !manifest.workspaces.is_empty() {
+                let names: Vec<&str> = manifest.workspaces.iter().map(|w| w.name.as_str()).collect();
+                println!("Workspaces (directories only, copy their files separately): {}", names.join(", "));
+            }
+            println!("Replaced files were kept as *.before-restore.");
+        }
+        Err(e) => {
+            eprintln!("❌ Restore failed, nothing was replaced: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Back up every `backup.interval-hours` while the bot runs, keeping the
+/// newest `backup.keep` archives
+async fn run_scheduled_backups(db: Arc<Database>, paths: backup::Paths, config: backup::BackupConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_hours.max(1) * 60 * 60));
+    // The first tick is immediate; the first backup is one interval after startup
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let (paths, config) = (paths.clone(), config.clone());
+        db.call(move |db| {
+            let dir = std::path::Path::new(&config.dir);
+            match backup::create(db, &paths, dir) {
+                Ok(archive) => tracing::info!("Backup written to {}", archive.display()),
+                Err(e) => {
+                    tracing::error!("Scheduled backup failed: {}", e);
+                    return;
+                }
+            }
+            match backup::prune(dir, config.keep) {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("Removed {} old backups", removed),
+                Err(e) => tracing::warn!("Failed to remove old backups: {}", e),
+            }
+        }).await;
+    }
+}
+
+/// `carik-bot db migrate|status`
+fn run_db_command(path: &str, action: DbAction) {
+    let db = match Database::open(path, DatabaseConfig::default()) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("❌ Failed to open {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    
+    match action {
+        DbAction::Migrate => match db.migrate() {
+            Ok(applied) if applied.is_empty() => println!("Database is up to date."),
+            Ok(applied) => {
+                for m in &applied {
+                    println!("Applied {:>3}  {}", m.version, m.name);
+                }
+                println!("Schema is now at version {}.", applied.last().map_or(0, |m| m.version));
+            }
+            Err(e) => {
+                eprintln!("❌ Migration failed, database left at the last applied version: {}", e);
+                std::process::exit(1);
+            }
+        },
+        DbAction::Status => match db.schema_status() {
+            Ok(status) => {
+                println!("Schema version {} (latest {})", status.current, status.latest);
+                for m in &status.applied {
+                    println!("  applied {:>3}  {}  ({})", m.version, m.name, m.applied_at);
+                }
+                for m in &status.pending {
+                    println!("  pending {:>3}  {}", m.version, m.name);
+                }
+                if status.current > status.latest {
+                    println!("⚠️ The database is newer than this build.");
+                }
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to read schema status: {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Load config from `config_path`, or from the environment if it is missing or invalid
+fn load_config(config_path: &str) -> Config {
+    if std::path::Path::new(config_path).exists() {
+        Config::load(config_path).unwrap_or_else(|e| {
             tracing::warn!("Failed to load config: {}, using defaults", e);
             Config::load_env()
         })
     } else {
         Config::load_env()
-    };
+    }
+}
 
-    tracing::info!("Starting carik-bot: {}", config.bot.name);
-    
-    // Initialize database
-    let db = match database::Database::new("carik-bot.db") {
+/// Load prompt templates, with overrides from the configured directory
+fn load_prompts(config: &Config) {
+    *PROMPTS.write().unwrap() = Prompts::load(prompts_dir(config));
+}
+
+/// Prompt override directory: `prompts.dir`, or `prompts/` in carik home
+fn prompts_dir(config: &Config) -> String {
+    config.prompts.clone().unwrap_or_default().dir.unwrap_or_else(|| format!("{}/prompts", get_carik_home()))
+}
+
+/// Guardrails from `guard:` in config, checking replies for the configured
+/// API keys and the bot token, logging to `security.audit`
+fn build_guard(config: &Config, llm_config: &LLMConfig, bot_token: Option<&str>) -> Guard {
+    let mut secrets = llm_config.secrets();
+    secrets.extend(bot_token.map(|t| t.to_string()));
+    Guard::new(config.guard.clone().unwrap_or_default(), secrets, AuditLog::new(&config.security.audit))
+}
+
+/// The bot's database; in memory if the file can't be opened, so the bot
+/// still runs (without persisting anything)
+fn open_database(config: &Config) -> Database {
+    match Database::with_config(DB_PATH, config.database.clone().unwrap_or_default()) {
         Ok(db) => {
             tracing::info!("Database initialized");
-            // Set global DB
-            *DB.lock().unwrap() = Some(db);
-            Some(())
+            db
         }
         Err(e) => {
-            tracing::error!("Failed to initialize database: {}", e);
-            None
+            tracing::error!("Failed to initialize database, nothing will be saved: {}", e);
+            Database::new(":memory:").expect("in-memory database")
         }
-    };
+    }
+}
+
+/// Store for user profiles and the message log: the database, or JSON files
+/// with `storage.backend: json`
+async fn open_store(config: &StorageConfig, db: &Arc<Database>) -> Arc<dyn Store> {
+    if config.backend == StorageBackend::Sqlite {
+        return db.clone();
+    }
+    let store = JsonStore::new(&config.path);
+    match store.init().await {
+        Ok(()) => {
+            tracing::info!("Storing users and messages under {}", config.path);
+            Arc::new(store)
+        }
+        Err(e) => {
+            tracing::error!("Failed to load the JSON store from {}, using the database: {}", config.path, e);
+            db.clone()
+        }
+    }
+}
+
+/// SOUL.md as the system persona
+fn load_persona() -> String {
+    match fs::read_to_string("SOUL.md") {
+        Ok(content) => {
+            tracing::info!("Loaded persona from SOUL.md");
+            content
+        }
+        Err(_) => {
+            tracing::warn!("SOUL.md not found, using default persona");
+            "You are carik-bot, a helpful and friendly AI assistant.".to_string()
+        }
+    }
+}
+
+fn run_bot(config_path: String, token_override: Option<String>) {
+    // Load config
+    let config = load_config(&config_path);
+
+    tracing::info!("Starting carik-bot: {}", config.bot.name);
+    
+    // Initialize database
+    let db = Arc::new(open_database(&config));
+    
+    // Drop conversation history past its retention
+    let history_config = config.history.clone().unwrap_or_default();
+    prune_history(&db, &history_config);
     
     // Initialize owner from config if not exists
-    if DB.lock().unwrap().is_some() {
-        if let Ok(config) = Config::load("config.yaml") {
-            for user_id in &config.whitelist.users {
-                let _ = DB.lock().unwrap().as_ref().unwrap().add_user(user_id, None, "owner");
-            }
+    if let Ok(config) = Config::load("config.yaml") {
+        for user_id in &config.whitelist.users {
+            let _ = db.add_user(user_id, None, "owner");
         }
     }
     
@@ -136,16 +406,16 @@ fn run_bot(config_path: String, token_override: Option<String>) {
     commands.register_defaults();
     
     // Register start command (welcome message)
-    register_start_command(&mut commands);
+    register_start_command(&mut commands, &db);
     
     // Register connect command (for guests)
     register_connect_command(&mut commands);
     
     // Register approve command (owner only)
-    register_approve_command(&mut commands);
+    register_approve_command(&mut commands, &db);
     
     // Register users command (owner/admin)
-    register_users_command(&mut commands);
+    register_users_command(&mut commands, &db);
     
     // Register workspace command
     register_workspace_command(&mut commands);
@@ -160,11 +430,52 @@ fn run_bot(config_path: String, token_override: Option<String>) {
     register_financial_command(&mut commands);
     
     // Register settings command
-    register_settings_command(&mut commands);
+    register_settings_command(&mut commands, &db);
+    
+    // Register alias command and load saved aliases
+    register_alias_command(&mut commands, &db);
+    load_aliases(&db, &commands);
+    
+    // Register conversation history commands (/clear, /history)
+    register_history_command(&mut commands, &db);
+    
+    // Register search command (full-text search over history)
+    register_search_command(&mut commands, &db);
+    
+    // Register mydata command (personal data export and deletion)
+    register_mydata_command(&mut commands, &db);
+    
+    // Register usage command (token/cost ledger)
+    let usage_config = config.usage.clone().unwrap_or_default();
+    register_usage_command(&mut commands, &db, usage_config.clone());
+    
+    // Register memory command (long-term user facts)
+    let memory_config = config.memory.clone().unwrap_or_default();
+    register_memory_command(&mut commands, &db);
+    
+    // Register docs command (retrieval over workspace and uploaded documents)
+    let rag_config = config.rag.clone().unwrap_or_default();
+    register_docs_command(&mut commands, &db);
+    
+    // Load prompt templates and register /prompt
+    load_prompts(&config);
+    register_prompt_command(&mut commands);
+    
+    // Register model command (per-chat provider/model)
+    register_model_command(&mut commands, &db);
+    
+    // Register cache command (LLM response cache stats)
+    register_cache_command(&mut commands, &db);
 
     // Select adapter
     let rt = tokio::runtime::Runtime::new().unwrap();
     
+    // Scheduled backups
+    if let Some(backup_config) = config.backup.clone().filter(|b| b.enabled) {
+        tracing::info!("Backing up every {}h to {}, keeping {}", backup_config.interval_hours, backup_config.dir, backup_config.keep);
+        rt.spawn(run_scheduled_backups(db.clone(), backup_paths(&config_path, &config, DB_PATH), backup_config));
+    }
+    
     if let Some(token) = token_override.or_else(|| {
         config.adapters.telegram
             .as_ref()
@@ -177,14 +488,18 @@ fn run_bot(config_path: String, token_override: Option<String>) {
             None
         };
         rt.block_on(async {
-            let mut bot = TelegramAdapter::new(token, allowed_users);
+            let llm_config = config.llm.clone().unwrap_or_default().with_env();
+            let guard = build_guard(&config, &llm_config, Some(&token));
+            let bot = TelegramAdapter::new(token, allowed_users);
             
             // Register bot commands with Telegram
             if let Err(e) = bot.register_commands().await {
                 tracing::warn!("Failed to register commands: {}", e);
             }
             
-            run_telegram_bot(&mut bot, &mut commands).await;
+            let intent_config = config.intent.clone().unwrap_or_default();
+            let store = open_store(&config.storage.clone().unwrap_or_default(), &db).await;
+            run_telegram_bot(bot, commands, plugin_manager, db, store, llm_config, intent_config, history_config, usage_config, memory_config, rag_config, guard).await;
         });
     } else {
         // Run console bot (dev mode)
@@ -195,10 +510,21 @@ fn run_bot(config_path: String, token_override: Option<String>) {
     }
 }
 
-async fn run_telegram_bot(bot: &mut TelegramAdapter, commands: &mut CommandService) {
-    use domain::entities::{Message, Content};
-    use infrastructure::adapters::telegram::TelegramAdapter;
-
+#[allow(clippy::too_many_arguments)]
+async fn run_telegram_bot(
+    mut bot: TelegramAdapter,
+    commands: CommandService,
+    plugins: PluginManager,
+    db: Arc<Database>,
+    store: Arc<dyn Store>,
+    llm_config: LLMConfig,
+    intent_config: IntentConfig,
+    history_config: HistoryConfig,
+    usage_config: UsageConfig,
+    memory_config: MemoryConfig,
+    rag_config: RagConfig,
+    guard: Guard,
+) {
     // Fetch bot info
     if let Err(e) = bot.fetch_bot_info().await {
         tracing::error!("Failed to fetch bot info: {}", e);
@@ -209,213 +535,417 @@ async fn run_telegram_bot(bot: &mut TelegramAdapter, commands: &mut CommandServi
     tracing::info!("Bot started: @{}", info.username);
 
     // Load SOUL.md as system persona
-    let system_prompt = match fs::read_to_string("SOUL.md") {
-        Ok(content) => content,
-        Err(_) => {
-            tracing::warn!("SOUL.md not found, using default persona");
-            "You are carik-bot, a helpful and friendly AI assistant.".to_string()
-        }
-    };
-    tracing::info!("Loaded persona from SOUL.md");
+    let system_prompt = load_persona();
 
-    // Initialize LLM (Groq with selected model)
-    let llm: Option<GroqProvider> = match std::env::var("GROQ_API_KEY") {
-        Ok(api_key) => {
-            // Check for saved model preference
-            let model = std::fs::read_to_string("/home/ubuntu/.carik-bot/groq-model.txt")
-                .map(|s| s.trim().to_string())
-                .unwrap_or_else(|_| "llama-3.3-70b-versatile".to_string());
-            
-            Some(GroqProvider::new(api_key, Some(&model)))
-        }
-        Err(_) => {
-            tracing::warn!("GROQ_API_KEY not set, using echo mode");
-            None
-        }
+    // Initialize LLM router (provider chain from config, per-chat picks from /model)
+    // Data subject requests share the guardrails' audit log
+    let audit = guard.audit_log();
+    
+    let context = ContextManager::new(llm_config.context.clone());
+    let router = LLMRouter::from_config(llm_config.clone())
+        .with_usage_sink(Arc::new(UsageLedger::new(db.clone(), usage_config)))
+        .with_model_selector(Arc::new(ChatModelSelector::new(db.clone(), llm_config)))
+        .with_guardrail(Arc::new(guard))
+        .with_response_cache(Arc::new(ResponseCacheStore::new(db.clone())));
+    let llm: Option<Arc<LLMRouter>> = if router.is_empty() {
+        tracing::warn!("No LLM provider configured, using echo mode");
+        None
+    } else {
+        tracing::info!("Using LLM providers: {}", router.provider_names().join(" -> "));
+        Some(Arc::new(router))
     };
-    if llm.is_some() {
-        let model = std::fs::read_to_string("/home/ubuntu/.carik-bot/groq-model.txt")
-            .map(|s| s.trim().to_string())
-            .unwrap_or_else(|_| "llama-3.3-70b-versatile".to_string());
-        tracing::info!("Using Groq {} for AI responses", model);
-    }
 
-    // Track first messages per chat for welcome
-    let mut first_message: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
+    // Intent classifiers (rules, and the LLM when one is available)
+    let intents = IntentRouter::from_config(&intent_config, llm.clone());
+    tracing::info!("Intent classifiers: {}", intents.classifier_names().join(" -> "));
 
-    // Conversation history per chat
-    let mut conversations: std::collections::HashMap<String, Vec<LLMMessage>> = std::collections::HashMap::new();
+    // Conversation history lives in SQLite; prune it periodically
+    let mut last_prune = std::time::Instant::now();
 
     let mut offset: i64 = 0;
     let timeout_seconds = 30;
 
+    // Ctrl-C cancels in-flight LLM calls and stops the loop; a second one exits
+    let shutdown = CancelToken::new();
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if shutdown.is_cancelled() {
+                    std::process::exit(130);
+                }
+                tracing::info!("Shutting down...");
+                shutdown.cancel();
+            }
+        });
+    }
+
+    let state = Arc::new(TelegramState {
+        bot,
+        commands: Arc::new(commands),
+        plugins: Arc::new(plugins),
+        db,
+        store,
+        llm,
+        intents,
+        context,
+        memory_config,
+        rag_config,
+        system_prompt,
+        audit,
+        shutdown,
+        first_message: Mutex::new(HashMap::new()),
+    });
+
+    // Chats are handled concurrently, each one's updates in order: a chat's
+    // task waits for the previous one from the same chat
+    let mut chats: HashMap<String, tokio::task::JoinHandle<()>> = HashMap::new();
+
     tracing::info!("Starting message loop...");
     
     loop {
-        match bot.get_updates(offset, timeout_seconds).await {
+        if last_prune.elapsed() >= HISTORY_PRUNE_INTERVAL {
+            let config = history_config.clone();
+            state.db.call(move |db| prune_history(db, &config)).await;
+            last_prune = std::time::Instant::now();
+        }
+        
+        let updates = tokio::select! {
+            updates = state.bot.get_updates(offset, timeout_seconds) => updates,
+            _ = state.shutdown.cancelled() => break,
+        };
+        match updates {
             Ok(updates) => {
                 if !updates.is_empty() {
                     tracing::info!("Received {} updates", updates.len());
                 }
-                for update in &updates {
-                    // Extract chat_id and text from message
-                    if let Some(msg) = &update.message {
-                        let chat_id = msg.chat.id.to_string();
-                        let mut text = msg.text.clone().unwrap_or_default();
-                        
-                        // Check for reply to another message
-                        let reply_text = msg.reply_to_message.as_ref()
-                            .and_then(|r| r.text.clone());
-                        
-                        // Update username if available
-                        let username = msg.from.as_ref().map(|u| u.username.as_deref());
-                        if let Some(uname) = username {
-                            update_user_username(&chat_id, uname);
+                offset = TelegramAdapter::get_next_offset(&updates);
+                
+                chats.retain(|_, task| !task.is_finished());
+                for update in updates {
+                    let chat_id = update_chat_id(&update);
+                    let previous = chats.remove(&chat_id);
+                    let state = state.clone();
+                    chats.insert(chat_id, tokio::spawn(async move {
+                        if let Some(previous) = previous {
+                            let _ = previous.await;
                         }
-                        
-                        // Check if bot is mentioned in group (group chats have negative IDs)
-                        let chat_id_i64: i64 = chat_id.parse().unwrap_or(0);
-                        let is_group = chat_id_i64 < 0;
-                        let is_mention = if is_group && !text.starts_with('/') {
-                            let mention = format!("@{}", info.username);
-                            if text.to_lowercase().contains(&mention.to_lowercase()) {
-                                // Remove mention from text
-                                text = text.replace(&mention, "").replace(&mention.to_lowercase(), "").trim().to_string();
-                                true
-                            } else {
-                                false
-                            }
+                        handle_update(&state, update).await;
+                    }));
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to get updates: {}", e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        }
+    }
+    
+    // In-flight LLM calls were cancelled; let the handlers send what they have
+    for (_, task) in chats {
+        let _ = task.await;
+    }
+}
+
+/// What the Telegram update handlers share
+struct TelegramState {
+    bot: TelegramAdapter,
+    commands: Arc<CommandService>,
+    plugins: Arc<PluginManager>,
+    db: Arc<Database>,
+    /// User profiles and the message log
+    store: Arc<dyn Store>,
+    llm: Option<Arc<LLMRouter>>,
+    intents: IntentRouter,
+    context: ContextManager,
+    memory_config: MemoryConfig,
+    rag_config: RagConfig,
+    system_prompt: String,
+    audit: Arc<AuditLog>,
+    shutdown: CancelToken,
+    /// Chats that have sent a message since startup
+    first_message: Mutex<HashMap<String, bool>>,
+}
+
+/// Chat an update comes from; updates from the same chat are handled in order
+fn update_chat_id(update: &infrastructure::adapters::telegram::Update) -> String {
+    update.message.as_ref()
+        .or_else(|| update.callback_query.as_ref().and_then(|cb| cb.message.as_ref()))
+        .map(|m| m.chat.id.to_string())
+        .unwrap_or_default()
+}
+
+/// Run a command handler on the blocking pool: handlers use the database
+/// and blocking HTTP clients
+async fn handle_command(commands: &Arc<CommandService>, msg: domain::entities::Message) -> Result<Option<String>, application::errors::CommandError> {
+    let commands = commands.clone();
+    match tokio::task::spawn_blocking(move || commands.handle(&msg)).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+async fn handle_update(state: &TelegramState, update: infrastructure::adapters::telegram::Update) {
+    if let Some(msg) = &update.message {
+        handle_message(state, msg).await;
+    }
+    
+    // Handle callback queries
+    if let Some(cb) = &update.callback_query {
+        let TelegramState { bot, db, .. } = state;
+        let chat_id = cb.message.as_ref().map(|m| m.chat.id.to_string()).unwrap_or_default();
+        let mut notice = None;
+        if let Some(page) = cb.data.as_deref().and_then(|d| d.strip_prefix("search:")) {
+            let message_id = cb.message.as_ref().map(|m| m.message_id.to_string()).unwrap_or_default();
+            notice = turn_search_page(bot, db, &chat_id, &message_id, &cb.from.id.to_string(), page).await;
+        } else if let Some(action) = cb.data.as_deref().and_then(|d| d.strip_prefix("mydata:")) {
+            notice = confirm_mydata(state, &chat_id, &cb.from.id.to_string(), action).await;
+        } else if let Some(data) = &cb.data {
+            let _ = bot.send_message(&chat_id, &format!("Callback: {}", data)).await;
+        }
+        let _ = bot.answer_callback(&cb.id, notice).await;
+    }
+}
+
+async fn handle_message(state: &TelegramState, msg: &infrastructure::adapters::telegram::Message) {
+    use domain::entities::{Message, User};
+    
+    let TelegramState {
+        bot, commands, plugins, db, store, llm, intents, context, memory_config, rag_config,
+        system_prompt, audit, shutdown, first_message,
+    } = state;
+    
+    let chat_id = msg.chat.id.to_string();
+    let user_id = msg.from.as_ref().map(|u| u.id.to_string()).unwrap_or_else(|| chat_id.clone());
+    let mut text = msg.text.clone().or_else(|| msg.caption.clone()).unwrap_or_default();
+    
+    // Check for reply to another message
+    let reply_text = msg.reply_to_message.as_ref()
+        .and_then(|r| r.text.clone());
+    
+    // Keep the stored username and name current, and log the message
+    if let Some(from) = &msg.from {
+        update_user_profile(store.as_ref(), &user_id, from).await;
+    }
+    if !text.is_empty() {
+        log_message(store.as_ref(), msg, &user_id, &text).await;
+    }
+    
+    // Check if bot is mentioned in group (group chats have negative IDs)
+    let chat_id_i64: i64 = chat_id.parse().unwrap_or(0);
+    let is_group = chat_id_i64 < 0;
+    let is_mention = if is_group && !text.starts_with('/') {
+        let mention = format!("@{}", bot.bot_info().username);
+        if text.to_lowercase().contains(&mention.to_lowercase()) {
+            // Remove mention from text
+            text = text.replace(&mention, "").replace(&mention.to_lowercase(), "").trim().to_string();
+            true
+        } else {
+            false
+        }
+    } else {
+        false
+    };
+    
+    // Uploaded files go into the sender's document index (in groups only when addressed)
+    if let Some(document) = msg.document.as_ref().filter(|_| rag_config.enabled && (!is_group || is_mention)) {
+        let response = index_upload(bot, db, llm.as_ref(), rag_config, &user_id, document).await;
+        if let Err(e) = bot.send_message(&chat_id, &response).await {
+            tracing::error!("Failed to send message: {}", e);
+        }
+    }
+    
+    // Photos, and text replying to one, go to the vision model
+    let photo = msg.photo.as_ref()
+        .or_else(|| msg.reply_to_message.as_ref().and_then(|r| r.photo.as_ref()).filter(|_| !text.is_empty()))
+        .filter(|_| (!is_group || is_mention) && !text.starts_with('/'));
+    if let Some(photo) = photo {
+        let question = if text.is_empty() { DEFAULT_PHOTO_QUESTION.to_string() } else { text.clone() };
+        let _ = bot.send_chat_action(&chat_id, "typing").await;
+        let (mut conversation, message_ids) = load_conversation(db, &chat_id, &user_id).await;
+        let loaded = conversation.messages.len();
+        let response = shutdown.clone()
+            .run(UsageScope::new(&user_id, &chat_id).run(answer_photo(bot, llm.as_ref(), &question, &mut conversation, context, system_prompt, photo)))
+            .await;
+        save_conversation(db, &chat_id, &user_id, &conversation, loaded, &message_ids).await;
+        if let Err(e) = bot.send_message(&chat_id, &response).await {
+            tracing::error!("Failed to send message: {}", e);
+        }
+        return;
+    }
+    
+    // Skip if just mentioned without any actual text
+    if text.is_empty() {
+        return;
+    }
+    
+    // Expand user-defined aliases and macros into the steps to run
+    let steps = match commands.expand_aliases(&user_id, &text) {
+        Ok(Some(steps)) => steps,
+        Ok(None) => vec![text.clone()],
+        Err(e) => {
+            if let Err(e) = bot.send_message(&chat_id, &format!("❌ {}", e)).await {
+                tracing::error!("Failed to send message: {}", e);
+            }
+            return;
+        }
+    };
+    
+    for text in steps {
+        // Check if this is the first message from this chat
+        first_message.lock().unwrap().entry(chat_id.clone()).or_insert(true);
+        
+        // Process command or message
+        if text.starts_with(&commands.prefix()) || text.starts_with('/') {
+            // Extract the first word (the command) for exact matching
+            // Remove leading / if present
+            let cmd_text = text.trim_start_matches('/').trim_start_matches(&commands.prefix());
+            let command = cmd_text.split_whitespace().next().unwrap_or("").to_lowercase();
+            
+            // Match exactly against known mini-app commands
+            match command.as_str() {
+                "scramble" | "hint" | "guess" | "quit" => {
+                    let handled = {
+                        let mut app_states = APP_STATES.lock().unwrap();
+                        let user_state = app_states.entry(chat_id.clone()).or_insert_with(AppState::default);
+                        MINI_APPS.handle(&text, &chat_id, user_state)
+                    };
+                    
+                    if let Some(response) = handled {
+                        if let Err(e) = bot.send_message(&chat_id, &response).await {
+                            tracing::error!("Failed to send message: {}", e);
+                        }
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            
+            // Check for /code command (coding agent)
+            let trimmed = text.trim_start_matches(&commands.prefix()).trim_start_matches('/');
+            if trimmed.starts_with("code") {
+                // Check guest access first
+                let chat_id_str = chat_id.to_string();
+                let response = match can_use_privileged(&chat_id_str) {
+                    Ok(false) => {
+                        "❌ Access denied.\n\nUse /connect to request one-time guest access,\nor ask the owner to add you.".to_string()
+                    }
+                    Err(e) => format!("Error: {}", e),
+                    _ => {
+                        // Extract the prompt from /code command
+                        let prompt = if trimmed.starts_with("code ") {
+                            // Has space after "code", grab everything after position 5
+                            trimmed[5..].trim().to_string()
+                        } else if trimmed.len() > 5 {
+                            // Has content after "code" (no space) - grab from position 4
+                            trimmed[4..].trim().to_string()
                         } else {
-                            false
+                            // Just "code" or "/code" with nothing after
+                            String::new()
                         };
                         
-                        // Skip if just mentioned without any actual text
-                        if text.is_empty() {
-                            continue;
-                        }
-                        
-                        if !text.is_empty() {
-                            // Check if this is the first message from this chat
-                            let is_first = first_message.get(&chat_id).is_none();
-                            if is_first {
-                                first_message.insert(chat_id.clone(), true);
-                            }
-                            
-                            // Process command or message
-                            if text.starts_with(&commands.prefix()) || text.starts_with('/') {
-                                // Extract the first word (the command) for exact matching
-                                // Remove leading / if present
-                                let cmd_text = text.trim_start_matches('/').trim_start_matches(&commands.prefix());
-                                let command = cmd_text.split_whitespace().next().unwrap_or("").to_lowercase();
-                                
-                                // Match exactly against known mini-app commands
-                                match command.as_str() {
-                                    "scramble" | "hint" | "guess" | "quit" => {
-                                        let mut app_states = APP_STATES.lock().unwrap();
-                                        let user_state = app_states.entry(chat_id.clone()).or_insert_with(AppState::default);
-                                        
-                                        if let Some(response) = MINI_APPS.handle(&text, &chat_id, user_state) {
-                                            if let Err(e) = bot.send_message(&chat_id, &response).await {
-                                                tracing::error!("Failed to send message: {}", e);
-                                            }
-                                            continue;
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                                
-                                // Check for /code command (coding agent)
-                                let trimmed = text.trim_start_matches(&commands.prefix()).trim_start_matches('/');
-                                if trimmed.starts_with("code") {
-                                    // Check guest access first
-                                    let chat_id_str = chat_id.to_string();
-                                    let response = match can_use_privileged(&chat_id_str) {
-                                        Ok(false) => {
-                                            "❌ Access denied.\n\nUse /connect to request one-time guest access,\nor ask the owner to add you.".to_string()
-                                        }
-                                        Err(e) => format!("Error: {}", e),
-                                        _ => {
-                                            // Extract the prompt from /code command
-                                            let prompt = if trimmed.starts_with("code ") {
-                                                // Has space after "code", grab everything after position 5
-                                                trimmed[5..].trim().to_string()
-                                            } else if trimmed.len() > 5 {
-                                                // Has content after "code" (no space) - grab from position 4
-                                                trimmed[4..].trim().to_string()
-                                            } else {
-                                                // Just "code" or "/code" with nothing after
-                                                String::new()
-                                            };
-                                            
-                                            if prompt.is_empty() {
-                                                "Usage: /code <your coding task>\nExample: /code write a hello world in python".to_string()
-                                            } else {
-                                                // Execute kiro-cli as coding agent
-                                                execute_kiro_cli(&prompt).await
-                                            }
-                                        }
-                                    };
-                                    
-                                    tracing::info!("Sending response to chat_id {}: {}", chat_id, response.chars().take(100).collect::<String>());
-                                    if let Err(e) = bot.send_message(&chat_id, &response).await {
-                                        tracing::error!("Failed to send message: {}", e);
-                                    }
-                                } else {
-                                    let cmd_parts: Vec<&str> = trimmed.split_whitespace().collect();
-                                    let cmd_name = cmd_parts.first().unwrap_or(&"").to_string();
-                                    let args: Vec<String> = cmd_parts[1..].iter().map(|s| s.to_string()).collect();
-                                    
-                                    let msg = Message::from_command(&chat_id, cmd_name, args);
-                                    let response = match commands.handle(&msg) {
-                                        Ok(Some(response)) => response,
-                                        Ok(None) => continue,
-                                        Err(e) => format!("Error: {}", e),
-                                    };
-                                    
-                                    tracing::info!("Sending response to chat_id {}: {}", chat_id, response.chars().take(100).collect::<String>());
-                                    if let Err(e) = bot.send_message(&chat_id, &response).await {
-                                        tracing::error!("Failed to send message: {}", e);
-                                    }
-                                }
-                            } else {
-                                // Auto-route: detect intent and route to appropriate handler
-                                match route_message(&text, &chat_id, &mut conversations, &llm, &system_prompt, reply_text.as_deref()).await {
-                                    Some(resp) => {
-                                        // Send response - use char indexing for Unicode
-                                        let preview = resp.chars().take(100).collect::<String>();
-                                        tracing::info!("Sending response to chat_id {}: {}", chat_id, preview);
-                                        if let Err(e) = bot.send_message(&chat_id, &resp).await {
-                                            tracing::error!("Failed to send message: {}", e);
-                                        }
-                                    }
-                                    None => {
-                                        // Echo mode when LLM is not available
-                                        let echo = format!("Echo: {}", text);
-                                        if let Err(e) = bot.send_message(&chat_id, &echo).await {
-                                            tracing::error!("Failed to send message: {}", e);
-                                        }
-                                    }
-                                }
-                            }
+                        if prompt.is_empty() {
+                            "Usage: /code <your coding task>\nExample: /code write a hello world in python".to_string()
+                        } else {
+                            // Execute kiro-cli as coding agent
+                            execute_kiro_cli(&prompt).await
                         }
                     }
-                    
-                    // Handle callback queries
-                    if let Some(cb) = &update.callback_query {
-                        let chat_id = cb.message.as_ref().map(|m| m.chat.id.to_string()).unwrap_or_default();
-                        if let Some(data) = &cb.data {
-                            let _ = bot.send_message(&chat_id, &format!("Callback: {}", data)).await;
-                        }
-                        let _ = bot.answer_callback(&cb.id, None).await;
+                };
+                
+                tracing::info!("Sending response to chat_id {}: {}", chat_id, response.chars().take(100).collect::<String>());
+                if let Err(e) = bot.send_message(&chat_id, &response).await {
+                    tracing::error!("Failed to send message: {}", e);
+                }
+            } else {
+                let cmd_parts: Vec<&str> = trimmed.split_whitespace().collect();
+                let cmd_name = cmd_parts.first().unwrap_or(&"").to_string();
+                let args: Vec<String> = cmd_parts[1..].iter().map(|s| s.to_string()).collect();
+                
+                // /history export is sent back as a file
+                if cmd_name == "history" && args.first().map(|a| a.as_str()) == Some("export") {
+                    let format = args.get(1).map(|f| f.as_str()).unwrap_or("md");
+                    send_history_export(bot, db, &chat_id, &user_id, format).await;
+                    continue;
+                }
+                
+                // /mydata export sends a file, delete asks to confirm
+                if cmd_name == "mydata" {
+                    handle_mydata(bot, db, store.as_ref(), audit, &chat_id, &user_id, &args).await;
+                    continue;
+                }
+                
+                // /search replies with paging buttons
+                if cmd_name == "search" {
+                    send_search(bot, db, &chat_id, &user_id, &args).await;
+                    continue;
+                }
+                
+                // /model checks names against the provider's model list
+                if let Some(router) = llm.as_ref().filter(|_| cmd_name == "model") {
+                    let response = select_model(router, db, &chat_id, &user_id, &args).await;
+                    if let Err(e) = bot.send_message(&chat_id, &response).await {
+                        tracing::error!("Failed to send message: {}", e);
                     }
+                    continue;
                 }
                 
-                // Update offset
-                offset = TelegramAdapter::get_next_offset(&updates);
+                // /docs index reads the workspace and calls the embedding API
+                if cmd_name == "docs" && args.first().map(|a| a.as_str()) == Some("index") {
+                    let response = if !can_use_privileged(&user_id).unwrap_or(false) {
+                        "❌ Only the owner and approved users can index the workspace.
+
+Send me a file to index it for yourself.".to_string()
+                    } else if !rag_config.enabled {
+                        "📚 Document retrieval is disabled (rag.enabled in config.yaml).".to_string()
+                    } else {
+                        let _ = bot.send_chat_action(&chat_id, "typing").await;
+                        index_workspace(db, llm.as_ref(), rag_config).await
+                    };
+                    if let Err(e) = bot.send_message(&chat_id, &response).await {
+                        tracing::error!("Failed to send message: {}", e);
+                    }
+                    continue;
+                }
+                
+                let msg = Message::from_command(&chat_id, cmd_name, args)
+                    .with_sender(User::new(&user_id));
+                let response = match handle_command(commands, msg).await {
+                    Ok(Some(response)) => response,
+                    Ok(None) => continue,
+                    Err(e) => format!("Error: {}", e),
+                };
+                
+                tracing::info!("Sending response to chat_id {}: {}", chat_id, response.chars().take(100).collect::<String>());
+                if let Err(e) = bot.send_message(&chat_id, &response).await {
+                    tracing::error!("Failed to send message: {}", e);
+                }
             }
-            Err(e) => {
-                tracing::error!("Failed to get updates: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        } else {
+            // Auto-route: detect intent and route to appropriate handler
+            let (mut conversation, message_ids) = load_conversation(db, &chat_id, &user_id).await;
+            let loaded = conversation.messages.len();
+            let routed = shutdown.clone()
+                .run(UsageScope::new(&user_id, &chat_id).run(route_message(db, &text, &chat_id, &user_id, &mut conversation, context, memory_config, rag_config, llm, intents, system_prompt, reply_text.as_deref(), commands, plugins)))
+                .await;
+            save_conversation(db, &chat_id, &user_id, &conversation, loaded, &message_ids).await;
+            
+            match routed {
+                Some(resp) => {
+                    // Send response - use char indexing for Unicode
+                    let preview = resp.chars().take(100).collect::<String>();
+                    tracing::info!("Sending response to chat_id {}: {}", chat_id, preview);
+                    if let Err(e) = bot.send_message(&chat_id, &resp).await {
+                        tracing::error!("Failed to send message: {}", e);
+                    }
+                }
+                None => {
+                    // Echo mode when LLM is not available
+                    let echo = format!("Echo: {}", text);
+                    if let Err(e) = bot.send_message(&chat_id, &echo).await {
+                        tracing::error!("Failed to send message: {}", e);
+                    }
+                }
             }
         }
     }
@@ -528,24 +1058,26 @@ fn can_use_privileged(user_id: &str) -> Result<bool, String> {
 }
 
 /// Start command - shows welcome message
-fn register_start_command(commands: &mut CommandService) {
+fn register_start_command(commands: &mut CommandService, db: &Arc<Database>) {
     use crate::domain::entities::{Command, Content};
     
+    let db_start = db.clone();
     commands.register(Command::new("start")
         .with_description("Start conversation")
-        .with_handler(|msg| {
+        .with_handler(move |msg| {
             let chat_id = &msg.chat_id;
-            let settings = get_user_settings(chat_id);
+            let settings = get_user_settings(&db_start, chat_id);
             let lang = settings.map(|s| s.language).unwrap_or_else(|| "en".to_string());
             Ok(generate_greeting("carik-bot", &lang))
         }));
     
     // About command - show bot capabilities
+    let db = db.clone();
     commands.register(Command::new("about")
         .with_description("About Carik Bot - capabilities")
-        .with_handler(|msg| {
+        .with_handler(move |msg| {
             let chat_id = &msg.chat_id;
-            let settings = get_user_settings(chat_id);
+            let settings = get_user_settings(&db, chat_id);
             let lang = settings.map(|s| s.language).unwrap_or_else(|| "en".to_string());
             Ok(generate_about(lang))
         }));
@@ -597,13 +1129,14 @@ fn register_connect_command(commands: &mut CommandService) {
 }
 
 /// Approve command for owner to approve guest requests
-fn register_approve_command(commands: &mut CommandService) {
+fn register_approve_command(commands: &mut CommandService, db: &Arc<Database>) {
     use crate::domain::entities::{Command, Content};
     
+    let db = db.clone();
     commands.register(Command::new("approve")
         .with_description("Approve guest request (owner only)")
         .with_usage("/approve <user_id>")
-        .with_handler(|msg| {
+        .with_handler(move |msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
             };
@@ -653,7 +1186,7 @@ fn register_approve_command(commands: &mut CommandService) {
                 crate::application::errors::CommandError::ExecutionFailed(e.to_string()))?;
             
             // Get user's language preference
-            let settings = get_user_settings(&target_id);
+            let settings = get_user_settings(&db, &target_id);
             let lang = settings.map(|s| s.language).unwrap_or_else(|| "en".to_string());
             let greeting = generate_greeting("carik-bot", &lang);
             Ok(format!("✅ Approved! User: {}\n\n{}\n\nThey can now use /code or /kiro", target_id, greeting))
@@ -814,73 +1347,6 @@ fn get_docker_workspace_dir() -> String {
     "/workspace/default-workspace".to_string()
 }
 
-/// Detect if user message is a coding task
-fn is_coding_intent(text: &str) -> bool {
-    let coding_keywords = [
-        "code", "write", "create", "build", "make", "debug", "fix", 
-        "program", "script", "function", "class", "algorithm",
-        "implement", "develop", "refactor", "optimize",
-        "python", "javascript", "rust", "java", "golang", "typescript",
-        "app", "application", "website", "web", "api", "database",
-    ];
-    
-    let lower = text.to_lowercase();
-    coding_keywords.iter().any(|kw| lower.contains(kw))
-}
-
-/// Detect if user message is asking for financial data
-fn is_financial_intent(text: &str) -> bool {
-    let financial_keywords = [
-        "crypto", "bitcoin", "btc", "ethereum", "eth", "solana", "sol",
-        "stock", "stocks", "s&p", "nasdaq", "dow", "jones", "index",
-        "forex", "currency", "exchange", "usd", "eur", "idr", "jpy",
-        "price", "market", "trading", "trading",
-        "crypto price", "stock price", "bitcoin price",
-    ];
-    
-    let lower = text.to_lowercase();
-    financial_keywords.iter().any(|kw| lower.contains(kw))
-}
-
-/// Detect if user message is asking for news/RSS
-fn is_rss_intent(text: &str) -> bool {
-    let rss_keywords = [
-        "news", "headlines", "latest", "feed", "rss",
-        "berita", "headline", "update", "breaking",
-    ];
-    
-    let lower = text.to_lowercase();
-    rss_keywords.iter().any(|kw| lower.contains(kw))
-}
-
-/// Detect if user is asking about bot capabilities/skills
-fn is_capabilities_intent(text: &str) -> bool {
-    let capability_keywords = [
-        "what can you do", "what can i do", "what are your skills",
-        "what can bot do", "your capabilities", "your features",
-        "apa yang kamu bisa", "bisa apa saja", "fitur apa saja",
-        "apa kemampuanmu", "skillmu apa", "bisa ngapain",
-        "apa gunanya", "kepake apa", "funktion",
-        "help me", "help me with", "tolong",
-    ];
-    
-    let lower = text.to_lowercase();
-    capability_keywords.iter().any(|kw| lower.contains(kw))
-}
-
-/// Detect if user is asking to translate something
-fn is_translate_intent(text: &str) -> bool {
-    let translate_keywords = [
-        "translate", "terjemahkan", "artinya", "meaning",
-        " apa arti", "artinya apa", "m translate",
-        "terjemahkan ini", " terjemahkan", "penterjemah",
-        " terjemah", " translation", " penterjemahan",
-    ];
-    
-    let lower = text.to_lowercase();
-    translate_keywords.iter().any(|kw| lower.contains(kw))
-}
-
 /// Extract target language from translate request
 fn detect_target_language(text: &str) -> String {
     let lower = text.to_lowercase();
@@ -912,25 +1378,6 @@ fn detect_target_language(text: &str) -> String {
     "English".to_string()
 }
 
-/// Detect if user message is asking for a skill/tool
-fn detect_skill(text: &str) -> Option<String> {
-    let skill_keywords = [
-        ("weather", &["weather", "cuaca"]),
-        ("github", &["github", "git"]),
-        ("spotify", &["spotify", "music"]),
-        ("weather", &["weather", "forecast"]),
-        ("obsidian", &["obsidian", "note"]),
-    ];
-    
-    let lower = text.to_lowercase();
-    for (skill, keywords) in skill_keywords {
-        if keywords.iter().any(|kw| lower.contains(kw)) {
-            return Some(skill.to_string());
-        }
-    }
-    None
-}
-
 /// Detect if user is responding to news selection and extract source
 fn detect_news_source(text: &str) -> Option<String> {
     let lower = text.to_lowercase();
@@ -1042,13 +1489,22 @@ fn is_news_conversation(conversation: &[LLMMessage]) -> bool {
 }
 
 /// Route message to appropriate handler with conversation history
+#[allow(clippy::too_many_arguments)]
 async fn route_message(
+    db: &Arc<Database>,
     text: &str, 
     chat_id: &str, 
-    conversations: &mut std::collections::HashMap<String, Vec<LLMMessage>>, 
-    llm: &Option<GroqProvider>, 
+    user_id: &str,
+    conversation: &mut Conversation, 
+    context: &ContextManager,
+    memory: &MemoryConfig,
+    rag: &RagConfig,
+    llm: &Option<Arc<LLMRouter>>, 
+    intents: &IntentRouter,
     system_prompt: &str,
-    reply_text: Option<&str>
+    reply_text: Option<&str>,
+    commands: &Arc<CommandService>,
+    plugins: &Arc<PluginManager>,
 ) -> Option<String> {
     // Check for mini-app commands first (games, etc.)
     {
@@ -1061,7 +1517,10 @@ async fn route_message(
     }
     
     // Get user settings
-    let user_settings = get_user_settings(chat_id);
+    let user_settings = {
+        let chat_id = chat_id.to_string();
+        db.call(move |db| get_user_settings(db, &chat_id)).await
+    };
     
     // Debug: log the language
     if let Some(ref settings) = user_settings {
@@ -1069,34 +1528,27 @@ async fn route_message(
     }
     
     // Build personalized system prompt with language instruction
-    let mut final_prompt = system_prompt.to_string();
+    let lang = user_settings.as_ref().map(|s| s.language.clone());
+    let lang = lang.as_deref();
+    let vars = Vars::new()
+        .with("persona", system_prompt)
+        .with("user.language", lang.unwrap_or_default());
+    let custom_prompt = user_settings.as_ref().and_then(|s| s.system_prompt.as_deref());
+    let mut final_prompt = chat_system_prompt(&vars, lang, custom_prompt);
     
-    // Add language instruction based on user preference
-    if let Some(settings) = &user_settings {
-        let lang_instruction = match settings.language.as_str() {
-            "jv" => "\n\nIMPORTANT: Respond in Javanese language (ꦧꦱꦗꦮ).",
-            "id" => "\n\nIMPORTANT: Respond in Indonesian language.",
-            _ => "",
-        };
-        final_prompt = format!("{}{}", final_prompt, lang_instruction);
-        
-        // Add custom prompt if any
-        if let Some(custom_prompt) = &settings.system_prompt {
-            final_prompt = format!("{}\n\nPersonal instructions: {}", final_prompt, custom_prompt);
+    // Explicit "remember ..." requests go straight to long-term memory
+    if memory.enabled {
+        if let Some(fact) = explicit_fact(text) {
+            let (user_id, max_facts) = (user_id.to_string(), memory.max_facts);
+            let response = db.call(move |db| remember_fact(db, &user_id, &fact, max_facts)).await;
+            conversation.messages.push(LLMMessage::user(text.to_string()));
+            conversation.messages.push(LLMMessage::assistant(response.clone()));
+            return Some(response);
         }
     }
     
-    // Get or create conversation history for this chat
-    let conversation = conversations.entry(chat_id.to_string()).or_insert_with(Vec::new);
-    
-    // Keep conversation history limited (last 10 messages)
-    if conversation.len() > 20 {
-        conversation.remove(0);
-        conversation.remove(0);
-    }
-    
     // Check if user is responding to news selection
-    if is_news_conversation(conversation) {
+    if is_news_conversation(&conversation.messages) {
         if let Some(source) = detect_news_source(text) {
             tracing::info!("Detected news source selection: {}, fetching RSS", source);
             
@@ -1129,21 +1581,11 @@ async fn route_message(
             
             // Use LLM to summarize
             if let Some(ref llm) = llm {
-                let summarize_prompt = format!(
-                    "You're a news reporter. Summarize these headlines in a friendly, conversational way (2-3 sentences, then bullet points). MUST include the article URL after each headline like this: Headline Title\n🔗 https://example.com\n\n{}",
-                    rss_content
-                );
-                
-                let messages = vec![
-                    LLMMessage::system(&final_prompt),
-                    LLMMessage::user(&summarize_prompt),
-                ];
-                
-                match llm.chat(messages, None, Some(0.7), None).await {
-                    Ok(response) => {
-                        let final_response = format!("{}{}", header, response.content);
-                        conversation.push(LLMMessage::user(text.to_string()));
-                        conversation.push(LLMMessage::assistant(final_response.clone()));
+                match summarize_news(llm, &final_prompt, lang, &rss_content, "").await {
+                    Ok(digest) => {
+                        let final_response = format!("{}{}", header, digest);
+                        conversation.messages.push(LLMMessage::user(text.to_string()));
+                        conversation.messages.push(LLMMessage::assistant(final_response.clone()));
                         return Some(final_response);
                     }
                     Err(_) => {
@@ -1154,26 +1596,31 @@ async fn route_message(
             
             // Fallback to raw feed
             let response = format!("📰 *{}*\n\n{}", source_display, rss_content);
-            conversation.push(LLMMessage::user(text.to_string()));
-            conversation.push(LLMMessage::assistant(response.clone()));
+            conversation.messages.push(LLMMessage::user(text.to_string()));
+            conversation.messages.push(LLMMessage::assistant(response.clone()));
             return Some(response);
         }
     }
     
+    // Classify what the message is asking for
+    let intent_context = IntentContext { is_reply: reply_text.is_some() };
+    let intent = intents.classify(text, &intent_context).await;
+    tracing::info!("Intent: {} ({:.2}, {})", intent.intent.as_str(), intent.confidence, intent.classifier);
+    
     // Check for capabilities/about intent
-    if is_capabilities_intent(text) {
+    if intent.intent == Intent::Capabilities {
         tracing::info!("Detected capabilities intent");
-        let settings = get_user_settings(chat_id);
-        let lang = settings.map(|s| s.language).unwrap_or_else(|| "en".to_string());
-        return Some(generate_about(lang));
+        return Some(generate_about(lang.unwrap_or("en").to_string()));
     }
     
     // Check for translate intent
-    if is_translate_intent(text) || reply_text.is_some() {
+    if intent.intent == Intent::Translate {
         tracing::info!("Detected translate intent");
         
         // Detect target language
-        let target_lang = detect_target_language(text);
+        let target_lang = intent.slot("target_language")
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| detect_target_language(text));
         
         // Get text to translate: prefer reply, otherwise extract from message
         let text_to_translate = if let Some(reply) = reply_text {
@@ -1211,23 +1658,13 @@ async fn route_message(
             return Some("❌ Please provide text to translate or reply to a message with translate command.".to_string());
         }
         
-        let translate_prompt = format!(
-            "Translate the following text to {}.\n\nText: \"{}\"\n\nTranslation:",
-            target_lang, text_to_translate
-        );
-        
         // Use LLM to translate
         if let Some(ref llm) = llm {
-            let messages = vec![
-                LLMMessage::system("You are a professional translator. Provide accurate translations."),
-                LLMMessage::user(&translate_prompt),
-            ];
-            
-            match llm.chat(messages, None, Some(0.3), None).await {
-                Ok(response) => {
-                    conversation.push(LLMMessage::user(text.to_string()));
-                    conversation.push(LLMMessage::assistant(response.content.clone()));
-                    return Some(response.content);
+            match translate(llm, lang, &vars, &target_lang, &text_to_translate).await {
+                Ok(translation) => {
+                    conversation.messages.push(LLMMessage::user(text.to_string()));
+                    conversation.messages.push(LLMMessage::assistant(translation.clone()));
+                    return Some(translation);
                 }
                 Err(e) => {
                     return Some(format!("❌ Translation error: {}", e));
@@ -1239,14 +1676,16 @@ async fn route_message(
     }
     
     // Check for RSS/news intent - fetch and summarize
-    if is_rss_intent(text) {
+    if intent.intent == Intent::News {
         tracing::info!("Detected RSS intent, fetching and summarizing news");
         
         // Detect topic (e.g., India, Indonesia, technology) - includes specific RSS URL
-        let topic = detect_news_topic(text);
+        let topic = intent.slot("topic").and_then(detect_news_topic)
+            .or_else(|| detect_news_topic(text));
         
         // Check if user specified a source explicitly
-        let explicit_source = detect_news_source(text);
+        let explicit_source = intent.slot("source").and_then(detect_news_source)
+            .or_else(|| detect_news_source(text));
         
         // Determine URL: topic-specific > explicit source > default
         let (url, source_display) = if let Some((topic_name, topic_url)) = &topic {
@@ -1289,29 +1728,19 @@ async fn route_message(
         // Check if RSS fetch failed - if so, return error directly without LLM
         if rss_content.starts_with("❌") {
             let error_response = format!("{}Sorry, couldn't fetch the news feed. Please try again or try a different source like /rss google\n\nError: {}", header, rss_content);
-            conversation.push(LLMMessage::user(text.to_string()));
-            conversation.push(LLMMessage::assistant(error_response.clone()));
+            conversation.messages.push(LLMMessage::user(text.to_string()));
+            conversation.messages.push(LLMMessage::assistant(error_response.clone()));
             return Some(error_response);
         }
         
         // Use LLM to summarize
         if let Some(ref llm) = llm {
             let topic_hint = topic.as_ref().map(|(t, _)| format!(" Focus on {} news.", t)).unwrap_or_default();
-            let summarize_prompt = format!(
-                "You're a news reporter. Summarize these headlines in a friendly, conversational way (2-3 sentences max, then bullet points). MUST include the article URL after each headline like this: Headline Title\n🔗 https://example.com{}\n\nHeadlines:\n{}",
-                topic_hint, rss_content
-            );
-            
-            let messages = vec![
-                LLMMessage::system(&final_prompt),
-                LLMMessage::user(&summarize_prompt),
-            ];
-            
-            match llm.chat(messages, None, Some(0.7), None).await {
-                Ok(response) => {
-                    let final_response = format!("{}{}", header, response.content);
-                    conversation.push(LLMMessage::user(text.to_string()));
-                    conversation.push(LLMMessage::assistant(final_response.clone()));
+            match summarize_news(llm, &final_prompt, lang, &rss_content, &topic_hint).await {
+                Ok(digest) => {
+                    let final_response = format!("{}{}", header, digest);
+                    conversation.messages.push(LLMMessage::user(text.to_string()));
+                    conversation.messages.push(LLMMessage::assistant(final_response.clone()));
                     return Some(final_response);
                 }
                 Err(e) => {
@@ -1324,18 +1753,20 @@ async fn route_message(
         
         // No LLM - return raw feed
         let response = format!("{}{}", header, rss_content);
-        conversation.push(LLMMessage::user(text.to_string()));
-        conversation.push(LLMMessage::assistant(response.clone()));
+        conversation.messages.push(LLMMessage::user(text.to_string()));
+        conversation.messages.push(LLMMessage::assistant(response.clone()));
         return Some(response);
     }
     
     // Check for financial intent - fetch and summarize with LLM
-    if is_financial_intent(text) {
+    if intent.intent == Intent::Finance {
         tracing::info!("Detected financial intent, fetching data");
         
         // Determine what data to fetch
         let lower = text.to_lowercase();
-        let data_type = if lower.contains("crypto") || lower.contains("bitcoin") || lower.contains("btc") {
+        let data_type = if let Some(category @ ("crypto" | "stocks" | "currency")) = intent.slot("category") {
+            category
+        } else if lower.contains("crypto") || lower.contains("bitcoin") || lower.contains("btc") {
             "crypto"
         } else if lower.contains("stock") || lower.contains("s&p") || lower.contains("nasdaq") {
             "stocks"
@@ -1350,20 +1781,17 @@ async fn route_message(
         
         // Use LLM to summarize
         if let Some(ref llm) = llm {
-            let summarize_prompt = format!(
-                "You're a financial analyst. Provide a brief, friendly summary (2-3 sentences) of these market data, then list the key numbers. Keep it conversational and easy to understand.\n\n{}",
-                financial_data
-            );
+            let summarize_prompt = prompt("finance_summary", lang, &vars.clone().with("market_data", guard::untrusted("market-data", &financial_data)));
             
             let messages = vec![
                 LLMMessage::system(&final_prompt),
                 LLMMessage::user(&summarize_prompt),
             ];
             
-            match llm.chat(messages, None, Some(0.7), None).await {
+            match llm.chat_task(LLMTask::Summarization, messages, Some(0.7), None).await {
                 Ok(response) => {
-                    conversation.push(LLMMessage::user(text.to_string()));
-                    conversation.push(LLMMessage::assistant(response.content.clone()));
+                    conversation.messages.push(LLMMessage::user(text.to_string()));
+                    conversation.messages.push(LLMMessage::assistant(response.content.clone()));
                     return Some(response.content);
                 }
                 Err(e) => {
@@ -1377,15 +1805,19 @@ async fn route_message(
         return Some(financial_data);
     }
     
-    // Check for coding intent
-    if is_coding_intent(text) {
-        tracing::info!("Detected coding intent, routing to Kiro");
-        let response = execute_kiro_cli(text).await;
-        return Some(response);
+    // Check for coding intent; the agent works in the workspace, so it needs
+    // the same access as /code and /kiro, otherwise this is answered as chat
+    if intent.intent == Intent::Coding {
+        if can_use_privileged(chat_id).unwrap_or(false) {
+            tracing::info!("Detected coding intent, routing to Kiro");
+            let response = execute_kiro_cli(text).await;
+            return Some(response);
+        }
+        tracing::info!("Coding intent from {} without access, answering as chat", chat_id);
     }
     
     // Check for skill intent
-    if let Some(skill) = detect_skill(text) {
+    if let (Intent::Skill, Some(skill)) = (intent.intent, intent.slot("skill")) {
         tracing::info!("Detected skill: {}", skill);
         // TODO: Load skill.md and execute
         return Some(format!("Skill '{}' detected. Skill execution coming soon!", skill));
@@ -1395,36 +1827,295 @@ async fn route_message(
     if let Some(ref llm) = llm {
         tracing::info!("Routing to LLM with history");
         
-        // Build messages with history
-        let mut messages = conversation.clone();
-        messages.push(LLMMessage::system(&final_prompt));
-        messages.push(LLMMessage::user(text));
+        // Add what we remember about the user
+        if memory.enabled {
+            let (user_id, text, limit) = (user_id.to_string(), text.to_string(), memory.inject_limit);
+            if let Some(section) = db.call(move |db| memory_prompt(db, &user_id, &text, limit)).await {
+                final_prompt = format!("{}\n\n{}", final_prompt, section);
+            }
+        }
+        
+        // Add excerpts from the user's documents
+        let hits = if rag.enabled {
+            retrieve_documents(db, llm, rag, user_id, text).await
+        } else {
+            Vec::new()
+        };
+        if let Some(section) = rag::prompt_section(&hits) {
+            final_prompt = format!("{}\n\n{}", final_prompt, section);
+        }
+        
+        // System prompt first, then history summarized to fit the model's budget
+        let messages = context.prepare(llm, &final_prompt, conversation, text).await;
         
-        match llm.chat(messages.clone(), None, Some(0.7), None).await {
-            Ok(response) => {
+        match chat_with_tools(llm, messages, chat_id, user_id, commands, plugins).await {
+            Ok(content) => {
                 // Add to conversation history
-                conversation.push(LLMMessage::user(text.to_string()));
-                conversation.push(LLMMessage::assistant(response.content.clone()));
-                return Some(response.content);
+                conversation.messages.push(LLMMessage::user(text.to_string()));
+                conversation.messages.push(LLMMessage::assistant(content.clone()));
+                
+                // Pick up new facts in the background
+                if memory.enabled && memory.auto_extract {
+                    let scope = UsageScope::current().unwrap_or_else(|| UsageScope::new(user_id, chat_id));
+                    let extractor = FactExtractor::new(llm.clone());
+                    let (db, user_id, text, reply) = (db.clone(), user_id.to_string(), text.to_string(), content.clone());
+                    let max_facts = memory.max_facts;
+                    tokio::spawn(scope.run(async move {
+                        extract_facts(&extractor, &db, &user_id, &text, &reply, max_facts).await;
+                    }));
+                }
+                
+                // List the documents the answer cites
+                return Some(match rag::sources_footer(&hits, &content) {
+                    Some(footer) => format!("{}\n\n{}", content, footer),
+                    None => content,
+                });
             }
-            Err(e) => return Some(format!("LLM Error: {}", e)),
+            Err(e) => return Some(llm_error_reply(e)),
         }
     }
     
     None
 }
 
+/// System prompt for chat: persona, the user's language instruction and
+/// personal instructions
+fn chat_system_prompt(vars: &Vars, lang: Option<&str>, instructions: Option<&str>) -> String {
+    let mut system = prompt("system", lang, vars);
+    
+    // Add language instruction based on user preference
+    let lang_instruction = prompt("language", lang, vars);
+    if !lang_instruction.is_empty() {
+        system = format!("{}\n\n{}", system, lang_instruction);
+    }
+    
+    // Add custom prompt if any
+    if let Some(instructions) = instructions {
+        let instructions = prompt("personal_instructions", lang, &vars.clone().with("instructions", instructions));
+        system = format!("{}\n\n{}", system, instructions);
+    }
+    system
+}
+
+/// Translate `text` to `target_lang` with the translation route
+async fn translate(llm: &LLMRouter, lang: Option<&str>, vars: &Vars, target_lang: &str, text: &str) -> Result<String, LLMError> {
+    let vars = vars.clone()
+        .with("target_language", target_lang)
+        .with("text", guard::untrusted("message", text));
+    let messages = vec![
+        LLMMessage::system(prompt("translate_system", lang, &vars)),
+        LLMMessage::user(prompt("translate", lang, &vars)),
+    ];
+    let response = llm.chat_task(LLMTask::Translation, messages, Some(0.3), None).await?;
+    Ok(response.content)
+}
+
+/// News summary requested from the summarization route
+#[derive(serde::Deserialize)]
+struct NewsDigest {
+    intro: String,
+    headlines: Vec<DigestHeadline>,
+}
+
+#[derive(serde::Deserialize)]
+struct DigestHeadline {
+    title: String,
+    url: String,
+}
+
+fn news_digest_schema() -> infrastructure::llm::ResponseSchema {
+    infrastructure::llm::ResponseSchema::new("news_digest", serde_json::json!({
+        "type": "object",
+        "properties": {
+            "intro": {"type": "string", "minLength": 1},
+            "headlines": {
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "title": {"type": "string", "minLength": 1},
+                        "url": {"type": "string", "minLength": 1}
+                    },
+                    "required": ["title", "url"]
+                }
+            }
+        },
+        "required": ["intro", "headlines"]
+    }))
+}
+
+/// Summarize fetched headlines; structured output keeps every article URL
+async fn summarize_news(
+    llm: &LLMRouter,
+    system_prompt: &str,
+    lang: Option<&str>,
+    rss_content: &str,
+    topic_hint: &str,
+) -> Result<String, LLMError> {
+    let vars = Vars::new()
+        .with("user.language", lang.unwrap_or_default())
+        .with("topic_hint", topic_hint)
+        .with("headlines", guard::untrusted("rss", rss_content));
+    let messages = vec![LLMMessage::system(system_prompt), LLMMessage::user(prompt("news_summary", lang, &vars))];
+    let digest: NewsDigest = llm.chat_structured(LLMTask::Summarization, messages, &news_digest_schema(), Some(0.7), None).await?;
+
+    let mut text = digest.intro.trim().to_string();
+    text.push('\n');
+    for headline in &digest.headlines {
+        text.push_str(&format!("\n• {}\n🔗 {}", headline.title.trim(), headline.url.trim()));
+    }
+    Ok(text)
+}
+
+/// Render a prompt template for a user with language `lang`
+fn prompt(name: &str, lang: Option<&str>, vars: &Vars) -> String {
+    PROMPTS.read().unwrap().render(name, lang, vars)
+}
+
+/// User-facing reply for a failed LLM call
+fn llm_error_reply(error: LLMError) -> String {
+    match error {
+        LLMError::BudgetExceeded(reason) => format!("💸 {}.\n\nSee /usage for details.", reason),
+        LLMError::RateLimited { retry_after } => {
+            let wait = retry_after.map(|d| format!(" in {}s", d.as_secs().max(1))).unwrap_or_default();
+            format!("⏳ The AI providers are busy right now. Please try again{}.", wait)
+        }
+        LLMError::Timeout => "⌛ The AI took too long to answer. Please try again.".to_string(),
+        e if matches!(e.status(), Some(401) | Some(403)) => {
+            tracing::error!("LLM provider rejected credentials: {}", e);
+            "🔑 The AI provider rejected the bot's API key. Please let the owner know.".to_string()
+        }
+        LLMError::Cancelled => "🛑 I'm restarting, please send that again in a moment.".to_string(),
+        LLMError::Blocked(reason) => format!("🛡 I can't help with that: {}.", reason),
+        e => format!("LLM Error: {}", e),
+    }
+}
+
+/// Question asked about a photo sent without a caption
+const DEFAULT_PHOTO_QUESTION: &str = "What's in this picture?";
+
+/// Longest side of the photo size sent to the vision model
+const MAX_PHOTO_SIDE: u32 = 1280;
+
+/// Answer a question about a photo with the `vision` route
+async fn answer_photo(
+    bot: &TelegramAdapter,
+    llm: Option<&Arc<LLMRouter>>,
+    text: &str,
+    conversation: &mut Conversation,
+    context: &ContextManager,
+    system_prompt: &str,
+    photo: &[infrastructure::adapters::telegram::PhotoSize],
+) -> String {
+    let Some(llm) = llm.filter(|llm| llm.has_route(LLMTask::Vision)) else {
+        return "🖼 I can't look at pictures: no vision model is configured (llm.routes.vision in config.yaml).".to_string();
+    };
+    // Telegram sends several sizes, smallest first; the largest is rarely needed
+    let Some(size) = photo.iter().rev().find(|p| p.width.max(p.height) <= MAX_PHOTO_SIDE).or(photo.first()) else {
+        return "❌ The photo has no downloadable sizes.".to_string();
+    };
+    let bytes = match bot.download_file(&size.file_id).await {
+        Ok(bytes) => bytes,
+        Err(e) => return format!("❌ Failed to download the photo: {}", e),
+    };
+
+    // Same history as a text message, with the image attached to the question
+    let mut messages = context.prepare(llm, system_prompt, conversation, text).await;
+    messages.pop();
+    messages.push(LLMMessage::user_with_images(text, vec![ImageSource::from_bytes(bytes)]));
+
+    match llm.chat_task(LLMTask::Vision, messages, Some(0.7), None).await {
+        Ok(response) => {
+            // History stays text-only; the photo isn't sent again on later turns
+            conversation.messages.push(LLMMessage::user(format!("[photo] {}", text)));
+            conversation.messages.push(LLMMessage::assistant(response.content.clone()));
+            response.content
+        }
+        Err(e) => llm_error_reply(e),
+    }
+}
+
+/// Maximum model/tool round trips for a single user message
+const MAX_TOOL_ROUNDS: usize = 4;
+
+/// Chat with registered commands and plugins offered as tools, running any
+/// tool calls and feeding the results back until the model answers
+async fn chat_with_tools(
+    llm: &LLMRouter,
+    mut messages: Vec<LLMMessage>,
+    chat_id: &str,
+    user_id: &str,
+    commands: &Arc<CommandService>,
+    plugins: &Arc<PluginManager>,
+) -> Result<String, infrastructure::llm::LLMError> {
+    let mut tools = commands.tool_definitions();
+    tools.extend(plugins.tool_definitions());
+
+    for round in 0..=MAX_TOOL_ROUNDS {
+        // Last round: no tools, so the model has to answer with what it has
+        let offered = if round < MAX_TOOL_ROUNDS { tools.as_slice() } else { &[] };
+        let response = llm.chat_task_with_tools(LLMTask::Chat, messages.clone(), offered, Some(0.7), None).await?;
+        if !response.has_tool_calls() || offered.is_empty() {
+            return Ok(response.content);
+        }
+
+        messages.push(LLMMessage::assistant_tool_calls(response.content.clone(), response.tool_calls.clone()));
+        for call in &response.tool_calls {
+            tracing::info!("LLM called tool {} with {}", call.name, call.arguments);
+            let output = run_tool(call, chat_id, user_id, commands, plugins).await;
+            messages.push(LLMMessage::tool_result(&call.id, output));
+        }
+    }
+
+    unreachable!("the final round never offers tools")
+}
+
+/// Execute a tool call as the user who sent the message
+async fn run_tool(
+    call: &infrastructure::llm::ToolCall,
+    chat_id: &str,
+    user_id: &str,
+    commands: &Arc<CommandService>,
+    plugins: &Arc<PluginManager>,
+) -> String {
+    let (call, chat_id, user_id) = (call.clone(), chat_id.to_string(), user_id.to_string());
+    let (commands, plugins) = (commands.clone(), plugins.clone());
+    // Command handlers use the database and blocking HTTP clients
+    let output = tokio::task::spawn_blocking(move || {
+        if commands.has_tool(&call.name) {
+            match commands.call_tool(&chat_id, &user_id, &call) {
+                Ok(output) => output,
+                Err(e) => format!("Error: {}", e),
+            }
+        } else if plugins.has_tool(&call.name) {
+            let result = plugins.call_tool(&call);
+            if result.success {
+                result.output
+            } else {
+                format!("Error: {}", result.error.unwrap_or_default())
+            }
+        } else {
+            format!("Error: unknown tool '{}'", call.name)
+        }
+    }).await;
+    output.unwrap_or_else(|e| format!("Error: the tool failed: {}", e))
+}
+
 /// Kiro CLI tmux session management
 const KIRO_SOCKET: &str = "/tmp/carik-kiro.sock";
 const KIRO_SESSION: &str = "carik-kiro";
 
 fn register_kiro_command(commands: &mut CommandService) {
-    use crate::domain::entities::{Command, Content};
+    use crate::domain::entities::{Command, Content, ToolParam};
     
     // Main kiro command - handles /kiro <prompt>
     commands.register(Command::new("kiro")
         .with_description("Run kiro-cli in Docker")
         .with_usage("/kiro <prompt>")
+        .with_tool(vec![
+            ToolParam::new("prompt", "Coding task for the Kiro agent, e.g. 'write a rust fn that parses CSV'"),
+        ])
         .with_handler(|msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
@@ -1451,6 +2142,7 @@ fn register_kiro_command(commands: &mut CommandService) {
     // Subcommands
     commands.register(Command::new("kiro-status")
         .with_description("Check kiro status")
+        .with_tool(Vec::new())
         .with_handler(|_| kiro_status().map_err(|e| crate::application::errors::CommandError::ExecutionFailed(e))));
     
     commands.register(Command::new("kiro-log")
@@ -1660,42 +2352,6 @@ fn register_kiro_command(commands: &mut CommandService) {
             }
         }));
     
-    // Groq model - switch Groq LLM model
-    commands.register(Command::new("model")
-        .with_description("Switch Groq LLM model")
-        .with_usage("/model [llama33|llama4|kimi|qwen|gpt-oss]")
-        .with_handler(|msg| {
-            let Content::Command { name: _, args } = &msg.content else {
-                return Ok("Error: invalid command".to_string());
-            };
-            
-            match can_use_privileged(&msg.chat_id) {
-                Ok(false) => return Ok("❌ Access denied. Use /connect first.".to_string()),
-                Err(e) => return Ok(format!("Error: {}", e)),
-                _ => {}
-            }
-            
-            if args.is_empty() {
-                return Ok("Available Groq models:\n• llama33 - Llama 3.3 70B\n• llama4 - Llama 4 Scout\n• kimi - Kimi Audio\n• qwen - Qwen 2.5 72B\n• gpt-oss - GPT-4o-mini\n\nCurrent model: llama33\nUsage: /model llama33".to_string());
-            }
-
-            let model = args[0].to_lowercase();
-            // Map to actual Groq model names
-            let model_name = match model.as_str() {
-                "llama33" => "llama-3.3-70b-versatile",
-                "llama4" => "llama-4-scout-17b-16e",
-                "kimi" => "kimi-audio-1.5-preview",
-                "qwen" => "qwen-2.5-72b-instruct",
-                "gpt-oss" => "gpt-4o-mini",
-                _ => return Ok("Unknown model. Use: llama33, llama4, kimi, qwen, gpt-oss".to_string()),
-            };
-            
-            // Save model preference to file
-            let _ = std::fs::write("/home/ubuntu/.carik-bot/groq-model.txt", model_name);
-
-            Ok(format!("✅ Groq model set to: {}\n\nNote: Restart bot for changes to take effect.", args[0]))
-        }));
-    
     // kiro model - switch Kiro model
     commands.register(Command::new("kiro-model")
         .with_description("Switch Kiro model")
@@ -1902,65 +2558,83 @@ fn is_owner(user_id: &str) -> bool {
 }
 
 /// Check if user is allowed based on role
-fn get_user_role(user_id: &str) -> String {
+fn get_user_role(db: &Database, user_id: &str) -> String {
     // First check if owner (from env var) - highest priority
     if is_owner(user_id) {
         return "owner".to_string();
     }
     
     // Then check database
-    if let Ok(db_guard) = DB.lock() {
-        if let Some(db) = db_guard.as_ref() {
-            if let Ok(Some(user)) = db.get_user_by_telegram_id(user_id) {
-                return user.role;
-            }
-        }
+    if let Ok(Some(user)) = db.get_user_by_telegram_id(user_id) {
+        return user.role;
     }
     
     "guest".to_string()
 }
 
-/// Update user username when they send a message
-fn update_user_username(user_id: &str, username: Option<&str>) {
-    if let Some(username) = username {
-        if let Ok(db_guard) = DB.lock() {
-            if let Some(db) = db_guard.as_ref() {
-                // Check if user exists
-                if let Ok(Some(_user)) = db.get_user_by_telegram_id(user_id) {
-                    // For now, we just ensure user exists
-                    // Username updates would require adding a method to update username
-                    let _ = db.add_user(user_id, Some(username), "user");
-                }
-            }
+/// [`get_user_role`] off the async runtime
+async fn user_role(db: &Database, user_id: &str) -> String {
+    let user_id = user_id.to_string();
+    db.call(move |db| get_user_role(db, &user_id)).await
+}
+
+/// Add an incoming message to the store's message log
+async fn log_message(store: &dyn Store, msg: &infrastructure::adapters::telegram::Message, user_id: &str, text: &str) {
+    let mut sender = domain::entities::User::new(user_id);
+    if let Some(from) = &msg.from {
+        sender.username = from.username.clone();
+        sender.first_name = from.first_name.clone();
+        sender.last_name = from.last_name.clone();
+    }
+    let mut message = domain::entities::Message::from_text(msg.chat.id.to_string(), text).with_sender(sender);
+    message.id = format!("{}:{}", msg.chat.id, msg.message_id);
+    message.platform = "telegram".to_string();
+    if let Err(e) = store.save_message(&message).await {
+        tracing::warn!("Failed to log message: {}", e);
+    }
+}
+
+/// Refresh a known user's username and name when they send a message;
+/// strangers aren't added
+async fn update_user_profile(store: &dyn Store, user_id: &str, from: &infrastructure::adapters::telegram::User) {
+    let user = match store.get_user(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("Failed to load user {}: {}", user_id, e);
+            return;
+        }
+    };
+    let updated = domain::entities::User {
+        username: from.username.clone().or(user.username.clone()),
+        first_name: from.first_name.clone().or(user.first_name.clone()),
+        last_name: from.last_name.clone().or(user.last_name.clone()),
+        ..user.clone()
+    };
+    if updated != user {
+        if let Err(e) = store.save_user(&updated).await {
+            tracing::warn!("Failed to update profile of {}: {}", user_id, e);
         }
     }
 }
 
 /// Get user settings from database
-fn get_user_settings(user_id: &str) -> Option<database::UserSettings> {
-    if let Ok(db_guard) = DB.lock() {
-        if let Some(db) = db_guard.as_ref() {
-            tracing::debug!("Getting settings for user: {}", user_id);
-            if let Ok(settings) = db.get_user_settings(user_id) {
-                tracing::debug!("Settings found: {:?}", settings);
-                return settings;
-            }
-        }
+fn get_user_settings(db: &Database, user_id: &str) -> Option<database::UserSettings> {
+    tracing::debug!("Getting settings for user: {}", user_id);
+    if let Ok(settings) = db.get_user_settings(user_id) {
+        tracing::debug!("Settings found: {:?}", settings);
+        return settings;
     }
     None
 }
 
 /// Check rate limit for user
-fn check_rate_limit(user_id: &str) -> Result<bool, String> {
+fn check_rate_limit(db: &Database, user_id: &str) -> Result<bool, String> {
     // Skip rate limiting for owner
-    if get_user_role(user_id) == "owner" {
+    if get_user_role(db, user_id) == "owner" {
         return Ok(true);
     }
     
-    // Get database
-    let db_guard = DB.lock().map_err(|e| e.to_string())?;
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    
     // Get user from database
     let user = db.get_user_by_telegram_id(user_id)
         .map_err(|e| e.to_string())?
@@ -1987,19 +2661,20 @@ fn check_rate_limit(user_id: &str) -> Result<bool, String> {
 }
 
 /// Register /users command for user management
-fn register_users_command(commands: &mut CommandService) {
+fn register_users_command(commands: &mut CommandService, db: &Arc<Database>) {
     use crate::domain::entities::{Command, Content};
     
+    let db = db.clone();
     commands.register(Command::new("users")
         .with_description("Manage users (owner/admin)")
         .with_usage("/users <list|add|remove> [args]")
-        .with_handler(|msg| {
+        .with_handler(move |msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
             };
             
             // Check if owner
-            let role = get_user_role(&msg.chat_id);
+            let role = get_user_role(&db, &msg.chat_id);
             if role != "owner" && role != "admin" {
                 return Ok("❌ Only owner/admin can manage users.".to_string());
             }
@@ -2010,28 +2685,24 @@ fn register_users_command(commands: &mut CommandService) {
             match parts.first().map(|s| *s) {
                 Some("list") | Some("ls") | None => {
                     // List all users
-                    let db_guard = DB.lock().unwrap();
-                    if let Some(db) = db_guard.as_ref() {
-                        match db.list_users() {
-                            Ok(users) => {
-                                let mut response = "📋 *Users List*\n\n".to_string();
-                                for user in users.iter().take(20) {
-                                    response.push_str(&format!(
-                                        "• {} (@{}) - {}\n",
-                                        user.telegram_id,
-                                        user.username.as_deref().unwrap_or("none"),
-                                        user.role
-                                    ));
-                                }
-                                if users.len() > 20 {
-                                    response.push_str(&format!("\n... and {} more", users.len() - 20));
-                                }
-                                Ok(response)
+                    match db.list_users() {
+                        Ok(users) => {
+                            let mut response = "📋 *Users List*\n\n".to_string();
+                            for user in users.iter().take(20) {
+                                response.push_str(&format!(
+                                    "• {}{} (@{}) - {}\n",
+                                    user.telegram_id,
+                                    user.first_name.as_deref().map(|n| format!(" {}", n)).unwrap_or_default(),
+                                    user.username.as_deref().unwrap_or("none"),
+                                    user.role
+                                ));
+                            }
+                            if users.len() > 20 {
+                                response.push_str(&format!("\n... and {} more", users.len() - 20));
                             }
-                            Err(e) => Ok(format!("Error: {}", e))
+                            Ok(response)
                         }
-                    } else {
-                        Ok("Database not initialized".to_string())
+                        Err(e) => Ok(format!("Error: {}", e))
                     }
                 }
                 Some("add") => {
@@ -2051,188 +2722,1716 @@ fn register_users_command(commands: &mut CommandService) {
                         return Ok("❌ Cannot modify owner (set via BOT_OWNER_ID env var).".to_string());
                     }
                     
-                    let db_guard = DB.lock().unwrap();
-                    if let Some(db) = db_guard.as_ref() {
-                        match db.add_user(target_id, None, role) {
-                            Ok(_) => Ok(format!("✅ User {} added as {}", target_id, role)),
-                            Err(e) => Ok(format!("Error adding user: {}", e))
+                    match db.add_user(target_id, None, role) {
+                        Ok(_) => Ok(format!("✅ User {} added as {}", target_id, role)),
+                        Err(e) => Ok(format!("Error adding user: {}", e))
+                    }
+                }
+                Some("remove") => {
+                    if parts.len() < 2 {
+                        return Ok("Usage: /users remove <telegram_id>".to_string());
+                    }
+                    let target_id = parts[1];
+                    
+                    // Check if target is owner (from env)
+                    if is_owner(target_id) {
+                        return Ok("❌ Cannot remove owner (set via BOT_OWNER_ID env var).".to_string());
+                    }
+                    
+                    match db.remove_user(target_id) {
+                        Ok(true) => Ok(format!("✅ User {} removed", target_id)),
+                        Ok(false) => Ok(format!("User {} not found", target_id)),
+                        Err(e) => Ok(format!("Error: {}", e))
+                    }
+                }
+                Some("info") => {
+                    if parts.len() < 2 {
+                        return Ok("Usage: /users info <telegram_id>".to_string());
+                    }
+                    let target_id = parts[1];
+                    
+                    match db.get_user_by_telegram_id(target_id) {
+                        Ok(Some(user)) => Ok(format!(
+                            "ℹ️ *User Info*\n\nID: {}\nUsername: @{}\nRole: {}\nJoined: {}",
+                            user.telegram_id,
+                            user.username.as_deref().unwrap_or("none"),
+                            user.role,
+                            user.created_at
+                        )),
+                        Ok(None) => Ok(format!("User {} not found", target_id)),
+                        Err(e) => Ok(format!("Error: {}", e))
+                    }
+                }
+                Some("setrole") | Some("role") => {
+                    if parts.len() < 3 {
+                        return Ok("Usage: /users setrole <telegram_id> <role>\nRoles: owner, admin, user, guest".to_string());
+                    }
+                    let target_id = parts[1];
+                    let new_role = parts[2];
+                    
+                    // Prevent setting owner role - owner can only be set via BOT_OWNER_ID env var
+                    if new_role == "owner" {
+                        return Ok("❌ Cannot set owner role. Owner is set via BOT_OWNER_ID environment variable.".to_string());
+                    }
+                    
+                    // Check if target is owner (from env)
+                    if is_owner(target_id) {
+                        return Ok("❌ Cannot modify owner role (set via BOT_OWNER_ID env var).".to_string());
+                    }
+                    
+                    match db.update_user_role(target_id, new_role) {
+                        Ok(true) => Ok(format!("✅ User {} role updated to {}", target_id, new_role)),
+                        Ok(false) => Ok(format!("User {} not found", target_id)),
+                        Err(e) => Ok(format!("Error: {}", e))
+                    }
+                }
+                _ => Ok("Usage: /users <list|add|remove|info|setrole> [args]".to_string())
+            }
+        }));
+}
+
+/// Register /settings command for user personalization
+fn register_settings_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("settings")
+        .with_description("Manage your personal settings")
+        .with_usage("/settings [get|set] [key] [value]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = &msg.chat_id;
+            let args_str = args.join(" ");
+            let parts: Vec<&str> = args_str.split_whitespace().collect();
+            
+            match parts.first().map(|s| *s) {
+                Some("get") | None => {
+                    // Show current settings
+                    match db.get_user_settings(user_id) {
+                        Ok(Some(settings)) => {
+                            Ok(format!(
+                                "⚙️ *Your Settings*\n\n\
+                                🌍 Language: {}\n\
+                                🕐 Timezone: {}\n\
+                                📝 Custom Prompt: {}\n\n\
+                                _Use /settings set <key> <value> to change_",
+                                settings.language,
+                                settings.timezone,
+                                settings.system_prompt.as_deref().unwrap_or("(none)")
+                            ))
+                        }
+                        Ok(None) => {
+                            Ok("⚙️ *Your Settings*\n\nUsing defaults:\n\
+                                🌍 Language: en\n\
+                                🕐 Timezone: UTC\n\
+                                📝 Custom Prompt: (none)\n\n\
+                                _Use /settings set <key> <value> to customize_".to_string())
+                        }
+                        Err(e) => Ok(format!("Error: {}", e))
+                    }
+                }
+                Some("set") => {
+                    if parts.len() < 3 {
+                        return Ok("Usage: /settings set <key> <value>\n\n\
+                                Keys:\n\
+                                • language - en, id, jv\n\
+                                • timezone - UTC, Asia/Jakarta, etc.\n\
+                                • prompt - custom system prompt".to_string());
+                    }
+                    
+                    let key = parts[1];
+                    let value = parts[2..].join(" ");
+                    
+                    // Get current settings
+                    let current = db.get_user_settings(user_id).ok().flatten()
+                        .unwrap_or_else(|| database::UserSettings {
+                            language: "en".to_string(),
+                            timezone: "UTC".to_string(),
+                            system_prompt: None,
+                            preferences: "{}".to_string(),
+                        });
+                    
+                    let mut settings = current;
+                    
+                    match key {
+                        "language" | "lang" => {
+                            if !["en", "id", "jv"].contains(&value.as_str()) {
+                                return Ok("Invalid language. Use: en, id, jv".to_string());
+                            }
+                            settings.language = value.to_string();
+                        }
+                        "timezone" | "tz" => {
+                            settings.timezone = value.to_string();
+                        }
+                        "prompt" | "system" => {
+                            settings.system_prompt = Some(value.to_string());
+                        }
+                        _ => return Ok("Invalid key. Use: language, timezone, prompt".to_string()),
+                    }
+                    
+                    if let Err(e) = db.set_user_settings(user_id, &settings) {
+                        return Ok(format!("Error saving: {}", e));
+                    }
+                    
+                    Ok(format!("✅ Setting saved:\n{} = {}", key, value))
+                }
+                _ => Ok("Usage: /settings <get|set> [key] [value]".to_string())
+            }
+        }));
+}
+
+/// Load saved aliases from the database into the command registry
+fn load_aliases(db: &Database, commands: &CommandService) {
+    use crate::domain::entities::{Alias, AliasScope};
+    
+    match db.list_aliases() {
+        Ok(records) => {
+            let table = commands.aliases();
+            for record in &records {
+                table.insert(Alias::new(&record.name, record.steps.clone(), AliasScope::from_key(&record.scope)));
+            }
+            tracing::info!("Loaded {} command aliases", records.len());
+        }
+        Err(e) => tracing::warn!("Failed to load aliases: {}", e),
+    }
+}
+
+/// Register /alias command for user-defined shortcuts and macros
+fn register_alias_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Alias, AliasScope, Command, Content};
+    use crate::domain::entities::alias::normalize_name;
+    
+    let table = commands.aliases();
+    let prefix = commands.prefix().to_string();
+    let db = db.clone();
+    
+    commands.register(Command::new("alias")
+        .with_description("Manage command aliases and macros")
+        .with_usage("/alias <list|add|remove> [--global] [name] [command; command...]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            // Aliases belong to whoever sent the command, also in groups
+            let user_id = msg.sender.as_ref().map(|u| u.id.clone()).unwrap_or_else(|| msg.chat_id.clone());
+            let mut parts: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            
+            // --global targets aliases shared with every user (owner/admin only)
+            let global = parts.iter().any(|p| *p == "--global" || *p == "-g");
+            parts.retain(|p| *p != "--global" && *p != "-g");
+            if global {
+                let role = get_user_role(&db, &user_id);
+                if role != "owner" && role != "admin" {
+                    return Ok("❌ Only owner/admin can manage global aliases.".to_string());
+                }
+            }
+            let scope = if global { AliasScope::Global } else { AliasScope::User(user_id.clone()) };
+            
+            match parts.first().copied() {
+                Some("list") | Some("ls") | None => {
+                    let aliases = table.list(&user_id);
+                    if aliases.is_empty() {
+                        return Ok("No aliases yet.\n\nUsage: /alias add hn /rss hn".to_string());
+                    }
+                    let mut response = "🔖 *Aliases*\n\n".to_string();
+                    for alias in aliases {
+                        let marker = if alias.scope == AliasScope::Global { " (global)" } else { "" };
+                        let kind = if alias.is_macro() { "macro" } else { "alias" };
+                        response.push_str(&format!("• /{} → {} [{}]{}\n", alias.name, alias.definition(), kind, marker));
+                    }
+                    Ok(response)
+                }
+                Some("add") | Some("set") => {
+                    if parts.len() < 3 {
+                        return Ok("Usage: /alias add [--global] <name> <command>[; <command>...]\n\n\
+                                Examples:\n\
+                                /alias add hn /rss hn\n\
+                                /alias add morning /rss hn; /finance crypto".to_string());
+                    }
+                    
+                    let name = normalize_name(parts[1]);
+                    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+                        return Ok("Invalid alias name. Use letters, digits, - and _ only.".to_string());
+                    }
+                    if table.is_reserved(&name) {
+                        return Ok(format!("❌ /{} is already a command.", name));
+                    }
+                    
+                    let steps = Alias::parse_steps(&parts[2..].join(" "));
+                    if steps.is_empty() {
+                        return Ok("❌ Alias needs at least one command.".to_string());
+                    }
+                    
+                    let alias = Alias::new(name, steps, scope);
+                    table.check_cycle(&alias, &prefix)?;
+                    
+                    let record = database::AliasRecord {
+                        name: alias.name.clone(),
+                        scope: alias.scope.as_key().to_string(),
+                        steps: alias.steps.clone(),
+                        created_by: user_id.clone(),
+                    };
+                    if let Err(e) = db.save_alias(&record) {
+                        return Ok(format!("Error saving alias: {}", e));
+                    }
+                    
+                    let response = format!("✅ /{} → {}", alias.name, alias.definition());
+                    table.insert(alias);
+                    Ok(response)
+                }
+                Some("remove") | Some("rm") | Some("delete") => {
+                    if parts.len() < 2 {
+                        return Ok("Usage: /alias remove [--global] <name>".to_string());
+                    }
+                    
+                    let name = normalize_name(parts[1]);
+                    if table.remove(&scope, &name).is_none() {
+                        return Ok(format!("Alias /{} not found", name));
+                    }
+                    
+                    if let Err(e) = db.remove_alias(&name, scope.as_key()) {
+                        return Ok(format!("Error removing alias: {}", e));
+                    }
+                    Ok(format!("✅ Alias /{} removed", name))
+                }
+                _ => Ok("Usage: /alias <list|add|remove> [--global] [name] [command; command...]".to_string())
+            }
+        }));
+}
+
+/// How often the Telegram loop prunes old conversation history
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete history past its retention, trim oversized conversations and
+/// drop expired cached LLM replies and saved searches
+fn prune_history(db: &Database, config: &HistoryConfig) {
+    match db.prune_messages(config.retention_days, config.max_per_conversation) {
+        Ok(0) => {}
+        Ok(removed) => tracing::info!("Pruned {} history messages", removed),
+        Err(e) => tracing::warn!("Failed to prune history: {}", e),
+    }
+    if let Err(e) = db.purge_expired_responses() {
+        tracing::warn!("Failed to purge expired cached replies: {}", e);
+    }
+    if let Err(e) = db.purge_kv("search:", SEARCH_TTL.as_secs()) {
+        tracing::warn!("Failed to purge expired searches: {}", e);
+    }
+}
+
+/// Load a user's conversation: the rolling summary plus the messages after
+/// it, with their database ids
+async fn load_conversation(db: &Database, chat_id: &str, user_id: &str) -> (Conversation, Vec<i64>) {
+    let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+    let (summary, stored) = db.call(move |db| {
+        let summary = db.conversation_summary(&chat_id, &user_id).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load summary for {}: {}", chat_id, e);
+            None
+        });
+        let stored = db.unsummarized_messages(&chat_id, &user_id)
+            .inspect_err(|e| tracing::warn!("Failed to load history for {}: {}", chat_id, e));
+        (summary, stored)
+    }).await;
+    match stored {
+        Ok(stored) => {
+            let ids = stored.iter().map(|m| m.id).collect();
+            let messages = stored.into_iter()
+                .map(|m| match m.role.as_str() {
+                    "assistant" => LLMMessage::assistant(m.content),
+                    _ => LLMMessage::user(m.content),
+                })
+                .collect();
+            (Conversation::new(summary, messages), ids)
+        }
+        Err(_) => (Conversation::new(summary, Vec::new()), Vec::new()),
+    }
+}
+
+/// Persist the messages added during this turn (the ones after the first
+/// `loaded`) and the updated summary if older turns were folded into it
+async fn save_conversation(db: &Database, chat_id: &str, user_id: &str, conversation: &Conversation, loaded: usize, message_ids: &[i64]) {
+    let summary = conversation.summary.clone()
+        .zip(conversation.summarized.checked_sub(1).and_then(|i| message_ids.get(i)).copied());
+    let added: Vec<(String, String)> = conversation.messages[loaded.saturating_sub(conversation.summarized)..].iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .map(|m| (m.role.clone(), m.content.clone()))
+        .collect();
+    let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+    db.call(move |db| {
+        if let Some((summary, through)) = summary {
+            if let Err(e) = db.save_conversation_summary(&chat_id, &user_id, &summary, through) {
+                tracing::warn!("Failed to save summary for {}: {}", chat_id, e);
+            }
+        }
+        
+        for (role, content) in &added {
+            if let Err(e) = db.append_message(&chat_id, &user_id, role, content) {
+                tracing::warn!("Failed to save history for {}: {}", chat_id, e);
+                return;
+            }
+        }
+    }).await
+}
+
+/// Render a user's conversation for /history export
+fn export_conversation(db: &Database, chat_id: &str, user_id: &str, format: database::ExportFormat) -> Result<(usize, String), String> {
+    let messages = db.conversation_history(chat_id, user_id).map_err(|e| e.to_string())?;
+    Ok((messages.len(), database::export_history(format, chat_id, user_id, &messages)))
+}
+
+/// Send a user's conversation history as a document
+async fn send_history_export(bot: &TelegramAdapter, db: &Database, chat_id: &str, user_id: &str, format: &str) {
+    let response = match database::ExportFormat::from_name(format) {
+        None => Some("Usage: /history export [json|md]".to_string()),
+        Some(format) => {
+            let exported = {
+                let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+                db.call(move |db| export_conversation(db, &chat_id, &user_id, format)).await
+            };
+            match exported {
+                Ok((0, _)) => Some("📭 No conversation history yet.".to_string()),
+                Ok((count, content)) => {
+                    let filename = format!("history-{}.{}", chat_id, format.extension());
+                    let caption = format!("🗂 {} messages", count);
+                    match bot.send_document(chat_id, &filename, content.into_bytes(), Some(&caption)).await {
+                        Ok(()) => None,
+                        Err(e) => Some(format!("Error sending export: {}", e)),
+                    }
+                }
+                Err(e) => Some(format!("Error exporting history: {}", e)),
+            }
+        }
+    };
+    
+    if let Some(response) = response {
+        if let Err(e) = bot.send_message(chat_id, &response).await {
+            tracing::error!("Failed to send message: {}", e);
+        }
+    }
+}
+
+/// Register /search; Telegram handles it in the update loop to add paging
+/// buttons, this answers elsewhere with the first page
+fn register_search_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("search")
+        .with_description("Search your chat history")
+        .with_usage("/search [here] <words>")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let chat_id = &msg.chat_id;
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(chat_id);
+            let (here, terms) = parse_search_args(args);
+            let search = SavedSearch { user_id: user_id.to_string(), terms, scope: search_scope(&db, chat_id, user_id, here), saved_at: 0 };
+            Ok(search_page(&db, &search, 0, false).0)
+        }));
+}
+
+/// /search results per page
+const SEARCH_PAGE_SIZE: usize = 5;
+
+/// How long the paging buttons of a /search reply keep working
+const SEARCH_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A search whose result pages can be turned with the inline buttons, kept
+/// under `search:<chat>:<message>` in the key-value store until [`SEARCH_TTL`]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedSearch {
+    user_id: String,
+    terms: String,
+    scope: database::SearchScope,
+    /// Unix time it was saved
+    #[serde(default)]
+    saved_at: i64,
+}
+
+impl SavedSearch {
+    fn expired(&self) -> bool {
+        chrono::Utc::now().timestamp() - self.saved_at > SEARCH_TTL.as_secs() as i64
+    }
+}
+
+/// Split `/search [here] <terms>`; `here` limits the search to this chat
+fn parse_search_args(args: &[String]) -> (bool, String) {
+    match args.split_first() {
+        Some((first, rest)) if first == "here" => (true, rest.join(" ")),
+        _ => (false, args.join(" ")),
+    }
+}
+
+/// Users search their own conversations, the owner everyone's. Results are
+/// posted in the chat, so in groups only that group's messages are searched.
+fn search_scope(db: &Database, chat_id: &str, user_id: &str, here: bool) -> database::SearchScope {
+    database::SearchScope {
+        user_id: (get_user_role(db, user_id) != "owner").then(|| user_id.to_string()),
+        chat_id: (here || chat_id.starts_with('-')).then(|| chat_id.to_string()),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// One page of results; `html` renders highlights in bold for Telegram,
+/// otherwise between «». Returns the text and whether there's a next page.
+fn search_page(db: &Database, search: &SavedSearch, page: usize, html: bool) -> (String, bool) {
+    let usage = "Usage: /search [here] <words>\nwords* matches a prefix; here limits it to this chat (groups always are)";
+    // One extra to know if there's a next page
+    let mut hits = match db.search_messages(&search.terms, &search.scope, SEARCH_PAGE_SIZE + 1, page * SEARCH_PAGE_SIZE) {
+        Ok(Some(hits)) => hits,
+        Ok(None) => return (usage.to_string(), false),
+        Err(e) => return (format!("Error searching history: {}", e), false),
+    };
+    if hits.is_empty() {
+        let text = if page == 0 { "🔎 No messages found." } else { "🔎 No more results." };
+        return (text.to_string(), false);
+    }
+    let more = hits.len() > SEARCH_PAGE_SIZE;
+    hits.truncate(SEARCH_PAGE_SIZE);
+    
+    let (bold, unbold) = if html { ("<b>", "</b>") } else { ("«", "»") };
+    let escape = |text: &str| if html { escape_html(text) } else { text.to_string() };
+    let first = page * SEARCH_PAGE_SIZE + 1;
+    let mut text = format!("🔎 {}{}{} — results {}–{}\n", bold, escape(&search.terms), unbold, first, first + hits.len() - 1);
+    for (i, hit) in hits.iter().enumerate() {
+        let who = match (hit.role.as_str(), &search.scope.user_id) {
+            ("assistant", _) => "🤖 bot".to_string(),
+            (_, Some(_)) => "👤 you".to_string(),
+            (_, None) => format!("👤 {}", hit.user_id),
+        };
+        let place = if search.scope.chat_id.is_some() { String::new() } else { format!(" in {}", hit.chat_id) };
+        let snippet = escape(&hit.snippet)
+            .replace(database::search::HIGHLIGHT_START, bold)
+            .replace(database::search::HIGHLIGHT_END, unbold);
+        text.push_str(&format!("\n{}. {}{} · {}\n{}\n", first + i, who, place, hit.created_at, snippet));
+    }
+    (text, more)
+}
+
+/// Previous/next buttons for a results page
+fn search_buttons(page: usize, more: bool) -> Vec<Vec<KeyboardButton>> {
+    let mut row = Vec::new();
+    if page > 0 {
+        row.push(KeyboardButton::new("◀ Previous").with_callback(format!("search:{}", page - 1)));
+    }
+    if more {
+        row.push(KeyboardButton::new("Next ▶").with_callback(format!("search:{}", page + 1)));
+    }
+    if row.is_empty() { Vec::new() } else { vec![row] }
+}
+
+/// Run /search and send the first page, with buttons for the next ones
+async fn send_search(bot: &TelegramAdapter, db: &Database, chat_id: &str, user_id: &str, args: &[String]) {
+    let (here, terms) = parse_search_args(args);
+    let (chat, user) = (chat_id.to_string(), user_id.to_string());
+    let (text, more, search) = db.call(move |db| {
+        let scope = search_scope(db, &chat, &user, here);
+        let search = SavedSearch { user_id: user, terms, scope, saved_at: chrono::Utc::now().timestamp() };
+        let (text, more) = search_page(db, &search, 0, true);
+        (text, more, search)
+    }).await;
+    
+    let message_id = match bot.show_page(chat_id, None, &text, search_buttons(0, more)).await {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!("Failed to send search results: {}", e);
+            return;
+        }
+    };
+    if more {
+        let key = format!("search:{}:{}", chat_id, message_id);
+        let saved = serde_json::to_string(&search).unwrap_or_default();
+        if let Err(e) = db.set(&key, &saved).await {
+            tracing::warn!("Failed to save search for paging: {}", e);
+        }
+    }
+}
+
+/// Turn a /search results page from its inline buttons; only for whoever searched
+async fn turn_search_page(bot: &TelegramAdapter, db: &Database, chat_id: &str, message_id: &str, from: &str, page: &str) -> Option<&'static str> {
+    let page: usize = page.parse().ok()?;
+    let key = format!("search:{}:{}", chat_id, message_id);
+    let saved = match db.get(&key).await {
+        Ok(Some(saved)) => saved,
+        _ => return Some("This search has expired, run /search again."),
+    };
+    let search: SavedSearch = serde_json::from_str(&saved).ok()?;
+    if search.expired() {
+        let _ = db.delete(&key).await;
+        return Some("This search has expired, run /search again.");
+    }
+    if search.user_id != from {
+        return Some("Only the person who searched can turn the pages.");
+    }
+    
+    let (text, more) = db.call(move |db| search_page(db, &search, page, true)).await;
+    if let Err(e) = bot.show_page(chat_id, Some(message_id), &text, search_buttons(page, more)).await {
+        tracing::error!("Failed to show search page: {}", e);
+    }
+    None
+}
+
+/// Register /mydata; Telegram handles it in the update loop (the export is
+/// sent as a file, deletion confirmed with a button and both audited), this
+/// answers elsewhere with the export inline
+fn register_mydata_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("mydata")
+        .with_description("Export or delete your data")
+        .with_usage("/mydata export|delete")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            match args.first().map(|a| a.as_str()) {
+                Some("export") => match mydata_subject(&db, user_id, args.get(1)) {
+                    Ok(subject) => match db.export_user_data(&subject) {
+                        Ok(data) => Ok(serde_json::to_string_pretty(&data).unwrap_or_default()),
+                        Err(e) => Ok(format!("Error exporting data: {}", e)),
+                    },
+                    Err(e) => Ok(e.to_string()),
+                },
+                Some("delete") => Ok("Deleting your data needs a confirmation button; use /mydata delete in Telegram.".to_string()),
+                _ => Ok("Usage: /mydata export|delete".to_string()),
+            }
+        }));
+}
+
+/// Whose data a `/mydata` request is about: the sender's own, or for the
+/// owner whoever they name
+fn mydata_subject(db: &Database, user_id: &str, target: Option<&String>) -> Result<String, &'static str> {
+    match target {
+        None => Ok(user_id.to_string()),
+        Some(target) if target == user_id => Ok(target.clone()),
+        Some(_) if get_user_role(db, user_id) != "owner" => Err("❌ Only the owner can handle requests for other users."),
+        Some(target) if target.parse::<i64>().is_err() => Err("Usage: /mydata export|delete [telegram id]"),
+        Some(target) => Ok(target.clone()),
+    }
+}
+
+/// Record a data subject request in the audit log
+fn audit_mydata(audit: &AuditLog, event: &str, actor: &str, chat_id: &str, subject: &str, detail: &str) {
+    let scope = UsageScope::new(actor, chat_id);
+    audit.record(&guard::AuditEvent::new(event, Some(&scope), format!("subject {}: {}", subject, detail)));
+}
+
+/// `/mydata export|delete [id]`: export sends a JSON file to the requester
+/// privately; delete asks for confirmation with a button first
+async fn handle_mydata(bot: &TelegramAdapter, db: &Database, store: &dyn Store, audit: &AuditLog, chat_id: &str, user_id: &str, args: &[String]) {
+    let action = args.first().map(|a| a.as_str());
+    let subject = {
+        let (user_id, target) = (user_id.to_string(), args.get(1).cloned());
+        db.call(move |db| mydata_subject(db, &user_id, target.as_ref())).await
+    };
+    let subject = match (action, subject) {
+        (Some("export") | Some("delete"), Ok(subject)) => subject,
+        (Some("export") | Some("delete"), Err(e)) => {
+            let _ = bot.send_message(chat_id, e).await;
+            return;
+        }
+        _ => {
+            let usage = "🔐 *Your data*\n\n\
+                /mydata export - everything stored about you, as a JSON file\n\
+                /mydata delete - erase it all (asks to confirm)\n\n\
+                _Owner: add a telegram id to handle someone else's request_";
+            let _ = bot.send_message(chat_id, usage).await;
+            return;
+        }
+    };
+    
+    if action == Some("delete") {
+        let target = if subject == user_id { "everything stored about you".to_string() } else { format!("everything stored about {}", subject) };
+        let text = format!(
+            "⚠️ This permanently erases {}: profile, settings, conversations, memories, documents, usage and saved searches. It can't be undone.",
+            target
+        );
+        let buttons = vec![vec![
+            KeyboardButton::new("🗑 Delete everything").with_callback(format!("mydata:delete:{}", subject)),
+            KeyboardButton::new("Cancel").with_callback("mydata:cancel"),
+        ]];
+        audit_mydata(audit, "data_deletion_requested", user_id, chat_id, &subject, "awaiting confirmation");
+        if let Err(e) = bot.send_with_keyboard(chat_id, &text, buttons).await {
+            tracing::error!("Failed to send message: {}", e);
+        }
+        return;
+    }
+    
+    let export = {
+        let subject = subject.clone();
+        db.call(move |db| db.export_user_data(&subject)).await
+    };
+    let messages = store.user_messages(&subject).await;
+    let json = match (export, messages) {
+        (Ok(mut data), Ok(messages)) => {
+            data["messages"] = serde_json::json!(messages);
+            serde_json::to_string_pretty(&data).unwrap_or_default()
+        }
+        (Err(e), _) => {
+            let _ = bot.send_message(chat_id, &format!("Error exporting data: {}", e)).await;
+            return;
+        }
+        (_, Err(e)) => {
+            let _ = bot.send_message(chat_id, &format!("Error exporting messages: {}", e)).await;
+            return;
+        }
+    };
+    // Personal data goes to the requester's private chat, not the group
+    let filename = format!("mydata-{}.json", subject);
+    match bot.send_document(user_id, &filename, json.into_bytes(), Some("🔐 Your data export")).await {
+        Ok(()) => {
+            audit_mydata(audit, "data_exported", user_id, chat_id, &subject, &filename);
+            if chat_id != user_id {
+                let _ = bot.send_message(chat_id, "📬 Sent to you in a private chat.").await;
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to send data export: {}", e);
+            let _ = bot.send_message(chat_id, "❌ Couldn't send the export. Start a private chat with me first.").await;
+        }
+    }
+}
+
+/// The confirm/cancel buttons of `/mydata delete`; only the subject or the
+/// owner can confirm
+async fn confirm_mydata(state: &TelegramState, chat_id: &str, from: &str, action: &str) -> Option<&'static str> {
+    let TelegramState { bot, db, store, audit, commands, first_message, .. } = state;
+    let Some(subject) = action.strip_prefix("delete:") else {
+        let _ = bot.send_message(chat_id, "👍 Nothing was deleted.").await;
+        return None;
+    };
+    if subject != from && user_role(db, from).await != "owner" {
+        return Some("Only that user or the owner can confirm.");
+    }
+    
+    let removed = {
+        let subject = subject.to_string();
+        db.call(move |db| db.delete_user_data(&subject)).await
+    };
+    // On SQLite the Store's rows went with the rest; a JSON store has its own
+    let logged = match &removed {
+        Ok(_) => store.delete_user(subject).await,
+        Err(_) => Ok(0),
+    };
+    let response = match (removed, logged) {
+        (Ok(_), Err(e)) => {
+            audit_mydata(audit, "data_deletion_failed", from, chat_id, subject, &format!("store: {}", e));
+            format!("❌ The database records were deleted, but not the message log: {}", e)
+        }
+        (Ok(mut removed), Ok(logged)) => {
+            if logged > 0 {
+                removed.push(("store", logged));
+            }
+            // State held in memory goes with the rows
+            APP_STATES.lock().unwrap().remove(subject);
+            commands.aliases().remove_user(subject);
+            first_message.lock().unwrap().remove(subject);
+            let total: usize = removed.iter().map(|(_, count)| count).sum();
+            let detail = removed.iter().map(|(table, count)| format!("{}={}", table, count)).collect::<Vec<_>>().join(" ");
+            audit_mydata(audit, "data_deleted", from, chat_id, subject, &detail);
+            format!("🗑 Deleted {} records about {}. Nothing about them is left in my database.", total, subject)
+        }
+        (Err(e), _) => {
+            audit_mydata(audit, "data_deletion_failed", from, chat_id, subject, &e.to_string());
+            format!("❌ Deletion failed, nothing was removed: {}", e)
+        }
+    };
+    let _ = bot.send_message(chat_id, &response).await;
+    None
+}
+
+/// Register /clear and /history for persisted conversation history
+fn register_history_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db_clear = db.clone();
+    commands.register(Command::new("clear")
+        .with_description("Clear conversation history")
+        .with_usage("/clear [all]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let chat_id = &msg.chat_id;
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(chat_id);
+            
+            // /clear all resets every conversation in this chat (owner/admin only)
+            if args.first().map(|a| a.as_str()) == Some("all") {
+                let role = get_user_role(&db_clear, user_id);
+                if role != "owner" && role != "admin" {
+                    return Ok("❌ Only owner/admin can clear the whole chat.".to_string());
+                }
+                return match db_clear.clear_chat(chat_id) {
+                    Ok(count) => Ok(format!("🧹 Chat history cleared ({} messages)", count)),
+                    Err(e) => Ok(format!("Error clearing history: {}", e)),
+                };
+            }
+            
+            match db_clear.clear_conversation(chat_id, user_id) {
+                Ok(count) => Ok(format!("🧹 Conversation cleared ({} messages)", count)),
+                Err(e) => Ok(format!("Error clearing history: {}", e)),
+            }
+        }));
+    
+    let db = db.clone();
+    commands.register(Command::new("history")
+        .with_description("Show or export conversation history")
+        .with_usage("/history [export json|md | clear]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let chat_id = &msg.chat_id;
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(chat_id);
+            
+            match args.first().map(|a| a.as_str()) {
+                None => {
+                    let messages = match db.conversation_history(chat_id, user_id) {
+                        Ok(messages) => messages,
+                        Err(e) => return Ok(format!("Error reading history: {}", e)),
+                    };
+                    let Some(first) = messages.first() else {
+                        return Ok("📭 No conversation history yet.".to_string());
+                    };
+                    Ok(format!(
+                        "🗂 *Conversation History*\n\n\
+                        Messages: {}\n\
+                        Since: {}\n\n\
+                        _/history export [json|md] to download, /clear to reset_",
+                        messages.len(), first.created_at
+                    ))
+                }
+                Some("export") => {
+                    let format = args.get(1).map(|f| f.as_str()).unwrap_or("md");
+                    let Some(format) = database::ExportFormat::from_name(format) else {
+                        return Ok("Usage: /history export [json|md]".to_string());
+                    };
+                    match export_conversation(&db, chat_id, user_id, format) {
+                        Ok((0, _)) => Ok("📭 No conversation history yet.".to_string()),
+                        Ok((_, content)) => Ok(content),
+                        Err(e) => Ok(format!("Error exporting history: {}", e)),
+                    }
+                }
+                Some("clear") => {
+                    match db.clear_conversation(chat_id, user_id) {
+                        Ok(count) => Ok(format!("🧹 Conversation cleared ({} messages)", count)),
+                        Err(e) => Ok(format!("Error clearing history: {}", e)),
+                    }
+                }
+                _ => Ok("Usage: /history [export json|md | clear]".to_string()),
+            }
+        }));
+}
+
+/// Start of the current month (UTC) in the database's timestamp format
+fn month_start() -> String {
+    chrono::Utc::now().format("%Y-%m-01 00:00:00").to_string()
+}
+
+/// Writes LLM usage to the database and enforces per-role monthly budgets
+struct UsageLedger {
+    db: Arc<Database>,
+    config: UsageConfig,
+}
+
+impl UsageLedger {
+    fn new(db: Arc<Database>, config: UsageConfig) -> Self {
+        Self { db, config }
+    }
+}
+
+#[async_trait]
+impl UsageSink for UsageLedger {
+    async fn record(&self, scope: Option<&UsageScope>, event: &UsageEvent) {
+        if event.estimated {
+            tracing::debug!("{}/{} reported no usage; estimated {} tokens", event.provider, event.model, event.total_tokens());
+        }
+        let (user_id, chat_id) = scope
+            .map(|s| (s.user_id.as_str(), s.chat_id.as_str()))
+            .unwrap_or(("system", ""));
+        let record = database::UsageRecord {
+            user_id: user_id.to_string(),
+            chat_id: chat_id.to_string(),
+            provider: event.provider.clone(),
+            model: event.model.clone(),
+            task: event.task.clone(),
+            prompt_tokens: event.prompt_tokens,
+            completion_tokens: event.completion_tokens,
+            cost: self.config.cost(event),
+        };
+        
+        if let Err(e) = self.db.call(move |db| db.record_usage(&record)).await {
+            tracing::warn!("Failed to record LLM usage: {}", e);
+        }
+    }
+    
+    async fn check_budget(&self, scope: &UsageScope) -> Result<(), String> {
+        let role = user_role(&self.db, &scope.user_id).await;
+        let Some(budget) = self.config.budget(&role) else {
+            return Ok(());
+        };
+        
+        let user_id = scope.user_id.clone();
+        match self.db.call(move |db| db.user_usage(&user_id, &month_start())).await {
+            Ok(used) => match budget.exceeded(used.total_tokens(), used.cost) {
+                Some(reason) => Err(reason),
+                None => Ok(()),
+            },
+            Err(e) => {
+                tracing::warn!("Failed to read LLM usage: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Response cache in the `llm_cache` table
+struct ResponseCacheStore {
+    db: Arc<Database>,
+}
+
+impl ResponseCacheStore {
+    fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+    
+    fn count(task: LLMTask, hit: bool) {
+        let mut metrics = CACHE_METRICS.lock().unwrap();
+        let entry = metrics.entry(task.as_str()).or_default();
+        if hit { entry.0 += 1 } else { entry.1 += 1 }
+    }
+}
+
+#[async_trait]
+impl ResponseCache for ResponseCacheStore {
+    async fn get(&self, task: LLMTask, key: &str) -> Option<infrastructure::llm::LLMResponse> {
+        let key = key.to_string();
+        let cached = self.db.call(move |db| db.cached_response(&key)).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to read LLM cache: {}", e);
+            None
+        });
+        Self::count(task, cached.is_some());
+        cached.map(|c| infrastructure::llm::LLMResponse {
+            content: c.content,
+            model: c.model,
+            usage: None,
+            finish_reason: Some("stop".to_string()),
+            tool_calls: Vec::new(),
+        })
+    }
+
+    async fn put(&self, task: LLMTask, key: &str, response: &infrastructure::llm::LLMResponse, ttl: Duration) {
+        let (key, model, content) = (key.to_string(), response.model.clone(), response.content.clone());
+        let stored = self.db.call(move |db| db.cache_response(&key, task.as_str(), &model, &content, ttl.as_secs())).await;
+        if let Err(e) = stored {
+            tracing::warn!("Failed to cache LLM reply: {}", e);
+        }
+    }
+}
+
+fn register_cache_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("cache")
+        .with_description("LLM response cache stats (owner only)")
+        .with_usage("/cache [clear [task]]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            if !is_owner(user_id) {
+                return Ok("❌ Only the owner can manage the response cache.".to_string());
+            }
+            
+            match args.first().map(|a| a.as_str()) {
+                Some("clear") => {
+                    let task = args.get(1).map(|t| t.to_lowercase());
+                    match db.clear_response_cache(task.as_deref()) {
+                        Ok(removed) => Ok(format!("🧹 Removed {} cached replies{}.", removed,
+                            task.map(|t| format!(" for {}", t)).unwrap_or_default())),
+                        Err(e) => Ok(format!("Error clearing cache: {}", e)),
+                    }
+                }
+                Some(other) => Ok(format!("Unknown option '{}'. Usage: /cache [clear [task]]", other)),
+                None => {
+                    let stats = match db.response_cache_stats() {
+                        Ok(stats) => stats,
+                        Err(e) => return Ok(format!("Error reading cache: {}", e)),
+                    };
+                    let metrics = CACHE_METRICS.lock().unwrap().clone();
+                    let mut tasks: Vec<String> = stats.iter().map(|s| s.task.clone())
+                        .chain(metrics.keys().map(|t| t.to_string()))
+                        .collect();
+                    tasks.sort();
+                    tasks.dedup();
+                    if tasks.is_empty() {
+                        return Ok("🗄 The response cache is empty.".to_string());
+                    }
+                    
+                    let mut response = "🗄 *LLM Response Cache*\n\n".to_string();
+                    for task in &tasks {
+                        let entries = stats.iter().find(|s| &s.task == task).map_or(0, |s| s.entries);
+                        let (hits, misses) = metrics.get(task.as_str()).copied().unwrap_or_default();
+                        let rate = if hits + misses > 0 { 100.0 * hits as f64 / (hits + misses) as f64 } else { 0.0 };
+                        response.push_str(&format!(
+                            "• {}: {} entries, {} hits / {} misses since start ({:.0}%)\n",
+                            task, entries, hits, misses, rate
+                        ));
+                    }
+                    Ok(response)
+                }
+            }
+        }));
+}
+
+/// Register /usage: the caller's LLM usage this month, or everyone's for the owner
+fn register_usage_command(commands: &mut CommandService, db: &Arc<Database>, config: UsageConfig) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("usage")
+        .with_description("Show your LLM token usage")
+        .with_usage("/usage [all]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            let role = get_user_role(&db, user_id);
+            let since = month_start();
+            
+            if args.first().map(|a| a.as_str()) == Some("all") {
+                if role != "owner" {
+                    return Ok("❌ Only the owner can see everyone's usage.".to_string());
+                }
+                let users = match db.usage_by_user(&since) {
+                    Ok(users) => users,
+                    Err(e) => return Ok(format!("Error reading usage: {}", e)),
+                };
+                if users.is_empty() {
+                    return Ok("📊 No LLM usage this month.".to_string());
+                }
+                let mut response = "📊 *LLM Usage This Month*\n\n".to_string();
+                for u in &users {
+                    response.push_str(&format!(
+                        "• {}: {} tokens, {} requests, ${:.4}\n",
+                        u.label, u.total_tokens(), u.requests, u.cost
+                    ));
+                }
+                let tokens: u64 = users.iter().map(|u| u.total_tokens()).sum();
+                let cost: f64 = users.iter().map(|u| u.cost).sum();
+                response.push_str(&format!("\nTotal: {} tokens, ${:.4}", tokens, cost));
+                return Ok(response);
+            }
+            
+            let (total, by_model) = match (db.user_usage(user_id, &since), db.user_usage_by_model(user_id, &since)) {
+                (Ok(total), Ok(by_model)) => (total, by_model),
+                (Err(e), _) | (_, Err(e)) => return Ok(format!("Error reading usage: {}", e)),
+            };
+            
+            let mut response = format!(
+                "📊 *Your LLM Usage This Month*\n\n\
+                Requests: {}\n\
+                Tokens: {} ({} prompt + {} completion)\n\
+                Cost: ${:.4}\n",
+                total.requests, total.total_tokens(), total.prompt_tokens, total.completion_tokens, total.cost
+            );
+            for m in &by_model {
+                response.push_str(&format!("• {}: {} tokens\n", m.label, m.total_tokens()));
+            }
+            
+            if let Some(budget) = config.budget(&role) {
+                response.push_str("\n*Budget*\n");
+                if let Some(limit) = budget.monthly_tokens {
+                    response.push_str(&format!("🔢 {} / {} tokens\n", total.total_tokens(), limit));
+                }
+                if let Some(limit) = budget.monthly_cost {
+                    response.push_str(&format!("💵 ${:.4} / ${:.2}\n", total.cost, limit));
+                }
+            }
+            Ok(response)
+        }));
+}
+
+/// Store an explicit "remember ..." fact and confirm it
+fn remember_fact(db: &Database, user_id: &str, fact: &str, max_facts: usize) -> String {
+    match db.add_memory(user_id, FactCategory::Other.as_str(), fact, "explicit") {
+        Ok(Some(_)) => {
+            let _ = db.trim_memories(user_id, max_facts);
+            format!("🧠 Got it, I'll remember: {}\n\n_See /memory to manage what I know._", fact)
+        }
+        Ok(None) => format!("🧠 I already know: {}", fact),
+        Err(e) => format!("Error saving memory: {}", e),
+    }
+}
+
+/// System prompt section with the user's facts most relevant to `text`
+fn memory_prompt(db: &Database, user_id: &str, text: &str, limit: usize) -> Option<String> {
+    let facts = load_facts(db, user_id);
+    memory::prompt_section(&memory::relevant(&facts, text, limit))
+}
+
+fn load_facts(db: &Database, user_id: &str) -> Vec<memory::Fact> {
+    match db.list_memories(user_id) {
+        Ok(records) => records.into_iter()
+            .map(|r| memory::Fact { id: r.id, category: FactCategory::from_name(&r.category), content: r.content })
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Failed to load memories for {}: {}", user_id, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Ask the LLM for durable facts in a chat turn and store the new ones
+async fn extract_facts(extractor: &FactExtractor, db: &Database, user_id: &str, text: &str, reply: &str, max_facts: usize) {
+    let user_id = user_id.to_string();
+    let known = {
+        let user_id = user_id.clone();
+        db.call(move |db| load_facts(db, &user_id)).await
+    };
+    let facts = extractor.extract(&known, text, reply).await;
+    if facts.is_empty() {
+        return;
+    }
+    
+    db.call(move |db| {
+        let mut added = 0;
+        for (category, fact) in &facts {
+            match db.add_memory(&user_id, category.as_str(), fact, "extracted") {
+                Ok(Some(_)) => added += 1,
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to save memory for {}: {}", user_id, e),
+            }
+        }
+        if added > 0 {
+            let _ = db.trim_memories(&user_id, max_facts);
+            tracing::info!("Remembered {} new facts about {}", added, user_id);
+        }
+    }).await
+}
+
+/// Register /memory so users can see and remove what the bot remembers
+fn register_memory_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("memory")
+        .with_description("What I remember about you")
+        .with_usage("/memory [list|forget <id>|clear]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            
+            match args.first().map(|a| a.as_str()) {
+                Some("list") | Some("ls") | None => {
+                    let memories = match db.list_memories(user_id) {
+                        Ok(memories) => memories,
+                        Err(e) => return Ok(format!("Error reading memories: {}", e)),
+                    };
+                    if memories.is_empty() {
+                        return Ok("🧠 I don't remember anything about you yet.\n\n\
+                            Tell me \"remember that ...\" and I will.".to_string());
+                    }
+                    let mut response = "🧠 *What I Remember*\n\n".to_string();
+                    for m in &memories {
+                        response.push_str(&format!("#{} [{}] {}\n", m.id, m.category, m.content));
+                    }
+                    response.push_str("\n_/memory forget <id> to remove one, /memory clear to remove all_");
+                    Ok(response)
+                }
+                Some("forget") | Some("rm") | Some("delete") => {
+                    let Some(id) = args.get(1).and_then(|a| a.trim_start_matches('#').parse::<i64>().ok()) else {
+                        return Ok("Usage: /memory forget <id>".to_string());
+                    };
+                    match db.forget_memory(user_id, id) {
+                        Ok(true) => Ok(format!("🗑 Forgot #{}", id)),
+                        Ok(false) => Ok(format!("Memory #{} not found", id)),
+                        Err(e) => Ok(format!("Error removing memory: {}", e)),
+                    }
+                }
+                Some("clear") => match db.clear_memories(user_id) {
+                    Ok(count) => Ok(format!("🧹 Forgot everything ({} facts)", count)),
+                    Err(e) => Ok(format!("Error clearing memories: {}", e)),
+                },
+                _ => Ok("Usage: /memory [list|forget <id>|clear]".to_string()),
+            }
+        }));
+}
+
+/// Index scope of a user's uploads
+fn user_scope(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+/// Index scope of the current workspace, e.g. `workspace:default-workspace`
+fn workspace_scope() -> String {
+    let dir = get_workspace_dir();
+    let name = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    format!("workspace:{}", name)
+}
+
+/// Scopes searched for a user: their uploads, plus the workspace for privileged users
+fn document_scopes(user_id: &str) -> Vec<String> {
+    let mut scopes = vec![user_scope(user_id)];
+    if can_use_privileged(user_id).unwrap_or(false) {
+        scopes.push(workspace_scope());
+    }
+    scopes
+}
+
+/// Chunk, embed and store a document. Returns the chunk count, or `None`
+/// if the same content is already indexed.
+async fn index_document(
+    db: &Database,
+    llm: Option<&Arc<LLMRouter>>,
+    rag: &RagConfig,
+    scope: &str,
+    source: &str,
+    text: &str,
+) -> Result<Option<usize>, String> {
+    let hash = hash::sha256(text);
+    let (scope, source) = (scope.to_string(), source.to_string());
+    let indexed = {
+        let (scope, source) = (scope.clone(), source.clone());
+        db.call(move |db| db.document_hash(&scope, &source)).await
+    };
+    if indexed.map_err(|e| e.to_string())?.as_deref() == Some(hash.as_str()) {
+        return Ok(None);
+    }
+    
+    let chunks = rag.chunk(&source, text);
+    // Embed with the section heading so short chunks keep their context
+    let inputs: Vec<String> = chunks.iter()
+        .map(|c| match &c.heading {
+            Some(heading) if !c.text.starts_with('#') => format!("{}\n{}", heading, c.text),
+            _ => c.text.clone(),
+        })
+        .collect();
+    let embedded = match llm {
+        Some(llm) if !inputs.is_empty() => Embedder::new(llm.clone()).embed_all(&inputs).await,
+        _ => None,
+    };
+    let (model, vectors) = match embedded {
+        Some((model, vectors)) if vectors.len() == chunks.len() => (Some(model), vectors.into_iter().map(Some).collect()),
+        _ => (None, vec![None; chunks.len()]),
+    };
+    
+    let records: Vec<database::DocumentChunk> = chunks.into_iter()
+        .zip(vectors)
+        .map(|(chunk, embedding)| database::DocumentChunk {
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            content: chunk.text,
+            embedding,
+        })
+        .collect();
+    
+    let count = records.len();
+    db.call(move |db| db.replace_document(&scope, &source, &hash, model.as_deref(), &records))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Some(count))
+}
+
+/// Index a file sent to the bot into the sender's documents
+async fn index_upload(
+    bot: &TelegramAdapter,
+    db: &Database,
+    llm: Option<&Arc<LLMRouter>>,
+    rag: &RagConfig,
+    user_id: &str,
+    document: &infrastructure::adapters::telegram::Document,
+) -> String {
+    let name = document.file_name.clone().unwrap_or_else(|| "document.txt".to_string());
+    if !rag.indexes(std::path::Path::new(&name)) {
+        return format!("📄 I can only read text and code files ({}).", rag.extensions.join(", "));
+    }
+    if document.file_size.unwrap_or(0) > rag.max_file_kb * 1024 {
+        return format!("📄 {} is too large to index (limit {} KB).", name, rag.max_file_kb);
+    }
+    
+    let bytes = match bot.download_file(&document.file_id).await {
+        Ok(bytes) => bytes,
+        Err(e) => return format!("Error downloading {}: {}", name, e),
+    };
+    let Ok(text) = String::from_utf8(bytes) else {
+        return format!("📄 {} is not a UTF-8 text file.", name);
+    };
+    
+    match index_document(db, llm, rag, &user_scope(user_id), &name, &text).await {
+        Ok(Some(chunks)) => format!("📚 Indexed {} ({} chunks). Ask me about it, or see /docs.", name, chunks),
+        Ok(None) => format!("📚 {} is already indexed.", name),
+        Err(e) => format!("Error indexing {}: {}", name, e),
+    }
+}
+
+/// Text and code files under `dir`, skipping hidden and build directories
+fn collect_documents(dir: &std::path::Path, rag: &RagConfig, files: &mut Vec<std::path::PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name == "target" || name == "node_modules" {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_documents(&path, rag, files);
+        } else if file_type.is_file()
+            && rag.indexes(&path)
+            && entry.metadata().is_ok_and(|m| m.len() <= rag.max_file_kb * 1024)
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Re-index the workspace: new and changed files are indexed, removed files dropped
+async fn index_workspace(db: &Database, llm: Option<&Arc<LLMRouter>>, rag: &RagConfig) -> String {
+    let dir = get_workspace_dir();
+    let scope = workspace_scope();
+    let mut files = Vec::new();
+    collect_documents(&dir, rag, &mut files);
+    
+    let (mut indexed, mut unchanged, mut failed) = (0, 0, 0);
+    let mut sources = std::collections::HashSet::new();
+    for path in &files {
+        let source = path.strip_prefix(&dir).unwrap_or(path).to_string_lossy().to_string();
+        let Ok(text) = fs::read_to_string(path) else {
+            failed += 1;
+            continue;
+        };
+        match index_document(db, llm, rag, &scope, &source, &text).await {
+            Ok(Some(_)) => indexed += 1,
+            Ok(None) => unchanged += 1,
+            Err(e) => {
+                tracing::warn!("Failed to index {}: {}", source, e);
+                failed += 1;
+            }
+        }
+        sources.insert(source);
+    }
+    
+    let removed = db.call(move |db| {
+        let mut removed = 0;
+        for document in db.list_documents(&scope).unwrap_or_default() {
+            if !sources.contains(&document.source) && db.remove_document(&scope, &document.source).unwrap_or(false) {
+                removed += 1;
+            }
+        }
+        removed
+    }).await;
+    
+    format!(
+        "📚 Indexed {}\n\n🆕 {} new or changed\n✅ {} unchanged\n🗑 {} removed\n⚠️ {} failed",
+        dir.display(), indexed, unchanged, removed, failed
+    )
+}
+
+/// Chunks from the user's documents relevant to `text`
+async fn retrieve_documents(db: &Database, llm: &Arc<LLMRouter>, rag: &RagConfig, user_id: &str, text: &str) -> Vec<rag::Hit> {
+    let scopes = document_scopes(user_id);
+    let chunks: Vec<rag::IndexedChunk> = match db.call(move |db| db.scope_chunks(&scopes)).await {
+        Ok(chunks) => chunks.into_iter()
+            .map(|c| rag::IndexedChunk {
+                source: c.source,
+                start_line: c.start_line,
+                end_line: c.end_line,
+                content: c.content,
+                embedding_model: c.embedding_model,
+                embedding: c.embedding,
+            })
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Failed to load documents for {}: {}", user_id, e);
+            return Vec::new();
+        }
+    };
+    if chunks.is_empty() {
+        return Vec::new();
+    }
+    
+    let query = if chunks.iter().any(|c| c.embedding.is_some()) {
+        Embedder::new(llm.clone()).embed_query(text).await
+    } else {
+        None
+    };
+    let hits = rag::search(&chunks, text, query.as_ref().map(|(model, v)| (model.as_str(), v.as_slice())), rag);
+    if !hits.is_empty() {
+        tracing::info!("Retrieved {} document chunks for {}", hits.len(), user_id);
+    }
+    hits
+}
+
+/// Register /docs to list and remove indexed documents
+fn register_docs_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("docs")
+        .with_description("Documents I can answer from")
+        .with_usage("/docs [list|index|remove <name>|clear [workspace]]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            let privileged = can_use_privileged(user_id).unwrap_or(false);
+            
+            match args.first().map(|a| a.as_str()) {
+                Some("list") | Some("ls") | None => {
+                    let mut response = "📚 *Your Documents*\n\n".to_string();
+                    match db.list_documents(&user_scope(user_id)) {
+                        Ok(documents) if documents.is_empty() => response.push_str("None yet. Send me a text or code file to index it.\n"),
+                        Ok(documents) => {
+                            for d in &documents {
+                                let search = if d.embedding_model.is_some() { "semantic" } else { "keyword" };
+                                let date = d.indexed_at.get(..10).unwrap_or(&d.indexed_at);
+                                response.push_str(&format!("📄 {} ({} chunks, {}, {})\n", d.source, d.chunks, search, date));
+                            }
                         }
-                    } else {
-                        Ok("Database not initialized".to_string())
-                    }
-                }
-                Some("remove") => {
-                    if parts.len() < 2 {
-                        return Ok("Usage: /users remove <telegram_id>".to_string());
-                    }
-                    let target_id = parts[1];
-                    
-                    // Check if target is owner (from env)
-                    if is_owner(target_id) {
-                        return Ok("❌ Cannot remove owner (set via BOT_OWNER_ID env var).".to_string());
+                        Err(e) => return Ok(format!("Error reading documents: {}", e)),
                     }
-                    
-                    let db_guard = DB.lock().unwrap();
-                    if let Some(db) = db_guard.as_ref() {
-                        match db.remove_user(target_id) {
-                            Ok(true) => Ok(format!("✅ User {} removed", target_id)),
-                            Ok(false) => Ok(format!("User {} not found", target_id)),
-                            Err(e) => Ok(format!("Error: {}", e))
-                        }
-                    } else {
-                        Ok("Database not initialized".to_string())
+                    if privileged {
+                        let workspace = db.list_documents(&workspace_scope()).unwrap_or_default();
+                        let chunks: usize = workspace.iter().map(|d| d.chunks).sum();
+                        response.push_str(&format!("\n🗂 Workspace: {} files, {} chunks (/docs index to refresh)\n", workspace.len(), chunks));
                     }
+                    Ok(response)
                 }
-                Some("info") => {
-                    if parts.len() < 2 {
-                        return Ok("Usage: /users info <telegram_id>".to_string());
-                    }
-                    let target_id = parts[1];
-                    
-                    let db_guard = DB.lock().unwrap();
-                    if let Some(db) = db_guard.as_ref() {
-                        match db.get_user_by_telegram_id(target_id) {
-                            Ok(Some(user)) => Ok(format!(
-                                "ℹ️ *User Info*\n\nID: {}\nUsername: @{}\nRole: {}\nJoined: {}",
-                                user.telegram_id,
-                                user.username.as_deref().unwrap_or("none"),
-                                user.role,
-                                user.created_at
-                            )),
-                            Ok(None) => Ok(format!("User {} not found", target_id)),
-                            Err(e) => Ok(format!("Error: {}", e))
-                        }
-                    } else {
-                        Ok("Database not initialized".to_string())
+                Some("remove") | Some("rm") | Some("delete") => {
+                    let Some(name) = args.get(1..).map(|rest| rest.join(" ")).filter(|n| !n.is_empty()) else {
+                        return Ok("Usage: /docs remove <name>".to_string());
+                    };
+                    match db.remove_document(&user_scope(user_id), &name) {
+                        Ok(true) => Ok(format!("🗑 Removed {}", name)),
+                        Ok(false) => Ok(format!("Document {} not found", name)),
+                        Err(e) => Ok(format!("Error removing document: {}", e)),
                     }
                 }
-                Some("setrole") | Some("role") => {
-                    if parts.len() < 3 {
-                        return Ok("Usage: /users setrole <telegram_id> <role>\nRoles: owner, admin, user, guest".to_string());
-                    }
-                    let target_id = parts[1];
-                    let new_role = parts[2];
-                    
-                    // Prevent setting owner role - owner can only be set via BOT_OWNER_ID env var
-                    if new_role == "owner" {
-                        return Ok("❌ Cannot set owner role. Owner is set via BOT_OWNER_ID environment variable.".to_string());
-                    }
-                    
-                    // Check if target is owner (from env)
-                    if is_owner(target_id) {
-                        return Ok("❌ Cannot modify owner role (set via BOT_OWNER_ID env var).".to_string());
-                    }
-                    
-                    let db_guard = DB.lock().unwrap();
-                    if let Some(db) = db_guard.as_ref() {
-                        match db.update_user_role(target_id, new_role) {
-                            Ok(true) => Ok(format!("✅ User {} role updated to {}", target_id, new_role)),
-                            Ok(false) => Ok(format!("User {} not found", target_id)),
-                            Err(e) => Ok(format!("Error: {}", e))
+                Some("clear") => {
+                    let scope = if args.get(1).map(|a| a.as_str()) == Some("workspace") {
+                        if !privileged {
+                            return Ok("❌ Only the owner and approved users can clear the workspace index.".to_string());
                         }
+                        workspace_scope()
                     } else {
-                        Ok("Database not initialized".to_string())
+                        user_scope(user_id)
+                    };
+                    match db.remove_scope(&scope) {
+                        Ok(count) => Ok(format!("🧹 Removed {} documents", count)),
+                        Err(e) => Ok(format!("Error clearing documents: {}", e)),
                     }
                 }
-                _ => Ok("Usage: /users <list|add|remove|info|setrole> [args]".to_string())
+                _ => Ok("Usage: /docs [list|index|remove <name>|clear [workspace]]".to_string()),
             }
         }));
 }
 
-/// Register /settings command for user personalization
-fn register_settings_command(commands: &mut CommandService) {
+/// Register /prompt to inspect and reload prompt templates (owner only)
+fn register_prompt_command(commands: &mut CommandService) {
     use crate::domain::entities::{Command, Content};
     
-    commands.register(Command::new("settings")
-        .with_description("Manage your personal settings")
-        .with_usage("/settings [get|set] [key] [value]")
+    commands.register(Command::new("prompt")
+        .with_description("Show or reload prompt templates (owner)")
+        .with_usage("/prompt show [name] [lang] | /prompt reload")
         .with_handler(|msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
             };
             
-            let user_id = &msg.chat_id;
-            let args_str = args.join(" ");
-            let parts: Vec<&str> = args_str.split_whitespace().collect();
-            
-            let db_guard = DB.lock().unwrap();
-            let db = match db_guard.as_ref() {
-                Some(db) => db,
-                None => return Ok("Database not initialized".to_string())
-            };
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            if !is_owner(user_id) {
+                return Ok("❌ Only the owner can manage prompt templates.".to_string());
+            }
             
-            match parts.first().map(|s| *s) {
-                Some("get") | None => {
-                    // Show current settings
-                    match db.get_user_settings(user_id) {
-                        Ok(Some(settings)) => {
-                            Ok(format!(
-                                "⚙️ *Your Settings*\n\n\
-                                🌍 Language: {}\n\
-                                🕐 Timezone: {}\n\
-                                📝 Custom Prompt: {}\n\n\
-                                _Use /settings set <key> <value> to change_",
-                                settings.language,
-                                settings.timezone,
-                                settings.system_prompt.as_deref().unwrap_or("(none)")
-                            ))
+            match args.first().map(|a| a.as_str()) {
+                Some("show") | None => {
+                    let prompts = PROMPTS.read().unwrap();
+                    let Some(name) = args.get(1) else {
+                        let mut response = "📝 *Prompt Templates*\n\n".to_string();
+                        for name in Prompts::names() {
+                            let source = match prompts.template(name, None) {
+                                Some((_, Source::File(path))) => path.display().to_string(),
+                                _ => "built-in".to_string(),
+                            };
+                            response.push_str(&format!("• {} ({})\n", name, source));
                         }
-                        Ok(None) => {
-                            Ok("⚙️ *Your Settings*\n\nUsing defaults:\n\
-                                🌍 Language: en\n\
-                                🕐 Timezone: UTC\n\
-                                📝 Custom Prompt: (none)\n\n\
-                                _Use /settings set <key> <value> to customize_".to_string())
+                        if let Some(dir) = prompts.dir() {
+                            response.push_str(&format!("\nOverride with {}/<name>.txt or <name>.<lang>.txt", dir.display()));
                         }
-                        Err(e) => Ok(format!("Error: {}", e))
+                        return Ok(response);
+                    };
+                    let lang = args.get(2).map(|l| l.as_str());
+                    match prompts.template(name, lang) {
+                        Some((text, source)) => {
+                            let source = match source {
+                                Source::File(path) => path.display().to_string(),
+                                Source::Builtin => "built-in".to_string(),
+                            };
+                            Ok(format!("📝 *{}* ({})\n\n{}", name, source, if text.is_empty() { "(empty)" } else { text }))
+                        }
+                        None => Ok(format!("Unknown template {}. See /prompt show", name)),
                     }
                 }
-                Some("set") => {
-                    if parts.len() < 3 {
-                        return Ok("Usage: /settings set <key> <value>\n\n\
-                                Keys:\n\
-                                • language - en, id, jv\n\
-                                • timezone - UTC, Asia/Jakarta, etc.\n\
-                                • prompt - custom system prompt".to_string());
+                Some("reload") => {
+                    let mut prompts = PROMPTS.write().unwrap();
+                    match prompts.reload() {
+                        Ok(count) => Ok(format!("🔄 Reloaded prompt templates ({} overrides)", count)),
+                        Err(e) => Ok(format!("Error reloading prompts: {}", e)),
                     }
-                    
-                    let key = parts[1];
-                    let value = parts[2..].join(" ");
-                    
-                    // Get current settings
-                    let current = db.get_user_settings(user_id).ok().flatten()
-                        .unwrap_or_else(|| database::UserSettings {
-                            language: "en".to_string(),
-                            timezone: "UTC".to_string(),
-                            system_prompt: None,
-                            preferences: "{}".to_string(),
-                        });
-                    
-                    let mut settings = current;
-                    
-                    match key {
-                        "language" | "lang" => {
-                            if !["en", "id", "jv"].contains(&value.as_str()) {
-                                return Ok("Invalid language. Use: en, id, jv".to_string());
-                            }
-                            settings.language = value.to_string();
-                        }
-                        "timezone" | "tz" => {
-                            settings.timezone = value.to_string();
-                        }
-                        "prompt" | "system" => {
-                            settings.system_prompt = Some(value.to_string());
+                }
+                _ => Ok("Usage: /prompt show [name] [lang] | /prompt reload".to_string()),
+            }
+        }));
+}
+
+/// Rank of a role, for "at least this role" checks
+fn role_rank(role: &str) -> u8 {
+    match role {
+        "owner" => 3,
+        "admin" => 2,
+        "user" => 1,
+        _ => 0,
+    }
+}
+
+/// Why `user_id` may not use `model` (per `llm.model-roles`), if they may not
+fn model_denied(db: &Database, config: &LLMConfig, model: &str, user_id: &str) -> Option<String> {
+    let required = config.model_role(model)?;
+    let role = get_user_role(db, user_id);
+    if role_rank(&role) >= role_rank(required) {
+        return None;
+    }
+    Some(format!("❌ {} is limited to the {} role and above (you are {}).", model, required, role))
+}
+
+/// Provider/model picked for a chat with /model
+fn get_chat_model(db: &Database, chat_id: &str) -> Option<database::ChatModel> {
+    match db.chat_model(chat_id) {
+        Ok(choice) => choice,
+        Err(e) => {
+            tracing::warn!("Failed to read chat model: {}", e);
+            None
+        }
+    }
+}
+
+/// Forget a chat's /model choice
+fn reset_chat_model(db: &Database, chat_id: &str) -> String {
+    match db.clear_chat_model(chat_id) {
+        Ok(true) => "🔄 This chat uses the default model again.".to_string(),
+        Ok(false) => "This chat already uses the default model.".to_string(),
+        Err(e) => format!("Error resetting model: {}", e),
+    }
+}
+
+/// Whether `user_id` may change the model of `chat_id`; group chats need owner/admin
+fn can_pick_model(db: &Database, chat_id: &str, user_id: &str) -> bool {
+    if chat_id.starts_with('-') {
+        let role = get_user_role(db, user_id);
+        return role == "owner" || role == "admin";
+    }
+    can_use_privileged(user_id).unwrap_or(false)
+}
+
+/// Applies the provider/model a chat picked with /model to each request
+struct ChatModelSelector {
+    db: Arc<Database>,
+    config: Arc<LLMConfig>,
+}
+
+impl ChatModelSelector {
+    fn new(db: Arc<Database>, config: LLMConfig) -> Self {
+        Self { db, config: Arc::new(config) }
+    }
+}
+
+#[async_trait]
+impl ModelSelector for ChatModelSelector {
+    async fn select(&self, scope: &UsageScope) -> Option<LLMRoute> {
+        let (scope, config) = (scope.clone(), self.config.clone());
+        self.db.call(move |db| {
+            let choice = get_chat_model(db, &scope.chat_id)?;
+            // In groups the pick may be above the role of whoever is asking;
+            // a provider-only pick is checked against the model it routes to
+            let model = choice.model.clone().unwrap_or_else(|| config.route_model(&LLMRoute::new(&choice.provider, None)));
+            if let Some(reason) = model_denied(db, &config, &model, &scope.user_id) {
+                tracing::debug!("Not using the chat's model for {}: {}", scope.user_id, reason);
+                return None;
+            }
+            Some(LLMRoute::new(choice.provider, choice.model.as_deref()))
+        }).await
+    }
+}
+
+/// Handle /model with an LLM available: show, list, reset, or pick a
+/// provider/model after checking it against the provider's model list
+async fn select_model(router: &LLMRouter, db: &Database, chat_id: &str, user_id: &str, args: &[String]) -> String {
+    let current = {
+        let chat_id = chat_id.to_string();
+        db.call(move |db| get_chat_model(db, &chat_id)).await
+    };
+    let (default_provider, default_model) = router.task_model(LLMTask::Chat).await.unwrap_or_default();
+    let current_provider = current.as_ref().map(|c| c.provider.clone()).unwrap_or(default_provider.clone());
+    
+    match args.first().map(|a| a.as_str()) {
+        None => {
+            let providers: Vec<String> = router.status().into_iter().map(|s| match s.cooldown {
+                Some(cooldown) => format!("{} (skipped for {}s)", s.name, cooldown.as_secs().max(1)),
+                None => s.name,
+            }).collect();
+            let active = match &current {
+                Some(c) => format!(
+                    "{}/{} (picked for this chat)",
+                    c.provider,
+                    c.model.clone().unwrap_or_else(|| router.config().route_model(&LLMRoute::new(&c.provider, None)))
+                ),
+                None => format!("{}/{} (default)", default_provider, default_model),
+            };
+            format!(
+                "🧠 *Model*\n\nCurrent: {}\nProviders: {}\n\n\
+                /model list [provider] - available models\n\
+                /model <provider> [model] or /model <model> - pick one for this chat\n\
+                /model reset - back to the default",
+                active, providers.join(", ")
+            )
+        }
+        Some("list") | Some("ls") => {
+            let provider = args.get(1).cloned().unwrap_or(current_provider);
+            let Some(models) = router.models(&provider).await else {
+                return format!("Unknown provider {}. See /model", provider);
+            };
+            let mut response = format!("🧠 *{} models*\n\n", provider);
+            for model in models.iter().take(MAX_LISTED_MODELS) {
+                match router.config().model_role(model) {
+                    Some(role) => response.push_str(&format!("• {} 🔒 {}\n", model, role)),
+                    None => response.push_str(&format!("• {}\n", model)),
+                }
+            }
+            if models.len() > MAX_LISTED_MODELS {
+                response.push_str(&format!("…and {} more\n", models.len() - MAX_LISTED_MODELS));
+            }
+            response
+        }
+        Some("reset") | Some("default") => {
+            let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+            db.call(move |db| {
+                if !can_pick_model(db, &chat_id, &user_id) {
+                    return "❌ Access denied. In groups only owner/admin can change the model.".to_string();
+                }
+                reset_chat_model(db, &chat_id)
+            }).await
+        }
+        Some(first) => {
+            let allowed = {
+                let (chat_id, user_id) = (chat_id.to_string(), user_id.to_string());
+                db.call(move |db| can_pick_model(db, &chat_id, &user_id)).await
+            };
+            if !allowed {
+                return "❌ Access denied. In groups only owner/admin can change the model.".to_string();
+            }
+            
+            // `/model <provider> [model]`, or `/model <model>` on the current provider
+            let (provider, requested) = if router.has_provider(first) {
+                (first.to_lowercase(), args.get(1).cloned())
+            } else {
+                (current_provider, Some(first.to_string()))
+            };
+            
+            let model = match requested {
+                Some(requested) => {
+                    let models = router.models(&provider).await.unwrap_or_default();
+                    match models.iter().find(|m| m.eq_ignore_ascii_case(&requested)) {
+                        Some(model) => Some(model.clone()),
+                        None => {
+                            let needle = requested.to_lowercase();
+                            let similar: Vec<&str> = models.iter()
+                                .filter(|m| m.to_lowercase().contains(&needle))
+                                .take(5)
+                                .map(|m| m.as_str())
+                                .collect();
+                            let hint = if similar.is_empty() {
+                                format!("See /model list {}", provider)
+                            } else {
+                                format!("Did you mean: {}?", similar.join(", "))
+                            };
+                            return format!("❌ {} doesn't serve {}. {}", provider, requested, hint);
                         }
-                        _ => return Ok("Invalid key. Use: language, timezone, prompt".to_string()),
                     }
-                    
-                    if let Err(e) = db.set_user_settings(user_id, &settings) {
-                        return Ok(format!("Error saving: {}", e));
+                }
+                None => None,
+            };
+            
+            let shown = model.clone().unwrap_or_else(|| router.config().route_model(&LLMRoute::new(&provider, None)));
+            let (config, chat_id, user_id) = (router.config().clone(), chat_id.to_string(), user_id.to_string());
+            db.call(move |db| {
+                if let Some(reason) = model_denied(db, &config, &shown, &user_id) {
+                    return reason;
+                }
+                if let Err(e) = db.set_chat_model(&chat_id, &provider, model.as_deref()) {
+                    return format!("Error saving model: {}", e);
+                }
+                format!("✅ This chat now uses {}/{}", provider, shown)
+            }).await
+        }
+    }
+}
+
+/// Models shown by /model list
+const MAX_LISTED_MODELS: usize = 40;
+
+/// Register /model; without an LLM only showing and resetting the choice works
+fn register_model_command(commands: &mut CommandService, db: &Arc<Database>) {
+    use crate::domain::entities::{Command, Content};
+    
+    let db = db.clone();
+    commands.register(Command::new("model")
+        .with_description("Pick the LLM provider and model for this chat")
+        .with_usage("/model [list [provider] | <provider> [model] | <model> | reset]")
+        .with_handler(move |msg| {
+            let Content::Command { name: _, args } = &msg.content else {
+                return Ok("Error: invalid command".to_string());
+            };
+            
+            let user_id = msg.sender.as_ref().map(|u| u.id.as_str()).unwrap_or(&msg.chat_id);
+            match args.first().map(|a| a.as_str()) {
+                None => match get_chat_model(&db, &msg.chat_id) {
+                    Some(c) => Ok(format!("🧠 This chat uses {}/{}", c.provider, c.model.as_deref().unwrap_or("default"))),
+                    None => Ok("🧠 This chat uses the default model".to_string()),
+                },
+                Some("reset") | Some("default") => {
+                    if !can_pick_model(&db, &msg.chat_id, user_id) {
+                        return Ok("❌ Access denied. In groups only owner/admin can change the model.".to_string());
                     }
-                    
-                    Ok(format!("✅ Setting saved:\n{} = {}", key, value))
+                    Ok(reset_chat_model(&db, &msg.chat_id))
                 }
-                _ => Ok("Usage: /settings <get|set> [key] [value]".to_string())
+                _ => Ok("❌ No LLM provider configured to check model names.".to_string()),
             }
         }));
 }
@@ -2373,7 +4572,12 @@ Carik is an AI assistant that can help you with:\n\n\
 • /workspace - Manage workspaces\n\
 • /settings - Language & preferences\n\
 • /users - User management (owner/admin)\n\
-• /clear - Clear conversation history\n\n\
+• /clear - Clear conversation history\n\
+• /history - Export conversation history\n\
+• /memory - What I remember about you\n\
+• /docs - Documents I answer from (send a file to add one)\n\
+• /usage - Your LLM token usage\n\
+• /prompt - Prompt templates (owner)\n\n\
 *🎯 Tips*\n\
 Just ask naturally! Examples:\n\
 \"how's bitcoin doing?\"\n\
@@ -2407,7 +4611,7 @@ fn generate_javanese_greeting(bot_username: &str) -> String {
     format!("Sugeng rawuh Pak Lurah Ing {}\n\nKulo niku Carik AI Assistant.\nNyuwun sewu, kepareng nepangaken.\nPanjenenganipun inggih punika tamu ing wewaton iki.\nMonggo kerso dipunbotenaken. Sendiko dawuh!\n\n/help - Pitulungan\n/about - Nepangaken Carik\n/ping - Mriki Piyambak\n/clear - Ngresikaken Obrolan\n/quote - UnggahQuote", bot_username)
 }
 
-async fn run_console_bot<B: Bot>(bot: B, mut commands: CommandService) {
+async fn run_console_bot<B: Bot>(bot: B, commands: CommandService) {
     use domain::entities::Message;
     use domain::entities::Content;
 
@@ -2418,6 +4622,7 @@ async fn run_console_bot<B: Bot>(bot: B, mut commands: CommandService) {
 
     let info = bot.bot_info();
     tracing::info!("Bot started: @{}", info.username);
+    let commands = Arc::new(commands);
 
     // Main loop (for console mode)
     loop {
@@ -2436,7 +4641,7 @@ async fn run_console_bot<B: Bot>(bot: B, mut commands: CommandService) {
                     .unwrap_or("");
 
                 let msg = Message::from_command("console", cmd_name, vec![]);
-                match commands.handle(&msg) {
+                match handle_command(&commands, msg).await {
                     Ok(Some(response)) => {
                         let _ = bot.send_message("console", &response).await;
                     }
@@ -2554,7 +4759,7 @@ fn fetch_financial_data(data_type: &str) -> String {
 }
 
 fn register_rss_command(commands: &mut CommandService) {
-    use crate::domain::entities::{Command, Content};
+    use crate::domain::entities::{Command, Content, ToolParam};
     
     // RSS feed presets
     let feeds = vec![
@@ -2569,6 +4774,9 @@ fn register_rss_command(commands: &mut CommandService) {
     commands.register(Command::new("rss")
         .with_description("Fetch RSS feeds")
         .with_usage("/rss [feed_name|list|URL]")
+        .with_tool(vec![
+            ToolParam::new("feed", "Feed name (yahoo, google, bbc, techcrunch, hn), 'list', or an RSS URL").optional(),
+        ])
         .with_handler(move |msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
@@ -2643,11 +4851,16 @@ fn fetch_rss_feed_blocking(url: &str) -> String {
 }
 
 fn register_financial_command(commands: &mut CommandService) {
-    use crate::domain::entities::{Command, Content};
+    use crate::domain::entities::{Command, Content, ToolParam};
     
     commands.register(Command::new("finance")
         .with_description("Get financial data: crypto, stocks, currency")
         .with_usage("/finance [crypto|stocks|currency|summary]")
+        .with_tool(vec![
+            ToolParam::new("category", "Market data to fetch")
+                .with_choices(&["crypto", "stocks", "currency", "summary"])
+                .optional(),
+        ])
         .with_handler(|msg| {
             let Content::Command { name: _, args } = &msg.content else {
                 return Ok("Error: invalid command".to_string());
@@ -2819,9 +5032,278 @@ fn register_financial_command(commands: &mut CommandService) {
         }));
 }
 
+/// Options of `carik-bot eval`
+struct EvalOptions {
+    suite: String,
+    provider: Option<String>,
+    model: Option<String>,
+    judge: bool,
+    baseline: Option<String>,
+    out: String,
+}
+
+/// Run the eval suite and write `<out>.json` and `<out>.md`; returns whether
+/// any case regressed against the baseline
+async fn run_eval(config_path: &str, options: EvalOptions) -> bool {
+    let config = load_config(config_path);
+    load_prompts(&config);
+    let suite = match eval::Suite::load(&options.suite) {
+        Ok(suite) => suite,
+        Err(e) => {
+            eprintln!("❌ Failed to load suite: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let baseline = match options.baseline.as_deref().map(eval::Report::load).transpose() {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            eprintln!("❌ Failed to load baseline: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let llm_config = config.llm.clone().unwrap_or_default().with_env();
+    let guard = build_guard(&config, &llm_config, None);
+    let mut router = LLMRouter::from_config(llm_config).with_guardrail(Arc::new(guard));
+    let target = match &options.provider {
+        Some(provider) => {
+            if !router.has_provider(provider) {
+                eprintln!("❌ Provider '{}' is not configured", provider);
+                std::process::exit(2);
+            }
+            router = router.with_model_selector(Arc::new(LLMRoute::new(provider, options.model.as_deref())));
+            format!("{}/{}", provider, options.model.as_deref().unwrap_or("default"))
+        }
+        None => "default".to_string(),
+    };
+    if router.is_empty() {
+        eprintln!("❌ No LLM provider configured");
+        std::process::exit(2);
+    }
+
+    let persona = load_persona();
+    println!("Running {} ({} cases) against {}", suite.name, suite.cases.len(), target);
+    let mut results = Vec::new();
+    for case in &suite.cases {
+        let started = std::time::Instant::now();
+        let reply = UsageScope::new("eval", "eval")
+            .run(eval_reply(&router, &persona, case))
+            .await
+            .map_err(|e| e.to_string());
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let verdict = match (&reply, options.judge) {
+            (Ok(text), true) => match eval::judge(&router, case, text).await {
+                Ok(verdict) => Some(verdict),
+                Err(e) => {
+                    tracing::warn!("Judge failed on {}: {}", case.name, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let result = eval::CaseResult::new(case, reply, verdict, suite.judge_threshold, latency_ms);
+        println!("  {} {} ({:.2})", if result.passed { "✅" } else { "❌" }, result.name, result.score);
+        results.push(result);
+    }
+
+    let report = eval::Report::new(&suite.name, target, results);
+    let comparison = baseline.as_ref().map(|b| report.compare(b));
+    let json_path = format!("{}.json", options.out);
+    let md_path = format!("{}.md", options.out);
+    if let Some(parent) = std::path::Path::new(&json_path).parent().filter(|p| !p.as_os_str().is_empty()) {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = fs::write(&json_path, serde_json::to_string_pretty(&report).unwrap())
+        .and_then(|_| fs::write(&md_path, report.to_markdown(comparison.as_ref())))
+    {
+        eprintln!("❌ Failed to write report: {}", e);
+        std::process::exit(2);
+    }
+
+    println!("\nPassed {}/{}, score {:.2}", report.passed(), report.cases.len(), report.score());
+    if let Some(c) = &comparison {
+        println!("Baseline {:.2} → {:.2} ({:+.2})", c.baseline_score, c.score, c.score - c.baseline_score);
+        if !c.regressions.is_empty() {
+            println!("Regressions: {}", c.regressions.join(", "));
+        }
+    }
+    println!("Report written to {} and {}", json_path, md_path);
+    comparison.is_some_and(|c| !c.regressions.is_empty())
+}
+
+/// Reply to an eval case the way the bot would, without tools
+async fn eval_reply(llm: &LLMRouter, persona: &str, case: &eval::Case) -> Result<String, LLMError> {
+    let lang = case.lang.as_deref();
+    let vars = Vars::new()
+        .with("persona", persona)
+        .with("user.language", lang.unwrap_or_default());
+    match case.kind {
+        eval::CaseKind::Translate => {
+            let target = case.target_language.as_deref().unwrap_or("English");
+            translate(llm, lang, &vars, target, &case.input).await
+        }
+        eval::CaseKind::News => {
+            let system = chat_system_prompt(&vars, lang, None);
+            let topic_hint = case.topic.as_deref().map(|t| format!(" Focus on {} news.", t)).unwrap_or_default();
+            summarize_news(llm, &system, lang, &case.input, &topic_hint).await
+        }
+        eval::CaseKind::Chat => {
+            let mut messages = vec![LLMMessage::system(chat_system_prompt(&vars, lang, None))];
+            for turn in &case.history {
+                messages.push(if turn.role == "assistant" {
+                    LLMMessage::assistant(&turn.content)
+                } else {
+                    LLMMessage::user(&turn.content)
+                });
+            }
+            messages.push(LLMMessage::user(&case.input));
+            let response = llm.chat_task(LLMTask::Chat, messages, Some(0.7), None).await?;
+            Ok(response.content)
+        }
+    }
+}
+
 fn init_config() {
     let config = Config::default();
     let yaml = serde_yaml::to_string(&config).unwrap();
     println!("{}", yaml);
     println!("\nSave this to config.yaml and adjust as needed.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use infrastructure::llm::{LLMProvider, MockProvider};
+
+    /// Router whose only provider is `mock`, as Groq
+    fn mock_router(mock: Arc<MockProvider>) -> LLMRouter {
+        let config = LLMConfig { provider: LLMProvider::Groq, ..LLMConfig::default() };
+        LLMRouter::new(config).with_provider("groq", mock)
+    }
+
+    #[tokio::test]
+    async fn test_translate_offline() {
+        let mock = Arc::new(MockProvider::new("groq").on("Sugeng enjing", "Good morning"));
+        let router = mock_router(mock.clone());
+
+        let translation = translate(&router, Some("jv"), &Vars::new(), "English", "Sugeng enjing").await.unwrap();
+        assert_eq!(translation, "Good morning");
+        let request = mock.last_request().unwrap();
+        assert!(request.last_user().contains("English"));
+        assert_eq!(request.temperature, Some(0.3));
+    }
+
+    #[tokio::test]
+    async fn test_news_summary_keeps_urls() {
+        let digest = r#"```json
+{"intro": "Here's the news:", "headlines": [{"title": "Rust 2.0", "url": "https://example.com/rust"}]}
+```"#;
+        // The first reply misses the headlines and is sent back for repair
+        let mock = Arc::new(MockProvider::new("groq")
+            .then(r#"{"intro": "Here's the news:"}"#)
+            .then(digest));
+        let router = mock_router(mock.clone());
+
+        let summary = summarize_news(&router, "persona", None, "1. Rust 2.0 - https://example.com/rust", "tech").await.unwrap();
+        assert!(summary.starts_with("Here's the news:"));
+        assert!(summary.contains("🔗 https://example.com/rust"));
+        assert_eq!(mock.calls(), 2);
+        assert!(mock.requests().iter().all(|r| r.json));
+    }
+
+    #[tokio::test]
+    async fn test_eval_chat_case_pinned_to_provider() {
+        let mock = Arc::new(MockProvider::new("groq").otherwise("Sugeng enjing! Menapa ingkang saged kula bantu?"));
+        let router = mock_router(mock.clone()).with_model_selector(Arc::new(LLMRoute::new("groq", Some("llama-eval"))));
+        let case: eval::Case = serde_yaml::from_str(
+            "name: jv-followup\nlang: jv\nhistory:\n- {role: user, content: Halo}\n- {role: assistant, content: Halo!}\ninput: Sugeng enjing\nchecks:\n  language: jv\n"
+        ).unwrap();
+
+        let reply = UsageScope::new("eval", "eval").run(eval_reply(&router, "persona", &case)).await;
+        let result = eval::CaseResult::new(&case, reply.map_err(|e| e.to_string()), None, 0.6, 0);
+        assert!(result.passed, "{:?}", result.checks);
+
+        let request = mock.last_request().unwrap();
+        assert_eq!(request.model.as_deref(), Some("llama-eval"));
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+    }
+
+    #[tokio::test]
+    async fn test_provider_pick_checks_model_roles() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.set_chat_model("-100", "groq", None).unwrap();
+        let mut config = LLMConfig::default();
+        config.model_roles.insert("llama-3.3".to_string(), "admin".to_string());
+
+        // `/model groq` routes to groq's default model, which is admin-only
+        let selector = ChatModelSelector::new(db.clone(), config);
+        assert_eq!(selector.select(&UsageScope::new("42", "-100")).await, None);
+        db.add_user("42", None, "admin").unwrap();
+        assert_eq!(selector.select(&UsageScope::new("42", "-100")).await, Some(LLMRoute::new("groq", None)));
+    }
+
+    #[test]
+    fn test_aliases_belong_to_the_sender() {
+        use crate::domain::entities::{Message, User};
+
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        let mut commands = CommandService::new("/");
+        register_alias_command(&mut commands, &db);
+        let alias = |args: &[&str]| {
+            let args = args.iter().map(|a| a.to_string()).collect();
+            commands.handle(&Message::from_command("-100", "alias", args).with_sender(User::new("42"))).unwrap().unwrap()
+        };
+
+        assert!(alias(&["add", "hn", "/rss", "hn"]).starts_with("✅"));
+        assert!(commands.expand_aliases("42", "/hn").unwrap().is_some());
+        assert!(commands.expand_aliases("-100", "/hn").unwrap().is_none());
+        assert_eq!(db.list_aliases().unwrap()[0].scope, "42");
+
+        // --global checks the sender's role, not the group's
+        db.add_user("-100", None, "admin").unwrap();
+        assert!(alias(&["add", "--global", "gm", "/ping"]).starts_with("❌"));
+        assert!(alias(&["add", "alias", "/ping"]).contains("already a command"));
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let db = Database::new(":memory:").unwrap();
+        for i in 0..6 {
+            db.append_message("42", "42", "user", &format!("note {} about <tags> & rust", i)).unwrap();
+        }
+        let args: Vec<String> = ["here", "rust"].iter().map(|a| a.to_string()).collect();
+        let (here, terms) = parse_search_args(&args);
+        let search = SavedSearch { user_id: "42".to_string(), terms, scope: search_scope(&db, "42", "42", here), saved_at: 0 };
+        assert_eq!(search.scope.user_id.as_deref(), Some("42"));
+        assert!(search.expired());
+
+        // Results are posted in the chat, so a group only searches itself
+        assert_eq!(search_scope(&db, "-100", "42", false).chat_id.as_deref(), Some("-100"));
+        assert_eq!(search_scope(&db, "42", "42", false).chat_id, None);
+
+        let (text, more) = search_page(&db, &search, 0, true);
+        assert!(more);
+        assert!(text.contains("results 1–5"));
+        assert!(text.contains("&lt;tags&gt; &amp; <b>rust</b>"));
+        assert!(text.contains("👤 you"));
+        assert_eq!(search_buttons(0, more)[0][0].callback_data.as_deref(), Some("search:1"));
+
+        let (text, more) = search_page(&db, &search, 1, false);
+        assert!(!more);
+        assert!(text.contains("6. 👤 you · ") && text.contains("<tags> & «rust»"));
+        assert_eq!(search_buttons(1, more)[0].len(), 1);
+        assert!(search_page(&db, &search, 2, false).0.contains("No more results"));
+    }
+
+    #[test]
+    fn test_mydata_subject() {
+        let db = Database::new(":memory:").unwrap();
+        db.add_user("42", None, "admin").unwrap();
+        assert_eq!(mydata_subject(&db, "42", None).as_deref(), Ok("42"));
+        assert_eq!(mydata_subject(&db, "42", Some(&"42".to_string())).as_deref(), Ok("42"));
+        // Even admins can't ask for someone else's data
+        assert!(mydata_subject(&db, "42", Some(&"7".to_string())).is_err());
+    }
+}
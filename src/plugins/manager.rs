@@ -1,6 +1,8 @@
 //! Plugin manager - handles plugin lifecycle and execution
 
 use crate::plugins::trait_def::{Plugin, ExtendedPluginConfig as PluginConfig, PluginResult};
+use crate::domain::entities::{ToolCall, ToolDefinition};
+use crate::domain::entities::tool::tool_name;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use tracing::{info, warn, error};
@@ -77,6 +79,40 @@ impl PluginManager {
         }).collect()
     }
     
+    /// Plugins that expose themselves as LLM tools
+    pub fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        let mut tools: Vec<ToolDefinition> = self.plugins.iter()
+            .filter_map(|(name, plugin)| {
+                plugin.tool_parameters().map(|parameters| ToolDefinition {
+                    name: tool_name(name),
+                    description: plugin.description().to_string(),
+                    parameters,
+                })
+            })
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+    
+    /// Whether `name` is a plugin exposed as a tool
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.tool_plugin(name).is_some()
+    }
+    
+    /// Execute the plugin behind a tool call
+    pub fn call_tool(&self, call: &ToolCall) -> PluginResult {
+        match self.tool_plugin(&call.name) {
+            Some(plugin_name) => self.execute(&plugin_name, call.arguments.clone()),
+            None => PluginResult::error(format!("Tool '{}' not found", call.name)),
+        }
+    }
+    
+    fn tool_plugin(&self, tool: &str) -> Option<String> {
+        self.plugins.iter()
+            .find(|(name, plugin)| tool_name(name) == tool && plugin.tool_parameters().is_some())
+            .map(|(name, _)| name.clone())
+    }
+    
     /// Check if a plugin exists
     pub fn has_plugin(&self, name: &str) -> bool {
         self.plugins.contains_key(name)
@@ -137,3 +173,45 @@ pub type SharedPluginManager = Arc<RwLock<PluginManager>>;
 pub fn create_plugin_manager(config: PluginConfig) -> SharedPluginManager {
     Arc::new(RwLock::new(PluginManager::new(config)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct EchoPlugin;
+
+    impl Plugin for EchoPlugin {
+        fn name(&self) -> &str {
+            "echo-back"
+        }
+
+        fn description(&self) -> &str {
+            "Echo the arguments"
+        }
+
+        fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value, String> {
+            Ok(args)
+        }
+
+        fn tool_parameters(&self) -> Option<serde_json::Value> {
+            Some(json!({"type": "object", "properties": {"text": {"type": "string"}}}))
+        }
+    }
+
+    #[test]
+    fn test_plugin_exposed_as_tool() {
+        let mut manager = PluginManager::new(PluginConfig::default());
+        manager.register(EchoPlugin).unwrap();
+
+        let tools = manager.tool_definitions();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo_back");
+
+        let call = ToolCall { id: "1".to_string(), name: "echo_back".to_string(), arguments: json!({"text": "hi"}) };
+        let result = manager.call_tool(&call);
+        assert!(result.success);
+        assert_eq!(result.output, r#"{"text":"hi"}"#);
+        assert!(!manager.call_tool(&ToolCall { name: "missing".to_string(), ..call }).success);
+    }
+}
@@ -21,6 +21,12 @@ pub trait Plugin: Send + Sync {
     fn metadata(&self) -> HashMap<String, String> {
         HashMap::new()
     }
+    
+    /// Optional: JSON schema of `execute` arguments. Plugins returning a
+    /// schema are offered to the LLM as tools.
+    fn tool_parameters(&self) -> Option<serde_json::Value> {
+        None
+    }
 }
 
 /// Types of plugins supported
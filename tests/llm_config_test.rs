@@ -1,19 +1,20 @@
 //! LLM Configuration Integration Tests
-//! Run with: cargo test --test llm_config_test
+//! These call the real Groq API and are ignored by default.
+//! Run with: GROQ_API_KEY=... cargo test --test llm_config_test -- --ignored
 
 use std::sync::Once;
-use reqwest;
 
 static INIT: Once = Once::new();
 
 fn ensure_init() {
     INIT.call_once(|| {
-        let _ = tracing_subscriber::fmt::init();
+        tracing_subscriber::fmt::init();
     });
 }
 
 /// Test that GROQ_API_KEY is set and has valid format
 #[test]
+#[ignore] // Requires GROQ_API_KEY environment variable
 fn test_groq_api_key_exists() {
     ensure_init();
     
@@ -29,6 +30,7 @@ fn test_groq_api_key_exists() {
 
 /// Test that we can make a simple API call to Groq
 #[tokio::test]
+#[ignore] // Requires GROQ_API_KEY environment variable
 async fn test_groq_api_call() {
     ensure_init();
     
@@ -72,6 +74,7 @@ async fn test_groq_api_call() {
 
 /// Test API key rejection with invalid key
 #[tokio::test]
+#[ignore] // Requires network access
 async fn test_invalid_api_key_rejected() {
     ensure_init();
     
@@ -100,6 +103,7 @@ async fn test_invalid_api_key_rejected() {
 
 /// Test different models are available
 #[tokio::test]
+#[ignore] // Requires GROQ_API_KEY environment variable
 async fn test_model_availability() {
     ensure_init();
     
@@ -139,6 +143,7 @@ async fn test_model_availability() {
 
 /// Test response contains expected metadata
 #[tokio::test]
+#[ignore] // Requires GROQ_API_KEY environment variable
 async fn test_response_metadata() {
     ensure_init();
     
@@ -177,6 +182,7 @@ async fn test_response_metadata() {
 
 /// Test system prompt is respected
 #[tokio::test]
+#[ignore] // Requires GROQ_API_KEY environment variable
 async fn test_system_prompt_respected() {
     ensure_init();
     